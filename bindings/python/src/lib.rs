@@ -0,0 +1,130 @@
+//! Python bindings to the appender, so Python processes name, rotate and symlink their files
+//! exactly like the Rust services.
+//!
+//! Build the extension module with `maturin build --release` in this folder, or with
+//! `cargo build --release -p local-rolling-file-python` and copy the library to
+//! `local_rolling_file.so` (`.pyd` on Windows):
+//!
+//! ```python
+//! from local_rolling_file import RollingFile
+//!
+//! with RollingFile("./log", "app.log", frequency="daily", max_files=9) as f:
+//!     f.write("started\n")
+//! ```
+
+use local_rolling_file::{BasicRollingFileAppender, RollingConditionBasic, RollingFrequency};
+use pyo3::{
+    exceptions::{PyTypeError, PyValueError},
+    prelude::*,
+    types::{PyBytes, PyString, PyTuple},
+};
+use std::{
+    io::Write,
+    sync::{Mutex, MutexGuard},
+};
+
+/// A writable file-like object backed by a rolling file appender.
+///
+/// `frequency` is one of `None`, `"daily"`, `"hourly"` or `"minutely"`, and `max_size` rolls
+/// over once a file reaches that many bytes, 0 meaning unlimited. Text is encoded as UTF-8.
+#[pyclass(module = "local_rolling_file")]
+struct RollingFile {
+    // None once closed
+    appender: Mutex<Option<BasicRollingFileAppender>>,
+}
+
+impl RollingFile {
+    fn lock(&self) -> MutexGuard<'_, Option<BasicRollingFileAppender>> {
+        self.appender.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[pymethods]
+impl RollingFile {
+    #[new]
+    #[pyo3(signature = (folder, prefix, frequency = Some("daily"), max_size = 0, max_files = 9))]
+    fn new(folder: &str, prefix: &str, frequency: Option<&str>, max_size: u64, max_files: usize) -> PyResult<Self> {
+        let mut condition = RollingConditionBasic::new();
+        condition = match frequency {
+            None => condition,
+            Some("daily") => condition.frequency(RollingFrequency::EveryDay),
+            Some("hourly") => condition.frequency(RollingFrequency::EveryHour),
+            Some("minutely") => condition.frequency(RollingFrequency::EveryMinute),
+            Some(other) => return Err(PyValueError::new_err(format!("unknown frequency {:?}", other))),
+        };
+        if max_size > 0 {
+            condition = condition.max_size(max_size);
+        }
+        let appender = BasicRollingFileAppender::new(folder, prefix, condition, max_files)?;
+        Ok(RollingFile {
+            appender: Mutex::new(Some(appender)),
+        })
+    }
+
+    /// Writes `str` as UTF-8, or `bytes`, returning the number of characters or bytes written.
+    fn write(&self, py: Python<'_>, data: &Bound<'_, PyAny>) -> PyResult<usize> {
+        let (bytes, written) = if let Ok(text) = data.downcast::<PyString>() {
+            let text = text.to_cow()?;
+            (text.as_bytes().to_vec(), text.chars().count())
+        } else if let Ok(bytes) = data.downcast::<PyBytes>() {
+            (bytes.as_bytes().to_vec(), bytes.as_bytes().len())
+        } else {
+            return Err(PyTypeError::new_err("write() argument must be str or bytes"));
+        };
+        // Other Python threads keep running while the file is written or rolled over
+        py.allow_threads(|| match self.lock().as_mut() {
+            Some(appender) => Ok(appender.write_all(&bytes)?),
+            None => Err(PyValueError::new_err("write to closed file")),
+        })?;
+        Ok(written)
+    }
+
+    fn flush(&self, py: Python<'_>) -> PyResult<()> {
+        py.allow_threads(|| match self.lock().as_mut() {
+            Some(appender) => Ok(appender.flush()?),
+            None => Ok(()),
+        })
+    }
+
+    /// Flushes and closes the appender. Closing a closed file does nothing.
+    fn close(&self, py: Python<'_>) -> PyResult<()> {
+        let appender = self.lock().take();
+        py.allow_threads(|| match appender {
+            Some(mut appender) => Ok(appender.flush()?),
+            None => Ok(()),
+        })
+    }
+
+    #[getter]
+    fn closed(&self) -> bool {
+        self.lock().is_none()
+    }
+
+    fn writable(&self) -> bool {
+        true
+    }
+
+    fn readable(&self) -> bool {
+        false
+    }
+
+    fn seekable(&self) -> bool {
+        false
+    }
+
+    fn __enter__(slf: Py<Self>) -> Py<Self> {
+        slf
+    }
+
+    #[pyo3(signature = (*_exc_info))]
+    fn __exit__(&self, py: Python<'_>, _exc_info: &Bound<'_, PyTuple>) -> PyResult<bool> {
+        self.close(py)?;
+        Ok(false)
+    }
+}
+
+#[pymodule]
+#[pyo3(name = "local_rolling_file")]
+fn local_rolling_file_py(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<RollingFile>()
+}
@@ -0,0 +1,85 @@
+"""Tests of the Python bindings against the built extension module, see src/lib.rs.
+
+    cargo build --release -p local-rolling-file-python
+    LOCAL_ROLLING_FILE_LIB=target/release/liblocal_rolling_file_py.so python3 -m unittest discover bindings/python
+
+Without LOCAL_ROLLING_FILE_LIB, the module installed by ``maturin develop`` is tested.
+"""
+
+import importlib.util
+import os
+import tempfile
+import unittest
+
+
+def _import():
+    path = os.environ.get("LOCAL_ROLLING_FILE_LIB")
+    if not path:
+        import local_rolling_file
+
+        return local_rolling_file
+    # The extension module is found by its name, whatever the file is called
+    spec = importlib.util.spec_from_file_location("local_rolling_file", path)
+    module = importlib.util.module_from_spec(spec)
+    spec.loader.exec_module(module)
+    return module
+
+
+RollingFile = _import().RollingFile
+
+
+class RollingFileTest(unittest.TestCase):
+    def rolled(self, folder, prefix):
+        contents = []
+        for name in sorted(n for n in os.listdir(folder) if n.startswith(prefix + ".")):
+            with open(os.path.join(folder, name), "rb") as f:
+                contents.append(f.read())
+        return contents
+
+    def test_write(self):
+        with tempfile.TemporaryDirectory() as folder:
+            with RollingFile(folder, "app.log") as f:
+                f.write("started\n")
+                f.write(b"bytes\n")
+                f.flush()
+                self.assertEqual(self.rolled(folder, "app.log"), [b"started\nbytes\n"])
+            self.assertTrue(f.closed)
+            with self.assertRaises(ValueError):
+                f.write("closed\n")
+
+    def test_symlink_to_latest(self):
+        with tempfile.TemporaryDirectory() as folder:
+            with RollingFile(folder, "app.log") as f:
+                f.write("latest\n")
+            if os.name != "nt":
+                self.assertTrue(os.path.islink(os.path.join(folder, "app.log")))
+            with open(os.path.join(folder, "app.log"), "rb") as f:
+                self.assertEqual(f.read(), b"latest\n")
+
+    def test_print_to_file(self):
+        with tempfile.TemporaryDirectory() as folder:
+            with RollingFile(folder, "app.log") as f:
+                print("printed", 42, file=f)
+            self.assertEqual(self.rolled(folder, "app.log"), [b"printed 42\n"])
+
+    def test_write_type(self):
+        with tempfile.TemporaryDirectory() as folder:
+            with RollingFile(folder, "app.log") as f:
+                with self.assertRaises(TypeError):
+                    f.write(42)
+
+    def test_max_size(self):
+        with tempfile.TemporaryDirectory() as folder:
+            with RollingFile(folder, "app.log", frequency=None, max_size=4, max_files=9) as f:
+                for line in ["one\n", "two\n", "three\n"]:
+                    f.write(line)
+            self.assertEqual(b"".join(self.rolled(folder, "app.log")), b"one\ntwo\nthree\n")
+            self.assertEqual(len(self.rolled(folder, "app.log")), 3)
+
+    def test_unknown_frequency(self):
+        with self.assertRaises(ValueError):
+            RollingFile(".", "app.log", frequency="weekly")
+
+
+if __name__ == "__main__":
+    unittest.main()
@@ -0,0 +1,48 @@
+/*
+ * C API of local-rolling-file, built with the `ffi` feature, e.g.
+ * `cargo build --release --features ffi`, which produces the shared library
+ * (liblocal_rolling_file.so, .dylib or local_rolling_file.dll) next to the Rust library.
+ *
+ * Functions returning int return 0 on success and -1 on failure, those returning a pointer
+ * return NULL on failure. A handle may be used from any thread, but not from several threads
+ * at once.
+ */
+#ifndef LOCAL_ROLLING_FILE_H
+#define LOCAL_ROLLING_FILE_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct RollingFile RollingFile;
+
+/* Rolling frequencies of rolling_file_new */
+#define ROLLING_FILE_NEVER 0
+#define ROLLING_FILE_DAILY 1
+#define ROLLING_FILE_HOURLY 2
+#define ROLLING_FILE_EVERY_MINUTE 3
+
+/*
+ * Creates an appender writing `prefix` in `folder`, rolling over at `frequency` or once the file
+ * reaches `max_size` bytes (0 = unlimited), and keeping `max_files` rolled files.
+ */
+RollingFile *rolling_file_new(const char *folder, const char *prefix, int frequency,
+                              uint64_t max_size, size_t max_files);
+
+/* Writes `len` bytes of `data` */
+int rolling_file_write(RollingFile *file, const uint8_t *data, size_t len);
+
+/* Flushes buffered data to the file */
+int rolling_file_flush(RollingFile *file);
+
+/* Flushes and closes the appender, `file` must not be used afterwards. NULL does nothing. */
+void rolling_file_close(RollingFile *file);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* LOCAL_ROLLING_FILE_H */
@@ -0,0 +1,251 @@
+//! Managing the appender of a running service over HTTP.
+//!
+//! [`SharedRollingFileAppender::admin_response`] answers `POST /rotate`, `POST /flush` and
+//! `GET /status` independently of any HTTP stack, so it can be plugged into an existing axum or
+//! hyper router. Services without one can start the small embedded server of
+//! [`SharedRollingFileAppender::serve_admin`] instead.
+
+use crate::{json_string, RollingCondition, SharedRollingFileAppender};
+use std::{
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+/// How long a client may take to send its whole request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The most bytes of a request read, request line and headers included
+const MAX_REQUEST_LEN: u64 = 8 * 1024;
+
+/// The answer to an admin request
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AdminResponse {
+    /// The HTTP status code
+    pub status: u16,
+    /// The JSON body
+    pub body: String,
+}
+
+impl AdminResponse {
+    fn new(status: u16, body: &str) -> AdminResponse {
+        AdminResponse {
+            status,
+            body: body.to_string(),
+        }
+    }
+
+    fn from_result(result: io::Result<()>) -> AdminResponse {
+        match result {
+            Ok(()) => AdminResponse::new(200, r#"{"ok":true}"#),
+            Err(e) => AdminResponse {
+                status: 500,
+                body: format!(r#"{{"ok":false,"error":{}}}"#, json_string(&e.to_string())),
+            },
+        }
+    }
+}
+
+fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        431 => "Request Header Fields Too Large",
+        _ => "Internal Server Error",
+    }
+}
+
+/// Serves admin requests over HTTP. Start it with [`SharedRollingFileAppender::serve_admin`];
+/// dropping it stops the server.
+#[derive(Debug)]
+pub struct AdminServer {
+    local_addr: SocketAddr,
+    stop: Arc<AtomicBool>,
+    thread_opt: Option<JoinHandle<()>>,
+}
+
+impl AdminServer {
+    /// Returns the address the server listens on
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+}
+
+impl Drop for AdminServer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Release);
+        // Wake up the accepting thread
+        let _ = TcpStream::connect(self.local_addr);
+        if let Some(thread) = self.thread_opt.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl<RC> SharedRollingFileAppender<RC>
+where
+    RC: RollingCondition + Send + 'static,
+{
+    /// Handles an admin request given its method and path:
+    /// - `POST /rotate` rolls over to a new file
+    /// - `POST /flush` flushes buffered data
+    /// - `GET /status` describes the active file and the rolling condition
+    pub fn admin_response(&self, method: &str, path: &str) -> AdminResponse {
+        let path = path.split_once('?').map_or(path, |(path, _)| path);
+        match (method, path) {
+            ("POST", "/rotate") => AdminResponse::from_result(self.lock().rollover()),
+            ("POST", "/flush") => AdminResponse::from_result(self.lock().flush()),
+            ("GET", "/status") => {
+                let rfa = self.lock();
+                let current = rfa
+                    .current_file_path()
+                    .map_or("null".to_string(), |p| json_string(&p.to_string_lossy()));
+                let max_lag = rfa
+                    .max_rollover_lag()
+                    .map_or("null".to_string(), |lag| lag.num_milliseconds().to_string());
+                AdminResponse {
+                    status: 200,
+                    body: format!(
+                        r#"{{"current_file":{},"current_size":{},"condition":{},"discarded_bytes":{},"max_rollover_lag_ms":{}}}"#,
+                        current,
+                        rfa.current_filesize,
+                        json_string(&rfa.condition_ref().describe()),
+                        rfa.discarded_bytes(),
+                        max_lag
+                    ),
+                }
+            },
+            (_, "/rotate" | "/flush" | "/status") => AdminResponse::new(405, r#"{"ok":false}"#),
+            _ => AdminResponse::new(404, r#"{"ok":false}"#),
+        }
+    }
+
+    /// Serves [`SharedRollingFileAppender::admin_response`] over HTTP on `addr`. There is no
+    /// authentication, so bind it to a loopback or otherwise protected address.
+    pub fn serve_admin(&self, addr: impl ToSocketAddrs) -> io::Result<AdminServer> {
+        let listener = TcpListener::bind(addr)?;
+        let local_addr = listener.local_addr()?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let options = self.lock().thread_options().clone();
+        let thread = {
+            let shared = self.clone();
+            let stop = stop.clone();
+            options.spawn("rolling-file-admin", move || {
+                for stream in listener.incoming() {
+                    if stop.load(Ordering::Acquire) {
+                        break;
+                    }
+                    if let Err(e) = stream.and_then(|stream| shared.serve_admin_connection(stream)) {
+                        eprintln!("WARNING: Failed to serve admin request {}", e);
+                    }
+                }
+            })?
+        };
+        Ok(AdminServer {
+            local_addr,
+            stop,
+            thread_opt: Some(thread),
+        })
+    }
+
+    /// Answers a single request and closes the connection.
+    fn serve_admin_connection(&self, stream: TcpStream) -> io::Result<()> {
+        // Requests are served one at a time, don't let a slow or endless client block the others
+        let deadline = Instant::now() + REQUEST_TIMEOUT;
+        let mut reader = BufReader::new(DeadlineReader { stream, deadline }.take(MAX_REQUEST_LEN));
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line)?;
+        // Skip the headers, requests have no body
+        let mut header = String::new();
+        // The blank line ends the headers, whether lines end with CRLF or a bare LF
+        while reader.read_line(&mut header)? > 0 && !header.trim_end().is_empty() {
+            header.clear();
+        }
+        let mut parts = request_line.split_whitespace();
+        let response = match (parts.next(), parts.next()) {
+            _ if reader.get_ref().limit() == 0 => AdminResponse::new(431, r#"{"ok":false}"#),
+            (Some(method), Some(path)) => self.admin_response(method, path),
+            _ => AdminResponse::new(404, r#"{"ok":false}"#),
+        };
+        let mut stream = reader.into_inner().into_inner().stream;
+        write!(
+            stream,
+            "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            response.status,
+            reason_phrase(response.status),
+            response.body.len(),
+            response.body
+        )?;
+        stream.flush()
+    }
+}
+
+/// Reads a request, failing once `deadline` passed
+struct DeadlineReader {
+    stream: TcpStream,
+    deadline: Instant,
+}
+
+impl Read for DeadlineReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = self.deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Err(io::Error::new(io::ErrorKind::TimedOut, "admin request took too long"));
+        }
+        self.stream.set_read_timeout(Some(remaining))?;
+        self.stream.read(buf)
+    }
+}
+
+#[cfg(test)]
+mod t {
+    use super::*;
+    use crate::*;
+    use std::io::Read;
+
+    #[test]
+    fn test_admin_endpoint() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let rfa = BasicRollingFileAppender::new(folder, "log.log", RollingConditionBasic::new().daily(), 3).unwrap();
+        let (shared, _guard) = SharedRollingFileAppender::new(rfa);
+        assert_eq!(shared.admin_response("GET", "/rotate").status, 405);
+        assert_eq!(shared.admin_response("GET", "/nothing").status, 404);
+        assert_eq!(shared.admin_response("POST", "/rotate").status, 200);
+
+        let server = shared.serve_admin("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(server.local_addr()).unwrap();
+        client
+            .write_all(b"GET /status HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains(r#""condition":"rollover=daily""#));
+        assert!(response.contains(r#""current_file":null"#));
+
+        // Bare LF line endings end the headers too
+        shared.lock().write_all(b"line\n").unwrap();
+        let mut client = TcpStream::connect(server.local_addr()).unwrap();
+        client.write_all(b"GET /status HTTP/1.1\nHost: localhost\n\n").unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains(r#""current_file":""#));
+
+        // Headers without an end are cut off at the limit
+        let mut client = TcpStream::connect(server.local_addr()).unwrap();
+        let mut request = b"GET /status HTTP/1.1\r\nX-Padding: ".to_vec();
+        request.resize(MAX_REQUEST_LEN as usize, b'0');
+        client.write_all(&request).unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 431 "));
+    }
+}
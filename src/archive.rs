@@ -0,0 +1,528 @@
+//! Bundling of rolled files into one tar archive per period, and archiving of expired files.
+//!
+//! Bundling needs the `bundle` feature, which writes the archives with the `tar` crate. With
+//! minutely rotation and long retention a folder quickly accumulates tens of thousands of
+//! files. Bundling moves all rolled files of a completed day or month into a single
+//! `log.log.yyyymm.tar` (or `log.log.yyyymmdd.tar`) and deletes the originals. Files still waiting
+//! for their upload are bundled once uploaded. The manifest of the folder, if any, is updated for
+//! the archives written and the files deleted. With `RollingFileAppender::with_bundling` this
+//! runs after every rollover, and the archives are subject to retention of their own.
+//!
+//! Files retention would delete can also be kept elsewhere, e.g. on cheaper storage, with
+//! [`ArchivePolicy::MoveTo`], see [`crate::RollingFileAppender::with_archive`].
+
+use crate::{filesystem, index, Fs};
+#[cfg(feature = "bundle")]
+use crate::{filesystem::FsReader, retention::RetainedFile, NamingScheme, RealFs, RetentionPolicy};
+#[cfg(all(feature = "bundle", feature = "manifest"))]
+use crate::manifest;
+#[cfg(feature = "bundle")]
+use chrono::{prelude::*, Months};
+#[cfg(feature = "bundle")]
+use std::{
+    collections::BTreeMap,
+    io::{Read, Take, Write},
+};
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+/// File name extension used by bundle archives.
+#[cfg(feature = "bundle")]
+pub const ARCHIVE_EXTENSION: &str = "tar";
+
+/// What happens to the files retention no longer keeps in the log folder
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub enum ArchivePolicy {
+    /// They are deleted
+    #[default]
+    Delete,
+    /// They are moved to this folder, by copying and deleting them across file systems. Files
+    /// in subfolders of the log folder keep their relative path.
+    MoveTo(PathBuf),
+}
+
+/// Moves the log file `path` of `folder` and its index sidecar to the archive folder.
+pub(crate) fn move_to_archive(fs: &dyn Fs, folder: &str, path: &Path, archive_folder: &Path) -> io::Result<()> {
+    let target = archive_folder.join(path.strip_prefix(folder).unwrap_or(path));
+    if let Some(parent) = target.parent() {
+        fs.create_dir_all(parent)?;
+    }
+    filesystem::move_file(fs, path, &target)?;
+    // Most files have no sidecar
+    let _ = filesystem::move_file(fs, &index::index_path_for(path), &index::index_path_for(&target));
+    internal_event!(source = %path.display(), target = %target.display(), "archived expired log file");
+    Ok(())
+}
+
+#[cfg(feature = "bundle")]
+/// Determines which rolled files end up in the same archive
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum BundlePeriod {
+    /// One archive per day
+    Daily,
+    /// One archive per month
+    Monthly,
+}
+
+#[cfg(feature = "bundle")]
+impl BundlePeriod {
+    fn key(&self, dt: &DateTime<Local>) -> String {
+        match self {
+            BundlePeriod::Daily => dt.format("%Y%m%d").to_string(),
+            BundlePeriod::Monthly => dt.format("%Y%m").to_string(),
+        }
+    }
+}
+
+#[cfg(feature = "bundle")]
+/// Bundles the rolled files named by `naming` of every period that ended before `now` into one
+/// archive `<prefix>.<period>.tar` per period, deleting the originals. Files already bundled by an earlier
+/// run are kept, the new files are appended to the existing archive. `active` is never bundled. Returns the
+/// archives that were written.
+pub fn bundle_rolled_files(
+    folder: &str,
+    prefix: &str,
+    naming: &dyn NamingScheme,
+    period: BundlePeriod,
+    now: &DateTime<Local>,
+    active: Option<&Path>,
+) -> io::Result<Vec<PathBuf>> {
+    bundle_rolled_files_in(&RealFs, folder, prefix, naming, period, now, active)
+}
+
+#[cfg(feature = "bundle")]
+/// Same as [`bundle_rolled_files`], through `fs`
+pub(crate) fn bundle_rolled_files_in(
+    fs: &dyn Fs,
+    folder: &str,
+    prefix: &str,
+    naming: &dyn NamingScheme,
+    period: BundlePeriod,
+    now: &DateTime<Local>,
+    active: Option<&Path>,
+) -> io::Result<Vec<PathBuf>> {
+    internal_span!("bundle", folder, prefix);
+    let current_key = period.key(now);
+    // The files of each period with the time they were started at
+    let mut groups: BTreeMap<String, Vec<(PathBuf, DateTime<Local>)>> = BTreeMap::new();
+    for name in naming.list(fs, Path::new(folder))? {
+        let path = Path::new(folder).join(&name);
+        // The active file and its sidecars stay in place
+        if Some(path.as_path()) == active {
+            continue;
+        }
+        #[cfg(feature = "upload")]
+        if crate::upload::is_marked(&path) {
+            continue;
+        }
+        if let Some(dt) = naming.started(&name) {
+            let key = period.key(&dt);
+            if key < current_key {
+                let files = groups.entry(key).or_default();
+                let index_path = index::index_path_for(&path);
+                if filesystem::exists(fs, &index_path) {
+                    files.push((index_path, dt));
+                }
+                files.push((path, dt));
+            }
+        }
+    }
+
+    let mut archives = vec![];
+    for (key, mut files) in groups {
+        files.sort();
+        let archive = Path::new(folder).join(format!("{}.{}.{}", prefix, key, ARCHIVE_EXTENSION));
+        append_to_archive(fs, &archive, folder, &files)?;
+        internal_event!(archive = %archive.display(), files = files.len(), "bundled rolled files");
+        let mut removed = vec![];
+        for (f, _) in files {
+            match fs.remove_file(&f) {
+                Ok(()) if f.extension().is_none_or(|ext| ext != index::INDEX_EXTENSION) => removed.push(f),
+                Ok(()) => {},
+                Err(e) => tracing::error!(
+                    "WARNING: Failed to remove bundled logfile {}: {}",
+                    f.to_string_lossy(),
+                    e
+                ),
+            }
+        }
+        #[cfg(feature = "manifest")]
+        manifest::record_maintenance(fs, folder, naming, std::slice::from_ref(&archive), &removed);
+        archives.push(archive);
+    }
+    Ok(archives)
+}
+
+#[cfg(feature = "bundle")]
+/// Deletes the archives `<prefix>.<period>.tar` of `folder` which `policy` doesn't keep, counted
+/// apart from the log files. An archive was last written when its period ended. Returns the
+/// deleted archives.
+pub(crate) fn remove_expired_archives(
+    fs: &dyn Fs,
+    folder: &str,
+    prefix: &str,
+    policy: &RetentionPolicy,
+    now: &DateTime<Local>,
+) -> io::Result<Vec<PathBuf>> {
+    if policy.keeps_everything() {
+        return Ok(vec![]);
+    }
+    let mut archives = fs
+        .read_dir(Path::new(folder))?
+        .into_iter()
+        .filter_map(|name| Some((period_bounds(prefix, &name)?, name)))
+        .collect::<Vec<_>>();
+    archives.sort_by(|a, b| b.cmp(a));
+    let retained = archives
+        .iter()
+        .map(|((_, ended), name)| RetainedFile {
+            len: match policy.needs_sizes() {
+                true => fs.file_len(&Path::new(folder).join(name)).unwrap_or(0),
+                false => 0,
+            },
+            ended_opt: Some(*ended),
+        })
+        .collect::<Vec<_>>();
+
+    let mut deleted = vec![];
+    for (reason_opt, (_, name)) in policy.expiry_reasons(&retained, now).into_iter().zip(archives) {
+        if reason_opt.is_none() {
+            continue;
+        }
+        let path = Path::new(folder).join(name);
+        match fs.remove_file(&path) {
+            Ok(()) => {
+                internal_event!(path = %path.display(), "removed expired archive");
+                deleted.push(path);
+            },
+            Err(e) => tracing::error!(
+                "WARNING: Failed to remove old archive {}: {}",
+                path.to_string_lossy(),
+                e
+            ),
+        }
+    }
+    Ok(deleted)
+}
+
+#[cfg(feature = "bundle")]
+/// Returns when the period of the archive `name` started and ended, if it is one of `prefix`
+fn period_bounds(prefix: &str, name: &str) -> Option<(DateTime<Local>, DateTime<Local>)> {
+    let key = name
+        .strip_prefix(prefix)?
+        .strip_prefix('.')?
+        .strip_suffix(ARCHIVE_EXTENSION)?
+        .strip_suffix('.')?;
+    if !key.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let (start, end) = match key.len() {
+        8 => {
+            let day = NaiveDate::parse_from_str(key, "%Y%m%d").ok()?;
+            (day, day.succ_opt()?)
+        },
+        6 => {
+            let month = NaiveDate::parse_from_str(&format!("{}01", key), "%Y%m%d").ok()?;
+            (month, month.checked_add_months(Months::new(1))?)
+        },
+        _ => return None,
+    };
+    let local = |d: NaiveDate| Local.from_local_datetime(&d.and_time(NaiveTime::MIN)).earliest();
+    Some((local(start)?, local(end)?))
+}
+
+/// Appends files to a tar archive, creating it if needed. The entries of the existing archive are
+/// copied to a temporary archive which is renamed into place, so it is never left half-written.
+#[cfg(feature = "bundle")]
+fn append_to_archive(
+    fs: &dyn Fs,
+    archive: &Path,
+    folder: &str,
+    files: &[(PathBuf, DateTime<Local>)],
+) -> io::Result<()> {
+    let mut tmp_name = archive.as_os_str().to_owned();
+    tmp_name.push(".tmp");
+    let tmp = PathBuf::from(tmp_name);
+    let result = (|| {
+        let mut builder = tar::Builder::new(filesystem::create_file(fs, &tmp)?);
+        if filesystem::exists(fs, archive) {
+            let mut existing = tar::Archive::new(FsReader::new(fs, archive)?);
+            for entry in existing.entries()? {
+                let entry = entry?;
+                let (mut header, path) = (entry.header().clone(), entry.path()?.into_owned());
+                builder.append_data(&mut header, path, entry)?;
+            }
+        }
+        for (f, started) in files {
+            append_entry(fs, &mut builder, folder, f, started)?;
+        }
+        let mut out = builder.into_inner()?;
+        out.flush()?;
+        out.sync_all()
+    })();
+    match result {
+        Ok(()) => fs.rename(&tmp, archive),
+        Err(e) => {
+            let _ = fs.remove_file(&tmp);
+            Err(e)
+        },
+    }
+}
+
+/// Appends the file `path` of `folder`, named after its path relative to the folder. Its
+/// modification time in the archive is the time it was started at.
+#[cfg(feature = "bundle")]
+fn append_entry<W: Write>(
+    fs: &dyn Fs,
+    builder: &mut tar::Builder<W>,
+    folder: &str,
+    path: &Path,
+    started: &DateTime<Local>,
+) -> io::Result<()> {
+    let name = path.strip_prefix(folder).unwrap_or(path);
+    let len = fs.file_len(path)?;
+    let mut header = tar::Header::new_gnu();
+    header.set_entry_type(tar::EntryType::Regular);
+    header.set_size(len);
+    header.set_mode(0o644);
+    header.set_mtime(u64::try_from(started.timestamp()).unwrap_or(0));
+    let data = ExactReader {
+        inner: FsReader::new(fs, path)?.take(len),
+        path,
+    };
+    // Names longer than the header's 100 bytes, e.g. in date subfolders, get a GNU long name entry
+    builder.append_data(&mut header, name, data)
+}
+
+/// Reads exactly the length recorded in an entry's header, failing if the file turns out
+/// shorter, so a file changed while archiving can't corrupt the archive
+#[cfg(feature = "bundle")]
+struct ExactReader<'a, R> {
+    inner: Take<R>,
+    path: &'a Path,
+}
+
+#[cfg(feature = "bundle")]
+impl<R: Read> Read for ExactReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if n == 0 && !buf.is_empty() && self.inner.limit() > 0 {
+            return Err(io::Error::other(format!("{} changed while archiving", self.path.display())));
+        }
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod t {
+    use super::*;
+    use crate::*;
+    use std::fs;
+
+    #[test]
+    fn test_move_to_archive() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let mut rfa = BasicRollingFileAppender::builder()
+            .folder(folder)
+            .prefix("log.log")
+            .condition(RollingConditionBasic::new().daily())
+            .max_files(2)
+            .archive(ArchivePolicy::MoveTo(archive.path().to_path_buf()))
+            .archive_retention(RetentionPolicy::MaxFiles(2))
+            .build()
+            .unwrap();
+        rfa.rollover().unwrap();
+        for day in 1..=5 {
+            let now = Local.with_ymd_and_hms(2099, 3, day, 1, 0, 0).unwrap();
+            rfa.write_with_datetime(format!("day {}\n", day).as_bytes(), &now)
+                .unwrap();
+        }
+        rfa.flush().unwrap();
+
+        let names = |path: &Path| {
+            let mut names = fs::read_dir(path)
+                .unwrap()
+                .map(|f| f.unwrap().file_name().into_string().unwrap())
+                .collect::<Vec<_>>();
+            names.sort();
+            names
+        };
+        assert_eq!(
+            names(dir.path()),
+            ["log.log", "log.log.20990304.010000", "log.log.20990305.010000"]
+        );
+        // The archive keeps its own two newest files
+        assert_eq!(
+            names(archive.path()),
+            ["log.log.20990302.010000", "log.log.20990303.010000"]
+        );
+        let archived = fs::read_to_string(archive.path().join("log.log.20990303.010000")).unwrap();
+        assert_eq!(archived, "day 3\n");
+    }
+
+    /// Returns the names and contents of the entries of a tar archive
+    #[cfg(feature = "bundle")]
+    fn entries(archive: &Path) -> Vec<(String, String)> {
+        let mut archive = tar::Archive::new(fs::File::open(archive).unwrap());
+        archive
+            .entries()
+            .unwrap()
+            .map(|entry| {
+                let mut entry = entry.unwrap();
+                let name = entry.path().unwrap().to_string_lossy().to_string();
+                let mut contents = String::new();
+                entry.read_to_string(&mut contents).unwrap();
+                (name, contents)
+            })
+            .collect()
+    }
+
+    #[cfg(feature = "bundle")]
+    #[test]
+    fn test_bundle_by_month() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let mut rfa =
+            BasicRollingFileAppender::new(folder, "log.log", RollingConditionBasic::new().daily(), 99).unwrap();
+        for (month, day) in [(3, 30), (3, 31), (4, 1)] {
+            let now = Local.with_ymd_and_hms(2021, month, day, 1, 0, 0).unwrap();
+            rfa.write_with_datetime(format!("{}-{}\n", month, day).as_bytes(), &now)
+                .unwrap();
+        }
+        rfa.flush().unwrap();
+
+        let now = Local.with_ymd_and_hms(2021, 4, 1, 2, 0, 0).unwrap();
+        let archives = rfa.bundle_rolled_files(BundlePeriod::Monthly, &now).unwrap();
+        assert_eq!(archives, vec![dir.path().join("log.log.202103.tar")]);
+        assert!(!dir.path().join("log.log.20210330.010000").exists());
+        assert!(dir.path().join("log.log.20210401.010000").exists());
+
+        assert_eq!(
+            entries(&archives[0]),
+            [
+                ("log.log.20210330.010000".to_string(), "3-30\n".to_string()),
+                ("log.log.20210331.010000".to_string(), "3-31\n".to_string())
+            ]
+        );
+    }
+
+    #[cfg(feature = "bundle")]
+    #[test]
+    fn test_bundle_long_names_and_append() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        // Longer than the 100 bytes a tar header has for the name, once in its date subfolder
+        let prefix = format!("{}.log", "service".repeat(12));
+        let mut rfa = BasicRollingFileAppender::builder()
+            .folder(folder)
+            .prefix(&prefix)
+            .condition(RollingConditionBasic::new().daily())
+            .max_files(99)
+            .naming_scheme(DailyFolderNaming::new(&prefix))
+            .build()
+            .unwrap();
+        for day in [28, 29, 30] {
+            let now = Local.with_ymd_and_hms(2021, 3, day, 1, 0, 0).unwrap();
+            rfa.write_with_datetime(format!("3-{}\n", day).as_bytes(), &now)
+                .unwrap();
+        }
+        rfa.flush().unwrap();
+        let now = Local.with_ymd_and_hms(2021, 3, 30, 2, 0, 0).unwrap();
+        let archives = rfa.bundle_rolled_files(BundlePeriod::Monthly, &now).unwrap();
+        assert!(archives.is_empty());
+
+        // The files of the month are appended to the archive a later run finds
+        let archive = dir.path().join(format!("{}.202103.tar", prefix));
+        let mut builder = tar::Builder::new(fs::File::create(&archive).unwrap());
+        let mut header = tar::Header::new_gnu();
+        header.set_size(6);
+        builder.append_data(&mut header, "earlier", &b"3-27\n\n"[..]).unwrap();
+        builder.finish().unwrap();
+        drop(builder);
+        let now = Local.with_ymd_and_hms(2021, 4, 1, 1, 0, 0).unwrap();
+        rfa.write_with_datetime(b"4-1\n", &now).unwrap();
+        rfa.flush().unwrap();
+        let now = Local.with_ymd_and_hms(2021, 4, 1, 2, 0, 0).unwrap();
+        assert_eq!(rfa.bundle_rolled_files(BundlePeriod::Monthly, &now).unwrap(), vec![archive.clone()]);
+
+        let found = entries(&archive);
+        let names = found.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>();
+        let name = |day| format!("2021-03-{}/{}.010000", day, prefix);
+        assert_eq!(names, ["earlier", &name(28), &name(29), &name(30)]);
+        assert!(name(28).len() > 100);
+        assert_eq!(found[3].1, "3-30\n");
+    }
+
+    #[cfg(feature = "bundle")]
+    #[test]
+    fn test_bundle_on_rollover() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let mut rfa = BasicRollingFileAppender::new(folder, "log.log", RollingConditionBasic::new().daily(), 9)
+            .unwrap()
+            .with_retention(RetentionPolicy::MaxFiles(2))
+            .with_bundling(BundlePeriod::Daily);
+        for day in 1..=5 {
+            let now = Local.with_ymd_and_hms(2099, 3, day, 1, 0, 0).unwrap();
+            rfa.write_with_datetime(b"line\n", &now).unwrap();
+        }
+        rfa.flush().unwrap();
+
+        let mut names = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+            .collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(
+            names,
+            vec![
+                "log.log",
+                "log.log.20990303.tar",
+                "log.log.20990304.tar",
+                "log.log.20990305.010000"
+            ]
+        );
+    }
+
+    #[cfg(all(feature = "bundle", feature = "manifest"))]
+    #[test]
+    fn test_bundle_updates_manifest() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let mut rfa = BasicRollingFileAppender::new(folder, "log.log", RollingConditionBasic::new().daily(), 99)
+            .unwrap()
+            .with_manifest(true);
+        rfa.rollover().unwrap();
+        for day in [29, 30, 31] {
+            let now = Local.with_ymd_and_hms(2099, 3, day, 1, 0, 0).unwrap();
+            rfa.write_with_datetime(b"line\n", &now).unwrap();
+        }
+        #[cfg(feature = "upload")]
+        fs::write(dir.path().join("log.log.20990329.010000.upload"), b"").unwrap();
+
+        let now = Local.with_ymd_and_hms(2099, 4, 1, 2, 0, 0).unwrap();
+        let archives = rfa.bundle_rolled_files(BundlePeriod::Daily, &now).unwrap();
+        let manifest = fs::read_to_string(manifest::manifest_path(folder)).unwrap();
+        let lines = manifest.lines().collect::<Vec<_>>();
+        let entry = format!(
+            "{{\"file\":\"log.log.20990330.tar\",\"size\":{},\"sha256\":\"{}\",\"start\":null,\"end\":null}}",
+            fs::metadata(&archives[archives.len() - 1]).unwrap().len(),
+            manifest::sha256_file(&archives[archives.len() - 1]).unwrap()
+        );
+        assert_eq!(lines[lines.len() - 2], entry);
+        assert_eq!(
+            lines[lines.len() - 1],
+            "{\"file\":\"log.log.20990330.010000\",\"removed\":true}"
+        );
+        // The file waiting for its upload is left in place
+        #[cfg(feature = "upload")]
+        {
+            assert!(!archives.contains(&dir.path().join("log.log.20990329.tar")));
+            assert!(dir.path().join("log.log.20990329.010000").exists());
+        }
+    }
+}
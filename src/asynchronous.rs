@@ -0,0 +1,327 @@
+//! Writing from async code without blocking the executor.
+//!
+//! An [`AsyncRollingFileAppender`] batches writes in memory, up to [`QUEUE_CAPACITY`] bytes, and
+//! hands them to tokio's blocking thread pool once the queue is full or on flush. There the
+//! writes, flushes, rollovers and retention checks run one operation at a time, like
+//! `tokio::fs::File`, so a slow operation, e.g. a rollover renaming files, updating the symlink
+//! and deleting old files, only holds up writes once the queue filled up again.
+
+use crate::{RollingCondition, RollingFileAppender};
+use std::{
+    future::Future,
+    io::{self, Write},
+    pin::Pin,
+    sync::{Mutex, MutexGuard},
+    task::{ready, Context, Poll},
+};
+use tokio::{io::AsyncWrite, task::JoinHandle};
+
+/// Most bytes batched before they are handed to the blocking pool. A single larger write is
+/// still queued when nothing else is.
+pub const QUEUE_CAPACITY: usize = 256 * 1024;
+
+type Operation<RC> = JoinHandle<(Box<RollingFileAppender<RC>>, io::Result<()>)>;
+
+/// What a blocking operation does after writing the queued data. Each one does all the work of
+/// the ones before.
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+enum Op {
+    Write,
+    Flush,
+    /// Flushes, fsyncs and waits for the maintenance jobs
+    Shutdown,
+}
+
+impl Op {
+    fn run<RC>(self, rfa: &mut RollingFileAppender<RC>, data: &[u8]) -> io::Result<()>
+    where
+        RC: RollingCondition,
+    {
+        rfa.write_all(data)?;
+        match self {
+            Op::Write => Ok(()),
+            Op::Flush => rfa.flush(),
+            Op::Shutdown => rfa.try_finish_pending(),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum State<RC>
+where
+    RC: RollingCondition,
+{
+    Idle(Option<Box<RollingFileAppender<RC>>>),
+    Busy(Operation<RC>, Op),
+    /// An operation panicked or was cancelled, taking the appender with it
+    Failed,
+}
+
+fn lost() -> io::Error {
+    io::Error::other("the appender was lost when a blocking operation panicked or was cancelled")
+}
+
+#[derive(Debug)]
+struct Inner<RC>
+where
+    RC: RollingCondition,
+{
+    state: State<RC>,
+    /// Data written since the last operation started
+    queued: Vec<u8>,
+}
+
+/// An appender implementing [`tokio::io::AsyncWrite`]. A write returns once its data is queued;
+/// the queue is written out when it is full or on flush, and the error of writing it, if any, is
+/// returned by the write finding the queue full or the flush. Like `tokio::io::BufWriter`, queued
+/// data is lost when the appender is dropped, so flush or [`shutdown`] it first.
+///
+/// [`shutdown`]: AsyncRollingFileAppender::shutdown
+#[derive(Debug)]
+pub struct AsyncRollingFileAppender<RC>
+where
+    RC: RollingCondition,
+{
+    /// Only locked by [`AsyncRollingFileAppender::shutdown`], the writes own the appender
+    inner: Mutex<Inner<RC>>,
+}
+
+impl<RC> AsyncRollingFileAppender<RC>
+where
+    RC: RollingCondition + Send + 'static,
+{
+    /// Wraps an appender. Must be used from within a tokio runtime.
+    pub fn new(appender: RollingFileAppender<RC>) -> AsyncRollingFileAppender<RC> {
+        AsyncRollingFileAppender {
+            inner: Mutex::new(Inner {
+                state: State::Idle(Some(Box::new(appender))),
+                queued: vec![],
+            }),
+        }
+    }
+
+    /// Writes the queued data, flushes and fsyncs the active file and waits until the
+    /// maintenance scheduler (if any) has finished every job queued so far. The appender stays
+    /// usable, like [`SharedRollingFileAppender::shutdown`](crate::SharedRollingFileAppender::shutdown).
+    pub async fn shutdown(&self) -> io::Result<()> {
+        std::future::poll_fn(|cx| self.lock().poll_op(cx, Op::Shutdown)).await
+    }
+
+    /// Waits for the pending operation, writes the queued data and returns the appender
+    pub async fn into_inner(mut self) -> io::Result<RollingFileAppender<RC>> {
+        let inner = self.inner_mut();
+        std::future::poll_fn(|cx| inner.poll_op(cx, Op::Write)).await?;
+        match &mut inner.state {
+            State::Idle(rfa_opt) => Ok(*rfa_opt.take().expect("the appender is only taken while busy")),
+            State::Busy(..) | State::Failed => unreachable!(),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Inner<RC>> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn inner_mut(&mut self) -> &mut Inner<RC> {
+        self.inner.get_mut().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl<RC> Inner<RC>
+where
+    RC: RollingCondition + Send + 'static,
+{
+    /// Waits until no operation is pending, returning its result. Once an operation failed to
+    /// complete, the appender is gone and every call fails.
+    fn poll_idle(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match &mut self.state {
+            State::Idle(_) => Poll::Ready(Ok(())),
+            State::Busy(operation, _) => match ready!(Pin::new(operation).poll(cx)) {
+                Ok((rfa, result)) => {
+                    self.state = State::Idle(Some(rfa));
+                    Poll::Ready(result)
+                },
+                Err(e) => {
+                    self.state = State::Failed;
+                    Poll::Ready(Err(io::Error::other(e)))
+                },
+            },
+            State::Failed => Poll::Ready(Err(lost())),
+        }
+    }
+
+    /// Drives the operations until everything written so far went through `op`. An operation
+    /// left running by a dropped call is waited for, and only counts if it does `op`'s work.
+    fn poll_op(&mut self, cx: &mut Context<'_>, op: Op) -> Poll<io::Result<()>> {
+        loop {
+            // Being idle is as good as a finished write
+            let running = match &self.state {
+                State::Busy(_, running) => *running,
+                State::Idle(_) | State::Failed => Op::Write,
+            };
+            let result = ready!(self.poll_idle(cx));
+            if running >= op && self.queued.is_empty() {
+                return Poll::Ready(result);
+            }
+            result?;
+            self.start_queued(op);
+        }
+    }
+
+    fn poll_write(&mut self, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        if !self.queued.is_empty() && self.queued.len() + buf.len() > QUEUE_CAPACITY {
+            // The queue is full, write it out once the running operation, e.g. a rollover, is
+            // done; the waker is registered to go on then
+            ready!(self.poll_idle(cx))?;
+            self.start_queued(Op::Write);
+        }
+        self.queued.extend_from_slice(buf);
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    /// Starts writing the queued data, then doing `op`
+    fn start_queued(&mut self, op: Op) {
+        let data = std::mem::take(&mut self.queued);
+        self.start(op, move |rfa| op.run(rfa, &data));
+    }
+
+    fn start<F>(&mut self, op: Op, f: F)
+    where
+        F: FnOnce(&mut RollingFileAppender<RC>) -> io::Result<()> + Send + 'static,
+    {
+        let mut rfa = match &mut self.state {
+            State::Idle(rfa_opt) => rfa_opt.take().expect("the appender is only taken while busy"),
+            State::Busy(..) | State::Failed => unreachable!(),
+        };
+        self.state = State::Busy(
+            tokio::task::spawn_blocking(move || {
+                let result = f(&mut rfa);
+                (rfa, result)
+            }),
+            op,
+        );
+    }
+}
+
+impl<RC> AsyncWrite for AsyncRollingFileAppender<RC>
+where
+    RC: RollingCondition + Send + 'static,
+{
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        self.get_mut().inner_mut().poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.get_mut().inner_mut().poll_op(cx, Op::Flush)
+    }
+
+    /// Writes the queued data, flushes and fsyncs the active file and waits for the maintenance
+    /// jobs, see [`AsyncRollingFileAppender::shutdown`]
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.get_mut().inner_mut().poll_op(cx, Op::Shutdown)
+    }
+}
+
+#[cfg(test)]
+mod t {
+    use super::*;
+    use crate::*;
+    use tokio::io::AsyncWriteExt;
+
+    #[tokio::test]
+    async fn test_async_writes() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let path = dir.path().join("log.log");
+        let rfa = BasicRollingFileAppender::new(folder, "log.log", RollingConditionBasic::new().daily(), 3).unwrap();
+        let mut appender = AsyncRollingFileAppender::new(rfa);
+        for i in 0..10 {
+            appender.write_all(format!("line {}\n", i).as_bytes()).await.unwrap();
+        }
+        // Batched until the flush
+        assert_eq!(std::fs::read_to_string(&path).unwrap_or_default(), "");
+        appender.flush().await.unwrap();
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(contents.lines().count(), 10);
+
+        appender.write_all(b"last\n").await.unwrap();
+        let mut rfa = appender.into_inner().await.unwrap();
+        rfa.flush().unwrap();
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert!(contents.ends_with("last\n"));
+    }
+
+    #[tokio::test]
+    async fn test_writes_queue_during_rollover() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        // Stands for a slow file system, the first rollover doesn't finish until the test says so
+        let (release, released) = std::sync::mpsc::channel::<()>();
+        let released = std::sync::Mutex::new(released);
+        let rfa = BasicRollingFileAppender::builder()
+            .folder(folder)
+            .prefix("log.log")
+            .condition(RollingConditionBasic::new().max_size(10))
+            .max_files(9)
+            .on_rotate(move |_, _| {
+                let _ = released.lock().unwrap().recv();
+            })
+            .build()
+            .unwrap();
+        let mut appender = AsyncRollingFileAppender::new(rfa);
+        appender.write_all(b"0123456789\n").await.unwrap();
+        appender.flush().await.unwrap();
+        // Fills the queue, so the next write starts writing it, rolling over and blocking in the
+        // hook
+        let mut full = vec![b'x'; QUEUE_CAPACITY - 1];
+        full.push(b'\n');
+        appender.write_all(&full).await.unwrap();
+        let queued = async {
+            for i in 0..100 {
+                appender.write_all(format!("queued {}\n", i).as_bytes()).await.unwrap();
+            }
+        };
+        tokio::time::timeout(std::time::Duration::from_secs(5), queued)
+            .await
+            .expect("writes waited for the rollover");
+        drop(release);
+        appender.flush().await.unwrap();
+
+        let contents = std::fs::read_to_string(dir.path().join("log.log")).unwrap();
+        let lines = contents.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 100);
+        assert_eq!(lines[0], "queued 0");
+        assert_eq!(lines[99], "queued 99");
+    }
+
+    #[tokio::test]
+    async fn test_dropped_flush() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let rfa = BasicRollingFileAppender::new(folder, "log.log", RollingConditionBasic::new().daily(), 3).unwrap();
+        let mut appender = AsyncRollingFileAppender::new(rfa);
+        appender.write_all(b"first\n").await.unwrap();
+        // Gives up on the flush while it still runs
+        let _ = tokio::time::timeout(std::time::Duration::ZERO, appender.flush()).await;
+        appender.write_all(b"second\n").await.unwrap();
+        appender.flush().await.unwrap();
+        let contents = std::fs::read_to_string(dir.path().join("log.log")).unwrap();
+        assert_eq!(contents, "first\nsecond\n");
+    }
+
+    #[tokio::test]
+    async fn test_panicking_operation() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let rfa = BasicRollingFileAppender::new(folder, "log.log", RollingConditionBasic::new().daily(), 3).unwrap();
+        let mut appender = AsyncRollingFileAppender::new(rfa);
+        appender
+            .inner_mut()
+            .start(Op::Write, |_| panic!("blocking operation panicked"));
+        assert!(appender.flush().await.is_err());
+        // The appender is gone, every later call fails instead of polling the finished task
+        appender.write_all(b"lost\n").await.unwrap();
+        assert!(appender.flush().await.is_err());
+        assert!(appender.shutdown().await.is_err());
+        assert!(appender.into_inner().await.is_err());
+    }
+}
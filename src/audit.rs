@@ -0,0 +1,136 @@
+//! An audit trail of the files deleted by retention.
+//!
+//! Compliance audits often ask to prove that logs were deleted according to policy. With
+//! [`crate::RollingFileAppender::with_retention_audit`] every deletion is appended to a
+//! `<prefix>.audit` sidecar next to the logs, one tab separated line per file stating when it
+//! was deleted and why. [`read_audit_log`] reads it back.
+
+use crate::Fs;
+use chrono::{DateTime, FixedOffset, Local, SecondsFormat};
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+/// File name extension of the audit sidecar
+pub const AUDIT_EXTENSION: &str = "audit";
+
+/// A file deleted by retention
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DeletionRecord {
+    /// When the file was deleted
+    pub deleted_at: DateTime<FixedOffset>,
+    /// The deleted file
+    pub path: PathBuf,
+    /// The retention rule which caused the deletion, e.g. `max_files=9`
+    pub reason: String,
+}
+
+/// Returns the path of the audit sidecar of the files of `prefix`
+pub fn audit_path(folder: &str, prefix: &str) -> PathBuf {
+    Path::new(folder).join(format!("{}.{}", prefix, AUDIT_EXTENSION))
+}
+
+/// Appends one record per file deleted at `now` to the audit sidecar and syncs it.
+pub(crate) fn record_deletions(
+    fs: &dyn Fs,
+    folder: &str,
+    prefix: &str,
+    deleted: &[PathBuf],
+    reason: &str,
+    now: &DateTime<Local>,
+) -> io::Result<()> {
+    if deleted.is_empty() {
+        return Ok(());
+    }
+    let now = now.to_rfc3339_opts(SecondsFormat::Millis, false);
+    let mut lines = String::new();
+    for path in deleted {
+        lines.push_str(&format!("{}\t{}\t{}\n", now, path.display(), reason));
+    }
+    let mut f = fs.open_append(&audit_path(folder, prefix))?;
+    f.write_all(lines.as_bytes())?;
+    f.flush()?;
+    f.sync_all()
+}
+
+/// Reads all records of the audit sidecar of `prefix`, oldest first. A missing sidecar means
+/// nothing was deleted yet.
+pub fn read_audit_log(folder: &str, prefix: &str) -> io::Result<Vec<DeletionRecord>> {
+    let contents = match fs::read_to_string(audit_path(folder, prefix)) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e),
+    };
+    contents
+        .lines()
+        .map(|line| {
+            let invalid = || io::Error::new(io::ErrorKind::InvalidData, format!("invalid audit record: {}", line));
+            let mut fields = line.splitn(3, '\t');
+            let (Some(deleted_at), Some(path), Some(reason)) = (fields.next(), fields.next(), fields.next()) else {
+                return Err(invalid());
+            };
+            Ok(DeletionRecord {
+                deleted_at: DateTime::parse_from_rfc3339(deleted_at).map_err(|_| invalid())?,
+                path: PathBuf::from(path),
+                reason: reason.to_string(),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod t {
+    use super::*;
+    use crate::*;
+
+    #[test]
+    fn test_deletions_are_audited() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let mut rfa = BasicRollingFileAppender::new(folder, "log.log", RollingConditionBasic::new().daily(), 2)
+            .unwrap()
+            .with_retention_audit(true);
+        for day in 1..=4 {
+            let now = Local.with_ymd_and_hms(2099, 3, day, 1, 0, 0).unwrap();
+            rfa.write_with_datetime(b"line\n", &now).unwrap();
+        }
+
+        let records = read_audit_log(folder, "log.log").unwrap();
+        let deleted = records
+            .iter()
+            .map(|r| r.path.file_name().unwrap().to_string_lossy().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(deleted.len(), 3);
+        assert!(deleted.contains(&"log.log.20990301.010000".to_string()));
+        assert!(deleted.contains(&"log.log.20990302.010000".to_string()));
+        assert!(records.iter().all(|r| r.reason == "max_files=2"));
+        assert!(dir.path().join("log.log.20990304.010000").exists());
+    }
+
+    #[test]
+    fn test_deletion_time_from_clock() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let clock = MockClock::new(Local.with_ymd_and_hms(2099, 3, 1, 1, 0, 0).unwrap());
+        let mut rfa = BasicRollingFileAppender::builder()
+            .folder(folder)
+            .prefix("log.log")
+            .condition(RollingConditionBasic::new().daily())
+            .max_files(1)
+            .clock(clock.clone())
+            .build()
+            .unwrap()
+            .with_retention_audit(true);
+        rfa.write_all(b"line\n").unwrap();
+        let deleted_at = Local.with_ymd_and_hms(2099, 3, 2, 1, 0, 0).unwrap();
+        clock.set(deleted_at);
+        rfa.write_all(b"line\n").unwrap();
+
+        let records = read_audit_log(folder, "log.log").unwrap();
+        let last = records.last().unwrap();
+        assert_eq!(last.path.file_name().unwrap(), "log.log.20990301.010000.1");
+        assert_eq!(last.deleted_at, deleted_at);
+    }
+}
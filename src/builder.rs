@@ -0,0 +1,721 @@
+//! Configuring an appender with chained setters instead of positional arguments.
+
+use crate::{
+    ArchivePolicy,
+    Clock,
+    Compression,
+    Encryptor,
+    ErrorPolicy,
+    Fs,
+    LatestLink,
+    LowSpacePolicy,
+    NamingScheme,
+    OpenMode,
+    RealFs,
+    RetentionPolicy,
+    RollingCondition,
+    RollingFileAppender,
+    RollingFileError,
+    SyncPolicy,
+    SystemClock,
+    TimestampNaming,
+    Timezone,
+    FILE_NAME_DATETIME_FORMAT,
+};
+use chrono::{
+    format::{Item, StrftimeItems},
+    DateTime,
+    Local,
+};
+use std::{fmt, path::Path, sync::Arc};
+
+type NameFn = dyn Fn(&DateTime<Local>) -> String + Send + Sync;
+type RotateFn = dyn Fn(&Path, &Path) + Send + Sync;
+type DeleteFn = dyn Fn(&Path) + Send + Sync;
+type VetoFn = dyn Fn(&Path) -> bool + Send + Sync;
+type BytesFn = dyn Fn() -> Vec<u8> + Send + Sync;
+
+/// Names the files of an appender, see [`RollingFileAppenderBuilder::file_name`]
+#[derive(Clone)]
+pub(crate) struct FileNameFn(pub(crate) Arc<NameFn>);
+
+impl fmt::Debug for FileNameFn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("FileNameFn")
+    }
+}
+
+/// Callbacks observing the files of an appender, see [`RollingFileAppenderBuilder::on_rotate`]
+#[derive(Clone, Default)]
+pub(crate) struct Hooks {
+    pub(crate) on_rotate_opt: Option<Arc<RotateFn>>,
+    pub(crate) on_delete_opt: Option<Arc<DeleteFn>>,
+    pub(crate) veto_delete_opt: Option<Arc<VetoFn>>,
+    pub(crate) file_header_opt: Option<Arc<BytesFn>>,
+    pub(crate) file_footer_opt: Option<Arc<BytesFn>>,
+    #[cfg(feature = "upload")]
+    pub(crate) uploads_opt: Option<crate::upload::UploadHandle>,
+}
+
+impl Hooks {
+    /// Returns true if retention must keep `path`, because the veto callback says so or it is
+    /// not uploaded yet
+    pub(crate) fn vetoes_deletion(&self, path: &Path) -> bool {
+        #[cfg(feature = "upload")]
+        if self
+            .uploads_opt
+            .as_ref()
+            .is_some_and(|uploads| uploads.is_pending(path))
+        {
+            return true;
+        }
+        self.veto_delete_opt.as_ref().is_some_and(|veto| veto(path))
+    }
+}
+
+impl fmt::Debug for Hooks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut s = f.debug_struct("Hooks");
+        s.field("on_rotate", &self.on_rotate_opt.is_some())
+            .field("on_delete", &self.on_delete_opt.is_some())
+            .field("veto_delete", &self.veto_delete_opt.is_some())
+            .field("file_header", &self.file_header_opt.is_some())
+            .field("file_footer", &self.file_footer_opt.is_some());
+        #[cfg(feature = "upload")]
+        s.field("uploads", &self.uploads_opt);
+        s.finish()
+    }
+}
+
+/// Builds a [`RollingFileAppender`]. The folder, prefix and condition must be set, everything
+/// else has a default.
+///
+/// # Examples
+///
+/// ```rust
+/// # fn docs() {
+/// # use local_rolling_file::*;
+/// let file_appender = BasicRollingFileAppender::builder()
+///     .folder("./log")
+///     .prefix("log.log")
+///     .condition(RollingConditionBasic::new().daily())
+///     .max_files(9)
+///     .build()
+///     .unwrap();
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct RollingFileAppenderBuilder<RC>
+where
+    RC: RollingCondition,
+{
+    folder_opt: Option<String>,
+    prefix_opt: Option<String>,
+    condition_opt: Option<RC>,
+    retention: RetentionPolicy,
+    buffer_capacity_opt: Option<usize>,
+    latest_link: LatestLink,
+    link_name_opt: Option<String>,
+    suffix_format: String,
+    timezone: Timezone,
+    file_name_fn_opt: Option<FileNameFn>,
+    append_to_latest: bool,
+    compression: Compression,
+    keep_uncompressed: usize,
+    hooks: Hooks,
+    create_dir: bool,
+    line_aligned: bool,
+    split_large_writes: bool,
+    sync_policy: SyncPolicy,
+    naming_opt: Option<Arc<dyn NamingScheme>>,
+    archive: ArchivePolicy,
+    archive_retention_opt: Option<RetentionPolicy>,
+    encryptor_opt: Option<Arc<dyn Encryptor>>,
+    clock: Arc<dyn Clock>,
+    open_mode: OpenMode,
+    failover_opt: Option<(String, chrono::Duration)>,
+    min_free_space_opt: Option<(u64, LowSpacePolicy)>,
+    error_policy: ErrorPolicy,
+    background_maintenance: bool,
+    fs: Arc<dyn Fs>,
+}
+
+impl<RC> Default for RollingFileAppenderBuilder<RC>
+where
+    RC: RollingCondition,
+{
+    fn default() -> Self {
+        RollingFileAppenderBuilder {
+            folder_opt: None,
+            prefix_opt: None,
+            condition_opt: None,
+            retention: RetentionPolicy::MaxFiles(usize::MAX),
+            buffer_capacity_opt: None,
+            latest_link: LatestLink::Symlink,
+            link_name_opt: None,
+            suffix_format: FILE_NAME_DATETIME_FORMAT.to_string(),
+            timezone: Timezone::Local,
+            file_name_fn_opt: None,
+            append_to_latest: false,
+            compression: Compression::None,
+            keep_uncompressed: 0,
+            hooks: Hooks::default(),
+            create_dir: true,
+            line_aligned: false,
+            split_large_writes: false,
+            sync_policy: SyncPolicy::Never,
+            naming_opt: None,
+            archive: ArchivePolicy::Delete,
+            archive_retention_opt: None,
+            encryptor_opt: None,
+            clock: Arc::new(SystemClock),
+            open_mode: OpenMode::Append,
+            failover_opt: None,
+            min_free_space_opt: None,
+            error_policy: ErrorPolicy::default(),
+            background_maintenance: false,
+            fs: Arc::new(RealFs),
+        }
+    }
+}
+
+impl<RC> RollingFileAppenderBuilder<RC>
+where
+    RC: RollingCondition,
+{
+    /// Creates a builder with nothing set
+    pub fn new() -> RollingFileAppenderBuilder<RC> {
+        RollingFileAppenderBuilder::default()
+    }
+
+    /// Sets the folder of the log files
+    pub fn folder(mut self, folder: &str) -> RollingFileAppenderBuilder<RC> {
+        self.folder_opt = Some(folder.to_string());
+        self
+    }
+
+    /// Sets the prefix of the log file names, which is also the name of the symlink
+    pub fn prefix(mut self, prefix: &str) -> RollingFileAppenderBuilder<RC> {
+        self.prefix_opt = Some(prefix.to_string());
+        self
+    }
+
+    /// Sets the rolling condition
+    pub fn condition(mut self, condition: RC) -> RollingFileAppenderBuilder<RC> {
+        self.condition_opt = Some(condition);
+        self
+    }
+
+    /// Sets how many log files are kept. All files are kept by default.
+    pub fn max_files(mut self, max_files: usize) -> RollingFileAppenderBuilder<RC> {
+        self.retention = RetentionPolicy::MaxFiles(max_files);
+        self
+    }
+
+    /// Deletes rolled files not written to for longer than `age`, in addition to the other limits
+    pub fn max_age(mut self, age: chrono::Duration) -> RollingFileAppenderBuilder<RC> {
+        self.retention = self.retention.and(RetentionPolicy::MaxAge(age));
+        self
+    }
+
+    /// Sets which log files are kept, replacing [`RollingFileAppenderBuilder::max_files`]
+    pub fn retention(mut self, retention: RetentionPolicy) -> RollingFileAppenderBuilder<RC> {
+        self.retention = retention;
+        self
+    }
+
+    /// Sets what happens to the files retention no longer keeps, see
+    /// [`RollingFileAppender::with_archive`]
+    pub fn archive(mut self, archive: ArchivePolicy) -> RollingFileAppenderBuilder<RC> {
+        self.archive = archive;
+        self
+    }
+
+    /// Sets which files are kept in the archive folder, see
+    /// [`RollingFileAppender::with_archive_retention`]
+    pub fn archive_retention(mut self, retention: RetentionPolicy) -> RollingFileAppenderBuilder<RC> {
+        self.archive_retention_opt = Some(retention);
+        self
+    }
+
+    /// Passes each file right after it is rolled through `encryptor`, see
+    /// [`RollingFileAppender::with_encryption`]
+    pub fn encryption<E: Encryptor + 'static>(mut self, encryptor: E) -> RollingFileAppenderBuilder<RC> {
+        self.encryptor_opt = Some(Arc::new(encryptor));
+        self
+    }
+
+    /// Sets the capacity of the write buffer. With 0, every write goes to the file right away, e.g.
+    /// for another process tailing it.
+    pub fn buffer_capacity(mut self, capacity: usize) -> RollingFileAppenderBuilder<RC> {
+        self.buffer_capacity_opt = Some(capacity);
+        self
+    }
+
+    /// Enables or disables the symlink named after the prefix pointing at the latest file.
+    /// Enabled by default.
+    pub fn symlink(mut self, symlink: bool) -> RollingFileAppenderBuilder<RC> {
+        self.latest_link = if symlink {
+            LatestLink::Symlink
+        } else {
+            LatestLink::Disabled
+        };
+        self
+    }
+
+    /// Sets how the link to the latest file is maintained, e.g. with a hard link fallback
+    pub fn latest_link(mut self, latest_link: LatestLink) -> RollingFileAppenderBuilder<RC> {
+        self.latest_link = latest_link;
+        self
+    }
+
+    /// Names the link to the latest file `name` instead of the prefix
+    pub fn link_name(mut self, name: &str) -> RollingFileAppenderBuilder<RC> {
+        self.link_name_opt = Some(name.to_string());
+        self
+    }
+
+    /// Sets the `strftime` format of the date/time suffix of file names, `%Y%m%d.%H%M%S` by
+    /// default. Files must sort by name in the order they were created for retention to delete
+    /// the oldest ones. Time range [`crate::search`], tiering and archiving only recognize the
+    /// default format.
+    pub fn suffix_format(mut self, format: &str) -> RollingFileAppenderBuilder<RC> {
+        self.suffix_format = format.to_string();
+        self
+    }
+
+    /// Names files with `f`, given the time the file is opened, instead of the prefix followed by
+    /// the suffix format. This allows names such as `app-2024-05-20.log` or names including the
+    /// hostname. Names should start with the prefix, so retention finds them, and sort by name in
+    /// the order they were created; [`RetentionPolicy::MaxAge`] can't tell their age and keeps
+    /// them.
+    pub fn file_name<F>(mut self, f: F) -> RollingFileAppenderBuilder<RC>
+    where
+        F: Fn(&DateTime<Local>) -> String + Send + Sync + 'static,
+    {
+        self.file_name_fn_opt = Some(FileNameFn(Arc::new(f)));
+        self
+    }
+
+    /// Names files after the wall clock of `timezone`, the local time zone by default. Set the
+    /// same zone on the rolling condition, e.g. with [`crate::RollingConditionBasic::timezone`],
+    /// so files start when their names say.
+    pub fn timezone(mut self, timezone: Timezone) -> RollingFileAppenderBuilder<RC> {
+        self.timezone = timezone;
+        self
+    }
+
+    /// Appends to the newest existing log file on startup if it was started in the current rollover
+    /// period, instead of opening a new file for every restart. Files named with
+    /// [`RollingFileAppenderBuilder::file_name`] are not recognized.
+    pub fn append_to_latest_on_startup(mut self, append: bool) -> RollingFileAppenderBuilder<RC> {
+        self.append_to_latest = append;
+        self
+    }
+
+    /// Compresses each file once it is rolled, see [`Compression`]
+    pub fn compression(mut self, compression: Compression) -> RollingFileAppenderBuilder<RC> {
+        self.compression = compression;
+        self
+    }
+
+    /// Leaves the `count` most recent rolled files uncompressed, for easy `tail` and `grep`.
+    /// Older files are compressed as newer ones are rolled.
+    pub fn keep_uncompressed(mut self, count: usize) -> RollingFileAppenderBuilder<RC> {
+        self.keep_uncompressed = count;
+        self
+    }
+
+    /// Calls `f` with the path of the completed file and the path of the new one after each
+    /// rollover, e.g. to ship the completed file elsewhere. It runs on the maintenance scheduler
+    /// if there is one, before the completed file is compressed or deleted by retention.
+    pub fn on_rotate<F>(mut self, f: F) -> RollingFileAppenderBuilder<RC>
+    where
+        F: Fn(&Path, &Path) + Send + Sync + 'static,
+    {
+        self.hooks.on_rotate_opt = Some(Arc::new(f));
+        self
+    }
+
+    /// Calls `f` with the path of each file deleted by retention
+    pub fn on_delete<F>(mut self, f: F) -> RollingFileAppenderBuilder<RC>
+    where
+        F: Fn(&Path) + Send + Sync + 'static,
+    {
+        self.hooks.on_delete_opt = Some(Arc::new(f));
+        self
+    }
+
+    /// Calls `f` with the path of each file retention is about to delete; the file is kept if it
+    /// returns true. [`RollingFileAppender::plan_retention`] reports vetoed deletions.
+    pub fn veto_delete<F>(mut self, f: F) -> RollingFileAppenderBuilder<RC>
+    where
+        F: Fn(&Path) -> bool + Send + Sync + 'static,
+    {
+        self.hooks.veto_delete_opt = Some(Arc::new(f));
+        self
+    }
+
+    /// Starts every new file with the bytes returned by `f`, e.g. a JSON line with the hostname,
+    /// pid and version. They are written as they are, before the first write to the file.
+    pub fn file_header<F>(mut self, f: F) -> RollingFileAppenderBuilder<RC>
+    where
+        F: Fn() -> Vec<u8> + Send + Sync + 'static,
+    {
+        self.hooks.file_header_opt = Some(Arc::new(f));
+        self
+    }
+
+    /// Ends every file written to with the bytes returned by `f` when it is rolled over. Files
+    /// still active when the appender is dropped get no footer.
+    pub fn file_footer<F>(mut self, f: F) -> RollingFileAppenderBuilder<RC>
+    where
+        F: Fn() -> Vec<u8> + Send + Sync + 'static,
+    {
+        self.hooks.file_footer_opt = Some(Arc::new(f));
+        self
+    }
+
+    /// Uploads every rolled file with the queue of `uploads`, see
+    /// [`RollingFileAppender::with_uploads`]
+    #[cfg(feature = "upload")]
+    pub fn uploads(mut self, uploads: crate::upload::UploadHandle) -> RollingFileAppenderBuilder<RC> {
+        self.hooks.uploads_opt = Some(uploads);
+        self
+    }
+
+    /// Creates the log folder and its missing parents when opening a file. Enabled by default;
+    /// when disabled, a missing folder is a [`RollingFileError::OpenFailed`] error.
+    pub fn create_dir_all(mut self, create: bool) -> RollingFileAppenderBuilder<RC> {
+        self.create_dir = create;
+        self
+    }
+
+    /// Only rolls over between lines, so a record ending with a line break is never split across
+    /// two files even if it is written in several chunks, see
+    /// [`RollingFileAppender::with_line_aligned_rollover`] and [`RollingFileAppender::write_record`]
+    pub fn line_aligned_rollover(mut self, line_aligned: bool) -> RollingFileAppenderBuilder<RC> {
+        self.line_aligned = line_aligned;
+        self
+    }
+
+    /// Splits writes across files so none exceeds the maximum size of the condition, see
+    /// [`RollingFileAppender::with_split_large_writes`]
+    pub fn split_large_writes(mut self, split: bool) -> RollingFileAppenderBuilder<RC> {
+        self.split_large_writes = split;
+        self
+    }
+
+    /// Sets how files are named and recognized, replacing the suffix format, time zone and file
+    /// name function; set the time zone on the scheme instead, e.g. with
+    /// [`crate::DailyFolderNaming::timezone`]. No link to the latest file is made while the active file has the name of
+    /// the link, as with [`crate::SequentialNaming`].
+    pub fn naming_scheme<N>(mut self, scheme: N) -> RollingFileAppenderBuilder<RC>
+    where
+        N: NamingScheme + 'static,
+    {
+        self.naming_opt = Some(Arc::new(scheme));
+        self
+    }
+
+    /// Runs the work following a rollover on a worker thread of the appender, see
+    /// [`RollingFileAppender::with_background_maintenance`]
+    pub fn background_maintenance(mut self, background: bool) -> RollingFileAppenderBuilder<RC> {
+        self.background_maintenance = background;
+        self
+    }
+
+    /// Sets when the active file is persisted to disk, see [`SyncPolicy`]
+    pub fn sync_policy(mut self, policy: SyncPolicy) -> RollingFileAppenderBuilder<RC> {
+        self.sync_policy = policy;
+        self
+    }
+
+    /// Sets how the file of a new period is opened, see [`OpenMode`]
+    pub fn open_mode(mut self, mode: OpenMode) -> RollingFileAppenderBuilder<RC> {
+        self.open_mode = mode;
+        self
+    }
+
+    /// Continues in `folder` while the disk of the log folder is full or failing, see
+    /// [`RollingFileAppender::with_failover_folder`]
+    pub fn failover_folder(mut self, folder: &str, retry_interval: chrono::Duration) -> RollingFileAppenderBuilder<RC> {
+        self.failover_opt = Some((folder.to_string(), retry_interval));
+        self
+    }
+
+    /// Keeps at least `bytes` free on the disk of the log folder, see
+    /// [`RollingFileAppender::with_min_free_space`]
+    pub fn min_free_space(mut self, bytes: u64, policy: LowSpacePolicy) -> RollingFileAppenderBuilder<RC> {
+        self.min_free_space_opt = Some((bytes, policy));
+        self
+    }
+
+    /// Retries failed writes and rollovers, see [`RollingFileAppender::with_error_policy`]
+    pub fn error_policy(mut self, policy: ErrorPolicy) -> RollingFileAppenderBuilder<RC> {
+        self.error_policy = policy;
+        self
+    }
+
+    /// Takes the time of writes and retention checks from `clock`, see
+    /// [`RollingFileAppender::with_clock`]
+    pub fn clock<C: Clock + 'static>(mut self, clock: C) -> RollingFileAppenderBuilder<RC> {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// Performs all file system operations through `fs`
+    pub fn fs(mut self, fs: Arc<dyn Fs>) -> RollingFileAppenderBuilder<RC> {
+        self.fs = fs;
+        self
+    }
+
+    /// Creates the appender and opens its first file
+    pub fn build(self) -> Result<RollingFileAppender<RC>, RollingFileError> {
+        let missing = |what: &str| RollingFileError::InvalidConfig(format!("{} is not set", what));
+        let folder = self.folder_opt.ok_or_else(|| missing("folder"))?;
+        let prefix = self.prefix_opt.ok_or_else(|| missing("prefix"))?;
+        let condition = self.condition_opt.ok_or_else(|| missing("condition"))?;
+        let invalid_format = StrftimeItems::new(&self.suffix_format).any(|item| item == Item::Error);
+        if invalid_format || self.suffix_format.contains(['/', '\\']) {
+            return Err(RollingFileError::InvalidConfig(format!(
+                "invalid suffix format {}",
+                self.suffix_format
+            )));
+        }
+        let mut rfa = RollingFileAppender::unopened(
+            &folder,
+            &prefix,
+            condition,
+            self.retention,
+            self.buffer_capacity_opt,
+            self.fs,
+        );
+        let has_file_name_fn = self.file_name_fn_opt.is_some();
+        rfa.naming = self.naming_opt.unwrap_or_else(|| {
+            let mut naming = TimestampNaming::new(&prefix)
+                .suffix_format(&self.suffix_format)
+                .timezone(self.timezone);
+            naming.file_name_fn_opt = self.file_name_fn_opt;
+            Arc::new(naming)
+        });
+        rfa.link.kind = self.latest_link;
+        rfa.link.name_opt = self.link_name_opt;
+        rfa.rolled.compression = self.compression;
+        rfa.rolled.keep_uncompressed = self.keep_uncompressed;
+        rfa.hooks = self.hooks;
+        #[cfg(feature = "upload")]
+        if let Some(uploads) = rfa.hooks.uploads_opt.as_ref() {
+            uploads.resume(&folder);
+        }
+        rfa.retention.archive = self.archive;
+        rfa.retention.archive_retention_opt = self.archive_retention_opt;
+        rfa.rolled.encryptor_opt = self.encryptor_opt;
+        rfa.create_dir = self.create_dir;
+        rfa.line_aligned = self.line_aligned;
+        rfa.split_large_writes = self.split_large_writes;
+        rfa.sync = crate::durability::SyncState::new(self.sync_policy);
+        rfa.clock = self.clock;
+        rfa.open_mode = self.open_mode;
+        rfa.error_policy = self.error_policy;
+        if let Some((folder, retry_interval)) = self.failover_opt.as_ref() {
+            rfa = rfa.with_failover_folder(folder, *retry_interval);
+        }
+        if let Some((bytes, policy)) = self.min_free_space_opt {
+            rfa = rfa.with_min_free_space(bytes, policy);
+        }
+        if self.background_maintenance {
+            rfa = rfa.with_background_maintenance()?;
+        }
+        let now = rfa.clock.now();
+        if self.append_to_latest && !has_file_name_fn {
+            rfa.resume_name_opt = rfa.resumable_file_name(&now);
+        }
+        rfa.open_writer_if_needed(&now)?;
+        Ok(rfa)
+    }
+}
+
+impl<RC> RollingFileAppender<RC>
+where
+    RC: RollingCondition,
+{
+    /// Returns a builder for an appender
+    pub fn builder() -> RollingFileAppenderBuilder<RC> {
+        RollingFileAppenderBuilder::new()
+    }
+}
+
+#[cfg(test)]
+mod t {
+    use crate::*;
+
+    #[test]
+    fn test_file_header_and_footer() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let mut rfa = BasicRollingFileAppender::builder()
+            .folder(folder)
+            .prefix("log.log")
+            .condition(RollingConditionBasic::new().daily())
+            .file_header(|| b"{\"pid\":42}\n".to_vec())
+            .file_footer(|| b"-- end --\n".to_vec())
+            .build()
+            .unwrap();
+        rfa.rollover().unwrap();
+        for day in 1..=2 {
+            let now = Local.with_ymd_and_hms(2099, 3, day, 1, 0, 0).unwrap();
+            rfa.write_with_datetime(format!("day {}\n", day).as_bytes(), &now)
+                .unwrap();
+        }
+        rfa.flush().unwrap();
+
+        let read = |name: &str| std::fs::read_to_string(dir.path().join(name)).unwrap();
+        assert_eq!(read("log.log.20990301.010000"), "{\"pid\":42}\nday 1\n-- end --\n");
+        assert_eq!(read("log.log.20990302.010000"), "{\"pid\":42}\nday 2\n");
+    }
+
+    #[test]
+    fn test_builder() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        assert!(BasicRollingFileAppender::builder().folder(folder).build().is_err());
+        let mut rfa = BasicRollingFileAppender::builder()
+            .folder(folder)
+            .prefix("log.log")
+            .condition(RollingConditionBasic::new().daily())
+            .max_files(2)
+            .buffer_capacity(16)
+            .symlink(false)
+            .suffix_format("%Y-%m-%d")
+            .build()
+            .unwrap();
+        rfa.rollover().unwrap();
+        for day in 1..=3 {
+            let now = Local.with_ymd_and_hms(2099, 3, day, 1, 0, 0).unwrap();
+            rfa.write_with_datetime(b"line\n", &now).unwrap();
+        }
+        rfa.flush().unwrap();
+
+        let mut names = std::fs::read_dir(folder)
+            .unwrap()
+            .map(|f| f.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names, ["log.log.2099-03-02", "log.log.2099-03-03"]);
+
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let mut rfa = BasicRollingFileAppender::builder()
+            .folder(folder)
+            .prefix("app")
+            .condition(RollingConditionBasic::new().daily())
+            .file_name(|now| format!("app-{}.log", now.format("%Y-%m-%d")))
+            .build()
+            .unwrap();
+        rfa.rollover().unwrap();
+        let now = Local.with_ymd_and_hms(2099, 3, 30, 1, 0, 0).unwrap();
+        rfa.write_with_datetime(b"line\n", &now).unwrap();
+        rfa.flush().unwrap();
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("app-2099-03-30.log")).unwrap(),
+            "line\n"
+        );
+        assert_eq!(std::fs::read_to_string(dir.path().join("app")).unwrap(), "line\n");
+    }
+
+    #[test]
+    fn test_create_dir_all() {
+        let dir = tempfile::tempdir().unwrap();
+        let nested = dir.path().join("a/b/c");
+        let builder = || {
+            BasicRollingFileAppender::builder()
+                .folder(nested.to_str().unwrap())
+                .prefix("log.log")
+                .condition(RollingConditionBasic::new().daily())
+        };
+        let e = builder().create_dir_all(false).build().unwrap_err();
+        assert!(matches!(e, RollingFileError::OpenFailed { .. }));
+        assert!(!dir.path().join("a").exists());
+
+        let mut rfa = builder().build().unwrap();
+        rfa.write_all(b"line\n").unwrap();
+        rfa.flush().unwrap();
+        assert_eq!(std::fs::read_to_string(nested.join("log.log")).unwrap(), "line\n");
+    }
+
+    #[test]
+    fn test_rotate_and_delete_hooks() {
+        use std::sync::{Arc, Mutex};
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let events = Arc::new(Mutex::new(vec![]));
+        let (rotated, deleted) = (events.clone(), events.clone());
+        let mut rfa = BasicRollingFileAppender::builder()
+            .folder(folder)
+            .prefix("log.log")
+            .condition(RollingConditionBasic::new().daily())
+            .max_files(2)
+            .on_rotate(move |old, new| {
+                let name = |p: &std::path::Path| p.file_name().unwrap().to_string_lossy().to_string();
+                rotated
+                    .lock()
+                    .unwrap()
+                    .push(format!("rotate {} {}", name(old), name(new)));
+            })
+            .on_delete(move |path| {
+                let name = path.file_name().unwrap().to_string_lossy();
+                deleted.lock().unwrap().push(format!("delete {}", name));
+            })
+            .build()
+            .unwrap();
+        rfa.rollover().unwrap();
+        let first = Local.with_ymd_and_hms(2099, 3, 1, 1, 0, 0).unwrap();
+        rfa.write_with_datetime(b"line\n", &first).unwrap();
+        events.lock().unwrap().clear();
+        for day in 2..=3 {
+            let now = Local.with_ymd_and_hms(2099, 3, day, 1, 0, 0).unwrap();
+            rfa.write_with_datetime(b"line\n", &now).unwrap();
+        }
+        // The file opened at construction is deleted as well
+        let events = events.lock().unwrap();
+        assert_eq!(
+            events.iter().filter(|e| e.contains("2099")).collect::<Vec<_>>(),
+            [
+                "rotate log.log.20990301.010000 log.log.20990302.010000",
+                "rotate log.log.20990302.010000 log.log.20990303.010000",
+                "delete log.log.20990301.010000",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_append_to_latest_on_startup() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let today = Local::now()
+            .date_naive()
+            .and_time(NaiveTime::MIN)
+            .and_local_timezone(Local)
+            .earliest()
+            .unwrap();
+        let current = format!("log.log.{}", today.format(FILE_NAME_DATETIME_FORMAT));
+        std::fs::write(dir.path().join("log.log.20000101.000000"), "old\n").unwrap();
+        std::fs::write(dir.path().join(&current), "before restart\n").unwrap();
+
+        let mut rfa = BasicRollingFileAppender::builder()
+            .folder(folder)
+            .prefix("log.log")
+            .condition(RollingConditionBasic::new().daily())
+            .append_to_latest_on_startup(true)
+            .build()
+            .unwrap();
+        rfa.write_all(b"after restart\n").unwrap();
+        rfa.flush().unwrap();
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join(&current)).unwrap(),
+            "before restart\nafter restart\n"
+        );
+        assert_eq!(std::fs::read_dir(folder).unwrap().count(), 3);
+    }
+}
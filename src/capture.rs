@@ -0,0 +1,140 @@
+//! Capturing the output of child processes into rolling files.
+//!
+//! [`SharedRollingFileAppender::capture_command`] spawns a command with piped stdout and stderr
+//! and copies both into the appender on background threads. Output is copied line by line, so
+//! lines of the two streams never get mixed up. Lines longer than [`MAX_LINE_LEN`] are split, and
+//! a last line without a newline gets one, so it never runs into the next writer's output.
+
+use crate::{RollingCondition, SharedRollingFileAppender};
+use std::{
+    io::{self, BufRead, BufReader, Read, Write},
+    process::{Child, Command, ExitStatus, Stdio},
+    thread::JoinHandle,
+};
+
+/// Longest line copied at once; longer lines are split into lines of at most this many bytes, so
+/// a writer never sending a newline can't make the capture buffer without limit
+pub const MAX_LINE_LEN: usize = 64 * 1024;
+
+/// A child process whose output is copied into an appender. Call [`CapturedChild::wait`] to wait
+/// for the process and the end of its output.
+#[derive(Debug)]
+pub struct CapturedChild {
+    child: Child,
+    pumps: Vec<JoinHandle<io::Result<u64>>>,
+}
+
+impl CapturedChild {
+    /// Returns the process id of the child
+    pub fn id(&self) -> u32 {
+        self.child.id()
+    }
+
+    /// Returns the child process, e.g. to kill it
+    pub fn child_mut(&mut self) -> &mut Child {
+        &mut self.child
+    }
+
+    /// Waits for the child to exit and for all of its output to be copied
+    pub fn wait(mut self) -> io::Result<ExitStatus> {
+        let status = self.child.wait()?;
+        for pump in self.pumps.drain(..) {
+            pump.join().map_err(|_| io::Error::other("capture thread panicked"))??;
+        }
+        Ok(status)
+    }
+}
+
+impl<RC> SharedRollingFileAppender<RC>
+where
+    RC: RollingCondition + Send + 'static,
+{
+    /// Spawns `command` with its stdout and stderr copied into the appender.
+    pub fn capture_command(&self, command: &mut Command) -> io::Result<CapturedChild> {
+        let mut child = command.stdout(Stdio::piped()).stderr(Stdio::piped()).spawn()?;
+        let mut pumps = vec![];
+        if let Some(stdout) = child.stdout.take() {
+            pumps.push(self.capture_reader(stdout)?);
+        }
+        if let Some(stderr) = child.stderr.take() {
+            pumps.push(self.capture_reader(stderr)?);
+        }
+        Ok(CapturedChild { child, pumps })
+    }
+
+    /// Copies `reader`, e.g. a pipe of a child process, into the appender line by line on a
+    /// background thread until it reaches its end. The thread returns the number of bytes copied.
+    pub fn capture_reader<R>(&self, reader: R) -> io::Result<JoinHandle<io::Result<u64>>>
+    where
+        R: Read + Send + 'static,
+    {
+        let shared = self.clone();
+        let options = self.lock().thread_options().clone();
+        options.spawn("rolling-file-capture", move || shared.copy_lines(reader))
+    }
+
+    /// Copies `reader` into the appender line by line until it reaches its end, returning the
+    /// number of bytes read. Lines are split after [`MAX_LINE_LEN`] bytes and the last line is
+    /// terminated if it isn't.
+    pub(crate) fn copy_lines<R: Read>(&self, reader: R) -> io::Result<u64> {
+        let mut reader = BufReader::new(reader);
+        let mut line = Vec::with_capacity(128);
+        let mut copied = 0;
+        loop {
+            line.clear();
+            let n = (&mut reader).take(MAX_LINE_LEN as u64).read_until(b'\n', &mut line)?;
+            if n == 0 {
+                break;
+            }
+            if line.last() != Some(&b'\n') {
+                line.push(b'\n');
+            }
+            self.lock().write_all(&line)?;
+            copied += n as u64;
+        }
+        self.lock().flush()?;
+        Ok(copied)
+    }
+}
+
+#[cfg(test)]
+mod t {
+    use crate::*;
+    use std::process::Command;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_capture_command() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let rfa = BasicRollingFileAppender::new(folder, "log.log", RollingConditionBasic::new().daily(), 3).unwrap();
+        let (shared, _guard) = SharedRollingFileAppender::new(rfa);
+        let child = shared
+            .capture_command(Command::new("sh").args(["-c", "echo out; echo err >&2"]))
+            .unwrap();
+        assert!(child.wait().unwrap().success());
+
+        let contents = std::fs::read_to_string(dir.path().join("log.log")).unwrap();
+        let mut lines = contents.lines().collect::<Vec<_>>();
+        lines.sort();
+        assert_eq!(lines, ["err", "out"]);
+    }
+
+    #[test]
+    fn test_capture_splits_and_terminates_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let rfa = BasicRollingFileAppender::new(folder, "log.log", RollingConditionBasic::new().daily(), 3).unwrap();
+        let (shared, _guard) = SharedRollingFileAppender::new(rfa);
+        let mut input = vec![b'x'; capture::MAX_LINE_LEN + 10];
+        input.extend_from_slice(b"\npartial");
+        let copied = shared.capture_reader(std::io::Cursor::new(input)).unwrap().join().unwrap();
+        assert_eq!(copied.unwrap(), capture::MAX_LINE_LEN as u64 + 18);
+        shared.capture_reader(&b"next\n"[..]).unwrap().join().unwrap().unwrap();
+
+        let contents = std::fs::read_to_string(dir.path().join("log.log")).unwrap();
+        let lines = contents.lines().map(str::len).collect::<Vec<_>>();
+        assert_eq!(lines, [capture::MAX_LINE_LEN, 10, 7, 4]);
+        assert!(contents.ends_with("partial\nnext\n"));
+    }
+}
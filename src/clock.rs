@@ -0,0 +1,109 @@
+//! The source of the current time.
+//!
+//! The appender asks its [`Clock`] for the time of every write made through [`std::io::Write`],
+//! and of the retention and tiering checks. [`SystemClock`] reads the system time and is used
+//! unless another clock is given, e.g. a [`MockClock`] to test a logging stack without waiting
+//! for midnight:
+//!
+//! ```rust
+//! use chrono::prelude::*;
+//! use local_rolling_file::*;
+//! use std::io::Write;
+//!
+//! # fn docs() {
+//! let clock = MockClock::new(Local.with_ymd_and_hms(2024, 5, 20, 23, 59, 0).unwrap());
+//! let mut file_appender = BasicRollingFileAppender::builder()
+//!     .folder("./log")
+//!     .prefix("log.log")
+//!     .condition(RollingConditionBasic::new().daily())
+//!     .clock(clock.clone())
+//!     .build()
+//!     .unwrap();
+//! writeln!(file_appender, "before midnight").unwrap();
+//! clock.advance(chrono::Duration::minutes(1));
+//! writeln!(file_appender, "in the file of May 21").unwrap();
+//! # }
+//! ```
+
+use chrono::prelude::*;
+use std::{
+    fmt::Debug,
+    sync::{Arc, Mutex},
+};
+
+/// Tells the current time
+pub trait Clock: Send + Sync + Debug {
+    /// Returns the current time
+    fn now(&self) -> DateTime<Local>;
+}
+
+/// The system time
+#[derive(Copy, Clone, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Local> {
+        Local::now()
+    }
+}
+
+/// A clock which only moves when told to. Clones share the same time.
+#[derive(Clone, Debug)]
+pub struct MockClock {
+    now: Arc<Mutex<DateTime<Local>>>,
+}
+
+impl MockClock {
+    /// Creates a clock stopped at `now`
+    pub fn new(now: DateTime<Local>) -> MockClock {
+        MockClock {
+            now: Arc::new(Mutex::new(now)),
+        }
+    }
+
+    /// Sets the time
+    pub fn set(&self, now: DateTime<Local>) {
+        *self.now.lock().unwrap_or_else(|e| e.into_inner()) = now;
+    }
+
+    /// Moves the time forward by `duration`
+    pub fn advance(&self, duration: chrono::Duration) {
+        *self.now.lock().unwrap_or_else(|e| e.into_inner()) += duration;
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> DateTime<Local> {
+        *self.now.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod t {
+    use crate::*;
+    use std::io::Write;
+
+    #[test]
+    fn test_mock_clock() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let clock = MockClock::new(Local.with_ymd_and_hms(2099, 3, 1, 23, 58, 0).unwrap());
+        let mut rfa = BasicRollingFileAppender::builder()
+            .folder(folder)
+            .prefix("log.log")
+            .condition(RollingConditionBasic::new().daily())
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+        rfa.rollover().unwrap();
+        clock.advance(chrono::Duration::minutes(1));
+        writeln!(rfa, "day 1").unwrap();
+        clock.advance(chrono::Duration::minutes(1));
+        writeln!(rfa, "day 2").unwrap();
+        rfa.flush().unwrap();
+
+        let read = |name: &str| std::fs::read_to_string(dir.path().join(name)).unwrap();
+        assert_eq!(read("log.log.20990301.235900"), "day 1\n");
+        assert_eq!(read("log.log.20990302.000000"), "day 2\n");
+    }
+}
@@ -0,0 +1,204 @@
+//! Composing rolling conditions.
+//!
+//! Conditions combine with [`RollingConditionExt::and`] and [`RollingConditionExt::or`], e.g. to
+//! roll hourly or at 100 MB, but only at night:
+//!
+//! ```rust
+//! use chrono::NaiveTime;
+//! use local_rolling_file::*;
+//! let night = TimeWindow::new(NaiveTime::MIN, NaiveTime::from_hms_opt(6, 0, 0).unwrap());
+//! let c = RollingConditionBasic::new()
+//!     .hourly()
+//!     .or(RollingConditionBasic::new().max_size(100 * 1024 * 1024))
+//!     .and(night);
+//! ```
+
+use crate::RollingCondition;
+use chrono::{DateTime, Local, NaiveTime};
+
+/// Combinators available on every [`RollingCondition`]
+pub trait RollingConditionExt: RollingCondition + Sized {
+    /// Rolls over when both conditions say so
+    fn and<O>(self, other: O) -> And<Self, O>
+    where
+        O: RollingCondition,
+    {
+        And(self, other)
+    }
+
+    /// Rolls over when either condition says so
+    fn or<O>(self, other: O) -> Or<Self, O>
+    where
+        O: RollingCondition,
+    {
+        Or(self, other)
+    }
+}
+
+impl<RC> RollingConditionExt for RC where RC: RollingCondition {}
+
+/// Two conditions which must both be met, see [`RollingConditionExt::and`]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct And<A, B>(pub A, pub B);
+
+impl<A, B> RollingCondition for And<A, B>
+where
+    A: RollingCondition,
+    B: RollingCondition,
+{
+    fn should_rollover(&mut self, now: &DateTime<Local>, current_filesize: u64) -> bool {
+        // Both are always asked, so each keeps track of the writes
+        let a = self.0.should_rollover(now, current_filesize);
+        let b = self.1.should_rollover(now, current_filesize);
+        a && b
+    }
+
+    fn should_rollover_with_lines(&mut self, now: &DateTime<Local>, current_filesize: u64, current_lines: u64) -> bool {
+        let a = self.0.should_rollover_with_lines(now, current_filesize, current_lines);
+        let b = self.1.should_rollover_with_lines(now, current_filesize, current_lines);
+        a && b
+    }
+
+    fn file_opened(&mut self, opened: &DateTime<Local>) {
+        self.0.file_opened(opened);
+        self.1.file_opened(opened);
+    }
+
+    fn written(&mut self, now: &DateTime<Local>) {
+        self.0.written(now);
+        self.1.written(now);
+    }
+
+    fn expected_file_size(&self, bytes_per_second: f64) -> Option<f64> {
+        let a = self.0.expected_file_size(bytes_per_second)?;
+        let b = self.1.expected_file_size(bytes_per_second)?;
+        Some(a.max(b))
+    }
+
+    fn max_file_size(&self) -> Option<u64> {
+        Some(self.0.max_file_size()?.max(self.1.max_file_size()?))
+    }
+
+    fn describe(&self) -> String {
+        format!("({}) and ({})", self.0.describe(), self.1.describe())
+    }
+}
+
+/// Two conditions of which one must be met, see [`RollingConditionExt::or`]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Or<A, B>(pub A, pub B);
+
+impl<A, B> RollingCondition for Or<A, B>
+where
+    A: RollingCondition,
+    B: RollingCondition,
+{
+    fn should_rollover(&mut self, now: &DateTime<Local>, current_filesize: u64) -> bool {
+        let a = self.0.should_rollover(now, current_filesize);
+        let b = self.1.should_rollover(now, current_filesize);
+        a || b
+    }
+
+    fn should_rollover_with_lines(&mut self, now: &DateTime<Local>, current_filesize: u64, current_lines: u64) -> bool {
+        let a = self.0.should_rollover_with_lines(now, current_filesize, current_lines);
+        let b = self.1.should_rollover_with_lines(now, current_filesize, current_lines);
+        a || b
+    }
+
+    fn file_opened(&mut self, opened: &DateTime<Local>) {
+        self.0.file_opened(opened);
+        self.1.file_opened(opened);
+    }
+
+    fn written(&mut self, now: &DateTime<Local>) {
+        self.0.written(now);
+        self.1.written(now);
+    }
+
+    fn next_rollover(&self, now: &DateTime<Local>) -> Option<DateTime<Local>> {
+        match (self.0.next_rollover(now), self.1.next_rollover(now)) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
+    }
+
+    fn expected_file_size(&self, bytes_per_second: f64) -> Option<f64> {
+        match (
+            self.0.expected_file_size(bytes_per_second),
+            self.1.expected_file_size(bytes_per_second),
+        ) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
+    }
+
+    fn max_file_size(&self) -> Option<u64> {
+        match (self.0.max_file_size(), self.1.max_file_size()) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
+    }
+
+    fn describe(&self) -> String {
+        format!("({}) or ({})", self.0.describe(), self.1.describe())
+    }
+}
+
+/// A condition met while the local time of day is in `[start, end)`, meant to be combined with
+/// [`RollingConditionExt::and`]. The window wraps around midnight if `end` is before `start`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct TimeWindow {
+    start: NaiveTime,
+    end: NaiveTime,
+}
+
+impl TimeWindow {
+    /// Creates a window from `start` until `end`
+    pub fn new(start: NaiveTime, end: NaiveTime) -> TimeWindow {
+        TimeWindow { start, end }
+    }
+
+    fn contains(&self, time: NaiveTime) -> bool {
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            self.start <= time || time < self.end
+        }
+    }
+}
+
+impl RollingCondition for TimeWindow {
+    fn should_rollover(&mut self, now: &DateTime<Local>, _current_filesize: u64) -> bool {
+        self.contains(now.time())
+    }
+
+    fn describe(&self) -> String {
+        format!("between={}-{}", self.start.format("%H:%M"), self.end.format("%H:%M"))
+    }
+}
+
+#[cfg(test)]
+mod t {
+    use crate::*;
+
+    #[test]
+    fn test_combined_conditions() {
+        let night = TimeWindow::new(NaiveTime::MIN, NaiveTime::from_hms_opt(6, 0, 0).unwrap());
+        let mut condition = RollingConditionBasic::new()
+            .hourly()
+            .or(RollingConditionBasic::new().max_size(100))
+            .and(night);
+        let at = |hour, minute| Local.with_ymd_and_hms(2099, 3, 30, hour, minute, 0).unwrap();
+        condition.should_rollover(&at(1, 0), 0);
+        assert!(!condition.should_rollover(&at(1, 30), 10));
+        assert!(condition.should_rollover(&at(1, 40), 100));
+        assert!(condition.should_rollover(&at(2, 0), 10));
+        // Outside of the window, neither the hour nor the size triggers a rollover
+        assert!(!condition.should_rollover(&at(7, 0), 10));
+        assert!(!condition.should_rollover(&at(7, 10), 100));
+        assert_eq!(
+            condition.describe(),
+            "((rollover=hourly) or (max_size=100)) and (between=00:00-06:00)"
+        );
+    }
+}
@@ -0,0 +1,506 @@
+//! Compression of rolled files.
+//!
+//! Once a file is rolled, it can be replaced by `<file>.gz` or `<file>.zst`, see [`Compression`].
+//! The compressed files keep their place in retention, which sorts them by name as before. Their
+//! time index is deleted along with the original, its offsets don't apply to the compressed data.
+//!
+//! Small log files compress poorly on their own since each one restarts with an empty history.
+//! Files of the same application are very similar though, so a dictionary trained on a handful of
+//! rolled files and reused for all of them brings the ratio close to that of one large file, see
+//! `DictionaryCompression` of the `zstd` feature. The dictionary is kept next to the logs as
+//! `<prefix>.dict` and is needed to read the files back, see `decompress_file`.
+
+#[cfg(feature = "zstd")]
+use crate::NamingScheme;
+use crate::{
+    filesystem::{self, FsReader},
+    index,
+    pool::Pool,
+    Fs,
+    RealFs,
+};
+use std::{
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+/// File name extension of gzip compressed log files
+pub const GZIP_EXTENSION: &str = "gz";
+
+/// File name extension of zstd compressed log files
+pub const ZSTD_EXTENSION: &str = "zst";
+
+/// File name extension of the dictionary kept next to the log files
+#[cfg(feature = "zstd")]
+pub const DICTIONARY_EXTENSION: &str = "dict";
+
+/// Number of bytes read from the start of each file to train the dictionary
+#[cfg(feature = "zstd")]
+const SAMPLE_LEN: u64 = 64 * 1024;
+
+/// Number of the latest rolled files sampled to train the dictionary
+#[cfg(feature = "zstd")]
+const MAX_SAMPLE_FILES: usize = 64;
+
+/// Number of bytes read at once from a file being compressed
+const READ_CHUNK_LEN: usize = 1024 * 1024;
+
+/// How rolled files are compressed
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum Compression {
+    /// Rolled files are left as they are
+    #[default]
+    None,
+    /// gzip at the given level, from 0 (none) to 9 (best)
+    #[cfg(feature = "gzip")]
+    Gzip(u32),
+    /// zstd at the given level, from 1 to 22; 0 selects zstd's default
+    #[cfg(feature = "zstd")]
+    Zstd(i32),
+}
+
+impl Compression {
+    /// Returns the file name extension of compressed files, `None` if files are not compressed
+    pub fn extension(&self) -> Option<&'static str> {
+        match self {
+            Compression::None => None,
+            #[cfg(feature = "gzip")]
+            Compression::Gzip(_) => Some(GZIP_EXTENSION),
+            #[cfg(feature = "zstd")]
+            Compression::Zstd(_) => Some(ZSTD_EXTENSION),
+        }
+    }
+
+    /// Compresses `src` next to it, then removes it. Returns the path of the compressed file.
+    pub fn compress_file(&self, src: &Path) -> io::Result<PathBuf> {
+        self.compress_file_in(&RealFs, src)
+    }
+
+    /// Same as [`Compression::compress_file`], through `fs`
+    pub(crate) fn compress_file_in(&self, fs: &dyn Fs, src: &Path) -> io::Result<PathBuf> {
+        let Some(extension) = self.extension() else {
+            return Ok(src.to_path_buf());
+        };
+        replace_with_compressed(fs, src, extension, |reader, writer| match self {
+            // Not reached, uncompressed files don't get an extension
+            Compression::None => io::copy(reader, writer).map(|_| ()),
+            #[cfg(feature = "gzip")]
+            Compression::Gzip(level) => {
+                let mut encoder = flate2::write::GzEncoder::new(writer, flate2::Compression::new(*level));
+                io::copy(reader, &mut encoder)?;
+                encoder.finish().map(|_| ())
+            },
+            #[cfg(feature = "zstd")]
+            Compression::Zstd(level) => {
+                let mut encoder = zstd::stream::Encoder::new(writer, *level)?;
+                io::copy(reader, &mut encoder)?;
+                encoder.finish().map(|_| ())
+            },
+        })
+    }
+}
+
+/// Returns `fname` without the extensions added by [`Compression`] and encryption, if any
+pub(crate) fn strip_rolled_extensions(fname: &str) -> &str {
+    let fname = fname
+        .strip_suffix(&format!(".{}", crate::encryption::ENCRYPTED_EXTENSION))
+        .unwrap_or(fname);
+    [GZIP_EXTENSION, ZSTD_EXTENSION]
+        .iter()
+        .find_map(|ext| fname.strip_suffix(ext)?.strip_suffix('.'))
+        .unwrap_or(fname)
+}
+
+/// Returns `rotated`, or the file replacing it once compressed or encrypted
+pub(crate) fn rolled_version(fs: &dyn Fs, rotated: &Path) -> Option<PathBuf> {
+    if filesystem::exists(fs, rotated) {
+        return Some(rotated.to_path_buf());
+    }
+    let (folder, fname) = (rotated.parent()?, rotated.file_name()?.to_string_lossy());
+    fs.read_dir(folder)
+        .ok()?
+        .into_iter()
+        .find(|name| name != &*fname && strip_rolled_extensions(name) == fname)
+        .map(|name| folder.join(name))
+}
+
+/// Compresses the rolled files among `names` in `folder` except `active` and the
+/// `keep_uncompressed` most recent ones. Names must sort in the order the files were created.
+/// Returns the paths of the compressed files.
+pub(crate) fn compress_rolled_files(
+    fs: &dyn Fs,
+    folder: &str,
+    names: &[String],
+    compression: Compression,
+    keep_uncompressed: usize,
+    active: &Path,
+    pool: &Pool,
+) -> io::Result<Vec<PathBuf>> {
+    let mut rolled = names
+        .iter()
+        .map(|fname| Path::new(folder).join(fname))
+        .filter(|path| path != active)
+        .collect::<Vec<_>>();
+    rolled.sort_by(|a, b| crate::cmp_file_names(&b.to_string_lossy(), &a.to_string_lossy()));
+    let results = Mutex::new(vec![]);
+    pool.for_each(&rolled[keep_uncompressed.min(rolled.len())..], |path| {
+        let result = compression.compress_file_in(fs, path);
+        results.lock().unwrap_or_else(|e| e.into_inner()).push(result);
+    });
+    // Every file got its chance, the first failure is reported
+    let mut compressed = results
+        .into_inner()
+        .unwrap_or_else(|e| e.into_inner())
+        .into_iter()
+        .collect::<io::Result<Vec<_>>>()?;
+    compressed.sort();
+    Ok(compressed)
+}
+
+/// Replaces `src` and its time index by `<src>.<extension>`, which `encode` writes from the
+/// contents of `src`. The compressed file is written under a temporary name first, so it is never
+/// left half-written.
+fn replace_with_compressed<E>(fs: &dyn Fs, src: &Path, extension: &str, encode: E) -> io::Result<PathBuf>
+where
+    E: FnOnce(&mut dyn Read, &mut dyn Write) -> io::Result<()>,
+{
+    let dst = with_extension_appended(src, extension);
+    let tmp = with_extension_appended(&dst, "tmp");
+    // A leftover of an interrupted run would be appended to
+    let _ = fs.remove_file(&tmp);
+    let written = (|| {
+        let mut reader = BufReader::with_capacity(READ_CHUNK_LEN, FsReader::new(fs, src)?);
+        let mut writer = BufWriter::new(fs.open_append(&tmp)?);
+        encode(&mut reader, &mut writer)?;
+        writer.flush()?;
+        writer.get_ref().sync_all()
+    })();
+    if let Err(e) = written {
+        let _ = fs.remove_file(&tmp);
+        return Err(e);
+    }
+    fs.rename(&tmp, &dst)?;
+    fs.remove_file(src)?;
+    match fs.remove_file(&index::index_path_for(src)) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => {
+            eprintln!("WARNING: Failed to remove the time index of {} {}", src.display(), e);
+        },
+        _ => {},
+    }
+    internal_event!(path = %dst.display(), "compressed file");
+    Ok(dst)
+}
+
+fn with_extension_appended(path: &Path, extension: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".");
+    name.push(extension);
+    PathBuf::from(name)
+}
+
+/// Compresses rolled files with a zstd dictionary trained on the latest rolled files.
+/// Attach it with [`crate::RollingFileAppender::with_dictionary_compression`].
+#[cfg(feature = "zstd")]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct DictionaryCompression {
+    level: i32,
+    max_dictionary_size: usize,
+    min_samples: usize,
+}
+#[cfg(feature = "zstd")]
+impl DictionaryCompression {
+    /// Compresses at the given zstd level, training a dictionary of up to 16 KiB once 8 rolled
+    /// files are available
+    pub fn new(level: i32) -> DictionaryCompression {
+        DictionaryCompression {
+            level,
+            max_dictionary_size: 16 * 1024,
+            min_samples: 8,
+        }
+    }
+
+    /// Sets the maximum size of the trained dictionary
+    pub fn max_dictionary_size(mut self, x: usize) -> DictionaryCompression {
+        self.max_dictionary_size = x;
+        self
+    }
+
+    /// Sets how many rolled files must exist before the dictionary is trained. Files stay
+    /// uncompressed until then.
+    pub fn min_samples(mut self, x: usize) -> DictionaryCompression {
+        self.min_samples = x.max(1);
+        self
+    }
+}
+
+/// Returns the path of the dictionary used for the files of `prefix`
+#[cfg(feature = "zstd")]
+pub fn dictionary_path(folder: &str, prefix: &str) -> PathBuf {
+    Path::new(folder).join(format!("{}.{}", prefix, DICTIONARY_EXTENSION))
+}
+
+/// Compresses all rolled files named by `naming` except `active`, training and persisting the
+/// dictionary of `prefix` first if there is none yet. Returns the paths of the compressed files.
+#[cfg(feature = "zstd")]
+pub(crate) fn compress_with_dictionary(
+    fs: &dyn Fs,
+    folder: &str,
+    prefix: &str,
+    naming: &dyn NamingScheme,
+    config: &DictionaryCompression,
+    active: Option<&Path>,
+) -> io::Result<Vec<PathBuf>> {
+    internal_span!("compress", folder, prefix);
+    let mut names = naming.list(fs, Path::new(folder))?;
+    names.retain(|name| {
+        let compressed = strip_rolled_extensions(name) != name;
+        !compressed && Some(Path::new(folder).join(name).as_path()) != active && naming.started(name).is_some()
+    });
+    names.sort_by(|a, b| naming.cmp_age(a, b));
+    let files = names
+        .iter()
+        .map(|name| Path::new(folder).join(name))
+        .collect::<Vec<_>>();
+
+    let dict_path = dictionary_path(folder, prefix);
+    let dictionary = match read_prefix(fs, &dict_path, u64::MAX) {
+        Ok(dictionary) => dictionary,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            if files.len() < config.min_samples {
+                return Ok(vec![]);
+            }
+            let latest = &files[files.len().saturating_sub(MAX_SAMPLE_FILES)..];
+            let samples = latest
+                .iter()
+                .map(|path| read_prefix(fs, path, SAMPLE_LEN))
+                .collect::<io::Result<Vec<_>>>()?;
+            let dictionary = zstd::dict::from_samples(&samples, config.max_dictionary_size)?;
+            write_atomically(fs, &dict_path, &dictionary)?;
+            internal_event!(path = %dict_path.display(), size = dictionary.len(), "trained dictionary");
+            dictionary
+        },
+        Err(e) => return Err(e),
+    };
+
+    files
+        .iter()
+        .map(|path| {
+            replace_with_compressed(fs, path, ZSTD_EXTENSION, |reader, writer| {
+                let mut encoder = zstd::stream::Encoder::with_dictionary(writer, config.level, &dictionary)?;
+                io::copy(reader, &mut encoder)?;
+                encoder.finish().map(|_| ())
+            })
+        })
+        .collect()
+}
+
+/// Reads up to `max_len` bytes from the start of a file
+#[cfg(feature = "zstd")]
+fn read_prefix(fs: &dyn Fs, path: &Path, max_len: u64) -> io::Result<Vec<u8>> {
+    let len = fs.file_len(path)?.min(max_len);
+    let mut data = vec![0; usize::try_from(len).unwrap_or(0)];
+    fs.read_exact_at(path, 0, &mut data)?;
+    Ok(data)
+}
+
+/// Reads a file compressed with the given dictionary, which is stored next to the logs as
+/// `<prefix>.dict`.
+#[cfg(feature = "zstd")]
+pub fn decompress_file(path: &Path, dictionary: &[u8]) -> io::Result<Vec<u8>> {
+    let file = std::fs::File::open(path)?;
+    let mut decoder = zstd::stream::Decoder::with_dictionary(BufReader::new(file), dictionary)?;
+    let mut out = vec![];
+    decoder.read_to_end(&mut out)?;
+    Ok(out)
+}
+
+#[cfg(feature = "zstd")]
+fn write_atomically(fs: &dyn Fs, path: &Path, data: &[u8]) -> io::Result<()> {
+    let tmp = path.with_extension(format!("{}.tmp", DICTIONARY_EXTENSION));
+    let _ = fs.remove_file(&tmp);
+    let mut f = fs.open_append(&tmp)?;
+    f.write_all(data)?;
+    f.sync_all()?;
+    fs.rename(&tmp, path)
+}
+
+#[cfg(all(test, any(feature = "gzip", feature = "zstd")))]
+mod t {
+    use crate::*;
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_dictionary_compression_round_trip() {
+        use super::*;
+        use std::fs;
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let condition = RollingConditionBasic::new().max_size(1);
+        let mut rfa = BasicRollingFileAppender::new(folder, "log.log", condition, 99)
+            .unwrap()
+            .with_dictionary_compression(DictionaryCompression::new(3).min_samples(20));
+        rfa.rollover().unwrap();
+        let mut lines = vec![];
+        for i in 0..40 {
+            let now = Local.with_ymd_and_hms(2099, 3, 30, 1, i, 0).unwrap();
+            let line = format!("{} INFO request handled path=/api/v1/items/{} status=200\n", now, i * 7);
+            rfa.write_with_datetime(line.as_bytes(), &now).unwrap();
+            lines.push(line);
+        }
+        rfa.flush().unwrap();
+
+        let dictionary = fs::read(dictionary_path(folder, "log.log")).unwrap();
+        let first = dir.path().join("log.log.20990330.010000.zst");
+        assert_eq!(decompress_file(&first, &dictionary).unwrap(), lines[0].as_bytes());
+        assert!(!dir.path().join("log.log.20990330.010000").exists());
+        // The active file is left alone
+        assert!(dir.path().join("log.log.20990330.013900").exists());
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_bounded_samples() {
+        use super::*;
+        use std::fs;
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("log.log.20990330.010000");
+        fs::write(&path, vec![b'x'; 3 * SAMPLE_LEN as usize]).unwrap();
+        assert_eq!(
+            read_prefix(&RealFs, &path, SAMPLE_LEN).unwrap().len(),
+            SAMPLE_LEN as usize
+        );
+        fs::write(&path, b"line\n").unwrap();
+        assert_eq!(read_prefix(&RealFs, &path, SAMPLE_LEN).unwrap(), b"line\n");
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_gzip_rolled_files() {
+        use std::io::Read;
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let mut rfa = BasicRollingFileAppender::builder()
+            .folder(folder)
+            .prefix("log.log")
+            .condition(RollingConditionBasic::new().daily())
+            .max_files(2)
+            .compression(Compression::Gzip(6))
+            .symlink(false)
+            .build()
+            .unwrap();
+        rfa.rollover().unwrap();
+        for day in 1..=2 {
+            let now = Local.with_ymd_and_hms(2099, 3, day, 1, 0, 0).unwrap();
+            rfa.write_with_datetime(b"line\n", &now).unwrap();
+        }
+        rfa.flush().unwrap();
+
+        let mut names = std::fs::read_dir(folder)
+            .unwrap()
+            .map(|f| f.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names, ["log.log.20990301.010000.gz", "log.log.20990302.010000"]);
+        let file = std::fs::File::open(dir.path().join(&names[0])).unwrap();
+        let mut contents = String::new();
+        flate2::read::GzDecoder::new(file)
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert_eq!(contents, "line\n");
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_compress_removes_time_index() {
+        use std::io::Read;
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("log.log.20990301.010000");
+        std::fs::write(&path, b"line\n").unwrap();
+        std::fs::write(index::index_path_for(&path), b"").unwrap();
+        let compressed = Compression::Gzip(6).compress_file(&path).unwrap();
+        assert_eq!(compressed, dir.path().join("log.log.20990301.010000.gz"));
+        assert!(!path.exists());
+        assert!(!index::index_path_for(&path).exists());
+        let mut contents = String::new();
+        flate2::read::GzDecoder::new(std::fs::File::open(&compressed).unwrap())
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert_eq!(contents, "line\n");
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_keep_recent_files_uncompressed() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let mut rfa = BasicRollingFileAppender::builder()
+            .folder(folder)
+            .prefix("log.log")
+            .condition(RollingConditionBasic::new().daily())
+            .max_files(3)
+            .compression(Compression::Gzip(6))
+            .keep_uncompressed(1)
+            .symlink(false)
+            .build()
+            .unwrap();
+        rfa.rollover().unwrap();
+        for day in 1..=3 {
+            let now = Local.with_ymd_and_hms(2099, 3, day, 1, 0, 0).unwrap();
+            rfa.write_with_datetime(b"line\n", &now).unwrap();
+        }
+        rfa.flush().unwrap();
+
+        let mut names = std::fs::read_dir(folder)
+            .unwrap()
+            .map(|f| f.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(
+            names,
+            [
+                "log.log.20990301.010000.gz",
+                "log.log.20990302.010000",
+                "log.log.20990303.010000"
+            ]
+        );
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_compress_backlog_on_pool() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        for day in 1..=8 {
+            let path = dir.path().join(format!("log.log.209903{:02}.010000", day));
+            std::fs::write(path, b"line\n").unwrap();
+        }
+        let mut rfa = BasicRollingFileAppender::builder()
+            .folder(folder)
+            .prefix("log.log")
+            .condition(RollingConditionBasic::new().daily())
+            .max_files(20)
+            .compression(Compression::Gzip(6))
+            .symlink(false)
+            .build()
+            .unwrap()
+            .with_maintenance_threads(4)
+            .with_thread_options(ThreadOptions::new().name("compressor"));
+        let now = Local.with_ymd_and_hms(2099, 3, 10, 1, 0, 0).unwrap();
+        rfa.write_with_datetime(b"line\n", &now).unwrap();
+
+        let names = std::fs::read_dir(folder)
+            .unwrap()
+            .map(|f| f.unwrap().file_name().into_string().unwrap())
+            .filter(|name| name.starts_with("log.log.209903"))
+            .collect::<Vec<_>>();
+        // The whole backlog, but not the active file
+        assert_eq!(
+            names.iter().filter(|name| name.ends_with(".gz")).count(),
+            8,
+            "{:?}",
+            names
+        );
+        assert!(names.contains(&"log.log.20990310.010000".to_string()));
+    }
+}
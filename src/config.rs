@@ -0,0 +1,131 @@
+//! Changing the settings of a running appender.
+//!
+//! Services reloading their configuration, e.g. on SIGHUP, pass a [`RollingFileConfig`] to
+//! [`RollingFileAppender::reconfigure`], or to [`crate::NonBlockingRollingFileAppender::reconfigure`]
+//! when a worker thread owns the appender, instead of recreating the appender. The new settings
+//! replace the old ones at once, between two writes.
+
+use crate::{RetentionPolicy, RollingCondition, RollingFileAppender};
+use chrono::prelude::*;
+use std::io;
+
+/// The settings [`RollingFileAppender::reconfigure`] replaces
+#[derive(Clone, Debug)]
+pub struct RollingFileConfig<RC>
+where
+    RC: RollingCondition,
+{
+    condition: RC,
+    retention: RetentionPolicy,
+    buffer_capacity_opt: Option<usize>,
+}
+
+impl<RC> RollingFileConfig<RC>
+where
+    RC: RollingCondition,
+{
+    /// Creates settings keeping `max_files` rolled files, with the default buffer capacity
+    pub fn new(condition: RC, max_files: usize) -> RollingFileConfig<RC> {
+        RollingFileConfig {
+            condition,
+            retention: RetentionPolicy::MaxFiles(max_files),
+            buffer_capacity_opt: None,
+        }
+    }
+
+    /// Replaces the retention policy, `max_files` included
+    pub fn retention(mut self, retention: RetentionPolicy) -> RollingFileConfig<RC> {
+        self.retention = retention;
+        self
+    }
+
+    /// Sets the capacity of the write buffer, 0 to write to the file right away
+    pub fn buffer_capacity(mut self, capacity: usize) -> RollingFileConfig<RC> {
+        self.buffer_capacity_opt = Some(capacity);
+        self
+    }
+}
+
+impl<RC> RollingFileAppender<RC>
+where
+    RC: RollingCondition,
+{
+    /// Replaces the condition, retention and buffer capacity, and rolls over right away if the new
+    /// condition says so. The new retention applies from the next rollover.
+    pub fn reconfigure(&mut self, config: RollingFileConfig<RC>) -> io::Result<()> {
+        let now = self.clock.now();
+        self.reconfigure_with_datetime(config, &now)
+    }
+
+    /// Same as [`RollingFileAppender::reconfigure`], using the given datetime to check the new
+    /// condition
+    pub fn reconfigure_with_datetime(
+        &mut self,
+        config: RollingFileConfig<RC>,
+        now: &DateTime<Local>,
+    ) -> io::Result<()> {
+        internal_event!("reconfigured appender");
+        self.condition = config.condition;
+        if let Some(opened) = self.opened_opt.filter(|_| self.writer_opt.is_some()) {
+            self.condition.file_opened(&opened);
+            self.scheduled_rollover_opt = self.condition.next_rollover(now);
+        }
+        if let Some(last_write) = self.last_write_opt {
+            self.condition.written(&last_write);
+        }
+        self.retention.policy = config.retention;
+        self.buffer_capacity = config.buffer_capacity_opt;
+        self.rebuild_writer();
+        if self.writer_opt.is_some() {
+            self.rollover_if_due(now)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod t {
+    use crate::*;
+
+    #[test]
+    fn test_reconfigure() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let mut rfa =
+            BasicRollingFileAppender::new(folder, "log.log", RollingConditionBasic::new().max_size(100), 9).unwrap();
+        rfa.rollover().unwrap();
+        let now = Local.with_ymd_and_hms(2099, 3, 1, 1, 0, 0).unwrap();
+        rfa.write_with_datetime(b"0123456789\n", &now).unwrap();
+
+        // The file is already larger than the new limit
+        let later = Local.with_ymd_and_hms(2099, 3, 1, 2, 0, 0).unwrap();
+        let config = RollingFileConfig::new(RollingConditionBasic::new().max_size(10), 3).buffer_capacity(16);
+        rfa.reconfigure_with_datetime(config, &later).unwrap();
+        rfa.write_with_datetime(b"new\n", &later).unwrap();
+        rfa.flush().unwrap();
+        let read = |name: &str| std::fs::read_to_string(dir.path().join(name)).unwrap();
+        assert_eq!(read("log.log.20990301.010000"), "0123456789\n");
+        assert_eq!(read("log.log.20990301.020000"), "new\n");
+    }
+
+    #[test]
+    fn test_reconfigure_age_of_open_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let mut rfa =
+            BasicRollingFileAppender::new(folder, "log.log", RollingConditionBasic::new().max_size(100), 9).unwrap();
+        rfa.rollover().unwrap();
+        let now = Local.with_ymd_and_hms(2099, 3, 1, 1, 0, 0).unwrap();
+        rfa.write_with_datetime(b"old\n", &now).unwrap();
+
+        // The open file counts as started at 1:00, not when the condition was replaced
+        let later = Local.with_ymd_and_hms(2099, 3, 1, 1, 30, 0).unwrap();
+        let config = RollingFileConfig::new(RollingConditionBasic::new().max_age(chrono::Duration::hours(1)), 3);
+        rfa.reconfigure_with_datetime(config, &later).unwrap();
+        let due = Local.with_ymd_and_hms(2099, 3, 1, 2, 10, 0).unwrap();
+        rfa.write_with_datetime(b"new\n", &due).unwrap();
+        rfa.flush().unwrap();
+        assert!(dir.path().join("log.log.20990301.021000").exists());
+        assert_eq!(rfa.last_rollover_lag(), Some(chrono::Duration::minutes(10)));
+    }
+}
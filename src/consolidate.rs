@@ -0,0 +1,316 @@
+//! Consolidation of small rolled files.
+//!
+//! Size triggered bursts can leave many tiny files behind. Consolidation appends consecutive
+//! small files of the same day to the first file of the run, which keeps its name since it
+//! holds the oldest data, and deletes the others. Files still waiting for their upload end a run.
+//! The manifest of the folder, if any, is updated for the files merged and deleted. It runs after
+//! each rollover with [`crate::RollingFileAppender::with_consolidation`].
+//!
+//! A merge survives crashes: the first file and the files appended to it are copied to
+//! `<file>.merged`, which then replaces the first file. A journal `<file>.merge` lists the
+//! appended files until they are deleted, so the next run either discards an incomplete copy or
+//! finishes deleting them, and no data ends up in the file twice.
+
+#[cfg(feature = "manifest")]
+use crate::manifest;
+use crate::{compression, filesystem::FsReader, index, Fs, NamingScheme};
+use std::{
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+/// File name extension of the journal of a merge in progress
+pub(crate) const JOURNAL_EXTENSION: &str = "merge";
+
+/// File name extension of the copy a merge is written to
+pub(crate) const MERGED_EXTENSION: &str = "merged";
+
+/// Concatenates runs of consecutive uncompressed rolled files named by `naming` and smaller than
+/// `max_size` that were opened on the same day, as long as the combined file stays below
+/// `max_size`. Index sidecars are merged along with their files. `active` is never touched.
+/// Returns the number of files removed.
+pub fn consolidate_small_files(
+    fs: &dyn Fs,
+    folder: &str,
+    naming: &dyn NamingScheme,
+    max_size: u64,
+    active: Option<&Path>,
+) -> io::Result<usize> {
+    consolidate_except(fs, folder, naming, max_size, &active.into_iter().collect::<Vec<_>>())
+}
+
+/// Same as [`consolidate_small_files`], leaving all files of `skipped` alone
+pub(crate) fn consolidate_except(
+    fs: &dyn Fs,
+    folder: &str,
+    naming: &dyn NamingScheme,
+    max_size: u64,
+    skipped: &[&Path],
+) -> io::Result<usize> {
+    internal_span!("consolidate", folder, max_size);
+    // Merges interrupted by a crash are finished or discarded first
+    for name in naming.list(fs, Path::new(folder))? {
+        let target = Path::new(folder).join(&name);
+        if fs.file_len(&with_extension(&target, JOURNAL_EXTENSION)).is_ok() {
+            finish_merge(fs, folder, &target)?;
+        } else {
+            remove_if_exists(fs, &with_extension(&target, MERGED_EXTENSION))?;
+        }
+    }
+
+    let mut files = vec![];
+    for name in naming.list(fs, Path::new(folder))? {
+        let path = Path::new(folder).join(&name);
+        if compression::strip_rolled_extensions(&name) != name {
+            continue;
+        }
+        if let Some(dt) = naming.started(&name) {
+            let len = fs.file_len(&path)?;
+            #[cfg(feature = "upload")]
+            let pending = crate::upload::is_marked(&path);
+            #[cfg(not(feature = "upload"))]
+            let pending = false;
+            files.push((dt, path, len, pending));
+        }
+    }
+    files.sort();
+
+    let mut runs: Vec<Run> = vec![];
+    let mut run_opt: Option<(chrono::NaiveDate, u64)> = None;
+    for (dt, path, len, pending) in files {
+        let left_alone = pending || skipped.contains(&path.as_path());
+        if let (Some((day, size)), Some(run)) = (run_opt.as_mut(), runs.last_mut()) {
+            if !left_alone && *day == dt.date_naive() && *size + len < max_size {
+                run.2.push((path, len));
+                *size += len;
+                continue;
+            }
+        }
+        run_opt = None;
+        if len < max_size && !left_alone {
+            run_opt = Some((dt.date_naive(), len));
+            runs.push((path, len, vec![]));
+        }
+    }
+
+    let (mut merged, mut removed) = (vec![], vec![]);
+    let result = runs
+        .into_iter()
+        .filter(|(_, _, sources)| !sources.is_empty())
+        .try_for_each(|(target, target_len, sources)| {
+            merge(fs, folder, &target, target_len, &sources)?;
+            merged.push(target);
+            removed.extend(sources.into_iter().map(|(source, _)| source));
+            Ok(())
+        });
+    #[cfg(feature = "manifest")]
+    manifest::record_maintenance(fs, folder, naming, &merged, &removed);
+    result.map(|_| removed.len())
+}
+
+/// A file with its length and the files to append to it with theirs
+type Run = (PathBuf, u64, Vec<(PathBuf, u64)>);
+
+/// Replaces `target` by a copy with `sources` appended, then deletes them
+fn merge(fs: &dyn Fs, folder: &str, target: &Path, target_len: u64, sources: &[(PathBuf, u64)]) -> io::Result<()> {
+    let copy = with_extension(target, MERGED_EXTENSION);
+    remove_if_exists(fs, &copy)?;
+    let written = (|| {
+        fs.copy(target, &copy)?;
+        let mut out = fs.open_append(&copy)?;
+        for (source, _) in sources {
+            io::copy(&mut FsReader::new(fs, source)?, &mut out)?;
+        }
+        out.flush()?;
+        out.sync_all()?;
+        let journal = sources
+            .iter()
+            .map(|(source, _)| format!("{}\n", source.strip_prefix(folder).unwrap_or(source).display()))
+            .collect::<String>();
+        let journal_path = with_extension(target, JOURNAL_EXTENSION);
+        remove_if_exists(fs, &journal_path)?;
+        let mut f = fs.open_append(&journal_path)?;
+        f.write_all(journal.as_bytes())?;
+        f.flush()?;
+        f.sync_all()
+    })();
+    if let Err(e) = written {
+        let _ = fs.remove_file(&copy);
+        return Err(e);
+    }
+    // From here on the journal lets the next run finish the merge
+    fs.rename(&copy, target)?;
+    let mut shift = target_len;
+    for (source, len) in sources {
+        index::append_shifted(fs, source, target, shift)?;
+        internal_event!(source = %source.display(), target = %target.display(), "consolidated file");
+        shift += len;
+    }
+    finish_merge(fs, folder, target)
+}
+
+/// Completes the merge journaled next to `target`: deletes the files appended to it if the
+/// merged copy replaced it, or discards the copy otherwise
+fn finish_merge(fs: &dyn Fs, folder: &str, target: &Path) -> io::Result<()> {
+    let journal_path = with_extension(target, JOURNAL_EXTENSION);
+    let copy = with_extension(target, MERGED_EXTENSION);
+    if fs.file_len(&copy).is_ok() {
+        fs.remove_file(&copy)?;
+    } else {
+        let mut journal = vec![0; usize::try_from(fs.file_len(&journal_path)?).unwrap_or(0)];
+        fs.read_exact_at(&journal_path, 0, &mut journal)?;
+        for name in String::from_utf8_lossy(&journal).lines() {
+            let source = Path::new(folder).join(name);
+            remove_if_exists(fs, &source)?;
+            remove_if_exists(fs, &index::index_path_for(&source))?;
+        }
+    }
+    fs.remove_file(&journal_path)
+}
+
+fn remove_if_exists(fs: &dyn Fs, path: &Path) -> io::Result<()> {
+    match fs.remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+fn with_extension(path: &Path, extension: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".");
+    name.push(extension);
+    PathBuf::from(name)
+}
+
+#[cfg(test)]
+mod t {
+    use super::*;
+    use crate::*;
+    use std::fs;
+
+    #[test]
+    fn test_consolidate_same_day() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let condition = RollingConditionBasic::new().daily().max_size(4);
+        let mut rfa = BasicRollingFileAppender::new(folder, "log.log", condition, 99).unwrap();
+        let times = [(30, 1, 0), (30, 1, 1), (30, 1, 2), (31, 1, 0), (31, 1, 1)];
+        for (day, minute, second) in times {
+            let now = Local.with_ymd_and_hms(2021, 3, day, 1, minute, second).unwrap();
+            rfa.write_with_datetime(b"abcd", &now).unwrap();
+        }
+        rfa.flush().unwrap();
+
+        let removed = rfa.consolidate_small_files(100).unwrap();
+        assert_eq!(removed, 2);
+        let merged = fs::read(dir.path().join("log.log.20210330.010100")).unwrap();
+        assert_eq!(merged, b"abcdabcdabcd");
+        assert!(!dir.path().join("log.log.20210330.010102").exists());
+        assert!(dir.path().join("log.log.20210331.010100").exists());
+    }
+
+    #[cfg(feature = "manifest")]
+    #[test]
+    fn test_consolidate_updates_manifest() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let condition = RollingConditionBasic::new().daily().max_size(4);
+        let mut rfa = BasicRollingFileAppender::new(folder, "log.log", condition, 99)
+            .unwrap()
+            .with_manifest(true);
+        rfa.rollover().unwrap();
+        for second in 0..3 {
+            let now = Local.with_ymd_and_hms(2099, 3, 30, 1, 0, second).unwrap();
+            rfa.write_with_datetime(b"abcd", &now).unwrap();
+        }
+        rfa.flush().unwrap();
+
+        assert_eq!(rfa.consolidate_small_files(9).unwrap(), 1);
+        let manifest = fs::read_to_string(manifest::manifest_path(folder)).unwrap();
+        let lines = manifest.lines().collect::<Vec<_>>();
+        let merged = dir.path().join("log.log.20990330.010000");
+        let entry = format!(
+            "{{\"file\":\"log.log.20990330.010000\",\"size\":8,\"sha256\":\"{}\",",
+            manifest::sha256_file(&merged).unwrap()
+        );
+        assert_eq!(fs::read(&merged).unwrap(), b"abcdabcd");
+        assert!(lines[lines.len() - 2].starts_with(&entry));
+        assert_eq!(
+            lines[lines.len() - 1],
+            "{\"file\":\"log.log.20990330.010001\",\"removed\":true}"
+        );
+    }
+
+    #[cfg(feature = "upload")]
+    #[test]
+    fn test_pending_uploads_are_not_merged() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let condition = RollingConditionBasic::new().daily().max_size(4);
+        let mut rfa = BasicRollingFileAppender::new(folder, "log.log", condition, 99).unwrap();
+        rfa.rollover().unwrap();
+        for second in 0..3 {
+            let now = Local.with_ymd_and_hms(2099, 3, 30, 1, 0, second).unwrap();
+            rfa.write_with_datetime(b"abcd", &now).unwrap();
+        }
+        rfa.flush().unwrap();
+        fs::write(dir.path().join("log.log.20990330.010001.upload"), b"").unwrap();
+
+        // The file waiting for its upload ends the run, the file after it starts a new one
+        assert_eq!(rfa.consolidate_small_files(100).unwrap(), 0);
+        assert!(dir.path().join("log.log.20990330.010001").exists());
+    }
+
+    #[test]
+    fn test_interrupted_merges() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let naming = TimestampNaming::new("log.log");
+        let path = |name: &str| dir.path().join(name);
+        // Crashed before the merged copy replaced the first file: the copy is discarded
+        fs::write(path("log.log.20990330.010000"), b"abcd").unwrap();
+        fs::write(path("log.log.20990330.010000.merged"), b"abcde").unwrap();
+        fs::write(path("log.log.20990330.010000.merge"), b"log.log.20990330.010001\n").unwrap();
+        fs::write(path("log.log.20990330.010001"), b"efgh").unwrap();
+        // Crashed after it: the appended files are deleted
+        fs::write(path("log.log.20990331.010000"), b"abcdefgh").unwrap();
+        fs::write(path("log.log.20990331.010000.merge"), b"log.log.20990331.010001\n").unwrap();
+        fs::write(path("log.log.20990331.010001"), b"efgh").unwrap();
+        fs::write(path("log.log.20990331.010002"), b"ijkl").unwrap();
+
+        let removed = consolidate_small_files(&RealFs, folder, &naming, 100, None).unwrap();
+        assert_eq!(removed, 2);
+        assert_eq!(fs::read(path("log.log.20990330.010000")).unwrap(), b"abcdefgh");
+        assert_eq!(fs::read(path("log.log.20990331.010000")).unwrap(), b"abcdefghijkl");
+        let mut names = fs::read_dir(folder)
+            .unwrap()
+            .map(|f| f.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names, ["log.log.20990330.010000", "log.log.20990331.010000"]);
+    }
+
+    #[test]
+    fn test_consolidate_after_rollover() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let condition = RollingConditionBasic::new().daily().max_size(4);
+        let mut rfa = BasicRollingFileAppender::new(folder, "log.log", condition, 99)
+            .unwrap()
+            .with_consolidation(100);
+        rfa.rollover().unwrap();
+        for second in 0..4 {
+            let now = Local.with_ymd_and_hms(2099, 3, 30, 1, 0, second).unwrap();
+            rfa.write_with_datetime(b"abcd", &now).unwrap();
+        }
+        rfa.flush().unwrap();
+
+        // The file rolled last is merged after the next rollover
+        assert_eq!(
+            fs::read(dir.path().join("log.log.20990330.010000")).unwrap(),
+            b"abcdabcd"
+        );
+        assert!(dir.path().join("log.log.20990330.010002").exists());
+        assert!(!dir.path().join("log.log.20990330.010001").exists());
+    }
+}
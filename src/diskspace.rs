@@ -0,0 +1,182 @@
+//! Keeping a minimum of free space on the disk of the log folder.
+//!
+//! An appender given a minimum with [`RollingFileAppender::with_min_free_space`] queries the space
+//! available in the log folder at most once a second when written to. When it runs short, it
+//! either deletes the oldest files, those of the archive folder first, until enough space is free
+//! again, or drops writes until enough space is freed by others.
+
+use crate::{audit, index, list_log_files, ArchivePolicy, RollingCondition, RollingFileAppender};
+use std::{
+    path::{Path, PathBuf},
+    sync::atomic::Ordering,
+    time::{Duration, Instant},
+};
+
+/// How often the available space is queried
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// What to do when less than the minimum of free space is available
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum LowSpacePolicy {
+    /// Deletes the oldest rolled and archived files, regardless of retention but respecting
+    /// vetoes. The active file is never deleted.
+    #[default]
+    DeleteOldest,
+    /// Drops writes, reporting success, and counts the discarded bytes
+    Drop,
+}
+
+/// The minimum of free space of an appender and the outcome of the last check
+#[derive(Clone, Debug)]
+pub(crate) struct SpaceGuard {
+    min_free: u64,
+    policy: LowSpacePolicy,
+    last_check_opt: Option<Instant>,
+    low: bool,
+}
+
+impl<RC> RollingFileAppender<RC>
+where
+    RC: RollingCondition,
+{
+    /// Keeps at least `bytes` free on the disk of the log folder, applying `policy` when less is
+    /// available. Has no effect where the available space can't be queried.
+    pub fn with_min_free_space(mut self, bytes: u64, policy: LowSpacePolicy) -> RollingFileAppender<RC> {
+        self.space_guard_opt = Some(SpaceGuard {
+            min_free: bytes,
+            policy,
+            last_check_opt: None,
+            low: false,
+        });
+        self
+    }
+
+    /// Checks the available space if due, deleting files if needed. Returns false if a write of
+    /// `len` bytes must be dropped.
+    pub(crate) fn check_free_space(&mut self, len: usize) -> bool {
+        let Some(guard) = self.space_guard_opt.as_mut() else {
+            return true;
+        };
+        if guard.last_check_opt.is_none_or(|last| last.elapsed() >= CHECK_INTERVAL) {
+            guard.last_check_opt = Some(Instant::now());
+            let (min_free, policy) = (guard.min_free, guard.policy);
+            let low = !(self.has_free_space(min_free)
+                || policy == LowSpacePolicy::DeleteOldest && self.delete_oldest_files(min_free));
+            if let Some(guard) = self.space_guard_opt.as_mut() {
+                guard.low = low;
+            }
+        }
+        let drops = self
+            .space_guard_opt
+            .as_ref()
+            .is_some_and(|guard| guard.low && guard.policy == LowSpacePolicy::Drop);
+        if drops {
+            self.discarded_bytes += u64::try_from(len).unwrap_or(u64::MAX);
+        }
+        !drops
+    }
+
+    /// Tells whether at least `min_free` bytes are available, or the space can't be queried
+    fn has_free_space(&self, min_free: u64) -> bool {
+        match self.fs.available_space(Path::new(&self.folder)) {
+            Ok(available) => available >= min_free,
+            Err(e) if e.kind() == std::io::ErrorKind::Unsupported => true,
+            Err(e) => {
+                eprintln!("WARNING: Failed to query free disk space {}", e);
+                true
+            },
+        }
+    }
+
+    /// Deletes the oldest files until `min_free` bytes are available. Returns whether they are.
+    fn delete_oldest_files(&mut self, min_free: u64) -> bool {
+        let mut candidates = vec![];
+        if let ArchivePolicy::MoveTo(archive_folder) = &self.retention.archive {
+            candidates.extend(self.oldest_files_first(&archive_folder.to_string_lossy(), None));
+        }
+        candidates.extend(self.oldest_files_first(&self.folder, self.link.name_opt.as_deref()));
+        let mut candidates = candidates
+            .into_iter()
+            .filter(|path| Some(path) != self.current_path_opt.as_ref() && !self.hooks.vetoes_deletion(path));
+        let mut deleted = vec![];
+        let enough = loop {
+            if self.has_free_space(min_free) {
+                break true;
+            }
+            let Some(path) = candidates.next() else {
+                break false;
+            };
+            match self.fs.remove_file(&path) {
+                Ok(()) => {
+                    let _ = self.fs.remove_file(&index::index_path_for(&path));
+                    deleted.push(path);
+                },
+                Err(e) => eprintln!("WARNING: Failed to remove old logfile {}: {}", path.display(), e),
+            }
+        };
+        drop(candidates);
+        if deleted.is_empty() {
+            return enough;
+        }
+        eprintln!(
+            "WARNING: Removed {} old logfiles to keep {} bytes free",
+            deleted.len(),
+            min_free
+        );
+        self.stats
+            .deleted_files
+            .fetch_add(deleted.len() as u64, Ordering::Relaxed);
+        if let Some(on_delete) = self.hooks.on_delete_opt.as_ref() {
+            deleted.iter().for_each(|path| on_delete(path));
+        }
+        if self.retention.audit {
+            let reason = format!("min_free_space={}", min_free);
+            let now = self.clock.now();
+            if let Err(e) = audit::record_deletions(&*self.fs, &self.folder, &self.prefix, &deleted, &reason, &now) {
+                eprintln!("WARNING: Failed to record deletions {}", e);
+            }
+        }
+        enough
+    }
+
+    /// Returns the paths of the log files in `folder`, oldest first
+    fn oldest_files_first(&self, folder: &str, link_name_opt: Option<&str>) -> Vec<PathBuf> {
+        let mut names = list_log_files(&*self.fs, folder, &*self.naming, link_name_opt).unwrap_or_default();
+        names.sort_by(|a, b| self.naming.cmp_age(a, b));
+        names.into_iter().map(|fname| Path::new(folder).join(fname)).collect()
+    }
+}
+
+#[cfg(test)]
+mod t {
+    use crate::*;
+
+    #[test]
+    #[cfg(unix)]
+    fn test_min_free_space() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let mut rfa =
+            BasicRollingFileAppender::new(folder, "log.log", RollingConditionBasic::new().daily(), 9).unwrap();
+        rfa.rollover().unwrap();
+        for day in 1..=3 {
+            let now = Local.with_ymd_and_hms(2099, 3, day, 1, 0, 0).unwrap();
+            rfa.write_with_datetime(b"line\n", &now).unwrap();
+        }
+        assert!(!rfa.list_rotated_files().unwrap().is_empty());
+
+        // No disk has that much space, every file but the active one goes
+        let now = Local.with_ymd_and_hms(2099, 3, 3, 2, 0, 0).unwrap();
+        let mut rfa = rfa.with_min_free_space(u64::MAX, LowSpacePolicy::DeleteOldest);
+        rfa.write_with_datetime(b"more\n", &now).unwrap();
+        assert!(rfa.list_rotated_files().unwrap().is_empty());
+        assert_eq!(rfa.stats().deleted_files, 3);
+
+        let mut rfa = rfa.with_min_free_space(u64::MAX, LowSpacePolicy::Drop);
+        assert_eq!(rfa.write_with_datetime(b"dropped\n", &now).unwrap(), 8);
+        assert_eq!(rfa.discarded_bytes(), 8);
+        rfa.flush().unwrap();
+        let active = rfa.current_file_path().unwrap();
+        assert_eq!(std::fs::read_to_string(active).unwrap(), "line\nmore\n");
+    }
+}
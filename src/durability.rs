@@ -0,0 +1,88 @@
+//! Persisting written data to disk at controlled points.
+//!
+//! Flushing only hands data to the OS, which writes it to disk on its own schedule, so a power
+//! loss may still lose it. Audit logs and similar can ask for `fsync` with a [`SyncPolicy`], see
+//! [`crate::RollingFileAppender::with_sync_policy`], or call
+//! [`crate::RollingFileAppender::flush_and_sync`] where they need it.
+
+use std::time::{Duration, Instant};
+
+/// When the appender asks the OS to persist the active file to disk
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum SyncPolicy {
+    /// Never, data reaches the disk whenever the OS writes it back
+    #[default]
+    Never,
+    /// After every write
+    EveryWrite,
+    /// After a write once at least this many bytes were written since the last sync
+    EveryNBytes(u64),
+    /// After the first write at least this long after the last sync
+    EveryDuration(Duration),
+    /// Only when rolling over from a file, which every policy but `Never` also does
+    OnRotate,
+}
+
+/// Tracks what was written since the last sync
+#[derive(Debug)]
+pub(crate) struct SyncState {
+    pub(crate) policy: SyncPolicy,
+    unsynced_bytes: u64,
+    last_sync: Instant,
+}
+
+impl SyncState {
+    pub(crate) fn new(policy: SyncPolicy) -> SyncState {
+        SyncState {
+            policy,
+            unsynced_bytes: 0,
+            last_sync: Instant::now(),
+        }
+    }
+
+    /// Records a write of `len` bytes and returns whether the file should be synced now
+    pub(crate) fn wrote(&mut self, len: u64) -> bool {
+        self.unsynced_bytes += len;
+        match self.policy {
+            SyncPolicy::Never | SyncPolicy::OnRotate => false,
+            SyncPolicy::EveryWrite => true,
+            SyncPolicy::EveryNBytes(n) => self.unsynced_bytes >= n,
+            SyncPolicy::EveryDuration(interval) => self.last_sync.elapsed() >= interval,
+        }
+    }
+
+    /// Returns whether a file should be synced before rolling over from it
+    pub(crate) fn on_rotate(&self) -> bool {
+        self.policy != SyncPolicy::Never
+    }
+
+    pub(crate) fn synced(&mut self) {
+        self.unsynced_bytes = 0;
+        self.last_sync = Instant::now();
+    }
+}
+
+#[cfg(test)]
+mod t {
+    use crate::*;
+
+    #[test]
+    fn test_sync_every_n_bytes() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let mut rfa = BasicRollingFileAppender::new(folder, "log.log", RollingConditionBasic::new().daily(), 9)
+            .unwrap()
+            .with_sync_policy(SyncPolicy::EveryNBytes(8));
+        rfa.rollover().unwrap();
+        let now = Local.with_ymd_and_hms(2099, 3, 30, 1, 0, 0).unwrap();
+        let path = dir.path().join("log.log.20990330.010000");
+        // Syncing flushes the buffer first, which makes the data visible
+        rfa.write_with_datetime(b"line\n", &now).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"");
+        rfa.write_with_datetime(b"line\n", &now).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"line\nline\n");
+        rfa.write_with_datetime(b"last\n", &now).unwrap();
+        rfa.flush_and_sync().unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"line\nline\nlast\n");
+    }
+}
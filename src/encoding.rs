@@ -0,0 +1,215 @@
+//! Text processing applied to written data: line ending normalization and transcoding into
+//! encodings other than UTF-8.
+
+use std::borrow::Cow;
+
+/// Character encoding used for the contents of log files.
+/// Data handed to the appender is always expected to be UTF-8.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum Encoding {
+    /// Writes data unchanged
+    #[default]
+    Utf8,
+    /// UTF-16, little endian
+    Utf16Le,
+    /// UTF-16, big endian
+    Utf16Be,
+}
+
+impl Encoding {
+    /// Returns the byte order mark of this encoding
+    pub fn bom(&self) -> &'static [u8] {
+        match self {
+            Encoding::Utf8 => &[0xef, 0xbb, 0xbf],
+            Encoding::Utf16Le => &[0xff, 0xfe],
+            Encoding::Utf16Be => &[0xfe, 0xff],
+        }
+    }
+
+    /// Appends `s` in this encoding to `out`
+    pub(crate) fn encode_into(&self, out: &mut Vec<u8>, s: &str) {
+        match self {
+            Encoding::Utf8 => out.extend_from_slice(s.as_bytes()),
+            Encoding::Utf16Le => s.encode_utf16().for_each(|u| out.extend_from_slice(&u.to_le_bytes())),
+            Encoding::Utf16Be => s.encode_utf16().for_each(|u| out.extend_from_slice(&u.to_be_bytes())),
+        }
+    }
+}
+
+/// Line ending written to log files
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum LineEnding {
+    /// Writes line endings unchanged
+    #[default]
+    Unchanged,
+    /// Uses `\r\n` on Windows and `\n` everywhere else
+    Native,
+    /// Converts `\r\n` to `\n`
+    Lf,
+    /// Converts a lone `\n` to `\r\n`
+    CrLf,
+}
+
+impl LineEnding {
+    fn resolve(self) -> LineEnding {
+        match self {
+            LineEnding::Native if cfg!(windows) => LineEnding::CrLf,
+            LineEnding::Native => LineEnding::Lf,
+            other => other,
+        }
+    }
+}
+
+/// Converts UTF-8 input into the configured line ending and encoding. A multi-byte character
+/// split across two writes is held back until it is complete; invalid input is replaced with
+/// U+FFFD. With [`LineEnding::Lf`], a trailing `\r` is held back until the next write shows
+/// whether it starts a `\r\n`.
+#[derive(Debug, Default)]
+pub(crate) struct Transcoder {
+    encoding: Encoding,
+    line_ending: LineEnding,
+    last_was_cr: bool,
+    pending: Vec<u8>,
+}
+
+impl Transcoder {
+    pub(crate) fn set_encoding(&mut self, encoding: Encoding) {
+        self.encoding = encoding;
+    }
+
+    pub(crate) fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    pub(crate) fn set_line_ending(&mut self, line_ending: LineEnding) {
+        self.line_ending = line_ending.resolve();
+    }
+
+    /// Returns true if data is written as-is
+    pub(crate) fn is_passthrough(&self) -> bool {
+        self.encoding == Encoding::Utf8 && self.line_ending == LineEnding::Unchanged
+    }
+
+    /// Returns an upper bound of the temporary memory needed to process `len` bytes of input.
+    pub(crate) fn scratch_len(&self, len: usize) -> usize {
+        let mut factor = 0;
+        if self.line_ending != LineEnding::Unchanged {
+            factor += 2;
+        }
+        if self.encoding != Encoding::Utf8 {
+            factor = factor.max(1) * 2 + 1;
+        }
+        (len + self.pending.len()).saturating_mul(factor)
+    }
+
+    pub(crate) fn transcode<'a>(&mut self, buf: &'a [u8]) -> Cow<'a, [u8]> {
+        let normalized = self.normalize_line_endings(buf);
+        if self.encoding == Encoding::Utf8 {
+            return normalized;
+        }
+        let mut input = std::mem::take(&mut self.pending);
+        input.extend_from_slice(&normalized);
+        let mut out = Vec::with_capacity(input.len() * 2);
+        let mut rest = &input[..];
+        loop {
+            match std::str::from_utf8(rest) {
+                Ok(s) => {
+                    self.push_str(&mut out, s);
+                    break;
+                },
+                Err(e) => {
+                    let (valid, after) = rest.split_at(e.valid_up_to());
+                    // Safe to unwrap: `valid_up_to` guarantees this prefix is valid UTF-8.
+                    self.push_str(&mut out, std::str::from_utf8(valid).unwrap());
+                    match e.error_len() {
+                        Some(n) => {
+                            self.push_str(&mut out, "\u{FFFD}");
+                            rest = &after[n..];
+                        },
+                        None => {
+                            self.pending = after.to_vec();
+                            break;
+                        },
+                    }
+                },
+            }
+        }
+        Cow::Owned(out)
+    }
+
+    fn normalize_line_endings<'a>(&mut self, buf: &'a [u8]) -> Cow<'a, [u8]> {
+        match self.line_ending {
+            LineEnding::Unchanged | LineEnding::Native => Cow::Borrowed(buf),
+            LineEnding::CrLf => {
+                if !buf.contains(&b'\n') {
+                    self.last_was_cr = buf.last().map_or(self.last_was_cr, |b| *b == b'\r');
+                    return Cow::Borrowed(buf);
+                }
+                let mut out = Vec::with_capacity(buf.len() + buf.len() / 8);
+                for b in buf {
+                    if *b == b'\n' && !self.last_was_cr {
+                        out.push(b'\r');
+                    }
+                    out.push(*b);
+                    self.last_was_cr = *b == b'\r';
+                }
+                Cow::Owned(out)
+            },
+            LineEnding::Lf => {
+                if !self.last_was_cr && !buf.contains(&b'\r') {
+                    return Cow::Borrowed(buf);
+                }
+                let mut out = Vec::with_capacity(buf.len() + 1);
+                for b in buf {
+                    if self.last_was_cr && *b != b'\n' {
+                        out.push(b'\r');
+                    }
+                    self.last_was_cr = *b == b'\r';
+                    if !self.last_was_cr {
+                        out.push(*b);
+                    }
+                }
+                Cow::Owned(out)
+            },
+        }
+    }
+
+    fn push_str(&self, out: &mut Vec<u8>, s: &str) {
+        self.encoding.encode_into(out, s);
+    }
+}
+
+#[cfg(test)]
+mod t {
+    use super::*;
+
+    fn transcoder(encoding: Encoding, line_ending: LineEnding) -> Transcoder {
+        let mut t = Transcoder::default();
+        t.set_encoding(encoding);
+        t.set_line_ending(line_ending);
+        t
+    }
+
+    #[test]
+    fn test_utf16_split_characters() {
+        let mut t = transcoder(Encoding::Utf16Le, LineEnding::Unchanged);
+        let input = "a\u{e9}\n".as_bytes();
+        let mut out = t.transcode(&input[..2]).into_owned();
+        out.extend_from_slice(&t.transcode(&input[2..]));
+        assert_eq!(out, vec![b'a', 0, 0xe9, 0, b'\n', 0]);
+
+        let mut t = transcoder(Encoding::Utf16Be, LineEnding::Unchanged);
+        assert_eq!(t.transcode(b"\xffa").into_owned(), vec![0xff, 0xfd, 0, b'a']);
+    }
+
+    #[test]
+    fn test_line_endings() {
+        let mut t = transcoder(Encoding::Utf8, LineEnding::CrLf);
+        assert_eq!(&*t.transcode(b"a\nb\r"), b"a\r\nb\r");
+        assert_eq!(&*t.transcode(b"\nc\n"), b"\nc\r\n");
+
+        let mut t = transcoder(Encoding::Utf8, LineEnding::Lf);
+        assert_eq!(&*t.transcode(b"a\r\nb\r"), b"a\nb");
+        assert_eq!(&*t.transcode(b"\nc\rd"), b"\nc\rd");
+    }
+}
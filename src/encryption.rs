@@ -0,0 +1,184 @@
+//! A hook to encrypt rolled files.
+//!
+//! With the `age` feature, `AgeEncryptor` encrypts each file to the X25519 recipients of your
+//! choice in the [age](https://age-encryption.org) format, whose payload is sealed with
+//! ChaCha20-Poly1305; `age --decrypt -i key.txt` reads the files back. Other ciphers plug in by
+//! implementing [`Encryptor`] with a vetted library.
+//!
+//! With an [`Encryptor`], see [`crate::RollingFileAppender::with_encryption`], each file is
+//! replaced by `<file>.enc` right after it is rolled, and after compression if it is compressed
+//! right away. The `.enc` files keep their place in retention, which sorts them by name as before.
+
+use crate::{
+    compression,
+    filesystem::{self, FsReader},
+    Fs,
+};
+use std::{
+    fmt::Debug,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+};
+
+/// File name extension of encrypted log files
+pub const ENCRYPTED_EXTENSION: &str = "enc";
+
+/// Encrypts rolled files, see `AgeEncryptor` of the `age` feature for the built-in implementation.
+pub trait Encryptor: Debug + Send + Sync {
+    /// Writes the ciphertext of everything read from `plaintext` to `ciphertext`
+    fn encrypt(&self, plaintext: &mut dyn Read, ciphertext: &mut dyn Write) -> io::Result<()>;
+}
+
+/// Encrypts files to X25519 recipients in the age format. Only the holders of a recipient's
+/// identity can decrypt them, the appender itself can't read its rolled files back.
+#[cfg(feature = "age")]
+#[derive(Debug, Clone)]
+pub struct AgeEncryptor {
+    recipients: Vec<age::x25519::Recipient>,
+}
+
+#[cfg(feature = "age")]
+impl AgeEncryptor {
+    /// Encrypts to a single recipient, e.g. `"age1...".parse()?` or the public key of a
+    /// generated `age::x25519::Identity`
+    pub fn new(recipient: age::x25519::Recipient) -> AgeEncryptor {
+        AgeEncryptor {
+            recipients: vec![recipient],
+        }
+    }
+
+    /// Additionally encrypts to `recipient`, each of them can decrypt the files on its own
+    pub fn recipient(mut self, recipient: age::x25519::Recipient) -> AgeEncryptor {
+        self.recipients.push(recipient);
+        self
+    }
+}
+
+#[cfg(feature = "age")]
+impl Encryptor for AgeEncryptor {
+    fn encrypt(&self, plaintext: &mut dyn Read, ciphertext: &mut dyn Write) -> io::Result<()> {
+        let recipients = self.recipients.iter().map(|r| r as &dyn age::Recipient);
+        let encryptor = age::Encryptor::with_recipients(recipients).map_err(io::Error::other)?;
+        let mut writer = encryptor.wrap_output(ciphertext)?;
+        io::copy(plaintext, &mut writer)?;
+        // Writes the last chunk, without it the file is truncated and fails to decrypt
+        writer.finish()?;
+        Ok(())
+    }
+}
+
+/// Replaces the rolled file `rotated`, or its compressed version, by its encryption. The
+/// plaintext is only removed once the encrypted file is synced. Returns the encrypted file.
+pub(crate) fn encrypt_rolled_file(fs: &dyn Fs, encryptor: &dyn Encryptor, rotated: &Path) -> io::Result<PathBuf> {
+    let src = compression::rolled_version(fs, rotated).ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))?;
+    if src.extension().is_some_and(|ext| ext == ENCRYPTED_EXTENSION) {
+        return Ok(src);
+    }
+    let mut dst = src.clone().into_os_string();
+    dst.push(format!(".{}", ENCRYPTED_EXTENSION));
+    let dst = PathBuf::from(dst);
+    if filesystem::exists(fs, &dst) {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} exists already", dst.display()),
+        ));
+    }
+    let written = (|| {
+        let mut reader = BufReader::new(FsReader::new(fs, &src)?);
+        let mut writer = BufWriter::new(filesystem::create_file(fs, &dst)?);
+        encryptor.encrypt(&mut reader, &mut writer)?;
+        writer.flush()?;
+        writer.get_ref().sync_all()
+    })();
+    if let Err(e) = written {
+        let _ = fs.remove_file(&dst);
+        return Err(e);
+    }
+    fs.remove_file(&src)?;
+    internal_event!(path = %dst.display(), "encrypted rolled file");
+    Ok(dst)
+}
+
+#[cfg(test)]
+mod t {
+    use super::*;
+    use crate::*;
+
+    /// Not encryption, just reversible and visible in tests
+    #[derive(Debug)]
+    struct Xor;
+
+    impl Encryptor for Xor {
+        fn encrypt(&self, plaintext: &mut dyn Read, ciphertext: &mut dyn Write) -> io::Result<()> {
+            let mut data = vec![];
+            plaintext.read_to_end(&mut data)?;
+            data.iter_mut().for_each(|b| *b ^= 0x5a);
+            ciphertext.write_all(&data)
+        }
+    }
+
+    #[test]
+    fn test_encrypt_rolled_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let mut rfa = BasicRollingFileAppender::builder()
+            .folder(folder)
+            .prefix("log.log")
+            .condition(RollingConditionBasic::new().daily())
+            .max_files(2)
+            .encryption(Xor)
+            .build()
+            .unwrap();
+        rfa.rollover().unwrap();
+        for day in 1..=3 {
+            let now = Local.with_ymd_and_hms(2099, 3, day, 1, 0, 0).unwrap();
+            rfa.write_with_datetime(format!("day {}\n", day).as_bytes(), &now)
+                .unwrap();
+        }
+        rfa.flush().unwrap();
+
+        let encrypted = dir.path().join("log.log.20990302.010000.enc");
+        let data = std::fs::read(&encrypted).unwrap();
+        assert_eq!(data.iter().map(|b| b ^ 0x5a).collect::<Vec<_>>(), b"day 2\n");
+        assert!(!dir.path().join("log.log.20990302.010000").exists());
+        // Retention treats encrypted files like the others
+        assert_eq!(rfa.list_rotated_files().unwrap(), [encrypted]);
+    }
+
+    #[cfg(feature = "age")]
+    #[test]
+    fn test_age_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let identity = age::x25519::Identity::generate();
+        let mut rfa = BasicRollingFileAppender::builder()
+            .folder(folder)
+            .prefix("log.log")
+            .condition(RollingConditionBasic::new().daily())
+            .max_files(3)
+            .encryption(AgeEncryptor::new(identity.to_public()))
+            .build()
+            .unwrap();
+        rfa.rollover().unwrap();
+        let day1 = Local.with_ymd_and_hms(2099, 3, 1, 1, 0, 0).unwrap();
+        let day2 = Local.with_ymd_and_hms(2099, 3, 2, 1, 0, 0).unwrap();
+        rfa.write_with_datetime(b"secret audit line\n", &day1).unwrap();
+        rfa.write_with_datetime(b"next day\n", &day2).unwrap();
+        rfa.flush().unwrap();
+
+        let encrypted = std::fs::read(dir.path().join("log.log.20990301.010000.enc")).unwrap();
+        assert!(!encrypted.windows(6).any(|w| w == b"secret"));
+        let decryptor = age::Decryptor::new(&encrypted[..]).unwrap();
+        let mut reader = decryptor
+            .decrypt(std::iter::once(&identity as &dyn age::Identity))
+            .unwrap();
+        let mut plaintext = String::new();
+        reader.read_to_string(&mut plaintext).unwrap();
+        assert_eq!(plaintext, "secret audit line\n");
+
+        // Another identity can't read it
+        let other = age::x25519::Identity::generate();
+        let decryptor = age::Decryptor::new(&encrypted[..]).unwrap();
+        assert!(decryptor.decrypt(std::iter::once(&other as &dyn age::Identity)).is_err());
+    }
+}
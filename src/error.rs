@@ -0,0 +1,124 @@
+//! Errors telling apart what went wrong.
+//!
+//! The appender keeps returning [`io::Error`] from [`std::io::Write`] and its other methods, with
+//! a [`RollingFileError`] inside when it knows which step failed. Converting the `io::Error` back
+//! with `RollingFileError::from` recovers it:
+//!
+//! ```rust
+//! # use local_rolling_file::*;
+//! let e = BasicRollingFileAppender::new("/dev/null/log", "log.log", RollingConditionBasic::new(), 3).unwrap_err();
+//! assert!(matches!(RollingFileError::from(e), RollingFileError::OpenFailed { .. }));
+//! ```
+
+use std::{error::Error, fmt, io, path::PathBuf};
+
+/// An error of the appender
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum RollingFileError {
+    /// The log folder couldn't be created or a log file couldn't be opened
+    OpenFailed { path: PathBuf, source: io::Error },
+    /// The current file couldn't be completed for a rollover
+    RotateFailed(io::Error),
+    /// The link to the latest file couldn't be updated
+    SymlinkFailed { link: PathBuf, source: io::Error },
+    /// Rolled files couldn't be listed or deleted
+    RetentionFailed(io::Error),
+    /// The configuration is invalid, e.g. a setting is missing
+    InvalidConfig(String),
+    /// Any other I/O error
+    Io(io::Error),
+}
+
+impl RollingFileError {
+    /// Returns the kind of the underlying I/O error, `InvalidInput` for configuration errors
+    pub fn kind(&self) -> io::ErrorKind {
+        match self {
+            RollingFileError::OpenFailed { source, .. } | RollingFileError::SymlinkFailed { source, .. } => {
+                source.kind()
+            },
+            RollingFileError::RotateFailed(e) | RollingFileError::RetentionFailed(e) | RollingFileError::Io(e) => {
+                e.kind()
+            },
+            RollingFileError::InvalidConfig(_) => io::ErrorKind::InvalidInput,
+        }
+    }
+}
+
+impl fmt::Display for RollingFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RollingFileError::OpenFailed { path, source } => {
+                write!(f, "failed to open {}: {}", path.display(), source)
+            },
+            RollingFileError::RotateFailed(e) => write!(f, "failed to rotate: {}", e),
+            RollingFileError::SymlinkFailed { link, source } => {
+                write!(f, "failed to update link {}: {}", link.display(), source)
+            },
+            RollingFileError::RetentionFailed(e) => write!(f, "failed to remove old files: {}", e),
+            RollingFileError::InvalidConfig(what) => write!(f, "invalid configuration: {}", what),
+            RollingFileError::Io(e) => e.fmt(f),
+        }
+    }
+}
+
+impl Error for RollingFileError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            RollingFileError::OpenFailed { source, .. } | RollingFileError::SymlinkFailed { source, .. } => {
+                Some(source)
+            },
+            RollingFileError::RotateFailed(e) | RollingFileError::RetentionFailed(e) | RollingFileError::Io(e) => {
+                Some(e)
+            },
+            RollingFileError::InvalidConfig(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for RollingFileError {
+    /// Recovers the [`RollingFileError`] inside `e`, or wraps it as [`RollingFileError::Io`]
+    fn from(e: io::Error) -> RollingFileError {
+        if !e.get_ref().is_some_and(|inner| inner.is::<RollingFileError>()) {
+            return RollingFileError::Io(e);
+        }
+        let kind = e.kind();
+        match e.into_inner().map(|inner| inner.downcast::<RollingFileError>()) {
+            Some(Ok(error)) => *error,
+            Some(Err(inner)) => RollingFileError::Io(io::Error::new(kind, inner)),
+            None => RollingFileError::Io(kind.into()),
+        }
+    }
+}
+
+impl From<RollingFileError> for io::Error {
+    /// Wraps `e` keeping the kind of the underlying error
+    fn from(e: RollingFileError) -> io::Error {
+        match e {
+            RollingFileError::Io(e) => e,
+            e => io::Error::new(e.kind(), e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod t {
+    use crate::*;
+
+    #[test]
+    fn test_error_kinds() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let missing = BasicRollingFileAppender::builder().folder(folder).build().unwrap_err();
+        assert!(matches!(missing, RollingFileError::InvalidConfig(_)));
+
+        // A file where the folder should be
+        let file = dir.path().join("file");
+        std::fs::write(&file, "").unwrap();
+        let e = BasicRollingFileAppender::new(file.to_str().unwrap(), "log.log", RollingConditionBasic::new(), 3)
+            .unwrap_err();
+        let e = RollingFileError::from(e);
+        assert!(matches!(&e, RollingFileError::OpenFailed { path, .. } if path == &file));
+        assert_eq!(io::Error::from(e).kind(), std::io::ErrorKind::AlreadyExists);
+    }
+}
@@ -0,0 +1,111 @@
+//! Writing to a secondary folder while the log folder is unusable.
+//!
+//! When a write or the opening of a file fails because the disk of the log folder is full or
+//! failing, an appender given a failover folder, e.g. `/tmp/log`, closes the file and continues in
+//! a new file of that folder instead of losing the data. Once the retry interval has elapsed, the
+//! next write rolls over back to the log folder, failing over again if it is still unusable.
+//! Files written to the failover folder stay there, retention doesn't apply to them.
+
+use chrono::prelude::*;
+use std::{error::Error, io};
+
+/// The failover folder of an appender and since when it is used
+#[derive(Clone, Debug)]
+pub(crate) struct Failover {
+    pub(crate) folder: String,
+    retry_interval: chrono::Duration,
+    since_opt: Option<DateTime<Local>>,
+}
+
+impl Failover {
+    pub(crate) fn new(folder: &str, retry_interval: chrono::Duration) -> Failover {
+        Failover {
+            folder: folder.to_string(),
+            retry_interval,
+            since_opt: None,
+        }
+    }
+
+    /// Tells whether files are written to the failover folder
+    pub(crate) fn is_active(&self) -> bool {
+        self.since_opt.is_some()
+    }
+
+    /// Switches to the failover folder if `e` means the disk of the log folder is unusable.
+    /// Returns whether it did.
+    pub(crate) fn activate(&mut self, e: &io::Error, now: &DateTime<Local>) -> bool {
+        if self.is_active() || !is_disk_failure(e) {
+            return false;
+        }
+        eprintln!("WARNING: Failing over to {} after {}", self.folder, e);
+        self.since_opt = Some(*now);
+        true
+    }
+
+    /// Tells whether the retry interval has elapsed since the failover
+    pub(crate) fn is_retry_due(&self, now: &DateTime<Local>) -> bool {
+        self.since_opt.is_some_and(|since| *now - since >= self.retry_interval)
+    }
+
+    /// Switches back to the log folder
+    pub(crate) fn deactivate(&mut self) {
+        self.since_opt = None;
+    }
+}
+
+/// Tells whether `e`, or an error it was caused by, is a full disk or an I/O error of the device
+fn is_disk_failure(e: &io::Error) -> bool {
+    let mut source_opt: Option<&(dyn Error + 'static)> = Some(e);
+    while let Some(source) = source_opt {
+        if let Some(e) = source.downcast_ref::<io::Error>() {
+            #[cfg(unix)]
+            if e.raw_os_error() == Some(libc::EIO) {
+                return true;
+            }
+            if e.kind() == io::ErrorKind::StorageFull {
+                return true;
+            }
+        }
+        source_opt = source.source();
+    }
+    false
+}
+
+#[cfg(test)]
+mod t {
+    use crate::*;
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_failover_folder() {
+        let dir = tempfile::tempdir().unwrap();
+        let (primary, secondary) = (dir.path().join("primary"), dir.path().join("secondary"));
+        let mut rfa = BasicRollingFileAppender::builder()
+            .folder(primary.to_str().unwrap())
+            .prefix("log.log")
+            .condition(RollingConditionBasic::new().daily())
+            .failover_folder(secondary.to_str().unwrap(), chrono::Duration::hours(1))
+            .build()
+            .unwrap();
+        rfa.rollover().unwrap();
+        // Writes to the file of the first hour fail as if the disk were full
+        std::os::unix::fs::symlink("/dev/full", primary.join("log.log.20990301.010000")).unwrap();
+        let now = Local.with_ymd_and_hms(2099, 3, 1, 1, 0, 0).unwrap();
+        // Until the buffer is full, nothing reaches the disk
+        let mut written = String::new();
+        for i in 0..1000 {
+            let line = format!("line {}\n", i);
+            rfa.write_with_datetime(line.as_bytes(), &now).unwrap();
+            written.push_str(&line);
+        }
+        assert!(rfa.is_failed_over());
+
+        let later = Local.with_ymd_and_hms(2099, 3, 1, 2, 0, 0).unwrap();
+        rfa.write_with_datetime(b"back\n", &later).unwrap();
+        assert!(!rfa.is_failed_over());
+        rfa.flush().unwrap();
+        let read = |path: std::path::PathBuf| std::fs::read_to_string(path).unwrap();
+        assert_eq!(read(secondary.join("log.log.20990301.010000")), written);
+        assert_eq!(read(primary.join("log.log.20990301.020000")), "back\n");
+    }
+}
@@ -0,0 +1,180 @@
+//! A C API to the appender, so C and C++ components produce the same file layout.
+//!
+//! `cargo build --release --features ffi` builds a shared library C can link with, declared by
+//! `include/local_rolling_file.h`. Functions returning `int` return 0 on success and -1 on
+//! failure, also when the Rust code panics, which never unwinds into C. A handle may be used from
+//! any thread, but not from several threads at once.
+
+use crate::{BasicRollingFileAppender, RollingConditionBasic, RollingFrequency};
+use std::{
+    ffi::{c_char, c_int, CStr},
+    io::Write,
+    panic::{catch_unwind, AssertUnwindSafe},
+};
+
+/// The appender behind a C handle
+pub struct RollingFile {
+    appender: BasicRollingFileAppender,
+}
+
+fn report_error(action: &str, e: std::io::Error) -> c_int {
+    eprintln!("WARNING: Failed to {} logfile {}", action, e);
+    -1
+}
+
+/// Runs the body of a C function, returning `on_panic` instead of unwinding into C. A handle
+/// whose appender panicked may be left inconsistent, but is still safe to write to and close.
+fn guard<T>(on_panic: T, f: impl FnOnce() -> T) -> T {
+    catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|_| {
+        eprintln!("WARNING: Caught a panic in the C API of the logfile");
+        on_panic
+    })
+}
+
+/// Creates an appender, returning null on failure. `frequency` is 0 for none, 1 for daily, 2 for
+/// hourly and 3 for every minute; a `max_size` of 0 is unlimited.
+///
+/// # Safety
+///
+/// `folder` and `prefix` must be valid NUL terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn rolling_file_new(
+    folder: *const c_char,
+    prefix: *const c_char,
+    frequency: c_int,
+    max_size: u64,
+    max_files: usize,
+) -> *mut RollingFile {
+    guard(std::ptr::null_mut(), || {
+        if folder.is_null() || prefix.is_null() {
+            return std::ptr::null_mut();
+        }
+        // SAFETY: the caller passes valid NUL terminated strings
+        let (folder, prefix) = unsafe { (CStr::from_ptr(folder), CStr::from_ptr(prefix)) };
+        let (Ok(folder), Ok(prefix)) = (folder.to_str(), prefix.to_str()) else {
+            return std::ptr::null_mut();
+        };
+        let mut condition = RollingConditionBasic::new();
+        condition = match frequency {
+            0 => condition,
+            1 => condition.frequency(RollingFrequency::EveryDay),
+            2 => condition.frequency(RollingFrequency::EveryHour),
+            3 => condition.frequency(RollingFrequency::EveryMinute),
+            _ => return std::ptr::null_mut(),
+        };
+        if max_size > 0 {
+            condition = condition.max_size(max_size);
+        }
+        match BasicRollingFileAppender::new(folder, prefix, condition, max_files) {
+            Ok(appender) => Box::into_raw(Box::new(RollingFile { appender })),
+            Err(e) => {
+                report_error("open", e);
+                std::ptr::null_mut()
+            },
+        }
+    })
+}
+
+/// Writes `len` bytes.
+///
+/// # Safety
+///
+/// `file` must come from [`rolling_file_new`] and not be closed, and `data` must point to `len`
+/// readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn rolling_file_write(file: *mut RollingFile, data: *const u8, len: usize) -> c_int {
+    guard(-1, || {
+        if file.is_null() || (data.is_null() && len > 0) {
+            return -1;
+        }
+        if len == 0 {
+            return 0;
+        }
+        // SAFETY: the caller passes a live handle and `len` readable bytes
+        let (file, data) = unsafe { (&mut *file, std::slice::from_raw_parts(data, len)) };
+        match file.appender.write_all(data) {
+            Ok(()) => 0,
+            Err(e) => report_error("write", e),
+        }
+    })
+}
+
+/// Flushes buffered data to the file.
+///
+/// # Safety
+///
+/// `file` must come from [`rolling_file_new`] and not be closed.
+#[no_mangle]
+pub unsafe extern "C" fn rolling_file_flush(file: *mut RollingFile) -> c_int {
+    guard(-1, || {
+        if file.is_null() {
+            return -1;
+        }
+        // SAFETY: the caller passes a live handle
+        let file = unsafe { &mut *file };
+        match file.appender.flush() {
+            Ok(()) => 0,
+            Err(e) => report_error("flush", e),
+        }
+    })
+}
+
+/// Flushes and closes the appender. Passing null does nothing.
+///
+/// # Safety
+///
+/// `file` must come from [`rolling_file_new`] and not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn rolling_file_close(file: *mut RollingFile) {
+    guard((), || {
+        if file.is_null() {
+            return;
+        }
+        // SAFETY: the caller passes a live handle and gives up its ownership
+        let mut file = unsafe { Box::from_raw(file) };
+        if let Err(e) = file.appender.flush() {
+            report_error("flush", e);
+        }
+    })
+}
+
+#[cfg(test)]
+mod t {
+    use super::*;
+
+    #[test]
+    fn test_c_api() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = std::ffi::CString::new(dir.path().to_str().unwrap()).unwrap();
+        let prefix = c"log.log";
+        unsafe {
+            let file = rolling_file_new(folder.as_ptr(), prefix.as_ptr(), 1, 0, 3);
+            assert!(!file.is_null());
+            let line = b"from c\n";
+            assert_eq!(rolling_file_write(file, line.as_ptr(), line.len()), 0);
+            assert_eq!(rolling_file_flush(file), 0);
+            rolling_file_close(file);
+            assert!(rolling_file_new(folder.as_ptr(), prefix.as_ptr(), 9, 0, 3).is_null());
+        }
+        assert_eq!(std::fs::read_to_string(dir.path().join("log.log")).unwrap(), "from c\n");
+    }
+
+    #[test]
+    fn test_panics_stay_in_rust() {
+        assert_eq!(guard(-1, || panic!("bug in the appender")), -1);
+        assert!(guard(std::ptr::null_mut::<RollingFile>(), || panic!("bug in the appender")).is_null());
+    }
+
+    #[test]
+    fn test_header_declares_api() {
+        let header = include_str!("../include/local_rolling_file.h");
+        for function in [
+            "rolling_file_new(",
+            "rolling_file_write(",
+            "rolling_file_flush(",
+            "rolling_file_close(",
+        ] {
+            assert!(header.contains(function), "{} is missing from the header", function);
+        }
+    }
+}
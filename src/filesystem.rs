@@ -0,0 +1,884 @@
+//! The file system operations used by the appender.
+//!
+//! The appender opens, lists and removes log files, maintains the symlink and runs its maintenance,
+//! e.g. compression, retention and the manifest, through the [`Fs`] trait. [`RealFs`] forwards to `std::fs`
+//! and is used unless another implementation is given to [`crate::RollingFileAppender::new_with_fs`], e.g. an
+//! in-memory file system for hermetic tests.
+
+use std::{
+    fmt::Debug,
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+    thread,
+    time::Duration,
+};
+use symlink::{remove_symlink_auto, symlink_auto, symlink_file};
+
+/// A log file opened for appending
+pub trait LogFile: Write + Send + Sync + Debug {
+    /// Asks the storage to persist all written data
+    fn sync_all(&self) -> io::Result<()>;
+}
+
+impl LogFile for File {
+    fn sync_all(&self) -> io::Result<()> {
+        File::sync_all(self)
+    }
+}
+
+/// The file system operations performed by the appender on its folder
+pub trait Fs: Send + Sync + Debug {
+    /// Opens a file for appending, creating it if needed
+    fn open_append(&self, path: &Path) -> io::Result<Box<dyn LogFile>>;
+
+    /// Returns the size of a file
+    fn file_len(&self, path: &Path) -> io::Result<u64>;
+
+    /// Creates a directory and all of its missing parents
+    fn create_dir_all(&self, path: &Path) -> io::Result<()>;
+
+    /// Returns the names of the entries of a directory
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<String>>;
+
+    /// Renames a file, replacing the destination if it exists
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()>;
+
+    /// Renames a file, failing with [`io::ErrorKind::AlreadyExists`] if the destination exists
+    fn rename_no_replace(&self, from: &Path, to: &Path) -> io::Result<()> {
+        if self.file_len(to).is_ok() {
+            return Err(io::ErrorKind::AlreadyExists.into());
+        }
+        self.rename(from, to)
+    }
+
+    /// Copies a file, replacing the destination if it exists, and returns the number of bytes copied
+    fn copy(&self, from: &Path, to: &Path) -> io::Result<u64>;
+
+    /// Fills `buf` with the contents of a file starting at `offset`
+    fn read_exact_at(&self, path: &Path, offset: u64, buf: &mut [u8]) -> io::Result<()>;
+
+    /// Truncates or extends a file to `len` bytes
+    fn set_len(&self, path: &Path, len: u64) -> io::Result<()>;
+
+    /// Removes a file
+    fn remove_file(&self, path: &Path) -> io::Result<()>;
+
+    /// Removes an empty directory
+    fn remove_dir(&self, _path: &Path) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "removing directories is not supported",
+        ))
+    }
+
+    /// Reserves `len` bytes of disk space for a file without changing its size
+    fn preallocate(&self, _path: &Path, _len: u64) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "preallocation is not supported",
+        ))
+    }
+
+    /// Returns the disk space available to this process on the file system of `path`
+    fn available_space(&self, _path: &Path) -> io::Result<u64> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "querying the available space is not supported",
+        ))
+    }
+
+    /// Makes `link` a symlink to `target`, replacing any previous link
+    fn symlink(&self, target: &Path, link: &Path) -> io::Result<()>;
+
+    /// Makes `link` a hard link to `target`, replacing any previous link
+    fn hard_link(&self, _target: &Path, _link: &Path) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "hard links are not supported",
+        ))
+    }
+}
+
+/// How the name pointing at the latest log file is maintained
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum LatestLink {
+    /// No link is maintained
+    Disabled,
+    /// A symlink
+    #[default]
+    Symlink,
+    /// A hard link, for file systems without symlinks. It keeps pointing at a file's data after
+    /// retention deletes the file, until the next rollover.
+    HardLink,
+    /// A symlink, or a hard link where symlinks can't be created, e.g. on some network shares
+    SymlinkOrHardLink,
+    /// A text file named after the link with a `.latest` extension, e.g. `log.log.latest`,
+    /// containing the name of the latest file
+    PointerFile,
+    /// A symlink, or a pointer file where symlinks can't be created, e.g. on Windows without
+    /// Developer Mode
+    SymlinkOrPointerFile,
+}
+
+/// How the appender opens the file of a new period
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum OpenMode {
+    /// Append to the file if it exists already
+    #[default]
+    Append,
+    /// Empty the file if it exists already, e.g. when names repeat every week
+    Truncate,
+    /// Append, and reserve the given number of bytes of disk space for a new file to reduce
+    /// fragmentation. The file keeps its size, so readers don't see the reserved space. Only
+    /// supported on Linux.
+    Preallocate(u64),
+}
+
+/// File name extension of the pointer file, see [`LatestLink::PointerFile`]
+pub(crate) const POINTER_EXTENSION: &str = "latest";
+
+impl LatestLink {
+    /// Points `link` at `target`, returning an error if no link could be made.
+    pub(crate) fn update(&self, fs: &dyn Fs, target: &Path, link: &Path) -> io::Result<()> {
+        match self {
+            LatestLink::Disabled => Ok(()),
+            LatestLink::Symlink => fs.symlink(target, link),
+            LatestLink::HardLink => fs.hard_link(target, link),
+            LatestLink::SymlinkOrHardLink => fs.symlink(target, link).or_else(|_| fs.hard_link(target, link)),
+            LatestLink::PointerFile => write_pointer_file(fs, target, link),
+            LatestLink::SymlinkOrPointerFile => fs
+                .symlink(target, link)
+                .or_else(|_| write_pointer_file(fs, target, link)),
+        }
+    }
+}
+
+/// Replaces the pointer file of `link` with one containing the file name of `target`
+fn write_pointer_file(fs: &dyn Fs, target: &Path, link: &Path) -> io::Result<()> {
+    let (Some(folder), Some(link_name), Some(target_name)) = (link.parent(), link.file_name(), target.file_name())
+    else {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid link path"));
+    };
+    let link_name = link_name.to_string_lossy();
+    let pointer = folder.join(format!("{}.{}", link_name, POINTER_EXTENSION));
+    // Written aside and renamed, so readers never see a partial name
+    let tmp = folder.join(format!(".{}.{}.tmp", link_name, POINTER_EXTENSION));
+    let _ = fs.remove_file(&tmp);
+    let mut file = fs.open_append(&tmp)?;
+    file.write_all(format!("{}\n", target_name.to_string_lossy()).as_bytes())?;
+    file.flush()?;
+    drop(file);
+    fs.rename(&tmp, &pointer)
+}
+
+/// How many times an operation failing with a sharing violation is retried
+const SHARING_VIOLATION_RETRIES: u32 = 5;
+
+/// Returns true for the errors Windows reports while another process, typically an antivirus or
+/// indexer, briefly holds the file open without sharing it.
+#[cfg(windows)]
+fn is_sharing_violation(e: &io::Error) -> bool {
+    const ERROR_ACCESS_DENIED: i32 = 5;
+    const ERROR_SHARING_VIOLATION: i32 = 32;
+    const ERROR_LOCK_VIOLATION: i32 = 33;
+    matches!(
+        e.raw_os_error(),
+        Some(ERROR_ACCESS_DENIED | ERROR_SHARING_VIOLATION | ERROR_LOCK_VIOLATION)
+    )
+}
+
+#[cfg(not(windows))]
+fn is_sharing_violation(_e: &io::Error) -> bool {
+    false
+}
+
+/// Runs `op`, retrying with a growing delay as long as it fails with a sharing violation.
+fn retry_on_sharing_violation<T>(mut op: impl FnMut() -> io::Result<T>) -> io::Result<T> {
+    let mut delay = Duration::from_millis(10);
+    for _ in 0..SHARING_VIOLATION_RETRIES {
+        match op() {
+            Err(e) if is_sharing_violation(&e) => {
+                thread::sleep(delay);
+                delay *= 2;
+            },
+            result => return result,
+        }
+    }
+    op()
+}
+
+/// Reads a file through [`Fs::read_exact_at`], borrowing the file system or owning a handle to it
+pub(crate) struct FsReader<F> {
+    fs: F,
+    path: PathBuf,
+    offset: u64,
+    len: u64,
+}
+
+impl<'a, F> FsReader<F>
+where
+    F: std::ops::Deref<Target = dyn Fs + 'a>,
+{
+    pub(crate) fn new(fs: F, path: &Path) -> io::Result<FsReader<F>> {
+        let len = fs.file_len(path)?;
+        Ok(FsReader {
+            fs,
+            path: path.to_path_buf(),
+            offset: 0,
+            len,
+        })
+    }
+
+    /// Continues reading at `offset`, or at the end of the file if it is shorter. Returns the
+    /// offset reading continues at.
+    pub(crate) fn skip_to(&mut self, offset: u64) -> u64 {
+        self.offset = offset.min(self.len);
+        self.offset
+    }
+}
+
+impl<'a, F> Read for FsReader<F>
+where
+    F: std::ops::Deref<Target = dyn Fs + 'a>,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let left = usize::try_from(self.len - self.offset).unwrap_or(usize::MAX);
+        let n = left.min(buf.len());
+        if n > 0 {
+            self.fs.read_exact_at(&self.path, self.offset, &mut buf[..n])?;
+            self.offset += n as u64;
+        }
+        Ok(n)
+    }
+}
+
+/// Reads a whole file through [`Fs::read_exact_at`]
+pub(crate) fn read_file(fs: &dyn Fs, path: &Path) -> io::Result<Vec<u8>> {
+    let mut data = vec![];
+    FsReader::new(fs, path)?.read_to_end(&mut data)?;
+    Ok(data)
+}
+
+/// Creates `path`, or truncates it if it exists, for writing through `fs`
+pub(crate) fn create_file(fs: &dyn Fs, path: &Path) -> io::Result<Box<dyn LogFile>> {
+    let f = fs.open_append(path)?;
+    fs.set_len(path, 0)?;
+    Ok(f)
+}
+
+/// Tells whether `path` exists according to `fs`
+pub(crate) fn exists(fs: &dyn Fs, path: &Path) -> bool {
+    fs.file_len(path).is_ok()
+}
+
+/// Moves a file, falling back to copying it when renaming fails, e.g. across file systems.
+pub(crate) fn move_file(fs: &dyn Fs, from: &Path, to: &Path) -> io::Result<()> {
+    if fs.rename(from, to).is_ok() {
+        return Ok(());
+    }
+    fs.copy(from, to)?;
+    fs.remove_file(from)
+}
+
+/// Moves a file like [`move_file`] without replacing an existing file: it takes the first free
+/// name among `to`, `to.1`, `to.2`... Returns the path the file was moved to.
+pub(crate) fn move_file_no_replace(fs: &dyn Fs, from: &Path, to: &Path) -> io::Result<PathBuf> {
+    let mut target = to.to_path_buf();
+    for n in 1.. {
+        match fs.rename_no_replace(from, &target) {
+            Ok(()) => return Ok(target),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {},
+            // Renaming fails across file systems
+            Err(_) if fs.file_len(&target).is_err() => {
+                fs.copy(from, &target)?;
+                fs.remove_file(from)?;
+                return Ok(target);
+            },
+            Err(_) => {},
+        }
+        let mut name = to.as_os_str().to_owned();
+        name.push(format!(".{}", n));
+        target = PathBuf::from(name);
+    }
+    unreachable!("every name is taken")
+}
+
+/// Returns `path` with its folder canonicalized.
+fn canonical_folder_path(path: &Path) -> io::Result<std::path::PathBuf> {
+    let folder = path
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let name = path
+        .file_name()
+        .ok_or_else(|| io::Error::other("path has no file name"))?;
+    Ok(folder.canonicalize()?.join(name))
+}
+
+/// The operating system's file system. Opening, renaming and removing files is retried for a
+/// short while on sharing violations, which are common on Windows servers.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct RealFs;
+
+impl Fs for RealFs {
+    fn open_append(&self, path: &Path) -> io::Result<Box<dyn LogFile>> {
+        let f = retry_on_sharing_violation(|| OpenOptions::new().append(true).create(true).open(path))?;
+        Ok(Box::new(f))
+    }
+
+    fn file_len(&self, path: &Path) -> io::Result<u64> {
+        fs::metadata(path).map(|m| m.len())
+    }
+
+    fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+        fs::create_dir_all(path)
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<String>> {
+        Ok(fs::read_dir(path)?
+            .flatten()
+            .map(|f| f.file_name().to_string_lossy().to_string())
+            .collect())
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        retry_on_sharing_violation(|| fs::rename(from, to))
+    }
+
+    fn rename_no_replace(&self, from: &Path, to: &Path) -> io::Result<()> {
+        // Linking fails atomically if the destination exists
+        match retry_on_sharing_violation(|| fs::hard_link(from, to)) {
+            Ok(()) => self.remove_file(from),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => Err(e),
+            // Without hard links, e.g. on FAT, only checking first is left
+            Err(_) if fs::symlink_metadata(to).is_ok() => Err(io::ErrorKind::AlreadyExists.into()),
+            Err(_) => self.rename(from, to),
+        }
+    }
+
+    fn copy(&self, from: &Path, to: &Path) -> io::Result<u64> {
+        retry_on_sharing_violation(|| fs::copy(from, to))
+    }
+
+    fn read_exact_at(&self, path: &Path, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+        let mut f = retry_on_sharing_violation(|| File::open(path))?;
+        f.seek(SeekFrom::Start(offset))?;
+        f.read_exact(buf)
+    }
+
+    fn set_len(&self, path: &Path, len: u64) -> io::Result<()> {
+        retry_on_sharing_violation(|| OpenOptions::new().write(true).open(path))?.set_len(len)
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        retry_on_sharing_violation(|| fs::remove_file(path))
+    }
+
+    fn remove_dir(&self, path: &Path) -> io::Result<()> {
+        fs::remove_dir(path)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn preallocate(&self, path: &Path, len: u64) -> io::Result<()> {
+        use std::os::unix::io::AsRawFd;
+        let f = retry_on_sharing_violation(|| OpenOptions::new().write(true).open(path))?;
+        let len = libc::off_t::try_from(len).map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?;
+        // SAFETY: the descriptor stays open while `f` lives
+        if unsafe { libc::fallocate(f.as_raw_fd(), libc::FALLOC_FL_KEEP_SIZE, 0, len) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    fn available_space(&self, path: &Path) -> io::Result<u64> {
+        use std::os::unix::ffi::OsStrExt;
+        let path = std::ffi::CString::new(path.as_os_str().as_bytes())
+            .map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?;
+        let mut stat = std::mem::MaybeUninit::<libc::statvfs>::uninit();
+        // SAFETY: `path` is a valid C string and `stat` is only read once filled in
+        if unsafe { libc::statvfs(path.as_ptr(), stat.as_mut_ptr()) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let stat = unsafe { stat.assume_init() };
+        // The field types differ between platforms
+        #[allow(clippy::useless_conversion)]
+        Ok(u64::from(stat.f_bavail).saturating_mul(u64::from(stat.f_frsize)))
+    }
+
+    #[cfg(windows)]
+    fn available_space(&self, path: &Path) -> io::Result<u64> {
+        use std::os::windows::ffi::OsStrExt;
+        #[link(name = "kernel32")]
+        extern "system" {
+            fn GetDiskFreeSpaceExW(dir: *const u16, available: *mut u64, total: *mut u64, free: *mut u64) -> i32;
+        }
+        let dir = path.as_os_str().encode_wide().chain(Some(0)).collect::<Vec<_>>();
+        let mut available = 0;
+        // SAFETY: `dir` is NUL terminated, the totals are optional
+        if unsafe { GetDiskFreeSpaceExW(dir.as_ptr(), &mut available, std::ptr::null_mut(), std::ptr::null_mut()) } == 0
+        {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(available)
+    }
+
+    fn hard_link(&self, target: &Path, link: &Path) -> io::Result<()> {
+        match retry_on_sharing_violation(|| fs::remove_file(link)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+            _ => {},
+        }
+        fs::hard_link(target, link)
+    }
+
+    fn symlink(&self, target: &Path, link: &Path) -> io::Result<()> {
+        if let (Some(name), true) = (target.file_name(), target.parent() == link.parent()) {
+            // A link next to its target points at it by name, which needs no path resolution
+            let _ = retry_on_sharing_violation(|| remove_symlink_auto(link));
+            return retry_on_sharing_violation(|| symlink_file(name, link));
+        }
+        // Only the folders are canonicalized, the target may not have been created yet
+        let target = canonical_folder_path(target)?;
+        let link = canonical_folder_path(link)?;
+        let _ = retry_on_sharing_violation(|| remove_symlink_auto(&link));
+        retry_on_sharing_violation(|| symlink_auto(&target, &link))
+    }
+}
+
+/// The operating system's file system, opening the log files with customized [`OpenOptions`].
+/// The customization runs after append and create are set, for platform specific needs such as
+/// `custom_flags` on Unix or `share_mode` on Windows. Pass it to
+/// [`crate::RollingFileAppender::new_with_fs`].
+#[derive(Clone)]
+pub struct OpenOptionsFs {
+    customize: Arc<dyn Fn(&mut OpenOptions) + Send + Sync>,
+}
+
+impl OpenOptionsFs {
+    /// Calls `customize` on the options of every log file before it is opened
+    pub fn new(customize: impl Fn(&mut OpenOptions) + Send + Sync + 'static) -> OpenOptionsFs {
+        OpenOptionsFs {
+            customize: Arc::new(customize),
+        }
+    }
+}
+
+impl Debug for OpenOptionsFs {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OpenOptionsFs").finish_non_exhaustive()
+    }
+}
+
+impl Fs for OpenOptionsFs {
+    fn open_append(&self, path: &Path) -> io::Result<Box<dyn LogFile>> {
+        let mut options = OpenOptions::new();
+        options.append(true).create(true);
+        (self.customize)(&mut options);
+        let f = retry_on_sharing_violation(|| options.open(path))?;
+        Ok(Box::new(f))
+    }
+
+    fn file_len(&self, path: &Path) -> io::Result<u64> {
+        RealFs.file_len(path)
+    }
+
+    fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+        RealFs.create_dir_all(path)
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<String>> {
+        RealFs.read_dir(path)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        RealFs.rename(from, to)
+    }
+
+    fn rename_no_replace(&self, from: &Path, to: &Path) -> io::Result<()> {
+        RealFs.rename_no_replace(from, to)
+    }
+
+    fn copy(&self, from: &Path, to: &Path) -> io::Result<u64> {
+        RealFs.copy(from, to)
+    }
+
+    fn read_exact_at(&self, path: &Path, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+        RealFs.read_exact_at(path, offset, buf)
+    }
+
+    fn set_len(&self, path: &Path, len: u64) -> io::Result<()> {
+        RealFs.set_len(path, len)
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        RealFs.remove_file(path)
+    }
+
+    fn remove_dir(&self, path: &Path) -> io::Result<()> {
+        RealFs.remove_dir(path)
+    }
+
+    fn preallocate(&self, path: &Path, len: u64) -> io::Result<()> {
+        RealFs.preallocate(path, len)
+    }
+
+    fn available_space(&self, path: &Path) -> io::Result<u64> {
+        RealFs.available_space(path)
+    }
+
+    fn symlink(&self, target: &Path, link: &Path) -> io::Result<()> {
+        RealFs.symlink(target, link)
+    }
+
+    fn hard_link(&self, target: &Path, link: &Path) -> io::Result<()> {
+        RealFs.hard_link(target, link)
+    }
+}
+
+#[cfg(test)]
+mod t {
+    use super::*;
+    use crate::*;
+    use std::{
+        collections::BTreeMap,
+        path::PathBuf,
+        sync::{Arc, Mutex},
+    };
+
+    type Files = Arc<Mutex<BTreeMap<PathBuf, Vec<u8>>>>;
+
+    #[derive(Debug, Default)]
+    struct MemFs {
+        files: Files,
+        // Number of bytes the next write accepts before failing, once
+        torn_write: Arc<Mutex<Option<usize>>>,
+    }
+
+    #[derive(Debug)]
+    struct MemFile {
+        path: PathBuf,
+        files: Files,
+        torn_write: Arc<Mutex<Option<usize>>>,
+    }
+
+    impl Write for MemFile {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let mut torn_write = self.torn_write.lock().unwrap();
+            let buf = match torn_write.take() {
+                Some(0) => return Err(io::Error::other("torn write")),
+                Some(len) => {
+                    *torn_write = Some(0);
+                    &buf[..len.min(buf.len())]
+                },
+                None => buf,
+            };
+            let mut files = self.files.lock().unwrap();
+            files.entry(self.path.clone()).or_default().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl LogFile for MemFile {
+        fn sync_all(&self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Fs for MemFs {
+        fn open_append(&self, path: &Path) -> io::Result<Box<dyn LogFile>> {
+            self.files.lock().unwrap().entry(path.to_path_buf()).or_default();
+            Ok(Box::new(MemFile {
+                path: path.to_path_buf(),
+                files: self.files.clone(),
+                torn_write: self.torn_write.clone(),
+            }))
+        }
+
+        fn file_len(&self, path: &Path) -> io::Result<u64> {
+            let files = self.files.lock().unwrap();
+            files
+                .get(path)
+                .map(|f| f.len() as u64)
+                .ok_or(io::ErrorKind::NotFound.into())
+        }
+
+        fn create_dir_all(&self, _path: &Path) -> io::Result<()> {
+            Ok(())
+        }
+
+        fn read_dir(&self, path: &Path) -> io::Result<Vec<String>> {
+            let files = self.files.lock().unwrap();
+            Ok(files
+                .keys()
+                .filter(|p| p.parent() == Some(path))
+                .filter_map(|p| Some(p.file_name()?.to_string_lossy().to_string()))
+                .collect())
+        }
+
+        fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+            let mut files = self.files.lock().unwrap();
+            let data = files.remove(from).ok_or(io::Error::from(io::ErrorKind::NotFound))?;
+            files.insert(to.to_path_buf(), data);
+            Ok(())
+        }
+
+        fn copy(&self, from: &Path, to: &Path) -> io::Result<u64> {
+            let mut files = self.files.lock().unwrap();
+            let data = files.get(from).ok_or(io::Error::from(io::ErrorKind::NotFound))?.clone();
+            let len = data.len() as u64;
+            files.insert(to.to_path_buf(), data);
+            Ok(len)
+        }
+
+        fn read_exact_at(&self, path: &Path, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+            let files = self.files.lock().unwrap();
+            let data = files.get(path).ok_or(io::Error::from(io::ErrorKind::NotFound))?;
+            let offset = offset as usize;
+            let src = data
+                .get(offset..offset + buf.len())
+                .ok_or(io::Error::from(io::ErrorKind::UnexpectedEof))?;
+            buf.copy_from_slice(src);
+            Ok(())
+        }
+
+        fn set_len(&self, path: &Path, len: u64) -> io::Result<()> {
+            let mut files = self.files.lock().unwrap();
+            let data = files.get_mut(path).ok_or(io::Error::from(io::ErrorKind::NotFound))?;
+            data.resize(len as usize, 0);
+            Ok(())
+        }
+
+        fn remove_file(&self, path: &Path) -> io::Result<()> {
+            let mut files = self.files.lock().unwrap();
+            files.remove(path).map(|_| ()).ok_or(io::ErrorKind::NotFound.into())
+        }
+
+        fn symlink(&self, _target: &Path, _link: &Path) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_open_options_fs() {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let fs = OpenOptionsFs::new(|options| {
+            options.mode(0o600);
+        });
+        let condition = RollingConditionBasic::new().daily();
+        let mut rfa = BasicRollingFileAppender::new_with_fs(folder, "log.log", condition, 2, Arc::new(fs)).unwrap();
+        rfa.rollover().unwrap();
+        let now = Local.with_ymd_and_hms(2099, 3, 1, 1, 0, 0).unwrap();
+        rfa.write_with_datetime(b"line\n", &now).unwrap();
+        rfa.flush().unwrap();
+
+        let metadata = fs::metadata(dir.path().join("log.log.20990301.010000")).unwrap();
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+        assert_eq!(metadata.len(), 5);
+    }
+
+    #[test]
+    fn test_hard_link_latest() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let mut rfa = BasicRollingFileAppender::builder()
+            .folder(folder)
+            .prefix("log.log")
+            .condition(RollingConditionBasic::new().daily())
+            .max_files(2)
+            .latest_link(LatestLink::HardLink)
+            .link_name("log.log.latest")
+            .build()
+            .unwrap();
+        for day in 1..=3 {
+            let now = Local.with_ymd_and_hms(2099, 3, day, 1, 0, 0).unwrap();
+            rfa.write_with_datetime(format!("day {}\n", day).as_bytes(), &now)
+                .unwrap();
+        }
+        rfa.flush().unwrap();
+
+        let latest = dir.path().join("log.log.latest");
+        assert!(!fs::symlink_metadata(&latest).unwrap().file_type().is_symlink());
+        assert_eq!(fs::read_to_string(&latest).unwrap(), "day 3\n");
+        assert!(dir.path().join("log.log.20990302.010000").exists());
+    }
+
+    #[test]
+    fn test_pointer_file_latest() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let mut rfa = BasicRollingFileAppender::builder()
+            .folder(folder)
+            .prefix("log.log")
+            .condition(RollingConditionBasic::new().daily())
+            .max_files(2)
+            .latest_link(LatestLink::PointerFile)
+            .build()
+            .unwrap();
+        for day in 1..=3 {
+            let now = Local.with_ymd_and_hms(2099, 3, day, 1, 0, 0).unwrap();
+            rfa.write_with_datetime(b"line\n", &now).unwrap();
+        }
+        rfa.flush().unwrap();
+
+        let pointer = dir.path().join("log.log.latest");
+        assert_eq!(fs::read_to_string(pointer).unwrap(), "log.log.20990303.010000\n");
+        // Retention doesn't count the pointer file
+        assert!(dir.path().join("log.log.20990302.010000").exists());
+        assert!(!dir.path().join("log.log").exists());
+    }
+
+    #[test]
+    fn test_in_memory_fs() {
+        let mem = Arc::new(MemFs::default());
+        let condition = RollingConditionBasic::new().daily();
+        let mut rfa = BasicRollingFileAppender::new_with_fs("/mem", "log.log", condition, 2, mem.clone()).unwrap();
+        for day in 1..=4 {
+            let now = Local.with_ymd_and_hms(2099, 3, day, 1, 0, 0).unwrap();
+            rfa.write_with_datetime(b"line\n", &now).unwrap();
+        }
+        rfa.flush().unwrap();
+
+        let names = mem.read_dir(Path::new("/mem")).unwrap();
+        assert_eq!(names, ["log.log.20990303.010000", "log.log.20990304.010000"]);
+        assert_eq!(mem.file_len(Path::new("/mem/log.log.20990304.010000")).unwrap(), 5);
+
+        rfa.write_with_datetime(b"more\n", &Local.with_ymd_and_hms(2099, 3, 4, 2, 0, 0).unwrap())
+            .unwrap();
+        assert_eq!(rfa.snapshot_current(Path::new("/mem/snapshot")).unwrap(), 10);
+        assert_eq!(mem.file_len(Path::new("/mem/snapshot")).unwrap(), 10);
+    }
+
+    /// Copies the plaintext, enough to see the file go through the file system
+    #[derive(Debug)]
+    struct Copy;
+
+    impl Encryptor for Copy {
+        fn encrypt(&self, plaintext: &mut dyn Read, ciphertext: &mut dyn Write) -> io::Result<()> {
+            io::copy(plaintext, ciphertext).map(|_| ())
+        }
+    }
+
+    #[test]
+    fn test_maintenance_in_memory() {
+        let dir = tempfile::tempdir().unwrap();
+        let real = dir.path().join("mem");
+        let folder = real.to_str().unwrap();
+        let mem = Arc::new(MemFs::default());
+        let condition = RollingConditionBasic::new().daily();
+        let manager = RetentionManager::new(1_000_000);
+        let rfa = BasicRollingFileAppender::new_with_fs(folder, "log.log", condition, 3, mem.clone())
+            .unwrap()
+            .with_time_index(IndexInterval::EveryMinute)
+            .with_retention_audit(true)
+            .with_encryption(Copy)
+            .with_tiering(TieringPolicy::new().delete_after(chrono::Duration::days(30)))
+            .with_retention_manager(manager.clone(), 1);
+        #[cfg(feature = "manifest")]
+        let rfa = rfa.with_manifest(true);
+        let mut rfa = rfa;
+        rfa.rollover().unwrap();
+        for day in 1..=5 {
+            let now = Local.with_ymd_and_hms(2099, 3, day, 1, 0, 0).unwrap();
+            rfa.write_with_datetime(format!("day {}\n", day).as_bytes(), &now)
+                .unwrap();
+        }
+        rfa.flush().unwrap();
+        manager.enforce().unwrap();
+        let now = Local.with_ymd_and_hms(2099, 3, 5, 2, 0, 0).unwrap();
+        let found = rfa
+            .search(now - chrono::Duration::days(30)..now, |line| line.starts_with("day"))
+            .unwrap()
+            .map(|m| m.unwrap().line)
+            .collect::<Vec<_>>();
+        assert_eq!(found, ["day 5"]);
+        #[cfg(feature = "bundle")]
+        rfa.bundle_rolled_files(BundlePeriod::Monthly, &Local.with_ymd_and_hms(2099, 4, 1, 0, 0, 0).unwrap())
+            .unwrap();
+
+        let names = mem.read_dir(&real).unwrap();
+        let mut expected = vec!["log.log.audit"];
+        if cfg!(feature = "bundle") {
+            expected.push("log.log.209903.tar");
+        }
+        if cfg!(feature = "manifest") {
+            expected.push("manifest.jsonl");
+        }
+        for name in expected {
+            assert!(names.iter().any(|n| n == name), "{} missing from {:?}", name, names);
+        }
+        assert!(!real.exists());
+    }
+
+    #[test]
+    fn test_retry_rest_of_torn_write() {
+        let mem = Arc::new(MemFs::default());
+        let mut rfa = BasicRollingFileAppender::builder()
+            .folder("/mem")
+            .prefix("log.log")
+            .condition(RollingConditionBasic::new().daily())
+            .max_files(2)
+            .buffer_capacity(0)
+            .fs(mem.clone())
+            .error_policy(ErrorPolicy::new(ErrorFallback::Propagate).retry(1, std::time::Duration::from_millis(1)))
+            .build()
+            .unwrap();
+        let now = Local.with_ymd_and_hms(2099, 3, 1, 1, 0, 0).unwrap();
+        rfa.write_with_datetime(b"first\n", &now).unwrap();
+        // Only the bytes the file didn't take are written again
+        *mem.torn_write.lock().unwrap() = Some(3);
+        rfa.write_with_datetime(b"second\n", &now).unwrap();
+        *mem.torn_write.lock().unwrap() = Some(4);
+        let bufs = [io::IoSlice::new(b"third "), io::IoSlice::new(b"line\n")];
+        rfa.write_vectored_with_datetime(&bufs, &now).unwrap();
+        rfa.flush().unwrap();
+
+        let path = rfa.current_file_path().unwrap();
+        let data = mem.files.lock().unwrap()[&path].clone();
+        assert_eq!(String::from_utf8(data).unwrap(), "first\nsecond\nthird line\n");
+    }
+
+    #[test]
+    fn test_open_modes() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        // Names repeat every week
+        let mut rfa = BasicRollingFileAppender::builder()
+            .folder(folder)
+            .prefix("log.log")
+            .condition(RollingConditionBasic::new().daily())
+            .file_name(|dt| format!("log.log.{}", dt.format("%a")))
+            .open_mode(OpenMode::Truncate)
+            .build()
+            .unwrap();
+        rfa.rollover().unwrap();
+        for day in [1, 2, 8] {
+            let now = Local.with_ymd_and_hms(2099, 3, day, 1, 0, 0).unwrap();
+            rfa.write_with_datetime(format!("day {}\n", day).as_bytes(), &now)
+                .unwrap();
+        }
+        rfa.flush().unwrap();
+        assert_eq!(fs::read_to_string(dir.path().join("log.log.Sun")).unwrap(), "day 8\n");
+
+        let mut rfa = BasicRollingFileAppender::new(folder, "pre.log", RollingConditionBasic::new().daily(), 9)
+            .unwrap()
+            .with_open_mode(OpenMode::Preallocate(1 << 20));
+        rfa.rollover().unwrap();
+        let now = Local.with_ymd_and_hms(2099, 3, 1, 1, 0, 0).unwrap();
+        rfa.write_with_datetime(b"line\n", &now).unwrap();
+        rfa.flush().unwrap();
+        // The reserved space isn't part of the file
+        assert_eq!(
+            fs::read_to_string(dir.path().join("pre.log.20990301.010000")).unwrap(),
+            "line\n"
+        );
+    }
+}
@@ -0,0 +1,68 @@
+//! Estimates of the disk space taken by an appender in the steady state.
+
+/// The steady state of an appender writing at a constant rate, see
+/// [`crate::RollingFileAppender::forecast_disk_usage`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct DiskForecast {
+    /// Size of a complete file before compression
+    pub file_size: u64,
+    /// Number of files kept, including the active one
+    pub file_count: usize,
+    /// Space used right before a rollover, when the active file is complete
+    pub peak_bytes: u64,
+    /// Time span covered by the rolled files
+    pub retained_span: chrono::Duration,
+    /// How many files are started per day
+    pub rollovers_per_day: f64,
+}
+
+impl DiskForecast {
+    /// Estimates the steady state of `max_files` files of `file_size` bytes written at
+    /// `bytes_per_second`, where rolled files shrink to `compression_ratio` times their size.
+    /// Returns `None` if nothing is written.
+    pub fn estimate(
+        file_size: f64,
+        max_files: usize,
+        bytes_per_second: f64,
+        compression_ratio: f64,
+    ) -> Option<DiskForecast> {
+        if !(bytes_per_second > 0.0 && file_size > 0.0) {
+            return None;
+        }
+        let file_count = max_files.max(1);
+        let rolled = (file_count - 1) as f64;
+        let seconds_per_file = file_size / bytes_per_second;
+        Some(DiskForecast {
+            file_size: file_size as u64,
+            file_count,
+            peak_bytes: (rolled * file_size * compression_ratio + file_size) as u64,
+            retained_span: chrono::Duration::milliseconds((rolled * seconds_per_file * 1000.0) as i64),
+            rollovers_per_day: 86400.0 / seconds_per_file,
+        })
+    }
+}
+
+#[cfg(test)]
+mod t {
+    use crate::*;
+
+    #[test]
+    fn test_forecast_hourly() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let condition = RollingConditionBasic::new().hourly().max_size(10_000_000);
+        let rfa = BasicRollingFileAppender::new(folder, "log.log", condition, 25).unwrap();
+        // 1 KB/s fills 3.6 MB per hour, below the size limit
+        let forecast = rfa.forecast_disk_usage(1000.0, 0.1).unwrap();
+        assert_eq!(forecast.file_size, 3_600_000);
+        assert_eq!(forecast.file_count, 25);
+        assert_eq!(forecast.peak_bytes, 24 * 360_000 + 3_600_000);
+        assert_eq!(forecast.retained_span, chrono::Duration::hours(24));
+        assert_eq!(forecast.rollovers_per_day, 24.0);
+
+        // 10 KB/s hits the size limit first
+        let forecast = rfa.forecast_disk_usage(10_000.0, 1.0).unwrap();
+        assert_eq!(forecast.file_size, 10_000_000);
+        assert_eq!(forecast.rollovers_per_day, 86.4);
+    }
+}
@@ -0,0 +1,180 @@
+//! Framed binary records.
+//!
+//! Each record is written as a little-endian `u32` payload length, a little-endian `u32`
+//! CRC-32 of the payload, and the payload itself. A frame is always written to the appender
+//! with a single write, so it never straddles a rollover.
+
+use std::{
+    fs::File,
+    io::{self, Read, Seek},
+};
+
+/// Size of the header preceding every payload.
+pub const FRAME_HEADER_LEN: usize = 8;
+
+/// Largest payload of a frame
+pub const MAX_FRAME_LEN: usize = 64 * 1024 * 1024;
+
+/// Encodes a payload into a complete frame.
+pub fn encode_frame(payload: &[u8]) -> io::Result<Vec<u8>> {
+    let len = u32::try_from(payload.len())
+        .ok()
+        .filter(|_| payload.len() <= MAX_FRAME_LEN)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "frame payload is too large"))?;
+    let mut frame = Vec::with_capacity(FRAME_HEADER_LEN + payload.len());
+    frame.extend_from_slice(&len.to_le_bytes());
+    frame.extend_from_slice(&crc32fast::hash(payload).to_le_bytes());
+    frame.extend_from_slice(payload);
+    Ok(frame)
+}
+
+/// Reads frames written by [`crate::RollingFileAppender::write_frame`] back from a file.
+///
+/// Iteration stops at a clean end of input. A frame cut short by the end of input yields an
+/// [`io::ErrorKind::UnexpectedEof`] error. A checksum mismatch, or a length above
+/// [`MAX_FRAME_LEN`] or beyond the end of a file opened with [`FrameReader::from_file`], yields an
+/// [`io::ErrorKind::InvalidData`] error.
+#[derive(Debug)]
+pub struct FrameReader<R> {
+    inner: R,
+    // Bytes left in the input, if known
+    remaining_opt: Option<u64>,
+}
+
+impl FrameReader<File> {
+    /// Creates a frame reader on the rest of `file`, which checks frame lengths against it
+    pub fn from_file(mut file: File) -> io::Result<FrameReader<File>> {
+        let len = file.metadata()?.len();
+        let position = file.stream_position()?;
+        Ok(FrameReader {
+            inner: file,
+            remaining_opt: Some(len.saturating_sub(position)),
+        })
+    }
+}
+
+impl<R> FrameReader<R>
+where
+    R: Read,
+{
+    /// Creates a frame reader on top of the given reader
+    pub fn new(inner: R) -> FrameReader<R> {
+        FrameReader {
+            inner,
+            remaining_opt: None,
+        }
+    }
+
+    /// Returns the underlying reader
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Reads the next payload, or `None` at a clean end of input.
+    pub fn read_frame(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let mut header = [0u8; FRAME_HEADER_LEN];
+        let mut filled = 0;
+        while filled < header.len() {
+            match self.inner.read(&mut header[filled..]) {
+                Ok(0) if filled == 0 => return Ok(None),
+                Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+                Ok(n) => filled += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {},
+                Err(e) => return Err(e),
+            }
+        }
+        let len = u32::from_le_bytes([header[0], header[1], header[2], header[3]]) as usize;
+        let crc = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+        // The length is read from the file, so it is checked before allocating the payload
+        if len > MAX_FRAME_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("frame length {} exceeds the maximum of {}", len, MAX_FRAME_LEN),
+            ));
+        }
+        if let Some(remaining) = self.remaining_opt.as_mut() {
+            *remaining = remaining.saturating_sub(FRAME_HEADER_LEN as u64);
+            if len as u64 > *remaining {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("frame length {} exceeds the {} bytes left in the file", len, remaining),
+                ));
+            }
+            *remaining -= len as u64;
+        }
+        let mut payload = vec![0u8; len];
+        self.inner.read_exact(&mut payload)?;
+        if crc32fast::hash(&payload) != crc {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "frame checksum mismatch"));
+        }
+        Ok(Some(payload))
+    }
+}
+
+impl<R> Iterator for FrameReader<R>
+where
+    R: Read,
+{
+    type Item = io::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_frame().transpose()
+    }
+}
+
+#[cfg(test)]
+mod t {
+    use super::*;
+    use crate::*;
+    use std::fs::File;
+
+    #[test]
+    fn test_frames_are_not_split_across_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let condition = RollingConditionBasic::new().max_size(10);
+        let mut rfa = BasicRollingFileAppender::new(folder, "log.log", condition, 9).unwrap();
+        let now = Local.with_ymd_and_hms(2021, 3, 30, 1, 0, 0).unwrap();
+        rfa.write_frame_with_datetime(b"first record", &now).unwrap();
+        rfa.write_frame_with_datetime(b"second", &now.with_second(1).unwrap())
+            .unwrap();
+        rfa.flush().unwrap();
+
+        let first = FrameReader::new(File::open(dir.path().join("log.log.20210330.010001")).unwrap())
+            .collect::<io::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(first, vec![b"second".to_vec()]);
+
+        let mut frame = encode_frame(b"payload").unwrap();
+        frame[FRAME_HEADER_LEN] ^= 1;
+        let err = FrameReader::new(&frame[..]).read_frame().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        let err = FrameReader::new(&frame[..5]).read_frame().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn test_frame_length_is_checked() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("frames");
+        // A corrupt header claiming a huge payload
+        let mut frames = encode_frame(b"payload").unwrap();
+        frames.extend_from_slice(&u32::MAX.to_le_bytes());
+        frames.extend_from_slice(&0u32.to_le_bytes());
+        std::fs::write(&path, &frames).unwrap();
+        let mut reader = FrameReader::from_file(File::open(&path).unwrap()).unwrap();
+        assert_eq!(reader.read_frame().unwrap().unwrap(), b"payload");
+        assert_eq!(reader.read_frame().unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+        // Within the maximum, but beyond the end of the file
+        let mut frame = encode_frame(b"payload").unwrap();
+        frame[0] = 8;
+        std::fs::write(&path, &frame).unwrap();
+        let mut reader = FrameReader::from_file(File::open(&path).unwrap()).unwrap();
+        assert_eq!(reader.read_frame().unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+        let err = FrameReader::new(&frames[..]).nth(1).unwrap().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(encode_frame(&vec![0; MAX_FRAME_LEN + 1]).is_err());
+    }
+}
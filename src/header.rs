@@ -0,0 +1,58 @@
+//! A metadata line at the start of every log file.
+//!
+//! Archived files are often read long after the fact, far from the host that wrote them. With
+//! [`crate::RollingFileAppender::with_metadata_header`] every new file starts with a line
+//! recording who wrote it and how, e.g.
+//! `# local-rolling-file 1.1.1 app=myapp/2.0.1 host=web-3 pid=4242 started=2024-05-20T01:01:01+08:00
+//! rollover=daily max_files=9`.
+
+use chrono::{DateTime, Local, SecondsFormat};
+
+/// Describes the metadata line written at the start of every new file
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct MetadataHeader {
+    app_opt: Option<String>,
+}
+
+impl MetadataHeader {
+    /// Records the crate version, hostname, process id, start time and rotation settings
+    pub fn new() -> MetadataHeader {
+        MetadataHeader::default()
+    }
+
+    /// Also records the name and version of the application
+    pub fn app(mut self, name: &str, version: &str) -> MetadataHeader {
+        self.app_opt = Some(format!("{}/{}", name, version));
+        self
+    }
+
+    /// Renders the line, ending with a line break.
+    pub(crate) fn render(&self, started: &DateTime<Local>, settings: &str) -> String {
+        let mut line = format!("# {} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+        if let Some(app) = self.app_opt.as_ref() {
+            line.push_str(&format!(" app={}", app));
+        }
+        line.push_str(&format!(
+            " host={} pid={} started={}",
+            hostname(),
+            std::process::id(),
+            started.to_rfc3339_opts(SecondsFormat::Secs, false)
+        ));
+        if !settings.is_empty() {
+            line.push(' ');
+            line.push_str(settings);
+        }
+        line.push('\n');
+        line
+    }
+}
+
+fn hostname() -> String {
+    let from_file = std::fs::read_to_string("/proc/sys/kernel/hostname").ok();
+    from_file
+        .or_else(|| std::env::var("COMPUTERNAME").ok())
+        .or_else(|| std::env::var("HOSTNAME").ok())
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "unknown".to_string())
+}
@@ -0,0 +1,184 @@
+//! Optional sparse time index kept next to each log file.
+//!
+//! Every log file `log.log.yyyymmdd.hhmmss` may have a sidecar `log.log.yyyymmdd.hhmmss.idx`
+//! where each line is `<unix timestamp in milliseconds> <byte offset>`. An entry means that
+//! everything before the offset was written no later than the timestamp, so a reader can
+//! seek directly to a time range instead of scanning a large file from the start.
+
+use crate::{filesystem, Fs, LogFile};
+use chrono::prelude::*;
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+/// File name extension used by index sidecar files.
+pub const INDEX_EXTENSION: &str = "idx";
+
+/// Determines how often an entry is added to the sparse time index
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum IndexInterval {
+    /// Adds an entry for the first write of every minute
+    EveryMinute,
+    /// Adds an entry every time at least the given number of bytes has been written
+    EveryBytes(u64),
+}
+
+/// Returns the path of the index sidecar belonging to the given log file.
+pub fn index_path_for(log_file: &Path) -> PathBuf {
+    let mut name = log_file.as_os_str().to_owned();
+    name.push(".");
+    name.push(INDEX_EXTENSION);
+    PathBuf::from(name)
+}
+
+/// Writes index entries for the active log file.
+#[derive(Debug)]
+pub(crate) struct TimeIndexWriter {
+    interval: IndexInterval,
+    file_opt: Option<Box<dyn LogFile>>,
+    last_entry_opt: Option<(i64, u64)>,
+}
+
+impl TimeIndexWriter {
+    pub(crate) fn new(interval: IndexInterval) -> TimeIndexWriter {
+        TimeIndexWriter {
+            interval,
+            file_opt: None,
+            last_entry_opt: None,
+        }
+    }
+
+    /// Starts indexing a newly opened log file.
+    pub(crate) fn reset(&mut self, fs: &dyn Fs, log_file: &Path) -> io::Result<()> {
+        self.file_opt = None;
+        self.last_entry_opt = None;
+        self.file_opt = Some(fs.open_append(&index_path_for(log_file))?);
+        Ok(())
+    }
+
+    /// Records an entry for data about to be written at `offset`, if the interval requires one.
+    pub(crate) fn record(&mut self, now: &DateTime<Local>, offset: u64) -> io::Result<()> {
+        let millis = now.timestamp_millis();
+        let due = match (self.last_entry_opt, self.interval) {
+            (None, _) => true,
+            (Some((last_millis, _)), IndexInterval::EveryMinute) => millis / 60_000 != last_millis / 60_000,
+            (Some((_, last_offset)), IndexInterval::EveryBytes(n)) => offset.saturating_sub(last_offset) >= n,
+        };
+        if !due {
+            return Ok(());
+        }
+        if let Some(f) = self.file_opt.as_mut() {
+            writeln!(f, "{} {}", millis, offset)?;
+            self.last_entry_opt = Some((millis, offset));
+        }
+        Ok(())
+    }
+}
+
+/// An in-memory copy of an index sidecar file.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct TimeIndex {
+    entries: Vec<(i64, u64)>,
+}
+
+impl TimeIndex {
+    /// Loads the index belonging to the given log file. Malformed lines are skipped.
+    pub fn load(log_file: &Path) -> io::Result<TimeIndex> {
+        let f = File::open(index_path_for(log_file))?;
+        let mut entries = vec![];
+        for line in BufReader::new(f).lines() {
+            entries.extend(parse_entry(&line?));
+        }
+        Ok(TimeIndex { entries })
+    }
+
+    /// Same as [`TimeIndex::load`], through `fs`
+    pub(crate) fn load_in(fs: &dyn Fs, log_file: &Path) -> io::Result<TimeIndex> {
+        let data = filesystem::read_file(fs, &index_path_for(log_file))?;
+        let entries = String::from_utf8_lossy(&data).lines().filter_map(parse_entry).collect();
+        Ok(TimeIndex { entries })
+    }
+
+    /// Returns a byte offset from which all data written at or after `since` can be read.
+    pub fn offset_for(&self, since: &DateTime<Local>) -> u64 {
+        let millis = since.timestamp_millis();
+        self.entries
+            .iter()
+            .filter(|(t, _)| *t < millis)
+            .map(|(_, offset)| *offset)
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+/// Parses an index line, `None` if it is malformed
+fn parse_entry(line: &str) -> Option<(i64, u64)> {
+    let mut parts = line.split_whitespace();
+    Some((parts.next()?.parse().ok()?, parts.next()?.parse().ok()?))
+}
+
+/// Appends the index entries of `source_log` to the index of `target_log` through `fs`, shifting
+/// their offsets by `shift`. Used when the contents of one log file are appended to another. A
+/// source without index adds nothing.
+pub(crate) fn append_shifted(fs: &dyn Fs, source_log: &Path, target_log: &Path, shift: u64) -> io::Result<()> {
+    let source = index_path_for(source_log);
+    let len = match fs.file_len(&source) {
+        Ok(len) => len,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    let mut data = vec![0; usize::try_from(len).unwrap_or(0)];
+    fs.read_exact_at(&source, 0, &mut data)?;
+    let mut shifted = String::new();
+    for (millis, offset) in String::from_utf8_lossy(&data).lines().filter_map(parse_entry) {
+        shifted.push_str(&format!("{} {}\n", millis, offset + shift));
+    }
+    let mut out = fs.open_append(&index_path_for(target_log))?;
+    out.write_all(shifted.as_bytes())?;
+    out.flush()
+}
+
+/// Opens a log file positioned at the data written at or after `since`, using its index if one
+/// exists and falling back to the start of the file otherwise.
+pub fn open_log_at(log_file: &Path, since: &DateTime<Local>) -> io::Result<BufReader<File>> {
+    let mut f = File::open(log_file)?;
+    let len = f.metadata()?.len();
+    let offset = TimeIndex::load(log_file).map_or(0, |index| index.offset_for(since));
+    f.seek(SeekFrom::Start(offset.min(len)))?;
+    Ok(BufReader::new(f))
+}
+
+#[cfg(test)]
+mod t {
+    use super::*;
+    use crate::*;
+    use std::io::Read;
+
+    #[test]
+    fn test_seek_with_index() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let mut rfa = BasicRollingFileAppender::new(folder, "log.log", RollingConditionBasic::new().daily(), 3)
+            .unwrap()
+            .with_time_index(IndexInterval::EveryMinute);
+        for minute in 0..5 {
+            let now = Local.with_ymd_and_hms(2021, 3, 30, 1, minute, 0).unwrap();
+            rfa.write_with_datetime(format!("Line {}\n", minute).as_bytes(), &now)
+                .unwrap();
+        }
+        rfa.flush().unwrap();
+
+        let log_file = dir.path().join("log.log.20210330.010000");
+        let index = TimeIndex::load(&log_file).unwrap();
+        assert_eq!(index.entries.len(), 5);
+
+        let mut rest = String::new();
+        open_log_at(&log_file, &Local.with_ymd_and_hms(2021, 3, 30, 1, 3, 0).unwrap())
+            .unwrap()
+            .read_to_string(&mut rest)
+            .unwrap();
+        assert_eq!(rest, "Line 2\nLine 3\nLine 4\n");
+    }
+}
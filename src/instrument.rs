@@ -0,0 +1,30 @@
+//! Optional tracing of the appender's own operations.
+//!
+//! With the `instrument` feature, rollover, retention, bundling, consolidation and maintenance
+//! jobs emit spans and events under the [`INTERNAL_TARGET`] target. Filter this target out of
+//! any subscriber writing into a rolling file appender, or the appender's own events would be
+//! written back into it. Without the feature the macros expand to nothing.
+
+/// Target of all spans and events emitted about the appender's own operations.
+pub const INTERNAL_TARGET: &str = "local_rolling_file::internal";
+
+/// Enters a debug span under [`INTERNAL_TARGET`] until the end of the enclosing scope.
+macro_rules! internal_span {
+    ($name:literal $(, $($fields:tt)*)?) => {
+        #[cfg(feature = "instrument")]
+        let _internal_span = tracing::debug_span!(
+            target: $crate::instrument::INTERNAL_TARGET,
+            $name
+            $(, $($fields)*)?
+        )
+        .entered();
+    };
+}
+
+/// Emits a debug event under [`INTERNAL_TARGET`].
+macro_rules! internal_event {
+    ($($args:tt)*) => {
+        #[cfg(feature = "instrument")]
+        tracing::debug!(target: $crate::instrument::INTERNAL_TARGET, $($args)*);
+    };
+}
@@ -1,13 +1,12 @@
 //! A rolling file appender with customizable rolling conditions.
 //! Includes built-in support for rolling conditions on date/time
 //! (daily, hourly, every minute) and/or size.
-
+//!
 //! Log files structures(with `log` as folder and `log.log` as prefix):
 //! - log.log `(a symbol link always points to the latest one log file)`
 //! - log.log.yyyymmdd.hhmmss `(e.g. log.log.20240520.010101)`
 //! - ..
 //!
-
 //! This is useful to combine with the tracing crate and
 //! tracing_appender::non_blocking::NonBlocking -- use it
 //! as an alternative to tracing_appender::rolling::RollingFileAppender.
@@ -29,17 +28,200 @@
 
 use chrono::prelude::*;
 use std::{
+    cell::Cell,
     convert::TryFrom,
-    fs::{self, File, OpenOptions},
-    io::{self, BufWriter, Write},
-    path::Path,
+    io::{self, IoSlice, Write},
+    path::{Path, PathBuf},
+    thread,
+    time::Instant,
 };
-use symlink::{remove_symlink_auto, symlink_auto};
+
+#[macro_use]
+pub mod instrument;
+
+#[cfg(feature = "admin")]
+pub mod admin;
+pub mod archive;
+#[cfg(feature = "async")]
+pub mod asynchronous;
+pub mod audit;
+pub mod builder;
+pub mod capture;
+pub mod clock;
+pub mod combinators;
+pub mod compression;
+pub mod config;
+pub mod consolidate;
+pub mod diskspace;
+pub mod durability;
+pub mod encoding;
+pub mod encryption;
+pub mod error;
+mod failover;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod filesystem;
+pub mod forecast;
+pub mod frame;
+pub mod header;
+pub mod index;
+#[cfg(feature = "log")]
+pub mod log_adapter;
+pub mod maintenance;
+#[cfg(feature = "manifest")]
+pub mod manifest;
+pub mod memory;
+pub mod multi;
+pub mod naming;
+pub mod nonblocking;
+pub mod panic;
+mod pool;
+mod post_rollover;
+pub mod recovery;
+pub mod retention;
+pub mod retry;
+pub mod search;
+pub mod shared;
+#[cfg(feature = "async")]
+mod shutdown;
+#[cfg(all(unix, feature = "signal"))]
+pub mod sighup;
+#[cfg(feature = "slog")]
+pub mod slog_drain;
+#[cfg(all(unix, feature = "unix-socket"))]
+pub mod socket;
+pub mod stats;
+#[cfg(feature = "syslog")]
+pub mod syslog;
+pub mod threads;
+pub mod tiering;
+pub mod timer;
+pub mod timezone;
+#[cfg(target_os = "linux")]
+pub mod tmpfile;
+pub mod trigger;
+#[cfg(feature = "upload")]
+pub mod upload;
+pub mod watchdog;
+mod writer;
+
+#[cfg(feature = "admin")]
+pub use admin::{AdminResponse, AdminServer};
+pub use archive::ArchivePolicy;
+#[cfg(feature = "bundle")]
+pub use archive::BundlePeriod;
+#[cfg(feature = "async")]
+pub use asynchronous::AsyncRollingFileAppender;
+pub use audit::DeletionRecord;
+use builder::Hooks;
+pub use builder::RollingFileAppenderBuilder;
+pub use capture::CapturedChild;
+pub use clock::{Clock, MockClock, SystemClock};
+pub use combinators::{And, Or, RollingConditionExt, TimeWindow};
+pub use compression::Compression;
+#[cfg(feature = "zstd")]
+pub use compression::DictionaryCompression;
+pub use config::RollingFileConfig;
+pub use diskspace::LowSpacePolicy;
+pub use durability::SyncPolicy;
+use encoding::Transcoder;
+pub use encoding::{Encoding, LineEnding};
+#[cfg(feature = "age")]
+pub use encryption::AgeEncryptor;
+pub use encryption::Encryptor;
+pub use error::RollingFileError;
+pub use filesystem::{Fs, LatestLink, LogFile, OpenMode, OpenOptionsFs, RealFs};
+pub use forecast::DiskForecast;
+pub use frame::FrameReader;
+pub use header::MetadataHeader;
+use index::TimeIndexWriter;
+pub use index::{IndexInterval, TimeIndex};
+pub use maintenance::{Maintenance, MaintenanceHandle, MaintenanceJob};
+pub use memory::{MemoryLimit, MemoryPolicy};
+pub use multi::MultiRollingAppender;
+pub use naming::{DailyFolderNaming, NamingScheme, SequentialNaming, TimestampNaming};
+pub use nonblocking::{NonBlockingRollingFileAppender, WorkerGuard};
+use post_rollover::{LinkSettings, PostRollover, RetentionSettings, RolledFileSettings};
+use recovery::Repair;
+pub use recovery::TornWriteRecovery;
+pub use retention::{PlannedFile, RetentionAction, RetentionManager, RetentionPlan, RetentionPolicy};
+pub use retry::{ErrorFallback, ErrorPolicy};
+pub use search::{Search, SearchMatch};
+pub use shared::{FlushGuard, SharedRollingFileAppender};
+#[cfg(feature = "tracing")]
+pub use shared::SharedWriter;
+#[cfg(all(unix, feature = "signal"))]
+pub use sighup::SighupAction;
+#[cfg(all(unix, feature = "unix-socket"))]
+pub use socket::SocketReceiver;
+pub use stats::RollingFileStats;
+use std::sync::Arc;
+#[cfg(feature = "syslog")]
+pub use syslog::{SyslogFormat, SyslogMessage, SyslogReceiver};
+pub use threads::ThreadOptions;
+pub use tiering::TieringPolicy;
+pub use timer::{FlushTimer, RolloverTimer};
+pub use timezone::Timezone;
+#[cfg(target_os = "linux")]
+pub use tmpfile::TmpfileFs;
+pub use trigger::{RollingConditionTriggered, TriggerHandle};
+#[cfg(feature = "upload")]
+pub use upload::{UploadHandle, UploadQueue, Uploader};
+use watchdog::IoProbe;
+pub use watchdog::{StallAction, Watchdog};
+use writer::FileWriter;
+
+/// Compiles the examples of the README
+#[cfg(doctest)]
+#[doc = include_str!("../README.md")]
+pub struct ReadmeDoctests;
 
 /// Determines when a file should be "rolled over".
 pub trait RollingCondition {
     /// Determine and return whether or not the file should be rolled over.
     fn should_rollover(&mut self, now: &DateTime<Local>, current_filesize: u64) -> bool;
+
+    /// Same as [`RollingCondition::should_rollover`], also given the number of lines this
+    /// appender wrote to the current file. Only conditions counting lines need to implement it.
+    fn should_rollover_with_lines(
+        &mut self,
+        now: &DateTime<Local>,
+        current_filesize: u64,
+        _current_lines: u64,
+    ) -> bool {
+        self.should_rollover(now, current_filesize)
+    }
+
+    /// Tells the condition that the appender opened a file started at `opened`, which is before
+    /// now if the file was resumed. Only conditions limiting the age of files need to implement it.
+    fn file_opened(&mut self, _opened: &DateTime<Local>) {}
+
+    /// Tells the condition that the appender wrote to the file at `now`. Unlike
+    /// [`RollingCondition::should_rollover`], it isn't called by timers checking the condition
+    /// without writing. Only conditions tracking writes need to implement it.
+    fn written(&mut self, _now: &DateTime<Local>) {}
+
+    /// Returns the next time at which the condition is known to roll over, if it is time based.
+    fn next_rollover(&self, _now: &DateTime<Local>) -> Option<DateTime<Local>> {
+        None
+    }
+
+    /// Returns the expected size of a complete file when writing at a constant rate, if the
+    /// condition bounds it. Used by [`RollingFileAppender::forecast_disk_usage`].
+    fn expected_file_size(&self, _bytes_per_second: f64) -> Option<f64> {
+        None
+    }
+
+    /// Returns the size at which the condition always rolls over, if any. Used by
+    /// [`RollingFileAppender::with_split_large_writes`].
+    fn max_file_size(&self) -> Option<u64> {
+        None
+    }
+
+    /// Describes the condition for humans, e.g. in [`MetadataHeader`] lines.
+    fn describe(&self) -> String {
+        String::new()
+    }
 }
 
 /// Determines how often a file should be rolled over
@@ -48,24 +230,90 @@ pub enum RollingFrequency {
     EveryDay,
     EveryHour,
     EveryMinute,
+    /// Every week, starting on the given day
+    EveryWeek(Weekday),
+    EveryMonth,
 }
 
 impl RollingFrequency {
     /// Calculates a datetime that will be different if data should be in
     /// different files.
     pub fn equivalent_datetime(&self, dt: &DateTime<Local>) -> DateTime<Local> {
-        match self {
-            RollingFrequency::EveryDay => Local
-                .with_ymd_and_hms(dt.year(), dt.month(), dt.day(), 0, 0, 0)
-                .unwrap(),
-            RollingFrequency::EveryHour => Local
-                .with_ymd_and_hms(dt.year(), dt.month(), dt.day(), dt.hour(), 0, 0)
-                .unwrap(),
-            RollingFrequency::EveryMinute => Local
-                .with_ymd_and_hms(dt.year(), dt.month(), dt.day(), dt.hour(), dt.minute(), 0)
-                .unwrap(),
-        }
+        self.equivalent_datetime_in(dt, Timezone::Local)
+    }
+
+    /// Same as [`RollingFrequency::equivalent_datetime`] with the periods following the wall
+    /// clock of `timezone`.
+    pub fn equivalent_datetime_in(&self, dt: &DateTime<Local>, timezone: Timezone) -> DateTime<Local> {
+        let naive = timezone.to_naive(dt);
+        let (hour, minute) = match self {
+            RollingFrequency::EveryHour => (naive.hour(), 0),
+            RollingFrequency::EveryMinute => (naive.hour(), naive.minute()),
+            _ => (0, 0),
+        };
+        let date = match self {
+            RollingFrequency::EveryWeek(first_day) => {
+                naive.date() - chrono::Duration::days(days_since(naive.weekday(), *first_day))
+            },
+            RollingFrequency::EveryMonth => naive.date().with_day(1).unwrap_or(naive.date()),
+            _ => naive.date(),
+        };
+        let start = date.and_hms_opt(hour, minute, 0).unwrap_or(naive);
+        timezone.from_naive(&start).unwrap_or(*dt)
     }
+
+    /// Calculates the start of the period following the one `dt` is in, i.e. the time at which
+    /// the next file will be opened.
+    pub fn next_boundary(&self, dt: &DateTime<Local>) -> DateTime<Local> {
+        self.next_boundary_in(dt, Timezone::Local)
+    }
+
+    /// Same as [`RollingFrequency::next_boundary`] with the periods following the wall clock of
+    /// `timezone`.
+    pub fn next_boundary_in(&self, dt: &DateTime<Local>, timezone: Timezone) -> DateTime<Local> {
+        let start = self.equivalent_datetime_in(dt, timezone);
+        let start_date = timezone.to_naive(&start).date();
+        let next_date = match self {
+            RollingFrequency::EveryHour => return start + chrono::Duration::hours(1),
+            RollingFrequency::EveryMinute => return start + chrono::Duration::minutes(1),
+            RollingFrequency::EveryDay => start_date + chrono::Duration::days(1),
+            RollingFrequency::EveryWeek(_) => start_date + chrono::Duration::days(7),
+            RollingFrequency::EveryMonth => start_date + chrono::Months::new(1),
+        };
+        // Midnight may not exist on days with a DST change, take the first valid time
+        (0..24)
+            .find_map(|h| timezone.from_naive(&next_date.and_hms_opt(h, 0, 0)?))
+            .unwrap_or(start + (next_date - start_date))
+    }
+}
+
+/// Parses a size in bytes with an optional unit: `k`, `M`, `G` and `T` are powers of 1000,
+/// `KiB`, `MiB`, `GiB` and `TiB` powers of 1024. Units are case insensitive and may end with `B`,
+/// e.g. `"250MB"`, `"512 KiB"` or `"4096"`.
+pub fn parse_size(size: &str) -> Result<u64, RollingFileError> {
+    let invalid = || RollingFileError::InvalidConfig(format!("invalid size {:?}", size));
+    let trimmed = size.trim();
+    let split = trimmed.find(|c: char| !c.is_ascii_digit()).unwrap_or(trimmed.len());
+    let (number, unit) = trimmed.split_at(split);
+    let number: u64 = number.parse().map_err(|_| invalid())?;
+    let multiplier: u64 = match unit.trim_start().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kb" => 1000,
+        "m" | "mb" => 1000 * 1000,
+        "g" | "gb" => 1000 * 1000 * 1000,
+        "t" | "tb" => 1000 * 1000 * 1000 * 1000,
+        "kib" => 1 << 10,
+        "mib" => 1 << 20,
+        "gib" => 1 << 30,
+        "tib" => 1 << 40,
+        _ => return Err(invalid()),
+    };
+    number.checked_mul(multiplier).ok_or_else(invalid)
+}
+
+/// Returns how many days `day` is after the most recent `first_day`
+fn days_since(day: Weekday, first_day: Weekday) -> i64 {
+    i64::from((day.num_days_from_monday() + 7 - first_day.num_days_from_monday()) % 7)
 }
 
 /// Implements a rolling condition based on a certain frequency
@@ -77,21 +325,36 @@ impl RollingFrequency {
 /// use local_rolling_file::*;
 /// let c = RollingConditionBasic::new().daily();
 /// let c = RollingConditionBasic::new().hourly().max_size(1024 * 1024);
+/// let c = RollingConditionBasic::new().daily().max_size_str("250MB").unwrap();
 /// ```
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub struct RollingConditionBasic {
+    last_check_opt: Option<DateTime<Local>>,
     last_write_opt: Option<DateTime<Local>>,
     frequency_opt: Option<RollingFrequency>,
     max_size_opt: Option<u64>,
+    max_lines_opt: Option<u64>,
+    max_age_opt: Option<chrono::Duration>,
+    idle_opt: Option<chrono::Duration>,
+    opened_opt: Option<DateTime<Local>>,
+    timezone: Timezone,
+    offset: chrono::Duration,
 }
 
 impl RollingConditionBasic {
     /// Constructs a new struct that does not yet have any condition set.
     pub fn new() -> RollingConditionBasic {
         RollingConditionBasic {
-            last_write_opt: Some(Local::now()),
+            last_check_opt: Some(Local::now()),
+            last_write_opt: None,
             frequency_opt: None,
             max_size_opt: None,
+            max_lines_opt: None,
+            max_age_opt: None,
+            idle_opt: None,
+            opened_opt: None,
+            timezone: Timezone::Local,
+            offset: chrono::Duration::zero(),
         }
     }
 
@@ -107,22 +370,87 @@ impl RollingConditionBasic {
         self
     }
 
+    /// Sets a condition to rollover every day at `time` instead of midnight, e.g. 02:30 to roll
+    /// after a nightly batch
+    pub fn daily_at(mut self, time: NaiveTime) -> RollingConditionBasic {
+        self.frequency_opt = Some(RollingFrequency::EveryDay);
+        self.offset = time - NaiveTime::MIN;
+        self
+    }
+
     /// Sets a condition to rollover when the date or hour changes
     pub fn hourly(mut self) -> RollingConditionBasic {
         self.frequency_opt = Some(RollingFrequency::EveryHour);
         self
     }
 
+    /// Sets a condition to rollover every hour at `minute` past the hour
+    pub fn hourly_at(mut self, minute: u32) -> RollingConditionBasic {
+        self.frequency_opt = Some(RollingFrequency::EveryHour);
+        self.offset = chrono::Duration::minutes(i64::from(minute % 60));
+        self
+    }
+
     pub fn minutely(mut self) -> RollingConditionBasic {
         self.frequency_opt = Some(RollingFrequency::EveryMinute);
         self
     }
 
+    /// Sets a condition to rollover when a new week starts on Monday
+    pub fn weekly(self) -> RollingConditionBasic {
+        self.weekly_starting_on(Weekday::Mon)
+    }
+
+    /// Sets a condition to rollover when a new week starts on `first_day`
+    pub fn weekly_starting_on(mut self, first_day: Weekday) -> RollingConditionBasic {
+        self.frequency_opt = Some(RollingFrequency::EveryWeek(first_day));
+        self
+    }
+
+    /// Sets a condition to rollover when the month changes
+    pub fn monthly(mut self) -> RollingConditionBasic {
+        self.frequency_opt = Some(RollingFrequency::EveryMonth);
+        self
+    }
+
+    /// Follows the wall clock of `timezone` for the time based condition, e.g. to roll daily at
+    /// midnight UTC. The local time zone is used by default.
+    pub fn timezone(mut self, timezone: Timezone) -> RollingConditionBasic {
+        self.timezone = timezone;
+        self
+    }
+
     /// Sets a condition to rollover when a certain size is reached
     pub fn max_size(mut self, x: u64) -> RollingConditionBasic {
         self.max_size_opt = Some(x);
         self
     }
+
+    /// Sets a condition to rollover when a size given with a unit is reached, e.g. `"250MB"` or
+    /// `"1GiB"`; see [`parse_size`]
+    pub fn max_size_str(self, size: &str) -> Result<RollingConditionBasic, RollingFileError> {
+        Ok(self.max_size(parse_size(size)?))
+    }
+
+    /// Sets a condition to rollover when a certain number of lines is reached
+    pub fn max_lines(mut self, x: u64) -> RollingConditionBasic {
+        self.max_lines_opt = Some(x);
+        self
+    }
+
+    /// Sets a condition to rollover when the current file was started longer than `age` ago,
+    /// even if the frequency says otherwise, e.g. a file resumed after a restart
+    pub fn max_age(mut self, age: chrono::Duration) -> RollingConditionBasic {
+        self.max_age_opt = Some(age);
+        self
+    }
+
+    /// Sets a condition to rollover on the first write after no writes for `idle`, or at the check
+    /// of a [`RolloverTimer`] if earlier, so each burst of activity gets its own file
+    pub fn idle_rollover(mut self, idle: chrono::Duration) -> RollingConditionBasic {
+        self.idle_opt = Some(idle);
+        self
+    }
 }
 
 impl Default for RollingConditionBasic {
@@ -133,10 +461,17 @@ impl Default for RollingConditionBasic {
 
 impl RollingCondition for RollingConditionBasic {
     fn should_rollover(&mut self, now: &DateTime<Local>, current_filesize: u64) -> bool {
+        self.should_rollover_with_lines(now, current_filesize, 0)
+    }
+
+    fn should_rollover_with_lines(&mut self, now: &DateTime<Local>, current_filesize: u64, current_lines: u64) -> bool {
         let mut rollover = false;
         if let Some(frequency) = self.frequency_opt.as_ref() {
-            if let Some(last_write) = self.last_write_opt.as_ref() {
-                if frequency.equivalent_datetime(now) != frequency.equivalent_datetime(last_write) {
+            if let Some(last_check) = self.last_check_opt.as_ref() {
+                // Periods starting at the offset are the periods of the shifted times
+                if frequency.equivalent_datetime_in(&(*now - self.offset), self.timezone)
+                    != frequency.equivalent_datetime_in(&(*last_check - self.offset), self.timezone)
+                {
                     rollover = true;
                 }
             }
@@ -146,13 +481,110 @@ impl RollingCondition for RollingConditionBasic {
                 rollover = true;
             }
         }
-        self.last_write_opt = Some(*now);
+        if let Some(max_lines) = self.max_lines_opt.as_ref() {
+            if current_lines >= *max_lines {
+                rollover = true;
+            }
+        }
+        if let (Some(max_age), Some(opened)) = (self.max_age_opt, self.opened_opt) {
+            if *now - opened >= max_age {
+                rollover = true;
+            }
+        }
+        if let (Some(idle), Some(last_write)) = (self.idle_opt, self.last_write_opt) {
+            if *now - last_write >= idle {
+                // Once per idle time, not again at each check until the next write
+                self.last_write_opt = None;
+                rollover = true;
+            }
+        }
+        self.last_check_opt = Some(*now);
         rollover
     }
+
+    fn file_opened(&mut self, opened: &DateTime<Local>) {
+        self.opened_opt = Some(*opened);
+    }
+
+    fn written(&mut self, now: &DateTime<Local>) {
+        self.last_write_opt = Some(*now);
+    }
+
+    fn next_rollover(&self, now: &DateTime<Local>) -> Option<DateTime<Local>> {
+        let by_time = self
+            .frequency_opt
+            .map(|frequency| frequency.next_boundary_in(&(*now - self.offset), self.timezone) + self.offset);
+        let by_age = self
+            .max_age_opt
+            .zip(self.opened_opt)
+            .map(|(max_age, opened)| opened + max_age);
+        let by_idle = self
+            .idle_opt
+            .zip(self.last_write_opt)
+            .map(|(idle, last_write)| last_write + idle);
+        [by_time, by_age, by_idle].into_iter().flatten().min()
+    }
+
+    fn max_file_size(&self) -> Option<u64> {
+        self.max_size_opt
+    }
+
+    fn expected_file_size(&self, bytes_per_second: f64) -> Option<f64> {
+        let by_time = self.frequency_opt.map(|frequency| {
+            let seconds = match frequency {
+                RollingFrequency::EveryDay => 86400.0,
+                RollingFrequency::EveryHour => 3600.0,
+                RollingFrequency::EveryMinute => 60.0,
+                RollingFrequency::EveryWeek(_) => 7.0 * 86400.0,
+                RollingFrequency::EveryMonth => 30.44 * 86400.0,
+            };
+            seconds * bytes_per_second
+        });
+        let by_time = match (by_time, self.max_age_opt) {
+            (Some(a), Some(max_age)) => Some(a.min(max_age.num_seconds() as f64 * bytes_per_second)),
+            (a, max_age_opt) => a.or(max_age_opt.map(|max_age| max_age.num_seconds() as f64 * bytes_per_second)),
+        };
+        let by_size = self.max_size_opt.map(|max_size| max_size as f64);
+        match (by_time, by_size) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
+    }
+
+    fn describe(&self) -> String {
+        let mut parts = vec![];
+        if let Some(frequency) = self.frequency_opt {
+            let name = match frequency {
+                RollingFrequency::EveryDay => "daily",
+                RollingFrequency::EveryHour => "hourly",
+                RollingFrequency::EveryMinute => "minutely",
+                RollingFrequency::EveryWeek(_) => "weekly",
+                RollingFrequency::EveryMonth => "monthly",
+            };
+            parts.push(format!("rollover={}", name));
+        }
+        if !self.offset.is_zero() {
+            parts.push(format!("at={}", (NaiveTime::MIN + self.offset).format("%H:%M")));
+        }
+        if let Some(max_size) = self.max_size_opt {
+            parts.push(format!("max_size={}", max_size));
+        }
+        if let Some(max_lines) = self.max_lines_opt {
+            parts.push(format!("max_lines={}", max_lines));
+        }
+        if let Some(max_age) = self.max_age_opt {
+            parts.push(format!("max_age={}s", max_age.num_seconds()));
+        }
+        if let Some(idle) = self.idle_opt {
+            parts.push(format!("idle={}s", idle.num_seconds()));
+        }
+        parts.join(" ")
+    }
 }
 
 /// Writes data to a file, and "rolls over" to preserve older data in
-/// a separate set of files. Old files have a Debian-style naming scheme
+/// a separate set of files. Files are named after the time they were started, or with
+/// [`SequentialNaming`] have a Debian-style naming scheme
 /// where we have base_filename, base_filename.1, ..., base_filename.N
 /// where N is the maximum number of rollover files to keep.
 #[derive(Debug)]
@@ -163,10 +595,59 @@ where
     condition: RC,
     folder: String,
     prefix: String,
-    max_files: usize,
+    retention: RetentionSettings,
     buffer_capacity: Option<usize>,
     current_filesize: u64,
-    writer_opt: Option<BufWriter<File>>,
+    current_lines: u64,
+    current_path_opt: Option<PathBuf>,
+    writer_opt: Option<FileWriter>,
+    index_opt: Option<TimeIndexWriter>,
+    transcoder: Transcoder,
+    bom: bool,
+    preamble_pending: bool,
+    torn_write_recovery_opt: Option<TornWriteRecovery>,
+    repair_pending: bool,
+    line_aligned: bool,
+    split_large_writes: bool,
+    at_line_start: bool,
+    staging_opt: Option<String>,
+    failover_opt: Option<failover::Failover>,
+    space_guard_opt: Option<diskspace::SpaceGuard>,
+    header_opt: Option<(MetadataHeader, DateTime<Local>)>,
+    rolled: RolledFileSettings,
+    scheduled_rollover_opt: Option<DateTime<Local>>,
+    // Start of the current file and time of the last write, given again to a condition replaced
+    // by `reconfigure`
+    opened_opt: Option<DateTime<Local>>,
+    last_write_opt: Option<DateTime<Local>>,
+    last_rollover_lag_opt: Option<chrono::Duration>,
+    max_rollover_lag_opt: Option<chrono::Duration>,
+    size_refresh_opt: Option<std::time::Duration>,
+    last_size_refresh: Instant,
+    pool: Arc<pool::Pool>,
+    maintenance_opt: Option<MaintenanceHandle>,
+    /// The scheduler of [`RollingFileAppender::with_background_maintenance`], finishing its queue
+    /// when dropped
+    own_maintenance_opt: Option<Maintenance>,
+    probe_opt: Option<Arc<IoProbe>>,
+    memory_limit_opt: Option<MemoryLimit>,
+    discarded_bytes: u64,
+    error_policy: ErrorPolicy,
+    double_buffered: bool,
+    thread_options: ThreadOptions,
+    link: LinkSettings,
+    resume_name_opt: Option<String>,
+    hooks: builder::Hooks,
+    create_dir: bool,
+    naming: Arc<dyn NamingScheme>,
+    base_name_opt: Option<String>,
+    sync: durability::SyncState,
+    stats: stats::StatsCounters,
+    #[cfg(all(unix, feature = "signal"))]
+    sighup_opt: Option<sighup::SighupHook>,
+    clock: Arc<dyn Clock>,
+    open_mode: OpenMode,
+    fs: Arc<dyn Fs>,
 }
 
 impl<RC> RollingFileAppender<RC>
@@ -176,10 +657,12 @@ where
     /// Creates a new rolling file appender with the given condition.
     /// The parent directory of the base path must already exist.
     pub fn new(folder: &str, prefix: &str, condition: RC, max_files: usize) -> io::Result<RollingFileAppender<RC>> {
-        Self::_new(folder, prefix, condition, max_files, None)
+        let retention = RetentionPolicy::MaxFiles(max_files);
+        Self::_new(folder, prefix, condition, retention, None, Arc::new(RealFs))
     }
 
     /// Creates a new rolling file appender with the given condition and write buffer capacity.
+    /// A capacity of 0 writes every write to the file right away.
     /// The parent directory of the base path must already exist.
     pub fn new_with_buffer_capacity(
         folder: &str,
@@ -188,159 +671,2345 @@ where
         max_files: usize,
         buffer_capacity: usize,
     ) -> io::Result<RollingFileAppender<RC>> {
-        Self::_new(folder, prefix, condition, max_files, Some(buffer_capacity))
+        Self::_new(
+            folder,
+            prefix,
+            condition,
+            RetentionPolicy::MaxFiles(max_files),
+            Some(buffer_capacity),
+            Arc::new(RealFs),
+        )
     }
 
-    fn _new(
+    /// Creates a new rolling file appender performing all file system operations through `fs`.
+    pub fn new_with_fs(
         folder: &str,
         prefix: &str,
         condition: RC,
         max_files: usize,
+        fs: Arc<dyn Fs>,
+    ) -> io::Result<RollingFileAppender<RC>> {
+        Self::_new(
+            folder,
+            prefix,
+            condition,
+            RetentionPolicy::MaxFiles(max_files),
+            None,
+            fs,
+        )
+    }
+
+    fn _new(
+        folder: &str,
+        prefix: &str,
+        condition: RC,
+        retention: RetentionPolicy,
         buffer_capacity: Option<usize>,
+        fs: Arc<dyn Fs>,
     ) -> io::Result<RollingFileAppender<RC>> {
+        let mut rfa = Self::unopened(folder, prefix, condition, retention, buffer_capacity, fs);
+        // Fail if we can't open the file initially...
+        rfa.open_writer_if_needed(&rfa.clock.now())?;
+        Ok(rfa)
+    }
+
+    /// Creates an appender which opens its first file on the first write.
+    fn unopened(
+        folder: &str,
+        prefix: &str,
+        condition: RC,
+        retention: RetentionPolicy,
+        buffer_capacity: Option<usize>,
+        fs: Arc<dyn Fs>,
+    ) -> RollingFileAppender<RC> {
         let folder = folder.to_string();
+        let naming = Arc::new(TimestampNaming::new(prefix));
         let prefix = prefix.to_string();
-        let mut rfa = RollingFileAppender {
+        RollingFileAppender {
             condition,
             folder,
             prefix,
-            max_files,
+            retention: RetentionSettings::new(retention),
             buffer_capacity,
             current_filesize: 0,
+            current_lines: 0,
+            current_path_opt: None,
             writer_opt: None,
-        };
-        // Fail if we can't open the file initially...
-        rfa.open_writer_if_needed(&Local::now())?;
-        Ok(rfa)
+            index_opt: None,
+            transcoder: Transcoder::default(),
+            bom: false,
+            preamble_pending: false,
+            torn_write_recovery_opt: None,
+            repair_pending: false,
+            line_aligned: false,
+            split_large_writes: false,
+            at_line_start: true,
+            staging_opt: None,
+            failover_opt: None,
+            space_guard_opt: None,
+            header_opt: None,
+            rolled: RolledFileSettings::default(),
+            scheduled_rollover_opt: None,
+            opened_opt: None,
+            last_write_opt: None,
+            last_rollover_lag_opt: None,
+            max_rollover_lag_opt: None,
+            size_refresh_opt: None,
+            last_size_refresh: Instant::now(),
+            pool: Arc::new(pool::Pool::new(1, ThreadOptions::new())),
+            maintenance_opt: None,
+            own_maintenance_opt: None,
+            probe_opt: None,
+            memory_limit_opt: None,
+            discarded_bytes: 0,
+            error_policy: ErrorPolicy::default(),
+            double_buffered: false,
+            thread_options: ThreadOptions::new(),
+            link: LinkSettings::default(),
+            resume_name_opt: None,
+            hooks: builder::Hooks::default(),
+            create_dir: true,
+            naming,
+            base_name_opt: None,
+            sync: durability::SyncState::new(SyncPolicy::Never),
+            stats: stats::StatsCounters::default(),
+            #[cfg(all(unix, feature = "signal"))]
+            sighup_opt: None,
+            clock: Arc::new(SystemClock),
+            open_mode: OpenMode::Append,
+            fs,
+        }
     }
 
-    fn check_and_remove_log_file(&mut self) -> io::Result<()> {
-        let files = std::fs::read_dir(&self.folder)?;
-
-        let mut log_files = vec![];
-        for f in files.flatten() {
-            let fname = f.file_name().to_string_lossy().to_string();
-            if fname.starts_with(&self.prefix) && fname != self.prefix {
-                log_files.push(fname);
+    /// Maintains a sparse time index next to each log file, so readers can seek to a time
+    /// range with [`index::open_log_at`] instead of scanning from the start.
+    pub fn with_time_index(mut self, interval: IndexInterval) -> RollingFileAppender<RC> {
+        let mut index = TimeIndexWriter::new(interval);
+        if let Some(path) = self.current_path_opt.as_ref() {
+            if let Err(e) = index.reset(&*self.fs, path) {
+                eprintln!(
+                    "WARNING: Failed to open time index for {}: {}",
+                    path.to_string_lossy(),
+                    e
+                );
             }
         }
+        self.index_opt = Some(index);
+        self
+    }
 
-        log_files.sort_by(|a, b| b.cmp(a));
+    /// Checks files resumed after a restart for an incomplete last line, left behind by a crash
+    /// during a write, and repairs it before writing to them.
+    pub fn with_torn_write_recovery(mut self, recovery: TornWriteRecovery) -> RollingFileAppender<RC> {
+        self.torn_write_recovery_opt = Some(recovery);
+        self
+    }
 
-        if log_files.len() > self.max_files {
-            for f in log_files.drain(self.max_files..) {
-                let p = Path::new(&self.folder).join(f);
-                if let Err(e) = fs::remove_file(&p) {
-                    tracing::error!("WARNING: Failed to remove old logfile {}: {}", p.to_string_lossy(), e);
-                }
-            }
-        }
-        Ok(())
+    /// Only rolls over between lines, so no line is split across two files. A write holding
+    /// several lines is split at its line breaks and the rolling condition is checked before each
+    /// line; a write ending mid-line postpones any rollover until the line is complete.
+    pub fn with_line_aligned_rollover(mut self, line_aligned: bool) -> RollingFileAppender<RC> {
+        self.line_aligned = line_aligned;
+        self
     }
 
-    /// Forces a rollover to happen immediately.
-    pub fn rollover(&mut self) -> io::Result<()> {
-        // Before closing, make sure all data is flushed successfully.
-        self.flush()?;
-        // We must close the current file before rotating files
-        self.writer_opt.take();
-        self.current_filesize = 0;
-        Ok(())
+    /// Asks the OS to persist the active file to disk at the points given by `policy`, see
+    /// [`SyncPolicy`]
+    pub fn with_sync_policy(mut self, policy: SyncPolicy) -> RollingFileAppender<RC> {
+        self.sync = durability::SyncState::new(policy);
+        self
     }
 
-    /// Returns a reference to the rolling condition
-    pub fn condition_ref(&self) -> &RC {
-        &self.condition
+    /// Flushes the active file and asks the OS to persist it to disk, whatever the
+    /// [`SyncPolicy`]
+    pub fn flush_and_sync(&mut self) -> io::Result<()> {
+        let probe_opt = self.probe_opt.clone();
+        let _probe_guard = probe_opt.as_deref().map(IoProbe::begin);
+        self.sync()
     }
 
-    /// Returns a mutable reference to the rolling condition, possibly to mutate its state dynamically.
-    pub fn condition_mut(&mut self) -> &mut RC {
-        &mut self.condition
+    /// Splits writes which would take the active file past the maximum size of the condition
+    /// across as many files as needed, so no file ever exceeds it. Otherwise a write always goes
+    /// into a single file, however large. Splitting takes precedence over
+    /// [`RollingFileAppender::with_line_aligned_rollover`] and
+    /// [`RollingFileAppender::write_record`], but not over
+    /// [`RollingFileAppender::write_frame`]: frames are never split. The chunks of a write share
+    /// its datetime, so the file names need to tell them apart.
+    pub fn with_split_large_writes(mut self, split: bool) -> RollingFileAppender<RC> {
+        self.split_large_writes = split;
+        self
     }
 
-    fn new_file_name(&self, now: &DateTime<Local>) -> String {
-        let data_str = now.format("%Y%m%d.%H%M%S").to_string();
-        format!("{}.{}", self.prefix, data_str)
+    /// Re-reads the size of the active file from the file system at most every `interval`, for
+    /// files other processes append to as well. Size based rollovers then account for their
+    /// writes too. The write buffer is flushed before each refresh.
+    pub fn with_size_refresh(mut self, interval: std::time::Duration) -> RollingFileAppender<RC> {
+        self.size_refresh_opt = Some(interval);
+        self
     }
 
-    /// Opens a writer for the current file.
-    fn open_writer_if_needed(&mut self, now: &DateTime<Local>) -> io::Result<()> {
-        if self.writer_opt.is_none() {
-            let p = self.new_file_name(now);
-            let new_file_path = std::path::Path::new(&self.folder).join(&p);
-            if std::fs::metadata(&self.folder).is_err() {
-                std::fs::create_dir_all(&self.folder)?;
-            }
-            let f = OpenOptions::new().append(true).create(true).open(&new_file_path)?;
-            self.writer_opt = Some(if let Some(capacity) = self.buffer_capacity {
-                BufWriter::with_capacity(capacity, f)
-            } else {
-                BufWriter::new(f)
-            });
-            // make a soft link to latest file
-            {
-                let folder = std::path::Path::new(&self.folder);
-                if let Ok(path) = folder.canonicalize() {
-                    let latest_log_symlink = path.join(&self.prefix);
-                    let _ = remove_symlink_auto(folder.join(&self.prefix));
-                    let _ = symlink_auto(new_file_path.canonicalize().unwrap(), latest_log_symlink);
-                }
+    /// Writes the active file to `staging`, typically on a fast local file system such as tmpfs,
+    /// and moves each completed file to the log folder in the background after a rollover. The
+    /// symlink points into the staging folder while a file is active. Files of the same prefix
+    /// left in `staging` by an earlier run are moved right away. Naming schemes renumbering the
+    /// rolled files, such as [`SequentialNaming`], are rejected.
+    pub fn with_staging_folder(mut self, staging: &str) -> io::Result<RollingFileAppender<RC>> {
+        if self.naming.renames_older_files() {
+            return Err(RollingFileError::InvalidConfig(format!(
+                "{:?} renames rolled files and can't use a staging folder",
+                self.naming
+            ))
+            .into());
+        }
+        let previous_opt = self.current_path_opt.take();
+        let was_empty = self.current_filesize == 0;
+        self.flush_writer()?;
+        self.writer_opt.take();
+        self.current_filesize = 0;
+        if let (Some(previous), true) = (previous_opt, was_empty) {
+            // The file opened at construction was never used, start over in the staging folder
+            let _ = self.fs.remove_file(&previous);
+            let _ = self.fs.remove_file(&index::index_path_for(&previous));
+        }
+        self.fs.create_dir_all(Path::new(staging))?;
+        for name in self.naming.list(&*self.fs, Path::new(staging))? {
+            if self.naming.started(&name).is_some() {
+                self.move_to_folder(staging, Path::new(staging).join(name));
             }
-            self.current_filesize = fs::metadata(&p).map_or(0, |m| m.len());
-            self.check_and_remove_log_file()?;
         }
-        Ok(())
+        self.staging_opt = Some(staging.to_string());
+        self.open_writer_if_needed(&self.clock.now())?;
+        Ok(self)
     }
 
-    /// Writes data using the given datetime to calculate the rolling condition
-    pub fn write_with_datetime(&mut self, buf: &[u8], now: &DateTime<Local>) -> io::Result<usize> {
-        if self.condition.should_rollover(now, self.current_filesize) {
-            if let Err(e) = self.rollover() {
-                // If we can't rollover, just try to continue writing anyway
-                // (better than missing data).
-                // This will likely used to implement logging, so
-                // avoid using log::warn and log to stderr directly
-                eprintln!("WARNING: Failed to rotate logfile  {}", e);
-            }
-        }
-        self.open_writer_if_needed(now)?;
-        if let Some(writer) = self.writer_opt.as_mut() {
-            let buf_len = buf.len();
-            writer.write_all(buf).map(|_| {
-                self.current_filesize += u64::try_from(buf_len).unwrap_or(u64::MAX);
-                buf_len
-            })
-        } else {
-            Err(io::Error::new(
-                io::ErrorKind::Other,
-                "unexpected condition: writer is missing",
-            ))
-        }
+    /// Continues in a new file of `failover`, e.g. `/tmp/log`, when the disk of the log folder is
+    /// full or failing, instead of losing the data. The first write after `retry_interval` rolls
+    /// over back to the log folder, failing over again if it is still unusable. Retention doesn't
+    /// apply to the files of the failover folder.
+    pub fn with_failover_folder(mut self, failover: &str, retry_interval: chrono::Duration) -> RollingFileAppender<RC> {
+        self.failover_opt = Some(failover::Failover::new(failover, retry_interval));
+        self
     }
-}
 
-impl<RC> io::Write for RollingFileAppender<RC>
-where
-    RC: RollingCondition,
-{
-    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        let now = Local::now();
-        self.write_with_datetime(buf, &now)
+    /// Tells whether files are currently written to the folder of
+    /// [`RollingFileAppender::with_failover_folder`]
+    pub fn is_failed_over(&self) -> bool {
+        self.failover_opt.as_ref().is_some_and(failover::Failover::is_active)
     }
 
-    fn flush(&mut self) -> io::Result<()> {
-        if let Some(writer) = self.writer_opt.as_mut() {
-            writer.flush()?;
-        }
-        Ok(())
+    /// Compresses rolled files with a zstd dictionary shared by all files of this prefix, which
+    /// is trained once enough files have been rolled. See [`compression`].
+    #[cfg(feature = "zstd")]
+    pub fn with_dictionary_compression(mut self, config: DictionaryCompression) -> RollingFileAppender<RC> {
+        self.rolled.dictionary_opt = Some(config);
+        self
     }
-}
 
-/// A rolling file appender with a rolling condition based on date/time or size.
-pub type BasicRollingFileAppender = RollingFileAppender<RollingConditionBasic>;
+    /// Sets how the file of a new period is opened, see [`OpenMode`]. A file resumed on startup
+    /// or reopened on SIGHUP is always appended to.
+    pub fn with_open_mode(mut self, mode: OpenMode) -> RollingFileAppender<RC> {
+        self.open_mode = mode;
+        self
+    }
+
+    /// Takes the time of writes and retention checks from `clock` instead of the system time. The
+    /// first file is opened at construction already, use [`RollingFileAppenderBuilder::clock`] to
+    /// name it after `clock` as well.
+    pub fn with_clock<C: Clock + 'static>(mut self, clock: C) -> RollingFileAppender<RC> {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// Starts every new file with a metadata line describing the writer and the rotation
+    /// settings, so archived files are self-describing. Not meant for files of frames.
+    pub fn with_metadata_header(mut self, header: MetadataHeader) -> RollingFileAppender<RC> {
+        self.header_opt = Some((header, self.clock.now()));
+        self
+    }
+
+    /// Replaces the retention policy, which keeps the `max_files` given at construction by
+    /// default. It is applied after every rollover.
+    pub fn with_retention(mut self, retention: RetentionPolicy) -> RollingFileAppender<RC> {
+        self.retention.policy = retention;
+        self
+    }
+
+    /// Deletes rolled files not written to for longer than `age` after each rollover, in addition
+    /// to the other retention limits such as `max_files`. See [`RetentionPolicy::MaxAge`].
+    pub fn with_max_age(mut self, age: chrono::Duration) -> RollingFileAppender<RC> {
+        self.retention.policy = self.retention.policy.and(RetentionPolicy::MaxAge(age));
+        self
+    }
+
+    /// Appends a record of every file deleted by retention to an audit sidecar, see [`audit`].
+    pub fn with_retention_audit(mut self, audit: bool) -> RollingFileAppender<RC> {
+        self.retention.audit = audit;
+        self
+    }
+
+    /// Registers the files of this appender with a disk budget shared with other appenders and
+    /// enforces it after every rollover, see [`retention`].
+    pub fn with_retention_manager(mut self, manager: RetentionManager, priority: u32) -> RollingFileAppender<RC> {
+        manager.register_with_fs(
+            &self.folder,
+            &self.prefix,
+            self.naming.clone(),
+            self.fs.clone(),
+            priority,
+        );
+        self.retention.manager_opt = Some(manager);
+        self
+    }
+
+    /// Moves rolled files through the tiers of `policy` as they age, after every rollover.
+    /// See [`tiering`].
+    pub fn with_tiering(mut self, policy: TieringPolicy) -> RollingFileAppender<RC> {
+        self.retention.tiering_opt = Some(policy);
+        self
+    }
+
+    /// Concatenates consecutive small rolled files of the same day after every rollover, as long
+    /// as the result stays below `max_size`, before they are compressed. See [`consolidate`].
+    pub fn with_consolidation(mut self, max_size: u64) -> RollingFileAppender<RC> {
+        self.rolled.consolidation_opt = Some(max_size);
+        self
+    }
+
+    /// Bundles the rolled files of every day or month that ended into a single tar archive per
+    /// period after every rollover. The archives are kept as the archive retention, or else the
+    /// retention, tells, counted apart from the log files. See [`archive`].
+    #[cfg(feature = "bundle")]
+    pub fn with_bundling(mut self, period: BundlePeriod) -> RollingFileAppender<RC> {
+        self.rolled.bundle_opt = Some(period);
+        self
+    }
+
+    /// Queues every rolled file for upload on the queue of `uploads`, once compressed if it is
+    /// compressed right away. Retention keeps the files not uploaded yet, see [`upload`]. The files
+    /// left marked for upload by an earlier run are queued again.
+    #[cfg(feature = "upload")]
+    pub fn with_uploads(mut self, uploads: upload::UploadHandle) -> RollingFileAppender<RC> {
+        uploads.resume(&self.folder);
+        self.hooks.uploads_opt = Some(uploads);
+        self
+    }
+
+    /// Passes each file right after it is rolled through `encryptor`, replacing it by
+    /// `<file>.enc`, e.g. with the `age` feature's `AgeEncryptor`.
+    pub fn with_encryption<E: Encryptor + 'static>(mut self, encryptor: E) -> RollingFileAppender<RC> {
+        self.rolled.encryptor_opt = Some(Arc::new(encryptor));
+        self
+    }
+
+    /// Records the size and SHA-256 of each rolled file in the manifest of the log folder, see
+    /// [`manifest`]
+    #[cfg(feature = "manifest")]
+    pub fn with_manifest(mut self, manifest: bool) -> RollingFileAppender<RC> {
+        self.rolled.manifest = manifest;
+        self
+    }
+
+    /// Sets what happens to the files retention no longer keeps: deleted, the default, or moved
+    /// to an archive folder.
+    pub fn with_archive(mut self, archive: ArchivePolicy) -> RollingFileAppender<RC> {
+        self.retention.archive = archive;
+        self
+    }
+
+    /// Sets which files are kept in the archive folder of [`ArchivePolicy::MoveTo`], applied
+    /// after files were moved there. All are kept by default. The archives of
+    /// `with_bundling` of the `bundle` feature follow it too, or the retention if it is not set.
+    pub fn with_archive_retention(mut self, retention: RetentionPolicy) -> RollingFileAppender<RC> {
+        self.retention.archive_retention_opt = Some(retention);
+        self
+    }
+
+    /// Sets the encoding of the log files. Written data is expected to be UTF-8 and is transcoded
+    /// before it reaches the file; size based conditions see the transcoded size.
+    pub fn with_encoding(mut self, encoding: Encoding) -> RollingFileAppender<RC> {
+        self.transcoder.set_encoding(encoding);
+        self
+    }
+
+    /// Normalizes line endings of written data, e.g. to produce CRLF files for Windows consumers.
+    pub fn with_line_ending(mut self, line_ending: LineEnding) -> RollingFileAppender<RC> {
+        self.transcoder.set_line_ending(line_ending);
+        self
+    }
+
+    /// Starts every newly created file with the byte order mark of the configured encoding.
+    pub fn with_bom(mut self, bom: bool) -> RollingFileAppender<RC> {
+        self.bom = bom;
+        self
+    }
+
+    /// Sets how many threads may be used when maintenance has to touch many files at once, e.g.
+    /// the first retention pass after lowering `max_files` or compressing a backlog of rolled
+    /// files. Defaults to 1 (no extra threads).
+    pub fn with_maintenance_threads(mut self, threads: usize) -> RollingFileAppender<RC> {
+        self.pool = Arc::new(pool::Pool::new(threads.max(1), self.thread_options.clone()));
+        self
+    }
+
+    /// Runs the symlink update and retention following a rollover on the given scheduler instead
+    /// of the writing thread. They fall back to running inline once the scheduler has shut down.
+    pub fn with_maintenance(mut self, maintenance: MaintenanceHandle) -> RollingFileAppender<RC> {
+        self.maintenance_opt = Some(maintenance);
+        self
+    }
+
+    /// Runs the link update, retention and other work following a rollover on a worker thread of
+    /// the appender's own, so writes never wait for the directory to be listed. The work queued
+    /// is finished when the appender is dropped.
+    pub fn with_background_maintenance(mut self) -> io::Result<RollingFileAppender<RC>> {
+        let maintenance = Maintenance::with_thread_options(&self.thread_options)?;
+        self.maintenance_opt = Some(maintenance.handle());
+        self.own_maintenance_opt = Some(maintenance);
+        Ok(self)
+    }
+
+    /// Returns the maintenance scheduler used by this appender, if any
+    pub fn maintenance(&self) -> Option<&MaintenanceHandle> {
+        self.maintenance_opt.as_ref()
+    }
+
+    /// Reports writes and flushes of this appender that block for too long to the given
+    /// watchdog. A watchdog watches a single appender.
+    pub fn with_watchdog(mut self, watchdog: &Watchdog) -> RollingFileAppender<RC> {
+        self.probe_opt = Some(watchdog.probe());
+        self
+    }
+
+    /// Caps the memory used for the write buffer and for processing written data; see
+    /// [`MemoryLimit`]. Shrinks the write buffer of the current file if needed.
+    pub fn with_memory_limit(mut self, limit: MemoryLimit) -> RollingFileAppender<RC> {
+        self.memory_limit_opt = Some(limit);
+        self.rebuild_writer();
+        self
+    }
+
+    /// Retries failed writes and rollovers and decides what happens to writes which still fail;
+    /// see [`ErrorPolicy`]
+    pub fn with_error_policy(mut self, policy: ErrorPolicy) -> RollingFileAppender<RC> {
+        self.error_policy = policy;
+        self
+    }
+
+    /// Fills one buffer while a background thread writes the previous one to disk, for high
+    /// throughput writers that would otherwise stall on every flush of the write buffer.
+    pub fn with_double_buffering(mut self, double_buffered: bool) -> RollingFileAppender<RC> {
+        self.double_buffered = double_buffered;
+        self.rebuild_writer();
+        self
+    }
+
+    /// Sets the name and priority of the threads spawned for this appender, i.e. the flusher of
+    /// [`RollingFileAppender::with_double_buffering`], the timer of
+    /// [`SharedRollingFileAppender::rollover_on_time`] and the extra threads of
+    /// [`RollingFileAppender::with_maintenance_threads`]. Maintenance schedulers and watchdogs
+    /// are configured where they are created.
+    pub fn with_thread_options(mut self, options: ThreadOptions) -> RollingFileAppender<RC> {
+        self.pool = Arc::new(pool::Pool::new(self.pool.threads(), options.clone()));
+        self.thread_options = options;
+        self.rebuild_writer();
+        self
+    }
+
+    /// Returns the options of the threads spawned for this appender
+    pub fn thread_options(&self) -> &ThreadOptions {
+        &self.thread_options
+    }
+
+    /// Replaces the writer of the current file with one matching the current settings.
+    fn rebuild_writer(&mut self) {
+        if let Err(e) = self.flush_writer() {
+            // Keep the current writer rather than losing its buffered data
+            eprintln!("WARNING: Failed to flush logfile {}", e);
+            return;
+        }
+        if let Some(writer) = self.writer_opt.take() {
+            let writer = writer.into_file().and_then(|f| {
+                FileWriter::new(
+                    f,
+                    self.effective_buffer_capacity(),
+                    self.double_buffered,
+                    &self.thread_options,
+                )
+            });
+            match writer {
+                Ok(writer) => self.writer_opt = Some(writer),
+                Err(e) => eprintln!("WARNING: Failed to set up writer for logfile {}", e),
+            }
+        }
+    }
+
+    /// Returns the number of bytes dropped because of [`MemoryPolicy::Discard`],
+    /// [`ErrorFallback::Drop`] or [`LowSpacePolicy::Drop`]
+    pub fn discarded_bytes(&self) -> u64 {
+        self.discarded_bytes
+    }
+
+    /// Returns the size the rolling condition sees for the active file: its size on disk when it
+    /// was opened, plus everything written since, including data still in the write buffer
+    pub fn current_file_size(&self) -> u64 {
+        self.current_filesize
+    }
+
+    /// Returns the counters of the appender
+    pub fn stats(&self) -> RollingFileStats {
+        self.stats.snapshot(self.current_filesize)
+    }
+
+    /// Returns the path of the file being written, `None` between a rollover and the next write
+    pub fn current_file_path(&self) -> Option<PathBuf> {
+        self.writer_opt.as_ref().and(self.current_path_opt.clone())
+    }
+
+    /// Returns the paths of the rolled files in the log folder, oldest first, including compressed
+    /// ones but not the file being written
+    pub fn list_rotated_files(&self) -> io::Result<Vec<PathBuf>> {
+        let current_opt = self.current_file_path();
+        let mut names = list_log_files(&*self.fs, &self.folder, &*self.naming, self.link.name_opt.as_deref())?;
+        names.sort_by(|a, b| self.naming.cmp_age(a, b));
+        let files = names
+            .into_iter()
+            .map(|fname| Path::new(&self.folder).join(fname))
+            .filter(|path| Some(path) != current_opt.as_ref())
+            .collect::<Vec<_>>();
+        Ok(files)
+    }
+
+    /// Tells what retention and compression would do to each log file if they ran now, and why,
+    /// without changing anything. Deletions vetoed by
+    /// [`RollingFileAppenderBuilder::veto_delete`] are reported as kept.
+    pub fn plan_retention(&self) -> io::Result<RetentionPlan> {
+        let naming = (&*self.naming, self.link.name_opt.as_deref());
+        let mut files = plan_expiry(
+            &*self.fs,
+            &self.folder,
+            naming,
+            &self.retention.policy,
+            &self.clock.now(),
+            Some(&self.hooks),
+        )?;
+        if let ArchivePolicy::MoveTo(_) = self.retention.archive {
+            files
+                .iter_mut()
+                .filter(|f| f.action == RetentionAction::Delete)
+                .for_each(|f| f.action = RetentionAction::Archive);
+        }
+        if self.rolled.compression != Compression::None {
+            let current_opt = self.current_file_path();
+            let mut uncompressed = 0;
+            for file in files.iter_mut() {
+                let fname = file
+                    .path
+                    .strip_prefix(&self.folder)
+                    .unwrap_or(&file.path)
+                    .to_string_lossy();
+                if Some(&file.path) == current_opt.as_ref()
+                    || compression::strip_rolled_extensions(&fname) != fname
+                    || self.naming.started(&fname).is_none()
+                {
+                    continue;
+                }
+                uncompressed += 1;
+                if file.action == RetentionAction::Keep && uncompressed > self.rolled.keep_uncompressed {
+                    file.action = RetentionAction::Compress;
+                    file.reason = format!("beyond keep_uncompressed={}", self.rolled.keep_uncompressed);
+                }
+            }
+        }
+        Ok(RetentionPlan { files })
+    }
+
+    /// Returns how many write buffers of [`RollingFileAppender::effective_buffer_capacity`] the
+    /// writer of the current file holds
+    fn buffer_count(&self) -> usize {
+        if self.double_buffered {
+            2
+        } else {
+            1
+        }
+    }
+
+    fn effective_buffer_capacity(&self) -> usize {
+        let capacity = self.buffer_capacity.unwrap_or(memory::DEFAULT_BUFFER_CAPACITY);
+        self.memory_limit_opt.map_or(capacity, |limit| {
+            limit.clamp_buffer_capacity(capacity, self.buffer_count())
+        })
+    }
+
+    /// Checks the free disk space, see [`RollingFileAppender::with_min_free_space`], and a
+    /// temporary allocation against the memory limit, see [`MemoryLimit::admit`].
+    fn admit(&mut self, needed: usize, len: usize) -> io::Result<bool> {
+        if !self.check_free_space(len) {
+            return Ok(false);
+        }
+        let limit = match self.memory_limit_opt {
+            Some(limit) if needed > 0 => limit,
+            _ => return Ok(true),
+        };
+        let buffer_capacity = self.writer_opt.as_ref().map_or(0, |w| w.capacity());
+        let admitted = limit.admit(buffer_capacity, needed)?;
+        if !admitted {
+            self.discarded_bytes += u64::try_from(len).unwrap_or(u64::MAX);
+        }
+        Ok(admitted)
+    }
+
+    /// Points the symlink at the newly opened file, processes the rolled one and applies
+    /// retention, see [`post_rollover`]. This touches the directory and may be slow, so it runs on
+    /// the maintenance scheduler if there is one, keeping the writing thread (or async executor)
+    /// free while the file system catches up.
+    fn finish_rollover(&self, previous_opt: Option<PathBuf>, new_file_path: PathBuf, now: &DateTime<Local>) {
+        // A staged file is on its way to the log folder
+        let rotated_opt = previous_opt
+            .filter(|previous| *previous != new_file_path)
+            .map(
+                |previous| match self.staging_opt.as_ref().map(|staging| previous.strip_prefix(staging)) {
+                    Some(Ok(name)) => Path::new(&self.folder).join(name),
+                    _ => previous,
+                },
+            );
+        let post = PostRollover {
+            fs: self.fs.clone(),
+            folder: self.folder.clone(),
+            prefix: self.prefix.clone(),
+            naming: self.naming.clone(),
+            now: *now,
+            new_file_path,
+            rotated_opt,
+            link: self.link.clone(),
+            rolled: self.rolled.clone(),
+            retention: self.retention.clone(),
+            pool: self.pool.clone(),
+            hooks: self.hooks.clone(),
+            clock: self.clock.clone(),
+            deleted_files: self.stats.deleted_files.clone(),
+            deleted: std::sync::Mutex::new(vec![]),
+        };
+        for job in post.into_jobs() {
+            let job = match self.maintenance_opt.as_ref() {
+                Some(maintenance) => match maintenance.submit(job) {
+                    Ok(()) => continue,
+                    Err(job) => job,
+                },
+                None => job,
+            };
+            job.run();
+        }
+    }
+
+    /// Moves a completed file and its index sidecar from the staging folder to the log folder,
+    /// on the maintenance scheduler if there is one.
+    fn move_to_folder(&self, staging: &str, path: PathBuf) {
+        let Ok(name) = path.strip_prefix(staging) else {
+            return;
+        };
+        let (fs, target) = (self.fs.clone(), Path::new(&self.folder).join(name));
+        let job = MaintenanceJob::new("move", move || {
+            if let Some(parent) = target.parent() {
+                fs.create_dir_all(parent)?;
+            }
+            // A file of the same name, e.g. moved before a restart, is kept
+            let target = filesystem::move_file_no_replace(&*fs, &path, &target)?;
+            let index_path = index::index_path_for(&path);
+            if fs.file_len(&index_path).is_ok() {
+                filesystem::move_file(&*fs, &index_path, &index::index_path_for(&target))?;
+            }
+            internal_event!(source = %path.display(), target = %target.display(), "moved staged file");
+            Ok(())
+        });
+        let job = match self.maintenance_opt.as_ref() {
+            Some(maintenance) => match maintenance.submit(job) {
+                Ok(()) => return,
+                Err(job) => job,
+            },
+            None => job,
+        };
+        job.run();
+    }
+
+    /// Forces a rollover to happen immediately.
+    pub fn rollover(&mut self) -> io::Result<()> {
+        internal_span!("rollover", size = self.current_filesize);
+        if let Err(e) = self.write_footer_if_needed() {
+            eprintln!("WARNING: Failed to write logfile footer {}", e);
+        }
+        // Before closing, make sure all data is flushed successfully.
+        let completed = if self.sync.on_rotate() {
+            self.sync()
+        } else {
+            self.flush_writer()
+        };
+        completed.map_err(|e| io::Error::from(RollingFileError::RotateFailed(e)))?;
+        // We must close the current file before rotating files
+        let was_open = self.writer_opt.take().is_some();
+        if was_open {
+            self.rename_rolled()
+                .map_err(|e| io::Error::from(RollingFileError::RotateFailed(e)))?;
+        }
+        self.current_filesize = 0;
+        self.scheduled_rollover_opt = None;
+        if let (Some(path), true, Some(staging)) = (self.current_path_opt.clone(), was_open, self.staging_opt.as_ref())
+        {
+            self.move_to_folder(staging, path);
+        }
+        Ok(())
+    }
+
+    /// Lets the naming scheme rename the file just closed, see [`NamingScheme::rename_rolled`]
+    fn rename_rolled(&mut self) -> io::Result<()> {
+        let folder = PathBuf::from(self.active_folder());
+        let Some(name) = self.current_name() else {
+            return Ok(());
+        };
+        if let Some(renamed) = self.naming.rename_rolled(&*self.fs, &folder, &name)? {
+            self.current_path_opt = Some(folder.join(renamed));
+            // The name is free again
+            self.base_name_opt = None;
+        }
+        Ok(())
+    }
+
+    /// Returns the folder new files are opened in
+    fn active_folder(&self) -> &str {
+        match self.failover_opt.as_ref() {
+            Some(failover) if failover.is_active() => &failover.folder,
+            _ => self.staging_opt.as_deref().unwrap_or(&self.folder),
+        }
+    }
+
+    /// Returns the name of the current file relative to its folder
+    fn current_name(&self) -> Option<String> {
+        let folder = Path::new(self.active_folder());
+        let path = self.current_path_opt.as_ref()?;
+        Some(path.strip_prefix(folder).ok()?.to_str()?.to_string())
+    }
+
+    /// Rolls over and opens the new file right away if the rolling condition says it is time,
+    /// without waiting for a write. Returns whether a rollover happened.
+    pub fn rollover_if_due(&mut self, now: &DateTime<Local>) -> io::Result<bool> {
+        if self.line_aligned && !self.at_line_start {
+            return Ok(false);
+        }
+        if !self
+            .condition
+            .should_rollover_with_lines(now, self.current_filesize, self.current_lines)
+        {
+            return Ok(false);
+        }
+        self.record_rollover_lag(now);
+        self.rollover()?;
+        self.open_writer_if_needed(now)?;
+        Ok(true)
+    }
+
+    /// Records how long after the scheduled time boundary a rollover happening at `now` is.
+    fn record_rollover_lag(&mut self, now: &DateTime<Local>) {
+        let Some(scheduled) = self.scheduled_rollover_opt.take() else {
+            return;
+        };
+        if *now < scheduled {
+            // Rolled over early, e.g. because of the size limit
+            return;
+        }
+        let lag = *now - scheduled;
+        internal_event!(lag_ms = lag.num_milliseconds(), "rollover after time boundary");
+        self.last_rollover_lag_opt = Some(lag);
+        self.max_rollover_lag_opt = Some(self.max_rollover_lag_opt.map_or(lag, |max| max.max(lag)));
+    }
+
+    /// Returns how late the last time based rollover happened relative to its boundary. Without
+    /// a [`RolloverTimer`], rollovers wait for the next write, so on a quiet service a period may
+    /// effectively end hours after its boundary.
+    pub fn last_rollover_lag(&self) -> Option<chrono::Duration> {
+        self.last_rollover_lag_opt
+    }
+
+    /// Returns the largest lag of a time based rollover seen so far, see
+    /// [`RollingFileAppender::last_rollover_lag`].
+    pub fn max_rollover_lag(&self) -> Option<chrono::Duration> {
+        self.max_rollover_lag_opt
+    }
+
+    /// Estimates the disk usage once retention kicks in, for data written at `bytes_per_second`
+    /// and rolled files shrinking to `compression_ratio` times their size (1.0 without
+    /// compression). Returns `None` if the rolling condition doesn't bound the file size or the
+    /// retention policy doesn't bound the number of files.
+    pub fn forecast_disk_usage(&self, bytes_per_second: f64, compression_ratio: f64) -> Option<DiskForecast> {
+        let file_size = self.condition.expected_file_size(bytes_per_second)?;
+        DiskForecast::estimate(
+            file_size,
+            self.retention.policy.max_files()?,
+            bytes_per_second,
+            compression_ratio,
+        )
+    }
+
+    /// Returns how long until the rolling condition switches to a new file, if it is time based.
+    /// The switch itself happens on the first write after that time.
+    pub fn time_until_rollover(&self) -> Option<chrono::Duration> {
+        let now = self.clock.now();
+        self.condition.next_rollover(&now).map(|next| next - now)
+    }
+
+    /// Flushes the active file and copies it to `dst`, which appears atomically once complete.
+    /// The copy can't interleave with a rollover or a write, so it holds exactly the lines written
+    /// so far. A hard link isn't used since it would keep growing with the active file. Returns the
+    /// number of bytes copied.
+    pub fn snapshot_current(&mut self, dst: &Path) -> io::Result<u64> {
+        self.flush()?;
+        let current = self
+            .current_path_opt
+            .clone()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no active log file"))?;
+        let mut tmp = dst.as_os_str().to_owned();
+        tmp.push(".tmp");
+        let tmp = PathBuf::from(tmp);
+        let copied = self.fs.copy(&current, &tmp)?;
+        if let Err(e) = self.fs.rename(&tmp, dst) {
+            let _ = self.fs.remove_file(&tmp);
+            return Err(e);
+        }
+        Ok(copied)
+    }
+
+    /// Returns a reference to the rolling condition
+    pub fn condition_ref(&self) -> &RC {
+        &self.condition
+    }
+
+    /// Returns a mutable reference to the rolling condition, possibly to mutate its state dynamically.
+    pub fn condition_mut(&mut self) -> &mut RC {
+        &mut self.condition
+    }
+
+    /// Bundles the rolled files of every day or month that ended before `now` into a single tar
+    /// archive per period and deletes the originals. The active file is never touched.
+    #[cfg(feature = "bundle")]
+    pub fn bundle_rolled_files(&mut self, period: BundlePeriod, now: &DateTime<Local>) -> io::Result<Vec<PathBuf>> {
+        archive::bundle_rolled_files_in(
+            &*self.fs,
+            &self.folder,
+            &self.prefix,
+            &*self.naming,
+            period,
+            now,
+            self.current_path_opt.as_deref(),
+        )
+    }
+
+    /// Applies the tiering policy to the rolled files as of `now`, see
+    /// [`RollingFileAppender::with_tiering`]. Returns the number of files changed.
+    pub fn apply_tiering(&mut self, now: &DateTime<Local>) -> io::Result<usize> {
+        match self.retention.tiering_opt.as_ref() {
+            Some(policy) => tiering::apply_tiering(
+                &*self.fs,
+                &self.folder,
+                &*self.naming,
+                policy,
+                now,
+                self.current_path_opt.as_deref(),
+            ),
+            None => Ok(0),
+        }
+    }
+
+    /// Concatenates consecutive rolled files of the same day that are smaller than `max_size`,
+    /// as long as the result stays below `max_size`. Returns the number of files removed.
+    pub fn consolidate_small_files(&mut self, max_size: u64) -> io::Result<usize> {
+        consolidate::consolidate_small_files(
+            &*self.fs,
+            &self.folder,
+            &*self.naming,
+            max_size,
+            self.current_path_opt.as_deref(),
+        )
+    }
+
+    /// Streams the lines matching `predicate` from all log files covering the given time range,
+    /// oldest first, decompressing compressed files. See [`search::search`].
+    pub fn search<P>(&mut self, range: std::ops::Range<DateTime<Local>>, predicate: P) -> io::Result<Search<P>>
+    where
+        P: FnMut(&str) -> bool,
+    {
+        self.flush()?;
+        let search = search::search_in(self.fs.clone(), &self.folder, &*self.naming, range, predicate)?;
+        #[cfg(feature = "zstd")]
+        let search = match filesystem::read_file(&*self.fs, &compression::dictionary_path(&self.folder, &self.prefix)) {
+            Ok(dictionary) => search.dictionary(dictionary),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => search,
+            Err(e) => return Err(e),
+        };
+        Ok(search)
+    }
+
+    /// Returns the name of the newest log file if it was started in the rollover period of `now`
+    fn resumable_file_name(&self, now: &DateTime<Local>) -> Option<String> {
+        let files = list_log_files(&*self.fs, &self.folder, &*self.naming, self.link.name_opt.as_deref()).ok()?;
+        files
+            .into_iter()
+            .filter(|fname| compression::strip_rolled_extensions(fname) == fname)
+            .filter_map(|fname| Some((self.naming.started(&fname)?, fname)))
+            .max()
+            .filter(|(started, _)| {
+                started <= now && self.condition.next_rollover(started).is_none_or(|next| next > *now)
+            })
+            .map(|(_, fname)| fname)
+    }
+
+    /// Returns the name of the file following a rollover, given the name `base` generated by the
+    /// naming scheme. If the file rolled over from got the same name, e.g. after two rollovers
+    /// within one second, a counter is appended: `.1`, `.2`... Other existing files of that name
+    /// are continued.
+    fn distinct_file_name(&self, base: &str) -> String {
+        if self.base_name_opt.as_deref() != Some(base) {
+            return base.to_string();
+        }
+        let counter = self
+            .current_name()
+            .and_then(|current| current.strip_prefix(base)?.strip_prefix('.')?.parse::<u64>().ok())
+            .unwrap_or(0);
+        format!("{}.{}", base, counter + 1)
+    }
+
+    /// Opens a writer for the current file.
+    fn open_writer_if_needed(&mut self, now: &DateTime<Local>) -> io::Result<()> {
+        if self.writer_opt.is_none() {
+            let resumed = self.resume_name_opt.is_some();
+            let p = match self.resume_name_opt.take() {
+                Some(name) => name,
+                None => {
+                    let base = self.naming.file_name(now);
+                    let p = self.distinct_file_name(&base);
+                    self.base_name_opt = Some(base);
+                    p
+                },
+            };
+            let folder = self.active_folder();
+            let new_file_path = Path::new(folder).join(&p);
+            let open_failed = |path: &Path| {
+                let path = path.to_path_buf();
+                move |source| io::Error::from(RollingFileError::OpenFailed { path, source })
+            };
+            if self.create_dir {
+                // Names may place files in subfolders
+                let parent = new_file_path.parent().unwrap_or(Path::new(folder));
+                self.fs.create_dir_all(parent).map_err(open_failed(parent))?;
+            }
+            if self.open_mode == OpenMode::Truncate && !resumed && self.fs.file_len(&new_file_path).is_ok() {
+                self.fs
+                    .set_len(&new_file_path, 0)
+                    .map_err(open_failed(&new_file_path))?;
+            }
+            let f = self
+                .fs
+                .open_append(&new_file_path)
+                .map_err(open_failed(&new_file_path))?;
+            internal_event!(path = %new_file_path.display(), "opened log file");
+            self.writer_opt = Some(FileWriter::new(
+                f,
+                self.effective_buffer_capacity(),
+                self.double_buffered,
+                &self.thread_options,
+            )?);
+            self.current_filesize = self.fs.file_len(&new_file_path).unwrap_or(0);
+            self.current_lines = 0;
+            if let (OpenMode::Preallocate(len), 0) = (self.open_mode, self.current_filesize) {
+                match self.fs.preallocate(&new_file_path, len) {
+                    Ok(()) => {},
+                    Err(e) if e.kind() == io::ErrorKind::Unsupported => {},
+                    Err(e) => eprintln!("WARNING: Failed to preallocate logfile {}: {}", p, e),
+                }
+            }
+            // Only files created from scratch get a preamble, it is written along with the first data
+            self.preamble_pending = self.current_filesize == 0;
+            self.repair_pending = self.current_filesize > 0;
+            if let Some(index) = self.index_opt.as_mut() {
+                if let Err(e) = index.reset(&*self.fs, &new_file_path) {
+                    eprintln!("WARNING: Failed to open time index for {}: {}", p, e);
+                }
+            }
+            let previous_opt = self.current_path_opt.replace(new_file_path.clone());
+            if previous_opt.as_ref().is_some_and(|previous| *previous != new_file_path) {
+                self.stats.rotations += 1;
+                self.stats.last_rotation_opt = Some(*now);
+            }
+            // A resumed file was started before
+            let opened = resumed.then(|| self.naming.started(&p)).flatten().unwrap_or(*now);
+            self.condition.file_opened(&opened);
+            self.opened_opt = Some(opened);
+            self.scheduled_rollover_opt = self.condition.next_rollover(now);
+            self.finish_rollover(previous_opt, new_file_path, now);
+        }
+        Ok(())
+    }
+
+    /// Writes data using the given datetime to calculate the rolling condition
+    pub fn write_with_datetime(&mut self, buf: &[u8], now: &DateTime<Local>) -> io::Result<usize> {
+        if !self.admit(self.transcoder.scratch_len(buf.len()), buf.len())? {
+            return Ok(buf.len());
+        }
+        if !self.line_aligned {
+            let data = self.transcoder.transcode(buf);
+            let written = self.write_raw_with_datetime(&data, now, true, true);
+            self.record_write(written)?;
+            self.current_lines += buf.iter().filter(|b| **b == b'\n').count() as u64;
+            return Ok(buf.len());
+        }
+        for line in buf.split_inclusive(|b| *b == b'\n') {
+            let may_rollover = self.at_line_start;
+            self.at_line_start = line.ends_with(b"\n");
+            let data = self.transcoder.transcode(line);
+            let written = self.write_raw_with_datetime(&data, now, may_rollover, true);
+            self.record_write(written)?;
+            self.current_lines += u64::from(self.at_line_start);
+        }
+        Ok(buf.len())
+    }
+
+    /// Writes a record entirely into one file: the rolling condition is checked once before it,
+    /// never within it, even if it holds several lines. Records written in several calls to
+    /// [`io::Write::write`], e.g. by `writeln!`, can only be kept together with
+    /// [`RollingFileAppender::with_line_aligned_rollover`].
+    pub fn write_record(&mut self, record: &[u8]) -> io::Result<()> {
+        let now = self.clock.now();
+        self.write_record_with_datetime(record, &now)
+    }
+
+    /// Same as [`RollingFileAppender::write_record`] using the given datetime to calculate the
+    /// rolling condition
+    pub fn write_record_with_datetime(&mut self, record: &[u8], now: &DateTime<Local>) -> io::Result<()> {
+        if !self.admit(self.transcoder.scratch_len(record.len()), record.len())? {
+            return Ok(());
+        }
+        // A line left incomplete by a previous write must not be split either
+        let may_rollover = !self.line_aligned || self.at_line_start;
+        let data = self.transcoder.transcode(record);
+        let written = self.write_raw_with_datetime(&data, now, may_rollover, true);
+        self.record_write(written)?;
+        if let Some(last) = record.last() {
+            self.at_line_start = *last == b'\n';
+        }
+        self.current_lines += record.iter().filter(|b| **b == b'\n').count() as u64;
+        Ok(())
+    }
+
+    /// Same as [`io::Write::write_vectored`] using the given datetime to calculate the rolling
+    /// condition. The slices are one record, they always land in the same file. Unless the data
+    /// has to be transcoded or split, they are handed to the file without being concatenated.
+    pub fn write_vectored_with_datetime(&mut self, bufs: &[IoSlice<'_>], now: &DateTime<Local>) -> io::Result<usize> {
+        let len = bufs.iter().map(|buf| buf.len()).sum::<usize>();
+        if !self.transcoder.is_passthrough() || self.split_limit().is_some() {
+            let record = bufs.iter().flat_map(|buf| buf.iter().copied()).collect::<Vec<_>>();
+            self.write_record_with_datetime(&record, now)?;
+            return Ok(len);
+        }
+        if !self.admit(0, len)? {
+            return Ok(len);
+        }
+        let may_rollover = !self.line_aligned || self.at_line_start;
+        let accepted = Cell::new(0);
+        let written = match self.write_slices_with_datetime(bufs, len, now, may_rollover, &accepted) {
+            Ok(()) => Ok(()),
+            Err(e) => self
+                .recover_write(e, bufs, &accepted, now, |rfa| {
+                    rfa.write_slices_with_datetime(
+                        bufs,
+                        len - accepted.get(),
+                        now,
+                        may_rollover && accepted.get() == 0,
+                        &accepted,
+                    )
+                })
+                .map(|_| ()),
+        };
+        self.record_write(written)?;
+        if let Some(last) = bufs.iter().rev().find_map(|buf| buf.last()) {
+            self.at_line_start = *last == b'\n';
+        }
+        self.current_lines += bufs
+            .iter()
+            .map(|buf| buf.iter().filter(|b| **b == b'\n').count() as u64)
+            .sum::<u64>();
+        Ok(len)
+    }
+
+    /// Writes the `len` bytes of `bufs` following the ones already `accepted` as-is to the active
+    /// file, rolling over first if needed. Adds the number of bytes the file accepted to
+    /// `accepted`, also when it fails midway.
+    fn write_slices_with_datetime(
+        &mut self,
+        bufs: &[IoSlice<'_>],
+        len: usize,
+        now: &DateTime<Local>,
+        may_rollover: bool,
+        accepted: &Cell<usize>,
+    ) -> io::Result<()> {
+        let probe_opt = self.probe_opt.clone();
+        let _probe_guard = probe_opt.as_deref().map(IoProbe::begin);
+        self.prepare_write(now, may_rollover, probe_opt.as_deref())?;
+        let writer = self
+            .writer_opt
+            .as_mut()
+            .ok_or_else(|| io::Error::other("unexpected condition: writer is missing"))?;
+        let mut written = 0;
+        let result = writer.write_all_vectored(bufs, accepted.get(), &mut written);
+        self.count_written(written, accepted);
+        result?;
+        let len = u64::try_from(len).unwrap_or(u64::MAX);
+        if self.sync.wrote(len) {
+            self.sync()?;
+        }
+        Ok(())
+    }
+
+    /// Keeps the error of a write for [`RollingFileAppender::stats`]
+    fn record_write(&mut self, written: io::Result<()>) -> io::Result<()> {
+        if let Err(e) = written.as_ref() {
+            self.stats.last_write_error_opt = Some(e.to_string());
+        }
+        written
+    }
+
+    /// Writes data to the file as-is, bypassing any text processing. Unless `split` is false,
+    /// e.g. for binary frames, it may be split as [`RollingFileAppender::with_split_large_writes`]
+    /// asks.
+    fn write_raw_with_datetime(
+        &mut self,
+        buf: &[u8],
+        now: &DateTime<Local>,
+        may_rollover: bool,
+        split: bool,
+    ) -> io::Result<()> {
+        let mut rest = buf;
+        let mut may_rollover = may_rollover;
+        loop {
+            let accepted = Cell::new(0);
+            let len = match self.write_chunk_with_datetime(rest, now, may_rollover, split, &accepted) {
+                Ok(len) => len,
+                Err(e) => self
+                    .recover_write(e, &[IoSlice::new(rest)], &accepted, now, |rfa| {
+                        let done = accepted.get();
+                        rfa.write_chunk_with_datetime(&rest[done..], now, may_rollover && done == 0, split, &accepted)
+                            .map(|len| done + len)
+                    })?
+                    .unwrap_or(rest.len()),
+            };
+            if len == rest.len() {
+                break;
+            }
+            rest = &rest[len..];
+            may_rollover = true;
+        }
+        if self.sync.wrote(u64::try_from(buf.len()).unwrap_or(u64::MAX)) {
+            self.sync()?;
+        }
+        Ok(())
+    }
+
+    /// Writes as much of `buf` as [`RollingFileAppender::with_split_large_writes`] allows into the
+    /// active file, all of it if `split` is false, rolling over first if needed. Returns the
+    /// number of bytes written. Adds the number of bytes the file accepted to `accepted`, also
+    /// when it fails midway.
+    fn write_chunk_with_datetime(
+        &mut self,
+        buf: &[u8],
+        now: &DateTime<Local>,
+        may_rollover: bool,
+        split: bool,
+        accepted: &Cell<usize>,
+    ) -> io::Result<usize> {
+        let probe_opt = self.probe_opt.clone();
+        let _probe_guard = probe_opt.as_deref().map(IoProbe::begin);
+        self.prepare_write(now, may_rollover, probe_opt.as_deref())?;
+        let chunk = match self.split_limit().filter(|_| split) {
+            Some(limit) => {
+                let room = usize::try_from(limit.saturating_sub(self.current_filesize)).unwrap_or(usize::MAX);
+                // With no room left after a rollover, e.g. because it failed, write anyway
+                &buf[..buf.len().min(if room == 0 && may_rollover { buf.len() } else { room })]
+            },
+            None => buf,
+        };
+        let writer = self
+            .writer_opt
+            .as_mut()
+            .ok_or_else(|| io::Error::other("unexpected condition: writer is missing"))?;
+        let mut written = 0;
+        let result = writer.write_all_vectored(&[IoSlice::new(chunk)], 0, &mut written);
+        self.count_written(written, accepted);
+        result.map(|_| chunk.len())
+    }
+
+    /// Counts `written` bytes the active file accepted
+    fn count_written(&mut self, written: usize, accepted: &Cell<usize>) {
+        let len = u64::try_from(written).unwrap_or(u64::MAX);
+        self.current_filesize += len;
+        self.stats.bytes_written += len;
+        accepted.set(accepted.get() + written);
+    }
+
+    /// Rolls over first if needed and allowed, then makes sure the active file is open and ready
+    /// for data
+    fn prepare_write(
+        &mut self,
+        now: &DateTime<Local>,
+        may_rollover: bool,
+        probe_opt: Option<&IoProbe>,
+    ) -> io::Result<()> {
+        if probe_opt.is_some_and(|p| p.take_reopen_request()) {
+            // The previous operation stalled, give up on the current handle even if it can't be
+            // flushed and start over with a new file
+            if let Err(e) = self.flush_writer() {
+                eprintln!("WARNING: Failed to flush stalled logfile {}", e);
+            }
+            self.writer_opt.take();
+            self.current_filesize = 0;
+        }
+        if may_rollover {
+            #[cfg(all(unix, feature = "signal"))]
+            self.handle_sighup();
+            self.refresh_size_if_due();
+            self.fail_back_if_due(now);
+        }
+        if may_rollover
+            && self
+                .condition
+                .should_rollover_with_lines(now, self.current_filesize, self.current_lines)
+        {
+            self.record_rollover_lag(now);
+            if let Err(e) = self.rollover_retrying() {
+                // If we can't rollover, just try to continue writing anyway
+                // (better than missing data).
+                // This will likely used to implement logging, so
+                // avoid using log::warn and log to stderr directly
+                eprintln!("WARNING: Failed to rotate logfile  {}", e);
+            }
+        }
+        self.open_writer_if_needed(now)?;
+        self.repair_torn_line_if_needed();
+        self.write_preamble_if_needed()?;
+        if let Some(index) = self.index_opt.as_mut() {
+            if let Err(e) = index.record(now, self.current_filesize) {
+                eprintln!("WARNING: Failed to update time index {}", e);
+            }
+        }
+        self.condition.written(now);
+        self.last_write_opt = Some(*now);
+        Ok(())
+    }
+
+    /// Rolls over, retrying failures as the error policy says
+    fn rollover_retrying(&mut self) -> io::Result<()> {
+        let mut result = self.rollover();
+        for delay in self.error_policy.delays() {
+            if result.is_ok() {
+                break;
+            }
+            thread::sleep(delay);
+            result = self.rollover();
+        }
+        result
+    }
+
+    /// Closes the current file to continue in the failover folder if `error` calls for it.
+    /// Returns whether it did.
+    fn fail_over(&mut self, error: &io::Error, now: &DateTime<Local>) -> bool {
+        if !self
+            .failover_opt
+            .as_mut()
+            .is_some_and(|failover| failover.activate(error, now))
+        {
+            return false;
+        }
+        // The buffered data can't be written to the unusable disk either
+        let unwritten = self
+            .writer_opt
+            .take()
+            .map(FileWriter::into_unwritten)
+            .unwrap_or_default();
+        self.current_filesize = 0;
+        // The name is free in the failover folder
+        self.base_name_opt = None;
+        if !unwritten.is_empty() {
+            // Writes already acknowledged go first into the new file
+            if let Err(e) = self.write_unwritten(&unwritten, now) {
+                eprintln!(
+                    "WARNING: Failed to move {} buffered bytes to the failover folder {}",
+                    unwritten.len(),
+                    e
+                );
+            }
+        }
+        true
+    }
+
+    /// Writes data taken from the buffer of a closed file to a new file
+    fn write_unwritten(&mut self, unwritten: &[u8], now: &DateTime<Local>) -> io::Result<()> {
+        self.open_writer_if_needed(now)?;
+        self.write_preamble_if_needed()?;
+        let writer = self
+            .writer_opt
+            .as_mut()
+            .ok_or_else(|| io::Error::other("unexpected condition: writer is missing"))?;
+        writer.write_all(unwritten)?;
+        self.current_filesize += u64::try_from(unwritten.len()).unwrap_or(u64::MAX);
+        Ok(())
+    }
+
+    /// Rolls over back to the log folder once the retry interval of the failover has elapsed
+    fn fail_back_if_due(&mut self, now: &DateTime<Local>) {
+        if !self
+            .failover_opt
+            .as_ref()
+            .is_some_and(|failover| failover.is_retry_due(now))
+        {
+            return;
+        }
+        if let Err(e) = self.rollover() {
+            eprintln!("WARNING: Failed to rotate logfile  {}", e);
+        }
+        if let Some(failover) = self.failover_opt.as_mut() {
+            failover.deactivate();
+        }
+    }
+
+    /// Applies the error policy to a write of `data` which failed with `error`: retries `write`,
+    /// then returns its result if a retry succeeds, `None` if the data went to the fallback. The
+    /// first `accepted` bytes of `data` reached the file already, `write` only writes the rest and
+    /// counts them in `accepted` as well, so no byte is written twice.
+    fn recover_write<T>(
+        &mut self,
+        error: io::Error,
+        data: &[IoSlice<'_>],
+        accepted: &Cell<usize>,
+        now: &DateTime<Local>,
+        mut write: impl FnMut(&mut Self) -> io::Result<T>,
+    ) -> io::Result<Option<T>> {
+        let mut error = error;
+        if self.fail_over(&error, now) {
+            match write(self) {
+                Ok(value) => return Ok(Some(value)),
+                Err(e) => error = e,
+            }
+        }
+        for delay in self.error_policy.delays() {
+            thread::sleep(delay);
+            match write(self) {
+                Ok(value) => return Ok(Some(value)),
+                Err(e) => error = e,
+            }
+        }
+        let mut data = unwritten_slices(data, accepted.get());
+        match self.error_policy.fallback() {
+            ErrorFallback::Propagate => return Err(error),
+            ErrorFallback::Stderr => {
+                let mut stderr = io::stderr().lock();
+                if let Err(e) = data.try_for_each(|buf| stderr.write_all(buf)) {
+                    eprintln!("WARNING: Failed to write to stderr instead of the logfile {}", e);
+                }
+            },
+            ErrorFallback::Drop => {
+                let len = data.map(<[u8]>::len).sum::<usize>();
+                self.discarded_bytes += u64::try_from(len).unwrap_or(u64::MAX);
+            },
+        }
+        self.stats.last_write_error_opt = Some(error.to_string());
+        Ok(None)
+    }
+
+    /// Returns the size no file may exceed if writes are split
+    fn split_limit(&self) -> Option<u64> {
+        if self.split_large_writes {
+            self.condition.max_file_size()
+        } else {
+            None
+        }
+    }
+
+    /// Replaces the tracked size of the active file with its actual size, which includes writes of
+    /// other processes.
+    fn refresh_size_if_due(&mut self) {
+        let Some(interval) = self.size_refresh_opt else {
+            return;
+        };
+        if self.last_size_refresh.elapsed() < interval {
+            return;
+        }
+        self.last_size_refresh = Instant::now();
+        let Some(path) = self.current_path_opt.clone() else {
+            return;
+        };
+        if self.writer_opt.is_none() {
+            return;
+        }
+        let refreshed = self.flush_writer().and_then(|_| self.fs.file_len(&path));
+        match refreshed {
+            Ok(len) => self.current_filesize = len,
+            Err(e) => eprintln!("WARNING: Failed to refresh logfile size {}", e),
+        }
+    }
+
+    /// Flushes the active file and asks the OS to persist it to disk.
+    pub(crate) fn sync(&mut self) -> io::Result<()> {
+        if let Some(writer) = self.writer_opt.as_mut() {
+            writer.sync_all()?;
+        }
+        self.sync.synced();
+        Ok(())
+    }
+
+    /// Flushes and fsyncs the active file, then waits for the maintenance jobs queued so far, such
+    /// as compressing and removing rolled files. Run by the guards when they are dropped.
+    pub(crate) fn finish_pending(&mut self) {
+        if let Err(e) = self.try_finish_pending() {
+            eprintln!("WARNING: Failed to sync logfile {}", e);
+        }
+    }
+
+    /// Like [`RollingFileAppender::finish_pending`], returning a failure to sync; the maintenance
+    /// jobs are waited for either way.
+    pub(crate) fn try_finish_pending(&mut self) -> io::Result<()> {
+        let result = self.sync();
+        if let Some(maintenance) = self.maintenance_opt.as_ref() {
+            maintenance.drain();
+        }
+        result
+    }
+
+    fn flush_writer(&mut self) -> io::Result<()> {
+        if let Some(writer) = self.writer_opt.as_mut() {
+            writer.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Repairs an incomplete last line of a resumed file before the first write to it.
+    fn repair_torn_line_if_needed(&mut self) {
+        if !std::mem::take(&mut self.repair_pending) {
+            return;
+        }
+        let (Some(recovery), Some(path)) = (self.torn_write_recovery_opt.as_ref(), self.current_path_opt.as_ref())
+        else {
+            return;
+        };
+        let encoding = self.transcoder.encoding();
+        let repaired = recovery::repair_torn_line(&*self.fs, path, self.current_filesize, encoding, recovery);
+        let result = match repaired {
+            Ok(Repair::Intact) => Ok(()),
+            Ok(Repair::Truncated(len)) => {
+                self.current_filesize = len;
+                self.preamble_pending = len == 0;
+                Ok(())
+            },
+            Ok(Repair::Append(data)) => match self.writer_opt.as_mut() {
+                Some(writer) => writer.write_all(&data).map(|_| {
+                    self.current_filesize += u64::try_from(data.len()).unwrap_or(u64::MAX);
+                }),
+                None => Ok(()),
+            },
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            eprintln!(
+                "WARNING: Failed to repair torn line of {}: {}",
+                path.to_string_lossy(),
+                e
+            );
+        }
+    }
+
+    /// Writes the data every new file starts with, such as a byte order mark.
+    fn write_preamble_if_needed(&mut self) -> io::Result<()> {
+        if !self.preamble_pending {
+            return Ok(());
+        }
+        self.preamble_pending = false;
+        let mut preamble = vec![];
+        if self.bom {
+            preamble.extend_from_slice(self.transcoder.encoding().bom());
+        }
+        if let Some((header, started)) = self.header_opt.as_ref() {
+            let mut settings = self.condition.describe();
+            settings.push(' ');
+            settings.push_str(&self.retention.policy.describe());
+            let line = header.render(started, settings.trim_start());
+            preamble.extend_from_slice(&self.transcoder.transcode(line.as_bytes()));
+        }
+        if let Some(header) = self.hooks.file_header_opt.as_ref() {
+            preamble.extend_from_slice(&header());
+        }
+        if let (Some(writer), false) = (self.writer_opt.as_mut(), preamble.is_empty()) {
+            writer.write_all(&preamble)?;
+            self.current_filesize += u64::try_from(preamble.len()).unwrap_or(u64::MAX);
+        }
+        Ok(())
+    }
+
+    /// Writes the footer ending a file before it is rolled, unless nothing was written to it.
+    fn write_footer_if_needed(&mut self) -> io::Result<()> {
+        let (Some(footer), Some(writer), false) = (
+            self.hooks.file_footer_opt.as_ref(),
+            self.writer_opt.as_mut(),
+            self.preamble_pending,
+        ) else {
+            return Ok(());
+        };
+        let data = footer();
+        writer.write_all(&data)?;
+        self.current_filesize += u64::try_from(data.len()).unwrap_or(u64::MAX);
+        Ok(())
+    }
+
+    /// Writes a length-prefixed, CRC-checked binary record. The whole frame always lands in a
+    /// single file; read it back with [`FrameReader`].
+    pub fn write_frame(&mut self, payload: &[u8]) -> io::Result<()> {
+        let now = self.clock.now();
+        self.write_frame_with_datetime(payload, &now)
+    }
+
+    /// Writes a binary record using the given datetime to calculate the rolling condition
+    pub fn write_frame_with_datetime(&mut self, payload: &[u8], now: &DateTime<Local>) -> io::Result<()> {
+        if !self.admit(payload.len() + frame::FRAME_HEADER_LEN, payload.len())? {
+            return Ok(());
+        }
+        let frame = frame::encode_frame(payload)?;
+        // A frame split across files can't be read back
+        self.write_raw_with_datetime(&frame, now, true, false)
+    }
+}
+
+impl<RC> io::Write for RollingFileAppender<RC>
+where
+    RC: RollingCondition,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let now = self.clock.now();
+        self.write_with_datetime(buf, &now)
+    }
+
+    /// Writes all slices as one record
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        let now = self.clock.now();
+        self.write_vectored_with_datetime(bufs, &now)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let probe_opt = self.probe_opt.clone();
+        let _probe_guard = probe_opt.as_deref().map(IoProbe::begin);
+        self.flush_writer()
+    }
+}
+
+/// Makes the link named after the prefix, or `link_name`, point to the given file. Failures are
+/// ignored since not every file system supports links.
+fn update_latest_link(fs: &dyn Fs, latest_link: LatestLink, folder: &str, link_name: &str, target: &Path) {
+    let link = Path::new(folder).join(link_name);
+    if link == target {
+        // The active file is named like the link, e.g. with `SequentialNaming`
+        return;
+    }
+    if let Err(source) = latest_link.update(fs, target, &link) {
+        eprintln!("WARNING: {}", RollingFileError::SymlinkFailed { link, source });
+    }
+}
+
+/// Returns the names of the log files of the naming scheme in `folder`, in no particular order.
+/// The default link, named after the prefix, is left out by the scheme itself if needed.
+fn list_log_files(
+    fs: &dyn Fs,
+    folder: &str,
+    naming: &dyn NamingScheme,
+    link_name_opt: Option<&str>,
+) -> io::Result<Vec<String>> {
+    let mut log_files = naming.list(fs, Path::new(folder))?;
+    log_files.retain(|fname| Some(fname.as_str()) != link_name_opt && !is_sidecar_file(fname));
+    Ok(log_files)
+}
+
+/// Tells which log files of the naming scheme `policy` keeps, newest first. The deletions
+/// hooks veto are kept.
+fn plan_expiry(
+    fs: &dyn Fs,
+    folder: &str,
+    (naming, link_name_opt): (&dyn NamingScheme, Option<&str>),
+    policy: &RetentionPolicy,
+    now: &DateTime<Local>,
+    hooks_opt: Option<&Hooks>,
+) -> io::Result<Vec<PlannedFile>> {
+    let mut log_files = list_log_files(fs, folder, naming, link_name_opt)
+        .map_err(|e| io::Error::from(RollingFileError::RetentionFailed(e)))?;
+    log_files.sort_by(|a, b| naming.cmp_age(b, a));
+
+    let needs_sizes = policy.needs_sizes();
+    let mut retained = vec![];
+    let mut newer_started_opt = None;
+    for fname in &log_files {
+        let len = if needs_sizes {
+            fs.file_len(&Path::new(folder).join(fname)).unwrap_or(0)
+        } else {
+            0
+        };
+        retained.push(retention::RetainedFile {
+            len,
+            ended_opt: newer_started_opt,
+        });
+        newer_started_opt = naming.started(fname);
+    }
+    let planned = policy
+        .expiry_reasons(&retained, now)
+        .into_iter()
+        .zip(log_files)
+        .enumerate()
+        .map(|(i, (reason_opt, fname))| {
+            let path = Path::new(folder).join(fname);
+            let (action, reason) = match reason_opt {
+                Some(reason) if hooks_opt.is_some_and(|hooks| hooks.vetoes_deletion(&path)) => {
+                    (RetentionAction::Keep, format!("deletion by {} vetoed", reason))
+                },
+                Some(reason) => (RetentionAction::Delete, reason),
+                None if i == 0 => (RetentionAction::Keep, "newest file".to_string()),
+                None => (RetentionAction::Keep, format!("within {}", policy.describe())),
+            };
+            PlannedFile { path, action, reason }
+        })
+        .collect();
+    Ok(planned)
+}
+
+/// Deletes or archives the log files of the naming scheme which `policy` doesn't keep, unless
+/// the hooks veto, then applies the retention of the archive folder if files were moved
+/// there. Returns the deleted files.
+fn remove_expired_files(
+    fs: &dyn Fs,
+    folder: &str,
+    naming: (&dyn NamingScheme, Option<&str>),
+    (policy, hooks_opt): (&RetentionPolicy, Option<&Hooks>),
+    (archive, archive_retention_opt): (&ArchivePolicy, Option<&RetentionPolicy>),
+    now: &DateTime<Local>,
+    pool: &pool::Pool,
+) -> io::Result<Vec<PathBuf>> {
+    internal_span!("retention", folder, naming = ?naming.0);
+    if policy.keeps_everything() {
+        return Ok(vec![]);
+    }
+    let expired = plan_expiry(fs, folder, naming, policy, now, hooks_opt)?
+        .into_iter()
+        .filter(|f| f.action == RetentionAction::Delete)
+        .map(|f| f.path)
+        .collect::<Vec<_>>();
+
+    let deleted = std::sync::Mutex::new(vec![]);
+    if !expired.is_empty() {
+        pool.for_each(&expired, |p| {
+            if let ArchivePolicy::MoveTo(archive_folder) = archive {
+                if let Err(e) = archive::move_to_archive(fs, folder, p, archive_folder) {
+                    tracing::error!("WARNING: Failed to archive old logfile {}: {}", p.to_string_lossy(), e);
+                }
+                return;
+            }
+            if let Err(e) = fs.remove_file(p) {
+                tracing::error!("WARNING: Failed to remove old logfile {}: {}", p.to_string_lossy(), e);
+            } else {
+                internal_event!(path = %p.display(), "removed expired log file");
+                let _ = fs.remove_file(&index::index_path_for(p));
+                deleted.lock().unwrap_or_else(|e| e.into_inner()).push(p.clone());
+            }
+        });
+    }
+    let mut deleted = deleted.into_inner().unwrap_or_else(|e| e.into_inner());
+    deleted.sort();
+    // Prune the subfolders emptied, e.g. of `DailyFolderNaming`. Removing a folder which still
+    // has files fails, which is fine.
+    let mut emptied = deleted
+        .iter()
+        .filter_map(|p| p.parent())
+        .filter(|dir| *dir != Path::new(folder))
+        .collect::<Vec<_>>();
+    emptied.dedup();
+    for dir in emptied {
+        let _ = fs.remove_dir(dir);
+    }
+    if let (ArchivePolicy::MoveTo(archive_folder), Some(archive_retention)) = (archive, archive_retention_opt) {
+        if !expired.is_empty() {
+            let archive_folder = archive_folder.to_string_lossy();
+            deleted.extend(remove_expired_files(
+                fs,
+                &archive_folder,
+                (naming.0, None),
+                (archive_retention, None),
+                (&ArchivePolicy::Delete, None),
+                now,
+                pool,
+            )?);
+        }
+    }
+    Ok(deleted)
+}
+
+/// Format of the date/time suffix of log file names.
+const FILE_NAME_DATETIME_FORMAT: &str = "%Y%m%d.%H%M%S";
+
+/// Parses the date/time of a log file name with the given suffix format, read in `timezone`.
+/// Formats without a time of day give midnight.
+pub(crate) fn file_name_datetime_with_format(
+    prefix: &str,
+    fname: &str,
+    format: &str,
+    timezone: Timezone,
+) -> Option<DateTime<Local>> {
+    let suffix = fname.strip_prefix(prefix)?.strip_prefix('.')?;
+    let parse = |suffix: &str| {
+        NaiveDateTime::parse_from_str(suffix, format)
+            .or_else(|_| NaiveDate::parse_from_str(suffix, format).map(|d| d.and_time(NaiveTime::MIN)))
+            .ok()
+    };
+    let naive = parse(suffix).or_else(|| {
+        // A file started in the same second as the previous one, see `distinct_file_name`
+        let (suffix, counter) = suffix.rsplit_once('.')?;
+        if counter.is_empty() || !counter.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        parse(suffix)
+    })?;
+    timezone.from_naive(&naive)
+}
+
+/// Orders log file names the way they were created: numbers compare by value, so a file with the
+/// counter `.10` comes after `.9`, and compressed files sort as if they were not.
+pub(crate) fn cmp_file_names(a: &str, b: &str) -> std::cmp::Ordering {
+    use std::cmp::Ordering;
+    let digits = |s: &[u8]| s.iter().take_while(|c| c.is_ascii_digit()).count();
+    let trim_zeros = |s: &[u8]| -> Vec<u8> { s.iter().copied().skip_while(|c| *c == b'0').collect() };
+    let (mut x, mut y) = (
+        compression::strip_rolled_extensions(a).as_bytes(),
+        compression::strip_rolled_extensions(b).as_bytes(),
+    );
+    loop {
+        let ordering = match (x.first(), y.first()) {
+            (None, None) => return a.cmp(b),
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(c), Some(d)) if c.is_ascii_digit() && d.is_ascii_digit() => {
+                let (m, n) = (digits(x), digits(y));
+                let (p, q) = (trim_zeros(&x[..m]), trim_zeros(&y[..n]));
+                let ordering = p.len().cmp(&q.len()).then(p.cmp(&q)).then(m.cmp(&n));
+                (x, y) = (&x[m..], &y[n..]);
+                ordering
+            },
+            (Some(c), Some(d)) => {
+                (x, y) = (&x[1..], &y[1..]);
+                c.cmp(d)
+            },
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+}
+
+/// Returns true for auxiliary files kept next to log files, which are not log files themselves.
+pub(crate) fn is_sidecar_file(fname: &str) -> bool {
+    Path::new(fname).extension().is_some_and(|ext| {
+        if ext == audit::AUDIT_EXTENSION || ext == filesystem::POINTER_EXTENSION {
+            return true;
+        }
+        #[cfg(feature = "zstd")]
+        if ext == compression::DICTIONARY_EXTENSION {
+            return true;
+        }
+        #[cfg(feature = "upload")]
+        if ext == upload::PENDING_EXTENSION {
+            return true;
+        }
+        if ext == consolidate::JOURNAL_EXTENSION || ext == consolidate::MERGED_EXTENSION {
+            return true;
+        }
+        ext == index::INDEX_EXTENSION
+    })
+}
+
+/// Quotes a string for JSON
+#[cfg(any(feature = "admin", feature = "manifest"))]
+pub(crate) fn json_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if c.is_control() => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Returns the data of `bufs` after its first `written` bytes, slice by slice
+fn unwritten_slices<'a>(bufs: &'a [IoSlice<'_>], written: usize) -> impl Iterator<Item = &'a [u8]> {
+    let mut skip = written;
+    bufs.iter().map(move |buf| {
+        let start = skip.min(buf.len());
+        skip -= start;
+        &buf[start..]
+    })
+}
+
+/// A rolling file appender with a rolling condition based on date/time or size.
+pub type BasicRollingFileAppender = RollingFileAppender<RollingConditionBasic>;
+
+#[cfg(test)]
+mod t {
+    #[test]
+    fn test_bom_on_new_files() {
+        use super::*;
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let mut rfa = BasicRollingFileAppender::new(folder, "log.log", RollingConditionBasic::new().hourly(), 3)
+            .unwrap()
+            .with_encoding(Encoding::Utf16Le)
+            .with_bom(true);
+        rfa.write_with_datetime(b"a", &Local.with_ymd_and_hms(2021, 3, 30, 1, 2, 3).unwrap())
+            .unwrap();
+        rfa.write_with_datetime(b"b", &Local.with_ymd_and_hms(2021, 3, 30, 1, 2, 4).unwrap())
+            .unwrap();
+        rfa.write_with_datetime(b"c", &Local.with_ymd_and_hms(2021, 3, 30, 2, 0, 0).unwrap())
+            .unwrap();
+        rfa.flush().unwrap();
+        let first = std::fs::read(dir.path().join("log.log.20210330.010203")).unwrap();
+        assert_eq!(first, vec![0xff, 0xfe, b'a', 0, b'b', 0]);
+        let second = std::fs::read(dir.path().join("log.log.20210330.020000")).unwrap();
+        assert_eq!(second, vec![0xff, 0xfe, b'c', 0]);
+    }
+
+    #[test]
+    fn test_line_aligned_rollover() {
+        use super::*;
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let condition = RollingConditionBasic::new().max_size(4);
+        let mut rfa = BasicRollingFileAppender::new(folder, "log.log", condition, 9)
+            .unwrap()
+            .with_line_aligned_rollover(true);
+        rfa.rollover().unwrap();
+        let now = Local.with_ymd_and_hms(2099, 3, 30, 1, 2, 3).unwrap();
+        rfa.write_with_datetime(b"abc", &now).unwrap();
+        rfa.write_with_datetime(b"def\ngh\n", &Local.with_ymd_and_hms(2099, 3, 30, 1, 2, 4).unwrap())
+            .unwrap();
+        rfa.flush().unwrap();
+        let first = std::fs::read(dir.path().join("log.log.20990330.010203")).unwrap();
+        assert_eq!(first, b"abcdef\n");
+        let second = std::fs::read(dir.path().join("log.log.20990330.010204")).unwrap();
+        assert_eq!(second, b"gh\n");
+    }
+
+    #[test]
+    fn test_staging_folder() {
+        use super::*;
+        let dir = tempfile::tempdir().unwrap();
+        let staging = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let condition = RollingConditionBasic::new().max_size(4);
+        let mut rfa = BasicRollingFileAppender::new(folder, "log.log", condition, 9)
+            .unwrap()
+            .with_staging_folder(staging.path().to_str().unwrap())
+            .unwrap();
+        rfa.rollover().unwrap();
+        rfa.write_with_datetime(b"abcd", &Local.with_ymd_and_hms(2099, 3, 30, 1, 2, 3).unwrap())
+            .unwrap();
+        rfa.write_with_datetime(b"efgh", &Local.with_ymd_and_hms(2099, 3, 30, 1, 2, 4).unwrap())
+            .unwrap();
+        rfa.flush().unwrap();
+
+        let first = std::fs::read(dir.path().join("log.log.20990330.010203")).unwrap();
+        assert_eq!(first, b"abcd");
+        assert!(!staging.path().join("log.log.20990330.010203").exists());
+        let active = staging.path().join("log.log.20990330.010204");
+        assert_eq!(std::fs::read(&active).unwrap(), b"efgh");
+        assert_eq!(
+            std::fs::canonicalize(dir.path().join("log.log")).unwrap(),
+            std::fs::canonicalize(active).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_staging_keeps_existing_files() {
+        use super::*;
+        let dir = tempfile::tempdir().unwrap();
+        let staging = tempfile::tempdir().unwrap();
+        let name = "log.log.20990330.010203";
+        std::fs::write(dir.path().join(name), "moved before\n").unwrap();
+        std::fs::write(staging.path().join(name), "left staged\n").unwrap();
+        let _rfa =
+            BasicRollingFileAppender::new(dir.path().to_str().unwrap(), "log.log", RollingConditionBasic::new(), 9)
+                .unwrap()
+                .with_staging_folder(staging.path().to_str().unwrap())
+                .unwrap();
+
+        let read = |name: &str| std::fs::read_to_string(dir.path().join(name)).unwrap();
+        assert_eq!(read(name), "moved before\n");
+        assert_eq!(read(&format!("{}.1", name)), "left staged\n");
+        assert!(!staging.path().join(name).exists());
+    }
+
+    #[test]
+    fn test_metadata_header() {
+        use super::*;
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let mut rfa = BasicRollingFileAppender::new(folder, "log.log", RollingConditionBasic::new().daily(), 9)
+            .unwrap()
+            .with_metadata_header(MetadataHeader::new().app("myapp", "2.0.1"));
+        rfa.rollover().unwrap();
+        rfa.write_with_datetime(b"first\n", &Local.with_ymd_and_hms(2099, 3, 30, 1, 2, 3).unwrap())
+            .unwrap();
+        rfa.flush().unwrap();
+        let contents = std::fs::read_to_string(dir.path().join("log.log.20990330.010203")).unwrap();
+        let (header, rest) = contents.split_once('\n').unwrap();
+        assert!(header.starts_with("# local-rolling-file "));
+        assert!(header.contains(" app=myapp/2.0.1 "));
+        assert!(header.contains(&format!(" pid={} ", std::process::id())));
+        assert!(header.ends_with(" rollover=daily max_files=9"));
+        assert_eq!(rest, "first\n");
+    }
+
+    #[test]
+    fn test_size_refresh() {
+        use super::*;
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let condition = RollingConditionBasic::new().max_size(20);
+        let mut rfa = BasicRollingFileAppender::new(folder, "log.log", condition, 9)
+            .unwrap()
+            .with_size_refresh(std::time::Duration::ZERO);
+        rfa.rollover().unwrap();
+        let now = Local.with_ymd_and_hms(2099, 3, 30, 1, 0, 0).unwrap();
+        rfa.write_with_datetime(b"mine\n", &now).unwrap();
+        rfa.flush().unwrap();
+        // Another process appends to the same file
+        let path = dir.path().join("log.log.20990330.010000");
+        let mut other = std::fs::OpenOptions::new().append(true).open(&path).unwrap();
+        other.write_all(b"someone else's line\n").unwrap();
+
+        let later = Local.with_ymd_and_hms(2099, 3, 30, 1, 0, 1).unwrap();
+        rfa.write_with_datetime(b"mine\n", &later).unwrap();
+        rfa.flush().unwrap();
+        assert!(dir.path().join("log.log.20990330.010001").exists());
+    }
+
+    #[test]
+    fn test_size_counts_buffered_and_existing_data() {
+        use super::*;
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let build = || {
+            BasicRollingFileAppender::builder()
+                .folder(folder)
+                .prefix("log.log")
+                .condition(RollingConditionBasic::new().max_size(100))
+                .buffer_capacity(4096)
+                .file_name(|_| "log.log.current".to_string())
+                .build()
+                .unwrap()
+        };
+        let mut rfa = build();
+        rfa.write_all(&[b'x'; 60]).unwrap();
+        assert_eq!(rfa.current_file_size(), 60);
+        drop(rfa);
+
+        // After a restart, the size of the reopened file counts
+        let mut rfa = build();
+        assert_eq!(rfa.current_file_size(), 60);
+        rfa.write_all(&[b'x'; 50]).unwrap();
+        assert_eq!(rfa.current_file_size(), 110);
+    }
+
+    #[test]
+    fn test_unbuffered_writes() {
+        use super::*;
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let mut rfa = BasicRollingFileAppender::builder()
+            .folder(folder)
+            .prefix("log.log")
+            .condition(RollingConditionBasic::new().daily())
+            .buffer_capacity(0)
+            .build()
+            .unwrap();
+        rfa.rollover().unwrap();
+        let now = Local.with_ymd_and_hms(2099, 3, 1, 1, 0, 0).unwrap();
+        rfa.write_with_datetime(b"line\n", &now).unwrap();
+        // Readers see the line without a flush
+        let path = dir.path().join("log.log.20990301.010000");
+        assert_eq!(std::fs::read_to_string(path).unwrap(), "line\n");
+    }
+
+    #[test]
+    fn test_write_vectored() {
+        use super::*;
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let condition = RollingConditionBasic::new().max_size(10);
+        let mut rfa = BasicRollingFileAppender::new(folder, "log.log", condition, 9).unwrap();
+        rfa.rollover().unwrap();
+        let at = |second| Local.with_ymd_and_hms(2099, 3, 1, 1, 0, second).unwrap();
+        let record = [IoSlice::new(b"header:"), IoSlice::new(b""), IoSlice::new(b"payload\n")];
+        assert_eq!(rfa.write_vectored_with_datetime(&record, &at(0)).unwrap(), 15);
+        // The file is full, the next record starts a new one as a whole
+        assert_eq!(rfa.current_file_size(), 15);
+        rfa.write_vectored_with_datetime(&record, &at(1)).unwrap();
+        rfa.flush().unwrap();
+        let read = |name: &str| std::fs::read_to_string(dir.path().join(name)).unwrap();
+        assert_eq!(read("log.log.20990301.010000"), "header:payload\n");
+        assert_eq!(read("log.log.20990301.010001"), "header:payload\n");
+    }
+
+    #[test]
+    fn test_parse_size() {
+        use super::*;
+        assert_eq!(parse_size("4096").unwrap(), 4096);
+        assert_eq!(parse_size("250MB").unwrap(), 250_000_000);
+        assert_eq!(parse_size("10k").unwrap(), 10_000);
+        assert_eq!(parse_size(" 512 KiB ").unwrap(), 512 * 1024);
+        assert_eq!(parse_size("1GiB").unwrap(), 1024 * 1024 * 1024);
+        for bad in ["", "MB", "1.5GB", "-1", "10 parsecs", "99999999999TB"] {
+            assert!(
+                matches!(parse_size(bad), Err(RollingFileError::InvalidConfig(_))),
+                "{}",
+                bad
+            );
+        }
+        let condition = RollingConditionBasic::new().max_size_str("2KiB").unwrap();
+        assert_eq!(condition.max_file_size(), Some(2048));
+    }
+
+    #[test]
+    fn test_daily_at() {
+        use super::*;
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let condition = RollingConditionBasic::new().daily_at(NaiveTime::from_hms_opt(2, 30, 0).unwrap());
+        assert_eq!(condition.describe(), "rollover=daily at=02:30");
+        let first = Local.with_ymd_and_hms(2099, 3, 1, 1, 0, 0).unwrap();
+        assert_eq!(
+            condition.next_rollover(&first),
+            Some(Local.with_ymd_and_hms(2099, 3, 1, 2, 30, 0).unwrap())
+        );
+        let mut rfa = BasicRollingFileAppender::new(folder, "log.log", condition, 9).unwrap();
+        rfa.rollover().unwrap();
+        for (day, hour, minute) in [(1, 1, 0), (1, 2, 0), (1, 3, 0), (2, 2, 29), (2, 2, 31)] {
+            let now = Local.with_ymd_and_hms(2099, 3, day, hour, minute, 0).unwrap();
+            rfa.write_with_datetime(format!("{:02}:{:02}\n", hour, minute).as_bytes(), &now)
+                .unwrap();
+        }
+        rfa.flush().unwrap();
+        let read = |name: &str| std::fs::read_to_string(dir.path().join(name)).unwrap();
+        assert_eq!(read("log.log.20990301.010000"), "01:00\n02:00\n");
+        assert_eq!(read("log.log.20990301.030000"), "03:00\n02:29\n");
+        assert_eq!(read("log.log.20990302.023100"), "02:31\n");
+    }
+
+    #[test]
+    fn test_max_age_condition() {
+        use super::*;
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let condition = RollingConditionBasic::new().daily().max_age(chrono::Duration::hours(6));
+        let mut rfa = BasicRollingFileAppender::new(folder, "log.log", condition, 9).unwrap();
+        rfa.rollover().unwrap();
+        for hour in [1, 6, 7, 12, 13] {
+            let now = Local.with_ymd_and_hms(2099, 3, 1, hour, 0, 0).unwrap();
+            rfa.write_with_datetime(format!("{:02}\n", hour).as_bytes(), &now)
+                .unwrap();
+        }
+        rfa.flush().unwrap();
+        let read = |name: &str| std::fs::read_to_string(dir.path().join(name)).unwrap();
+        assert_eq!(read("log.log.20990301.010000"), "01\n06\n");
+        assert_eq!(read("log.log.20990301.070000"), "07\n12\n");
+        assert_eq!(read("log.log.20990301.130000"), "13\n");
+    }
+
+    #[test]
+    fn test_idle_rollover() {
+        use super::*;
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let condition = RollingConditionBasic::new().idle_rollover(chrono::Duration::minutes(10));
+        let mut rfa = BasicRollingFileAppender::new(folder, "log.log", condition, 9).unwrap();
+        rfa.rollover().unwrap();
+        // Two bursts, each write within 10 minutes of the previous one
+        for minute in [0, 5, 14, 30, 31] {
+            let now = Local.with_ymd_and_hms(2099, 3, 1, 1, minute, 0).unwrap();
+            rfa.write_with_datetime(format!("{:02}\n", minute).as_bytes(), &now)
+                .unwrap();
+        }
+        rfa.flush().unwrap();
+        let read = |name: &str| std::fs::read_to_string(dir.path().join(name)).unwrap();
+        assert_eq!(read("log.log.20990301.010000"), "00\n05\n14\n");
+        assert_eq!(read("log.log.20990301.013000"), "30\n31\n");
+    }
+
+    #[test]
+    fn test_idle_rollover_with_timer() {
+        use super::*;
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let condition = || RollingConditionBasic::new().idle_rollover(chrono::Duration::minutes(10));
+        let mut rfa = BasicRollingFileAppender::new(folder, "log.log", condition(), 9).unwrap();
+        rfa.rollover().unwrap();
+        let at = |minute| Local.with_ymd_and_hms(2099, 3, 1, 1, minute, 0).unwrap();
+        rfa.write_with_datetime(b"00\n", &at(0)).unwrap();
+        // Neither the checks of a rollover timer nor a reconfiguration count as writes
+        for minute in [4, 8] {
+            assert!(!rfa.rollover_if_due(&at(minute)).unwrap());
+        }
+        rfa.reconfigure_with_datetime(RollingFileConfig::new(condition(), 9), &at(9))
+            .unwrap();
+        rfa.write_with_datetime(b"11\n", &at(11)).unwrap();
+        // The timer rolls over once idle, then waits for the next write
+        assert!(!rfa.rollover_if_due(&at(20)).unwrap());
+        assert!(rfa.rollover_if_due(&at(22)).unwrap());
+        assert!(!rfa.rollover_if_due(&at(40)).unwrap());
+        rfa.write_with_datetime(b"45\n", &at(45)).unwrap();
+        rfa.flush().unwrap();
+        let read = |name: &str| std::fs::read_to_string(dir.path().join(name)).unwrap();
+        assert_eq!(read("log.log.20990301.010000"), "00\n");
+        assert_eq!(read("log.log.20990301.011100"), "11\n");
+        assert_eq!(read("log.log.20990301.012200"), "45\n");
+    }
+
+    #[test]
+    fn test_max_lines() {
+        use super::*;
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let condition = RollingConditionBasic::new().max_lines(2);
+        let mut rfa = BasicRollingFileAppender::new(folder, "log.log", condition, 9).unwrap();
+        rfa.rollover().unwrap();
+        for second in 0..5 {
+            let now = Local.with_ymd_and_hms(2099, 3, 30, 1, 0, second).unwrap();
+            rfa.write_with_datetime(b"line\n", &now).unwrap();
+        }
+        rfa.flush().unwrap();
+        let read = |name: &str| std::fs::read_to_string(dir.path().join(name)).unwrap();
+        assert_eq!(read("log.log.20990330.010000"), "line\nline\n");
+        assert_eq!(read("log.log.20990330.010002"), "line\nline\n");
+        assert_eq!(read("log.log.20990330.010004"), "line\n");
+    }
+
+    #[test]
+    fn test_list_rotated_files() {
+        use super::*;
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let mut rfa =
+            BasicRollingFileAppender::new(folder, "log.log", RollingConditionBasic::new().daily(), 9).unwrap();
+        rfa.rollover().unwrap();
+        assert_eq!(rfa.current_file_path(), None);
+        for day in 1..=3 {
+            let now = Local.with_ymd_and_hms(2099, 3, day, 1, 0, 0).unwrap();
+            rfa.write_with_datetime(b"line\n", &now).unwrap();
+        }
+        assert_eq!(
+            rfa.current_file_path(),
+            Some(dir.path().join("log.log.20990303.010000"))
+        );
+        let rotated = rfa.list_rotated_files().unwrap();
+        // The first one is the file opened at construction
+        assert_eq!(rotated.len(), 3);
+        assert_eq!(
+            rotated[1..],
+            [
+                dir.path().join("log.log.20990301.010000"),
+                dir.path().join("log.log.20990302.010000")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_write_record() {
+        use super::*;
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let condition = RollingConditionBasic::new().daily();
+        let mut rfa = BasicRollingFileAppender::new(folder, "log.log", condition, 9)
+            .unwrap()
+            .with_line_aligned_rollover(true);
+        rfa.rollover().unwrap();
+        let day = |d| Local.with_ymd_and_hms(2099, 3, d, 1, 0, 0).unwrap();
+        // A record written in chunks stays together as long as it ends with a line break
+        rfa.write_with_datetime(b"first ", &day(1)).unwrap();
+        rfa.write_with_datetime(b"record\n", &day(2)).unwrap();
+        // Several lines in one record go into the file chosen before the record
+        rfa.write_record_with_datetime(b"second\nrecord\n", &day(2)).unwrap();
+        rfa.write_record_with_datetime(b"third\nrecord\n", &day(3)).unwrap();
+        rfa.flush().unwrap();
+        let read = |name: &str| std::fs::read_to_string(dir.path().join(name)).unwrap();
+        assert_eq!(read("log.log.20990301.010000"), "first record\n");
+        assert_eq!(read("log.log.20990302.010000"), "second\nrecord\n");
+        assert_eq!(read("log.log.20990303.010000"), "third\nrecord\n");
+    }
+
+    #[test]
+    fn test_split_large_writes() {
+        use super::*;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let count = AtomicUsize::new(0);
+        let mut rfa = BasicRollingFileAppender::builder()
+            .folder(folder)
+            .prefix("log.log")
+            .condition(RollingConditionBasic::new().max_size(4))
+            .file_name(move |_| format!("log.log.{}", count.fetch_add(1, Ordering::Relaxed)))
+            .split_large_writes(true)
+            .symlink(false)
+            .build()
+            .unwrap();
+        let now = Local.with_ymd_and_hms(2099, 3, 30, 1, 0, 0).unwrap();
+        rfa.write_with_datetime(b"ab", &now).unwrap();
+        rfa.write_with_datetime(b"cdefghij", &now).unwrap();
+        rfa.flush().unwrap();
+        let read = |name: &str| std::fs::read_to_string(dir.path().join(name)).unwrap();
+        assert_eq!(read("log.log.0"), "abcd");
+        assert_eq!(read("log.log.1"), "efgh");
+        assert_eq!(read("log.log.2"), "ij");
+    }
+
+    #[test]
+    fn test_split_large_writes_keeps_frames() {
+        use super::*;
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let mut rfa = BasicRollingFileAppender::builder()
+            .folder(folder)
+            .prefix("log.log")
+            .condition(RollingConditionBasic::new().max_size(16))
+            .split_large_writes(true)
+            .build()
+            .unwrap();
+        rfa.rollover().unwrap();
+        let now = Local.with_ymd_and_hms(2099, 3, 30, 1, 0, 0).unwrap();
+        rfa.write_frame_with_datetime(b"the first frame", &now).unwrap();
+        let later = Local.with_ymd_and_hms(2099, 3, 30, 1, 0, 1).unwrap();
+        rfa.write_frame_with_datetime(b"a payload above the size limit", &later)
+            .unwrap();
+        rfa.flush().unwrap();
+        let read = |name: &str| {
+            let file = std::fs::File::open(dir.path().join(name)).unwrap();
+            FrameReader::new(file).collect::<io::Result<Vec<_>>>().unwrap()
+        };
+        assert_eq!(read("log.log.20990330.010000"), [b"the first frame".to_vec()]);
+        assert_eq!(
+            read("log.log.20990330.010001"),
+            [b"a payload above the size limit".to_vec()]
+        );
+    }
+
+    #[test]
+    fn test_rollovers_within_one_second() {
+        use super::*;
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let condition = RollingConditionBasic::new().max_size(5);
+        let mut rfa = BasicRollingFileAppender::new(folder, "log.log", condition, 3).unwrap();
+        rfa.rollover().unwrap();
+        let now = Local.with_ymd_and_hms(2099, 3, 30, 1, 0, 0).unwrap();
+        for i in 0..12 {
+            rfa.write_with_datetime(format!("{:04}\n", i).as_bytes(), &now).unwrap();
+        }
+        rfa.flush().unwrap();
+        let read = |name: &str| std::fs::read_to_string(dir.path().join(name)).unwrap();
+        assert_eq!(read("log.log.20990330.010000.11"), "0011\n");
+        // The newest files are kept, although `.9` sorts after `.10` by name
+        let rotated = rfa
+            .list_rotated_files()
+            .unwrap()
+            .into_iter()
+            .map(|path| path.file_name().unwrap().to_string_lossy().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(rotated, ["log.log.20990330.010000.9", "log.log.20990330.010000.10"]);
+        assert_eq!(read("log.log.20990330.010000.9"), "0009\n");
+    }
+
+    #[test]
+    fn test_max_age() {
+        use super::*;
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let mut rfa = BasicRollingFileAppender::new(folder, "log.log", RollingConditionBasic::new().daily(), 99)
+            .unwrap()
+            .with_max_age(chrono::Duration::days(7));
+        rfa.rollover().unwrap();
+        for day in [1, 5, 20] {
+            let now = Local.with_ymd_and_hms(2099, 3, day, 1, 0, 0).unwrap();
+            rfa.write_with_datetime(b"line\n", &now).unwrap();
+        }
+        rfa.flush().unwrap();
+
+        // The first file was last written on the 5th, when the second one started
+        assert!(!dir.path().join("log.log.20990301.010000").exists());
+        assert!(dir.path().join("log.log.20990305.010000").exists());
+        assert!(dir.path().join("log.log.20990320.010000").exists());
+    }
+
+    #[test]
+    fn test_rollover_lag() {
+        use super::*;
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let mut rfa =
+            BasicRollingFileAppender::new(folder, "log.log", RollingConditionBasic::new().daily(), 9).unwrap();
+        rfa.rollover().unwrap();
+        rfa.write_with_datetime(b"a", &Local.with_ymd_and_hms(2099, 3, 30, 10, 0, 0).unwrap())
+            .unwrap();
+        assert_eq!(rfa.last_rollover_lag(), None);
+        rfa.write_with_datetime(b"b", &Local.with_ymd_and_hms(2099, 3, 31, 3, 0, 0).unwrap())
+            .unwrap();
+        assert_eq!(rfa.last_rollover_lag(), Some(chrono::Duration::hours(3)));
+        assert_eq!(rfa.max_rollover_lag(), Some(chrono::Duration::hours(3)));
+    }
+
+    #[test]
+    fn test_next_boundary() {
+        use super::*;
+        let dt = Local.with_ymd_and_hms(2021, 3, 31, 23, 59, 30).unwrap();
+        assert_eq!(
+            RollingFrequency::EveryDay.next_boundary(&dt),
+            Local.with_ymd_and_hms(2021, 4, 1, 0, 0, 0).unwrap()
+        );
+        assert_eq!(
+            RollingFrequency::EveryHour.next_boundary(&dt),
+            Local.with_ymd_and_hms(2021, 4, 1, 0, 0, 0).unwrap()
+        );
+        assert_eq!(
+            RollingFrequency::EveryMinute.next_boundary(&dt),
+            Local.with_ymd_and_hms(2021, 4, 1, 0, 0, 0).unwrap()
+        );
+        // 2021-03-31 is a Wednesday
+        assert_eq!(
+            RollingFrequency::EveryWeek(Weekday::Mon).next_boundary(&dt),
+            Local.with_ymd_and_hms(2021, 4, 5, 0, 0, 0).unwrap()
+        );
+        assert_eq!(
+            RollingFrequency::EveryWeek(Weekday::Thu).next_boundary(&dt),
+            Local.with_ymd_and_hms(2021, 4, 1, 0, 0, 0).unwrap()
+        );
+        assert_eq!(
+            RollingFrequency::EveryMonth.equivalent_datetime(&dt),
+            Local.with_ymd_and_hms(2021, 3, 1, 0, 0, 0).unwrap()
+        );
+        assert_eq!(
+            RollingFrequency::EveryMonth.next_boundary(&dt),
+            Local.with_ymd_and_hms(2021, 4, 1, 0, 0, 0).unwrap()
+        );
+        let condition = RollingConditionBasic::new().hourly();
+        let next = condition.next_rollover(&dt).unwrap();
+        assert_eq!(next - dt, chrono::Duration::seconds(30));
+    }
 
-#[cfg(test)]
-mod t {
     #[test]
     fn test_number_of_log_files() {
         use super::*;
@@ -0,0 +1,124 @@
+//! A [`log`] backend writing into a rolling file.
+//!
+//! [`init`] installs a [`RollingFileLogger`] as the global logger, so programs using the `log`
+//! macros rather than `tracing` get rolling files too:
+//!
+//! ```rust,no_run
+//! # use local_rolling_file::*;
+//! let file_appender = BasicRollingFileAppender::new("./log", "log.log", RollingConditionBasic::new().daily(), 9).unwrap();
+//! let _guard = log_adapter::init(file_appender, log::LevelFilter::Info).unwrap();
+//! log::info!("written to ./log/log.log");
+//! ```
+
+use crate::{FlushGuard, RollingCondition, RollingFileAppender, SharedRollingFileAppender};
+use chrono::Local;
+use log::{LevelFilter, Log, Metadata, Record, SetLoggerError};
+use std::io::Write;
+
+/// A [`Log`] implementation writing each record as one line, e.g.
+/// `2024-05-20 01:01:01.000001 INFO my_crate::module: message`, through a
+/// [`SharedRollingFileAppender`]. Records less severe than its level are dropped.
+#[derive(Debug)]
+pub struct RollingFileLogger<RC>
+where
+    RC: RollingCondition,
+{
+    shared: SharedRollingFileAppender<RC>,
+    level: LevelFilter,
+}
+
+impl<RC> RollingFileLogger<RC>
+where
+    RC: RollingCondition,
+{
+    /// Creates a logger writing the records of at most `level` through `shared`.
+    pub fn new(shared: SharedRollingFileAppender<RC>, level: LevelFilter) -> RollingFileLogger<RC> {
+        RollingFileLogger { shared, level }
+    }
+
+    /// The most verbose level written
+    pub fn level(&self) -> LevelFilter {
+        self.level
+    }
+}
+
+impl<RC> Log for RollingFileLogger<RC>
+where
+    RC: RollingCondition + Send,
+{
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        // A logger has nowhere to report its own failures, the appender's error policy applies
+        let _ = writeln!(
+            &self.shared,
+            "{} {} {}: {}",
+            Local::now().format("%Y-%m-%d %H:%M:%S%.6f"),
+            record.level(),
+            record.target(),
+            record.args()
+        );
+    }
+
+    fn flush(&self) {
+        let _ = (&self.shared).flush();
+    }
+}
+
+/// Installs a [`RollingFileLogger`] writing into `appender` as the global logger and sets the
+/// maximum level of the `log` macros to `level`. Fails if a global logger is already set. Keep
+/// the returned guard alive until the end of `main`, it flushes the appender when dropped.
+pub fn init<RC>(appender: RollingFileAppender<RC>, level: LevelFilter) -> Result<FlushGuard<RC>, SetLoggerError>
+where
+    RC: RollingCondition + Send + 'static,
+{
+    let (shared, guard) = SharedRollingFileAppender::new(appender);
+    log::set_boxed_logger(Box::new(RollingFileLogger::new(shared, level)))?;
+    log::set_max_level(level);
+    Ok(guard)
+}
+
+#[cfg(test)]
+mod t {
+    use super::*;
+    use crate::*;
+    use log::Level;
+
+    #[test]
+    fn test_level_filtering() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let rfa = BasicRollingFileAppender::new(folder, "log.log", RollingConditionBasic::new().daily(), 3).unwrap();
+        let (shared, guard) = SharedRollingFileAppender::new(rfa);
+        let logger = RollingFileLogger::new(shared, LevelFilter::Info);
+        for (level, message) in [
+            (Level::Error, "an error"),
+            (Level::Info, "some info"),
+            (Level::Debug, "debugging"),
+            (Level::Trace, "tracing"),
+        ] {
+            logger.log(
+                &Record::builder()
+                    .level(level)
+                    .target("app")
+                    .args(format_args!("{}", message))
+                    .build(),
+            );
+        }
+        assert!(logger.enabled(&Metadata::builder().level(Level::Warn).build()));
+        assert!(!logger.enabled(&Metadata::builder().level(Level::Debug).build()));
+        drop(logger);
+        drop(guard);
+
+        let contents = std::fs::read_to_string(dir.path().join("log.log")).unwrap();
+        let lines = contents.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].ends_with(" ERROR app: an error"));
+        assert!(lines[1].ends_with(" INFO app: some info"));
+    }
+}
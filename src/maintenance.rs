@@ -0,0 +1,199 @@
+//! Background scheduler for maintenance work on rolled files.
+//!
+//! Retention, bundling, consolidation and similar jobs can take a while on a large folder.
+//! A [`Maintenance`] owns a worker thread running submitted jobs one at a time in submission
+//! order, retrying failed jobs, so the writing thread never waits for them. Several appenders
+//! may share one scheduler through [`MaintenanceHandle`]s.
+
+use crate::ThreadOptions;
+use std::{
+    fmt,
+    io,
+    sync::mpsc::{self, Receiver, Sender},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+/// A unit of maintenance work.
+pub struct MaintenanceJob {
+    name: String,
+    retries: u32,
+    run: Box<dyn FnMut() -> io::Result<()> + Send>,
+}
+
+impl MaintenanceJob {
+    /// Creates a job which is not retried on failure
+    pub fn new<F>(name: &str, run: F) -> MaintenanceJob
+    where
+        F: FnMut() -> io::Result<()> + Send + 'static,
+    {
+        MaintenanceJob {
+            name: name.to_string(),
+            retries: 0,
+            run: Box::new(run),
+        }
+    }
+
+    /// Sets how many times the job is retried after a failure
+    pub fn retries(mut self, retries: u32) -> MaintenanceJob {
+        self.retries = retries;
+        self
+    }
+
+    /// Returns the name of the job
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Runs the job on the current thread, retrying with a growing delay. Failures are reported
+    /// to stderr since this likely runs on behalf of a logger.
+    pub fn run(mut self) {
+        internal_span!("maintenance_job", name = %self.name);
+        let mut delay = Duration::from_millis(100);
+        for attempt in 0..=self.retries {
+            match (self.run)() {
+                Ok(()) => return,
+                Err(e) if attempt == self.retries => {
+                    eprintln!("WARNING: Maintenance job {} failed: {}", self.name, e);
+                },
+                Err(_e) => {
+                    internal_event!(error = %_e, attempt, "maintenance job failed, retrying");
+                    thread::sleep(delay);
+                    delay *= 2;
+                },
+            }
+        }
+    }
+}
+
+impl fmt::Debug for MaintenanceJob {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MaintenanceJob")
+            .field("name", &self.name)
+            .field("retries", &self.retries)
+            .finish()
+    }
+}
+
+enum Message {
+    Job(MaintenanceJob),
+    Shutdown,
+}
+
+/// Submits jobs to a [`Maintenance`] scheduler. Cheap to clone.
+#[derive(Clone, Debug)]
+pub struct MaintenanceHandle {
+    sender: Sender<Message>,
+}
+
+impl MaintenanceHandle {
+    /// Queues a job. If the scheduler has shut down, the job is handed back so the caller can
+    /// run it inline instead.
+    pub fn submit(&self, job: MaintenanceJob) -> Result<(), MaintenanceJob> {
+        self.sender.send(Message::Job(job)).map_err(|e| match e.0 {
+            Message::Job(job) => job,
+            Message::Shutdown => unreachable!(),
+        })
+    }
+
+    /// Blocks until every job submitted so far has run. Returns immediately if the scheduler has
+    /// shut down, since it finished its queue before doing so.
+    pub fn drain(&self) {
+        let (done, drained) = mpsc::channel();
+        let job = MaintenanceJob::new("drain", move || {
+            let _ = done.send(());
+            Ok(())
+        });
+        if self.submit(job).is_ok() {
+            let _ = drained.recv();
+        }
+    }
+}
+
+/// Owns the maintenance worker thread. Dropping it finishes all queued jobs and joins the
+/// thread, see [`Maintenance::shutdown`].
+#[derive(Debug)]
+pub struct Maintenance {
+    handle: MaintenanceHandle,
+    thread_opt: Option<JoinHandle<()>>,
+}
+
+impl Maintenance {
+    /// Starts a new scheduler with its own worker thread
+    pub fn new() -> io::Result<Maintenance> {
+        Self::with_thread_options(&ThreadOptions::new())
+    }
+
+    /// Starts a new scheduler whose worker thread has the given name and priority
+    pub fn with_thread_options(options: &ThreadOptions) -> io::Result<Maintenance> {
+        let (sender, receiver) = mpsc::channel();
+        let thread = options.spawn("rolling-file-maintenance", move || Self::work(receiver))?;
+        Ok(Maintenance {
+            handle: MaintenanceHandle { sender },
+            thread_opt: Some(thread),
+        })
+    }
+
+    /// Returns a handle to submit jobs, e.g. to pass to
+    /// [`crate::RollingFileAppender::with_maintenance`]
+    pub fn handle(&self) -> MaintenanceHandle {
+        self.handle.clone()
+    }
+
+    /// Runs every job queued so far, then stops the worker thread. Jobs submitted afterwards are
+    /// handed back to their submitter.
+    pub fn shutdown(mut self) {
+        self.stop();
+    }
+
+    fn stop(&mut self) {
+        if let Some(thread) = self.thread_opt.take() {
+            let _ = self.handle.sender.send(Message::Shutdown);
+            let _ = thread.join();
+        }
+    }
+
+    fn work(receiver: Receiver<Message>) {
+        while let Ok(Message::Job(job)) = receiver.recv() {
+            job.run();
+        }
+    }
+}
+
+impl Drop for Maintenance {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+#[cfg(test)]
+mod t {
+    use super::*;
+    use crate::*;
+
+    #[test]
+    fn test_retention_in_background() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let maintenance = Maintenance::new().unwrap();
+        let mut rfa = BasicRollingFileAppender::new(folder, "log.log", RollingConditionBasic::new().hourly(), 2)
+            .unwrap()
+            .with_maintenance(maintenance.handle());
+        for hour in 1..6 {
+            let now = Local.with_ymd_and_hms(2021, 3, 30, hour, 0, 0).unwrap();
+            rfa.write_with_datetime(b"Line\n", &now).unwrap();
+        }
+        rfa.flush().unwrap();
+        maintenance.shutdown();
+
+        let log_files = std::fs::read_dir(folder)
+            .unwrap()
+            .flatten()
+            .filter(|f| f.file_name() != "log.log")
+            .count();
+        assert_eq!(log_files, 2);
+
+        let job = MaintenanceJob::new("late", || Ok(()));
+        assert_eq!(rfa.maintenance().unwrap().submit(job).unwrap_err().name(), "late");
+    }
+}
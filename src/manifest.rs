@@ -0,0 +1,192 @@
+//! A manifest of checksums of the rolled files.
+//!
+//! Needs the `manifest` feature, which hashes with the `sha2` crate. For tamper evidence,
+//! [`crate::RollingFileAppender::with_manifest`] appends a line to
+//! `manifest.jsonl` in the log folder for every rolled file, once compressed or encrypted if it
+//! is right away:
+//!
+//! ```text
+//! {"file":"log.log.20240520.010101","size":1024,"sha256":"…","start":"2024-05-20T01:01:01+02:00","end":"2024-05-21T00:00:00+02:00"}
+//! ```
+//!
+//! `start` is when the file was started, as told by its name, or `null`; `end` is the rollover,
+//! or `null` for files written by maintenance. [`sha256_file`] computes the same checksum to
+//! verify a file.
+//!
+//! Maintenance keeps the manifest of a folder up to date: a file consolidation appended to, or a
+//! bundle archive, gets a new entry, which supersedes the earlier ones of the same file. A file
+//! consolidation or bundling deleted gets a removal entry:
+//!
+//! ```text
+//! {"file":"log.log.20240520.020202","removed":true}
+//! ```
+
+use crate::{
+    filesystem::{self, FsReader},
+    json_string,
+    Fs,
+    RealFs,
+};
+use chrono::{DateTime, Local, SecondsFormat};
+use sha2::{Digest, Sha256};
+use std::{
+    fmt::Write as _,
+    io::{self, Read},
+    path::{Path, PathBuf},
+};
+
+/// File name of the manifest in the log folder
+pub const MANIFEST_FILE_NAME: &str = "manifest.jsonl";
+
+/// Returns the path of the manifest of `folder`
+pub fn manifest_path(folder: &str) -> PathBuf {
+    Path::new(folder).join(MANIFEST_FILE_NAME)
+}
+
+/// Returns the SHA-256 of the contents of a file as lowercase hex
+pub fn sha256_file(path: &Path) -> io::Result<String> {
+    sha256_file_in(&RealFs, path)
+}
+
+/// Same as [`sha256_file`], through `fs`
+pub(crate) fn sha256_file_in(fs: &dyn Fs, path: &Path) -> io::Result<String> {
+    let mut f = FsReader::new(fs, path)?;
+    let mut sha = Sha256::new();
+    let mut buf = [0u8; 8192];
+    loop {
+        let n = f.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        sha.update(&buf[..n]);
+    }
+    Ok(sha.finalize().iter().fold(String::new(), |mut hex, b| {
+        let _ = write!(hex, "{:02x}", b);
+        hex
+    }))
+}
+
+/// Tells whether `folder` has a manifest to keep up to date
+pub(crate) fn exists(fs: &dyn Fs, folder: &str) -> bool {
+    filesystem::exists(fs, &manifest_path(folder))
+}
+
+/// Appends the entry of the rolled file `path` of `folder` to the manifest and syncs it.
+pub(crate) fn record_file(
+    fs: &dyn Fs,
+    folder: &str,
+    path: &Path,
+    started_opt: Option<DateTime<Local>>,
+    ended_opt: Option<&DateTime<Local>>,
+) -> io::Result<()> {
+    let name = path.strip_prefix(folder).unwrap_or(path).to_string_lossy();
+    let size = fs.file_len(path)?;
+    let sha256 = sha256_file_in(fs, path)?;
+    let json_time = |dt_opt: Option<&DateTime<Local>>| {
+        dt_opt.map_or("null".to_string(), |dt| {
+            json_string(&dt.to_rfc3339_opts(SecondsFormat::Secs, false))
+        })
+    };
+    let line = format!(
+        "{{\"file\":{},\"size\":{},\"sha256\":\"{}\",\"start\":{},\"end\":{}}}\n",
+        json_string(&name),
+        size,
+        sha256,
+        json_time(started_opt.as_ref()),
+        json_time(ended_opt)
+    );
+    append_line(fs, folder, &line)
+}
+
+/// Appends the removal entry of the file `path` of `folder` to the manifest and syncs it.
+pub(crate) fn record_removal(fs: &dyn Fs, folder: &str, path: &Path) -> io::Result<()> {
+    let name = path.strip_prefix(folder).unwrap_or(path).to_string_lossy();
+    append_line(
+        fs,
+        folder,
+        &format!("{{\"file\":{},\"removed\":true}}\n", json_string(&name)),
+    )
+}
+
+/// Keeps the manifest of `folder` up to date after maintenance rewrote the files `changed` and
+/// deleted the files `removed`. Failures are reported, the files are changed already.
+pub(crate) fn record_maintenance(
+    fs: &dyn Fs,
+    folder: &str,
+    naming: &dyn crate::NamingScheme,
+    changed: &[PathBuf],
+    removed: &[PathBuf],
+) {
+    if !exists(fs, folder) {
+        return;
+    }
+    for path in changed {
+        let name = path.strip_prefix(folder).unwrap_or(path).to_string_lossy();
+        if let Err(e) = record_file(fs, folder, path, naming.started(&name), None) {
+            eprintln!("WARNING: Failed to record {} in the manifest {}", path.display(), e);
+        }
+    }
+    for path in removed {
+        if let Err(e) = record_removal(fs, folder, path) {
+            eprintln!(
+                "WARNING: Failed to record the removal of {} in the manifest {}",
+                path.display(),
+                e
+            );
+        }
+    }
+}
+
+fn append_line(fs: &dyn Fs, folder: &str, line: &str) -> io::Result<()> {
+    let mut f = fs.open_append(&manifest_path(folder))?;
+    f.write_all(line.as_bytes())?;
+    f.sync_all()
+}
+
+#[cfg(test)]
+mod t {
+    use super::*;
+    use crate::*;
+
+    #[test]
+    fn test_manifest() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let mut rfa = BasicRollingFileAppender::new(folder, "log.log", RollingConditionBasic::new().daily(), 9)
+            .unwrap()
+            .with_manifest(true);
+        rfa.rollover().unwrap();
+        let day1 = Local.with_ymd_and_hms(2099, 3, 1, 1, 0, 0).unwrap();
+        let day2 = Local.with_ymd_and_hms(2099, 3, 2, 1, 0, 0).unwrap();
+        rfa.write_with_datetime(b"day 1\n", &day1).unwrap();
+        rfa.write_with_datetime(b"day 2\n", &day2).unwrap();
+
+        let manifest = std::fs::read_to_string(manifest_path(folder)).unwrap();
+        let line = manifest.lines().last().unwrap();
+        let rfc3339 = |dt: DateTime<Local>| dt.to_rfc3339_opts(SecondsFormat::Secs, false);
+        assert_eq!(
+            line,
+            format!(
+                "{{\"file\":\"log.log.20990301.010000\",\"size\":6,\"sha256\":\"{}\",\"start\":\"{}\",\"end\":\"{}\"}}",
+                "d890e3961dc708a7e67795fe45cfccc4ba18c5ec6bae7c2ce4a8441048de0fc8",
+                rfc3339(day1),
+                rfc3339(day2)
+            )
+        );
+
+        // Retention still runs when the manifest can't be written
+        std::fs::remove_file(manifest_path(folder)).unwrap();
+        std::fs::create_dir(manifest_path(folder)).unwrap();
+        let mut rfa = rfa.with_retention(RetentionPolicy::MaxFiles(1));
+        let day3 = Local.with_ymd_and_hms(2099, 3, 3, 1, 0, 0).unwrap();
+        rfa.write_with_datetime(b"day 3\n", &day3).unwrap();
+        assert!(!dir.path().join("log.log.20990301.010000").exists());
+
+        let data = dir.path().join("data");
+        std::fs::write(&data, [b'a'; 1000]).unwrap();
+        assert_eq!(
+            sha256_file(&data).unwrap(),
+            "41edece42d63e8d9bf515a9ba6932e1c20cbc9f5a5d134645adb5db1b9737ea3"
+        );
+    }
+}
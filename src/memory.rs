@@ -0,0 +1,93 @@
+//! Upper bound on the memory used by an appender.
+
+use std::io;
+
+/// Default capacity of the write buffer, matching `std::io::BufWriter`.
+pub(crate) const DEFAULT_BUFFER_CAPACITY: usize = 8 * 1024;
+
+/// What to do with a write that would exceed the memory limit
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum MemoryPolicy {
+    /// Fails the write with [`io::ErrorKind::OutOfMemory`]
+    Reject,
+    /// Drops the write, reporting success, and counts the discarded bytes
+    Discard,
+}
+
+/// Caps the memory an appender may use for buffering and processing data. The write buffers take
+/// at most half of the limit, i.e. a quarter each when double buffering; the rest is available to
+/// the temporary buffers needed to transcode a write or encode a frame. Data written without any
+/// processing is never copied and so never hits the limit. A [`NonBlockingRollingFileAppender`]
+/// counts the data queued for its worker against the limit as well.
+///
+/// [`NonBlockingRollingFileAppender`]: crate::NonBlockingRollingFileAppender
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct MemoryLimit {
+    max_bytes: usize,
+    policy: MemoryPolicy,
+}
+
+impl MemoryLimit {
+    /// Creates a limit of `max_bytes` applying `policy` to writes that don't fit
+    pub fn new(max_bytes: usize, policy: MemoryPolicy) -> MemoryLimit {
+        MemoryLimit { max_bytes, policy }
+    }
+
+    /// Returns the limit in bytes
+    pub fn max_bytes(&self) -> usize {
+        self.max_bytes
+    }
+
+    /// Returns the policy applied to writes that don't fit
+    pub fn policy(&self) -> MemoryPolicy {
+        self.policy
+    }
+
+    /// Returns the capacity of each of `buffers` write buffers allowed by this limit. Unbuffered
+    /// writers stay so.
+    pub(crate) fn clamp_buffer_capacity(&self, capacity: usize, buffers: usize) -> usize {
+        capacity.min(self.max_bytes / 2 / buffers.max(1)).max(capacity.min(1))
+    }
+
+    /// Checks whether a temporary allocation of `needed` bytes fits next to a write buffer of
+    /// `buffer_capacity` bytes. Returns `Ok(true)` if it does, `Ok(false)` if the write should be
+    /// discarded and an error if it should be rejected.
+    pub(crate) fn admit(&self, buffer_capacity: usize, needed: usize) -> io::Result<bool> {
+        if buffer_capacity.saturating_add(needed) <= self.max_bytes {
+            return Ok(true);
+        }
+        match self.policy {
+            MemoryPolicy::Discard => Ok(false),
+            MemoryPolicy::Reject => Err(io::Error::new(
+                io::ErrorKind::OutOfMemory,
+                "write exceeds the memory limit of the rolling file appender",
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod t {
+    use crate::*;
+
+    #[test]
+    fn test_memory_limit_policies() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let mut rfa = BasicRollingFileAppender::new(folder, "log.log", RollingConditionBasic::new(), 3)
+            .unwrap()
+            .with_encoding(Encoding::Utf16Le)
+            .with_memory_limit(MemoryLimit::new(64, MemoryPolicy::Reject));
+        rfa.write_all(b"short").unwrap();
+        let err = rfa.write_all(&[b'x'; 100]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::OutOfMemory);
+
+        let mut rfa = rfa.with_memory_limit(MemoryLimit::new(64, MemoryPolicy::Discard));
+        assert_eq!(rfa.write(&[b'x'; 100]).unwrap(), 100);
+        assert_eq!(rfa.discarded_bytes(), 100);
+
+        // Both buffers of a double buffered writer fit in half of the limit
+        let rfa = rfa.with_double_buffering(true);
+        assert_eq!(rfa.writer_opt.as_ref().unwrap().capacity(), 32);
+    }
+}
@@ -0,0 +1,303 @@
+//! Routing writes to several appenders.
+//!
+//! Services often split their logs, e.g. errors to `error.log` and requests to `access.log`.
+//! A [`MultiRollingAppender`] owns one appender per route, each behind its own lock, and sends
+//! every write to the route a classifier picks for it, so writers of different routes don't wait
+//! for each other. Through [`std::io::Write`], a line written in several pieces, e.g. by
+//! `writeln!`, is held back until it is complete so the classifier sees the whole record. A
+//! retention policy set on it applies to all routes.
+
+use crate::{RetentionPolicy, RollingCondition, RollingFileAppender};
+use chrono::prelude::*;
+use std::{
+    collections::HashMap,
+    fmt,
+    io::{self, Write},
+    sync::{Mutex, MutexGuard},
+    thread::{self, ThreadId},
+};
+
+type ClassifyFn = dyn Fn(&[u8]) -> &'static str + Send + Sync;
+
+/// Appenders selected per write by a classifier. Share it between threads with an `Arc`; writes
+/// take `&self`.
+///
+/// # Examples
+///
+/// ```rust
+/// use local_rolling_file::*;
+///
+/// # fn docs() {
+/// let condition = || RollingConditionBasic::new().daily();
+/// let multi = MultiRollingAppender::new(|buf| if buf.starts_with(b"ERROR") { "error" } else { "app" })
+///     .route("app", BasicRollingFileAppender::new("./log", "app.log", condition(), 9).unwrap())
+///     .route("error", BasicRollingFileAppender::new("./log", "error.log", condition(), 9).unwrap())
+///     .with_retention(RetentionPolicy::MaxFiles(30));
+/// # }
+/// ```
+pub struct MultiRollingAppender<RC>
+where
+    RC: RollingCondition,
+{
+    routes: Vec<(&'static str, Mutex<RollingFileAppender<RC>>)>,
+    classifier: Box<ClassifyFn>,
+    retention_opt: Option<RetentionPolicy>,
+    /// The incomplete last line written by each thread
+    pending: Mutex<HashMap<ThreadId, Vec<u8>>>,
+}
+
+impl<RC> MultiRollingAppender<RC>
+where
+    RC: RollingCondition,
+{
+    /// Creates a router without routes. `classifier` returns the name of the route of each
+    /// write; writes to an unknown route go to the first one added.
+    pub fn new<F>(classifier: F) -> MultiRollingAppender<RC>
+    where
+        F: Fn(&[u8]) -> &'static str + Send + Sync + 'static,
+    {
+        MultiRollingAppender {
+            routes: vec![],
+            classifier: Box::new(classifier),
+            retention_opt: None,
+            pending: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Adds the route `name` writing to `appender`, replacing any route of that name
+    pub fn route(mut self, name: &'static str, appender: RollingFileAppender<RC>) -> MultiRollingAppender<RC> {
+        let appender = match self.retention_opt.clone() {
+            Some(retention) => appender.with_retention(retention),
+            None => appender,
+        };
+        self.routes.retain(|(n, _)| *n != name);
+        self.routes.push((name, Mutex::new(appender)));
+        self
+    }
+
+    /// Sets the retention policy of every route, including those added later
+    pub fn with_retention(mut self, retention: RetentionPolicy) -> MultiRollingAppender<RC> {
+        self.routes = std::mem::take(&mut self.routes)
+            .into_iter()
+            .map(|(name, appender)| {
+                let appender = appender.into_inner().unwrap_or_else(|e| e.into_inner());
+                (name, Mutex::new(appender.with_retention(retention.clone())))
+            })
+            .collect();
+        self.retention_opt = Some(retention);
+        self
+    }
+
+    /// Locks the appender of route `name`, if there is one
+    pub fn lock(&self, name: &str) -> Option<MutexGuard<'_, RollingFileAppender<RC>>> {
+        let (_, appender) = self.routes.iter().find(|(n, _)| *n == name)?;
+        Some(appender.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    /// Writes `buf` to the route picked by the classifier, using the given datetime to calculate
+    /// the rolling condition
+    pub fn write_with_datetime(&self, buf: &[u8], now: &DateTime<Local>) -> io::Result<usize> {
+        self.route_of(buf)?.write_with_datetime(buf, now)
+    }
+
+    /// Locks the appender of the route picked by the classifier for `buf`
+    fn route_of(&self, buf: &[u8]) -> io::Result<MutexGuard<'_, RollingFileAppender<RC>>> {
+        let name = (self.classifier)(buf);
+        let (_, appender) = self
+            .routes
+            .iter()
+            .find(|(n, _)| *n == name)
+            .or(self.routes.first())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no route to write to"))?;
+        Ok(appender.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    /// Writes the complete lines of `buf` to the routes picked for them, holding back an
+    /// incomplete last line until the calling thread writes the rest of it
+    fn write_lines(&self, buf: &[u8]) -> io::Result<usize> {
+        let complete = buf.iter().rposition(|b| *b == b'\n').map_or(0, |i| i + 1);
+        let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+        let held_opt = pending.remove(&thread::current().id());
+        if held_opt.is_none() && complete == buf.len() {
+            drop(pending);
+            self.route_of(buf)?.write_all(buf)?;
+            return Ok(buf.len());
+        }
+        let mut record = held_opt.unwrap_or_default();
+        record.extend_from_slice(&buf[..complete]);
+        let rest = &buf[complete..];
+        if complete == 0 {
+            record.extend_from_slice(rest);
+            pending.insert(thread::current().id(), record);
+            return Ok(buf.len());
+        }
+        if !rest.is_empty() {
+            pending.insert(thread::current().id(), rest.to_vec());
+        }
+        drop(pending);
+        self.route_of(&record)?.write_all(&record)?;
+        Ok(buf.len())
+    }
+
+    /// Writes the incomplete line held back for the calling thread, or those of all threads, as
+    /// they are
+    fn write_pending(&self, all_threads: bool) -> io::Result<()> {
+        let pending = {
+            let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+            match all_threads {
+                true => std::mem::take(&mut *pending).into_values().collect(),
+                false => pending.remove(&thread::current().id()).into_iter().collect::<Vec<_>>(),
+            }
+        };
+        let mut result = Ok(());
+        for record in pending {
+            result = result.and(self.route_of(&record).and_then(|mut route| route.write_all(&record)));
+        }
+        result
+    }
+
+    /// Flushes every route, including the incomplete line held back for the calling thread,
+    /// returning the first error. The incomplete lines of other threads are left for them to
+    /// finish, or written when the router is dropped.
+    pub fn flush_all(&self) -> io::Result<()> {
+        let mut result = self.write_pending(false);
+        for (_, appender) in &self.routes {
+            let flushed = appender.lock().unwrap_or_else(|e| e.into_inner()).flush();
+            result = result.and(flushed);
+        }
+        result
+    }
+}
+
+impl<RC> fmt::Debug for MultiRollingAppender<RC>
+where
+    RC: RollingCondition,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MultiRollingAppender")
+            .field("routes", &self.routes.iter().map(|(name, _)| name).collect::<Vec<_>>())
+            .field("retention", &self.retention_opt)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<RC> Drop for MultiRollingAppender<RC>
+where
+    RC: RollingCondition,
+{
+    fn drop(&mut self) {
+        if let Err(e) = self.write_pending(true) {
+            eprintln!("WARNING: Failed to write incomplete log lines {}", e);
+        }
+    }
+}
+
+impl<RC> Write for &MultiRollingAppender<RC>
+where
+    RC: RollingCondition,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.write_lines(buf)
+    }
+
+    /// Classifies the formatted record as a whole
+    fn write_fmt(&mut self, args: fmt::Arguments<'_>) -> io::Result<()> {
+        let record = args.as_str().map_or_else(|| fmt::format(args), str::to_string);
+        self.write_lines(record.as_bytes()).map(|_| ())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.flush_all()
+    }
+}
+
+impl<RC> Write for MultiRollingAppender<RC>
+where
+    RC: RollingCondition,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.write_lines(buf)
+    }
+
+    /// Classifies the formatted record as a whole
+    fn write_fmt(&mut self, args: fmt::Arguments<'_>) -> io::Result<()> {
+        (&*self).write_fmt(args)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.flush_all()
+    }
+}
+
+#[cfg(test)]
+mod t {
+    use crate::*;
+
+    #[test]
+    fn test_route_by_level() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let appender = |prefix: &str| {
+            let mut rfa =
+                BasicRollingFileAppender::new(folder, prefix, RollingConditionBasic::new().daily(), 9).unwrap();
+            rfa.rollover().unwrap();
+            rfa
+        };
+        let multi = MultiRollingAppender::new(|buf| if buf.starts_with(b"ERROR") { "error" } else { "app" })
+            .route("app", appender("app.log"))
+            .route("error", appender("error.log"))
+            .with_retention(RetentionPolicy::MaxFiles(1));
+        for day in 1..=2 {
+            let now = Local.with_ymd_and_hms(2099, 3, day, 1, 0, 0).unwrap();
+            multi.write_with_datetime(b"INFO started\n", &now).unwrap();
+            multi.write_with_datetime(b"ERROR failed\n", &now).unwrap();
+        }
+        (&multi).flush().unwrap();
+
+        let read = |name: &str| std::fs::read_to_string(dir.path().join(name)).unwrap();
+        assert_eq!(read("app.log.20990302.010000"), "INFO started\n");
+        assert_eq!(read("error.log.20990302.010000"), "ERROR failed\n");
+        // The shared retention keeps one file per route
+        assert!(!dir.path().join("app.log.20990301.010000").exists());
+        assert!(!dir.path().join("error.log.20990301.010000").exists());
+        assert_eq!(multi.lock("error").unwrap().list_rotated_files().unwrap().len(), 0);
+    }
+
+    #[test]
+    fn test_route_whole_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let appender =
+            |prefix: &str| BasicRollingFileAppender::new(folder, prefix, RollingConditionBasic::new(), 9).unwrap();
+        let mut multi = MultiRollingAppender::new(|buf| if buf.starts_with(b"ERROR") { "error" } else { "app" })
+            .route("app", appender("app.log"))
+            .route("error", appender("error.log"));
+        // Pieces of a line are routed together
+        multi.write_all(b"ERR").unwrap();
+        let what = "split";
+        write!(multi, "OR {}", what).unwrap();
+        writeln!(multi).unwrap();
+        multi.flush().unwrap();
+        let read = |name: &str| std::fs::read_to_string(dir.path().join(name)).unwrap();
+        assert_eq!(read("error.log"), "ERROR split\n");
+        assert_eq!(read("app.log"), "");
+    }
+
+    #[test]
+    fn test_flush_own_line_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let rfa = BasicRollingFileAppender::new(folder, "app.log", RollingConditionBasic::new(), 9).unwrap();
+        let multi = MultiRollingAppender::new(|_| "app").route("app", rfa);
+        std::thread::scope(|s| {
+            s.spawn(|| (&multi).write_all(b"other ").unwrap());
+        });
+        (&multi).write_all(b"mine ").unwrap();
+        (&multi).flush().unwrap();
+        let read = || std::fs::read_to_string(dir.path().join("app.log")).unwrap();
+        // The other thread may still complete its line
+        assert_eq!(read(), "mine ");
+        drop(multi);
+        assert_eq!(read(), "mine other ");
+    }
+}
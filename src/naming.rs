@@ -0,0 +1,506 @@
+//! How log files are named.
+//!
+//! A [`NamingScheme`] generates the name of each new file and recognizes the files it named, so
+//! retention, compression and [`crate::RollingFileAppender::list_rotated_files`] find them. By
+//! default each file is named after the time it was started, `log.log.20240520.010101`, and keeps
+//! its name once rolled, see [`TimestampNaming`]. With [`SequentialNaming`] the active file is
+//! always named after the prefix and rolled files are renumbered on each rollover, like
+//! logrotate does: `log.log`, `log.log.1` (the newest rolled file), `log.log.2`... With
+//! [`DailyFolderNaming`] files go to a subfolder per day, `2024-05-20/log.log.010101`, which keeps
+//! folders of thousands of files manageable.
+//!
+//! Other layouts implement the trait, e.g. one file per day in nested folders:
+//!
+//! ```rust
+//! use chrono::{DateTime, Local, NaiveDate, TimeZone};
+//! use local_rolling_file::*;
+//! use std::path::Path;
+//!
+//! /// Names files `2024/05/20/app.log`
+//! #[derive(Debug)]
+//! struct Nested;
+//!
+//! impl NamingScheme for Nested {
+//!     fn file_name(&self, now: &DateTime<Local>) -> String {
+//!         now.format("%Y/%m/%d/app.log").to_string()
+//!     }
+//!
+//!     fn matches(&self, name: &str) -> bool {
+//!         self.started(name).is_some()
+//!     }
+//!
+//!     fn started(&self, name: &str) -> Option<DateTime<Local>> {
+//!         let date = NaiveDate::parse_from_str(name, "%Y/%m/%d/app.log").ok()?;
+//!         Local.from_local_datetime(&date.and_hms_opt(0, 0, 0)?).earliest()
+//!     }
+//!
+//!     fn list(&self, fs: &dyn Fs, folder: &Path) -> std::io::Result<Vec<String>> {
+//!         let mut names = vec![];
+//!         for year in fs.read_dir(folder)? {
+//!             for month in fs.read_dir(&folder.join(&year)).unwrap_or_default() {
+//!                 for day in fs.read_dir(&folder.join(&year).join(&month)).unwrap_or_default() {
+//!                     names.push(format!("{}/{}/{}/app.log", year, month, day));
+//!                 }
+//!             }
+//!         }
+//!         Ok(names.into_iter().filter(|name| self.matches(name)).collect())
+//!     }
+//! }
+//!
+//! # fn docs() {
+//! let file_appender = BasicRollingFileAppender::builder()
+//!     .folder("./log")
+//!     .prefix("app")
+//!     .condition(RollingConditionBasic::new().daily())
+//!     .naming_scheme(Nested)
+//!     .max_files(7)
+//!     .build()
+//!     .unwrap();
+//! # }
+//! ```
+
+use crate::{builder::FileNameFn, compression, Fs, Timezone, FILE_NAME_DATETIME_FORMAT};
+use chrono::{DateTime, Local, NaiveDate};
+use std::{
+    cmp::Ordering,
+    fmt::Debug,
+    io,
+    path::{Path, PathBuf},
+};
+
+/// Generates and recognizes the names of log files. Names are relative to the log folder and
+/// may contain `/` to place files in subfolders, which are created as needed.
+pub trait NamingScheme: Debug + Send + Sync {
+    /// Returns the name of a file started at `now`. If it is the name of the file rolled over
+    /// from, e.g. after two rollovers within one second, the appender appends a counter to it:
+    /// `.1`, `.2`...
+    fn file_name(&self, now: &DateTime<Local>) -> String;
+
+    /// Returns true if `name` is a log file named by this scheme, possibly compressed. Only
+    /// matching files are subject to retention.
+    fn matches(&self, name: &str) -> bool;
+
+    /// Returns the time the file `name` was started, if the name tells. Files of unknown age are
+    /// kept by [`crate::RetentionPolicy::MaxAge`] and not compressed.
+    fn started(&self, _name: &str) -> Option<DateTime<Local>> {
+        None
+    }
+
+    /// Orders two names from the older file to the newer one
+    fn cmp_age(&self, a: &str, b: &str) -> Ordering {
+        crate::cmp_file_names(a, b)
+    }
+
+    /// Returns the names of the log files in `folder`, in no particular order
+    fn list(&self, fs: &dyn Fs, folder: &Path) -> io::Result<Vec<String>> {
+        let mut names = fs.read_dir(folder)?;
+        names.retain(|name| self.matches(name));
+        Ok(names)
+    }
+
+    /// Called after the active file `name` in `folder` was closed for a rollover. Returns the new
+    /// name of the file if the scheme renamed it.
+    fn rename_rolled(&self, _fs: &dyn Fs, _folder: &Path, _name: &str) -> io::Result<Option<String>> {
+        Ok(None)
+    }
+
+    /// Returns true if [`NamingScheme::rename_rolled`] renames the files rolled before too. Such
+    /// schemes can't be combined with a staging folder, where the rolled files aren't.
+    fn renames_older_files(&self) -> bool {
+        false
+    }
+}
+
+/// Names files after the prefix followed by the time they were started, the default. Only files
+/// whose suffix parses with the format are recognized, so other files sharing the prefix, e.g.
+/// `log.log-backup` or those of an appender with the prefix `log.log.audit`, are left alone.
+#[derive(Clone, Debug)]
+pub struct TimestampNaming {
+    prefix: String,
+    suffix_format: String,
+    timezone: Timezone,
+    pub(crate) file_name_fn_opt: Option<FileNameFn>,
+}
+
+impl TimestampNaming {
+    /// Names files `<prefix>.<%Y%m%d.%H%M%S>` in the local time zone
+    pub fn new(prefix: &str) -> TimestampNaming {
+        TimestampNaming {
+            prefix: prefix.to_string(),
+            suffix_format: FILE_NAME_DATETIME_FORMAT.to_string(),
+            timezone: Timezone::Local,
+            file_name_fn_opt: None,
+        }
+    }
+
+    /// Sets the `strftime` format of the date/time suffix, see
+    /// [`crate::RollingFileAppenderBuilder::suffix_format`]
+    pub fn suffix_format(mut self, format: &str) -> TimestampNaming {
+        self.suffix_format = format.to_string();
+        self
+    }
+
+    /// Formats the suffix in the wall clock of `timezone`
+    pub fn timezone(mut self, timezone: Timezone) -> TimestampNaming {
+        self.timezone = timezone;
+        self
+    }
+}
+
+impl NamingScheme for TimestampNaming {
+    fn file_name(&self, now: &DateTime<Local>) -> String {
+        if let Some(f) = self.file_name_fn_opt.as_ref() {
+            return (f.0)(now);
+        }
+        format!("{}.{}", self.prefix, self.timezone.format(now, &self.suffix_format))
+    }
+
+    fn matches(&self, name: &str) -> bool {
+        if self.file_name_fn_opt.is_some() {
+            // Names of a custom function can't be parsed, any file of the prefix may be one
+            return name.starts_with(&self.prefix) && name != self.prefix && !crate::is_sidecar_file(name);
+        }
+        self.started(name).is_some()
+    }
+
+    fn started(&self, name: &str) -> Option<DateTime<Local>> {
+        let name = compression::strip_rolled_extensions(name);
+        crate::file_name_datetime_with_format(&self.prefix, name, &self.suffix_format, self.timezone)
+    }
+}
+
+/// Names the active file after the prefix and renumbers rolled files on each rollover: the
+/// prefix followed by `.1` for the newest, `.2` for the one before... Rolled files are not
+/// compressed.
+#[derive(Clone, Debug)]
+pub struct SequentialNaming {
+    prefix: String,
+}
+
+impl SequentialNaming {
+    /// Names the active file `prefix`
+    pub fn new(prefix: &str) -> SequentialNaming {
+        SequentialNaming {
+            prefix: prefix.to_string(),
+        }
+    }
+
+    fn numbered(&self, folder: &Path, number: u64) -> PathBuf {
+        folder.join(format!("{}.{}", self.prefix, number))
+    }
+}
+
+impl NamingScheme for SequentialNaming {
+    fn file_name(&self, _now: &DateTime<Local>) -> String {
+        self.prefix.clone()
+    }
+
+    fn matches(&self, name: &str) -> bool {
+        name == self.prefix || sequence_number(&self.prefix, name).is_some()
+    }
+
+    fn cmp_age(&self, a: &str, b: &str) -> Ordering {
+        // The active file is the newest, then the rolled files by number
+        let age = |name: &str| sequence_number(&self.prefix, name).unwrap_or(0);
+        age(b).cmp(&age(a))
+    }
+
+    fn rename_rolled(&self, fs: &dyn Fs, folder: &Path, name: &str) -> io::Result<Option<String>> {
+        let mut numbers = fs
+            .read_dir(folder)?
+            .iter()
+            .filter_map(|fname| sequence_number(&self.prefix, fname))
+            .collect::<Vec<_>>();
+        // Oldest first, so no file is overwritten
+        numbers.sort_unstable_by(|a, b| b.cmp(a));
+        for number in numbers {
+            fs.rename(&self.numbered(folder, number), &self.numbered(folder, number + 1))?;
+        }
+        fs.rename(&folder.join(name), &self.numbered(folder, 1))?;
+        Ok(Some(format!("{}.1", self.prefix)))
+    }
+
+    fn renames_older_files(&self) -> bool {
+        true
+    }
+}
+
+/// Returns the number of a rolled file named by [`SequentialNaming`]
+fn sequence_number(prefix: &str, fname: &str) -> Option<u64> {
+    let number = fname.strip_prefix(prefix)?.strip_prefix('.')?;
+    if number.is_empty() || !number.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    number.parse().ok()
+}
+
+/// Names files after the time they were started, in a subfolder per day:
+/// `<%Y-%m-%d>/<prefix>.<%H%M%S>`. Subfolders are created as needed and removed once retention
+/// deleted all their files. The scheme replaces the appender's time zone, set the one of the
+/// rolling condition with [`DailyFolderNaming::timezone`] so folders follow the same days.
+#[derive(Clone, Debug)]
+pub struct DailyFolderNaming {
+    prefix: String,
+    timezone: Timezone,
+}
+
+impl DailyFolderNaming {
+    /// Names files `<%Y-%m-%d>/<prefix>.<%H%M%S>` in the local time zone
+    pub fn new(prefix: &str) -> DailyFolderNaming {
+        DailyFolderNaming {
+            prefix: prefix.to_string(),
+            timezone: Timezone::Local,
+        }
+    }
+
+    /// Names the folders and files in the wall clock of `timezone`
+    pub fn timezone(mut self, timezone: Timezone) -> DailyFolderNaming {
+        self.timezone = timezone;
+        self
+    }
+}
+
+/// Format of the subfolder names of [`DailyFolderNaming`]
+const DAILY_FOLDER_FORMAT: &str = "%Y-%m-%d";
+
+impl NamingScheme for DailyFolderNaming {
+    fn file_name(&self, now: &DateTime<Local>) -> String {
+        format!(
+            "{}/{}.{}",
+            self.timezone.format(now, DAILY_FOLDER_FORMAT),
+            self.prefix,
+            self.timezone.format(now, "%H%M%S")
+        )
+    }
+
+    fn matches(&self, name: &str) -> bool {
+        self.started(name).is_some()
+    }
+
+    fn started(&self, name: &str) -> Option<DateTime<Local>> {
+        let name = compression::strip_rolled_extensions(name);
+        let (day, fname) = name.split_once('/')?;
+        let time = fname.strip_prefix(&self.prefix)?;
+        // Parsed as `<prefix>.<%Y-%m-%d>.<%H%M%S>`, keeping a counter suffix if any
+        let flat = format!("{}.{}{}", self.prefix, day, time);
+        let format = format!("{}.%H%M%S", DAILY_FOLDER_FORMAT);
+        crate::file_name_datetime_with_format(&self.prefix, &flat, &format, self.timezone)
+    }
+
+    fn list(&self, fs: &dyn Fs, folder: &Path) -> io::Result<Vec<String>> {
+        let mut names = vec![];
+        for day in fs.read_dir(folder)? {
+            if NaiveDate::parse_from_str(&day, DAILY_FOLDER_FORMAT).is_err() {
+                continue;
+            }
+            for fname in fs.read_dir(&folder.join(&day)).unwrap_or_default() {
+                let name = format!("{}/{}", day, fname);
+                if self.matches(&name) {
+                    names.push(name);
+                }
+            }
+        }
+        Ok(names)
+    }
+}
+
+#[cfg(test)]
+mod t {
+    use crate::*;
+
+    #[test]
+    fn test_sequential_names() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let mut rfa = BasicRollingFileAppender::builder()
+            .folder(folder)
+            .prefix("log.log")
+            .condition(RollingConditionBasic::new().daily())
+            .max_files(3)
+            .naming_scheme(SequentialNaming::new("log.log"))
+            .build()
+            .unwrap();
+        for day in 1..=4 {
+            let now = Local.with_ymd_and_hms(2099, 3, day, 1, 0, 0).unwrap();
+            rfa.write_with_datetime(format!("day {}\n", day).as_bytes(), &now)
+                .unwrap();
+        }
+        rfa.flush().unwrap();
+
+        let mut names = std::fs::read_dir(folder)
+            .unwrap()
+            .map(|f| f.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names, ["log.log", "log.log.1", "log.log.2"]);
+        let read = |name: &str| std::fs::read_to_string(dir.path().join(name)).unwrap();
+        assert_eq!(read("log.log"), "day 4\n");
+        assert_eq!(read("log.log.1"), "day 3\n");
+        assert_eq!(read("log.log.2"), "day 2\n");
+        assert_eq!(
+            rfa.list_rotated_files().unwrap(),
+            [dir.path().join("log.log.2"), dir.path().join("log.log.1")]
+        );
+    }
+
+    #[test]
+    fn test_maintenance_follows_naming() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let mut rfa = BasicRollingFileAppender::builder()
+            .folder(folder)
+            .prefix("log.log")
+            .condition(RollingConditionBasic::new().hourly())
+            .max_files(9)
+            .suffix_format("%Y-%m-%d_%H%M")
+            .build()
+            .unwrap();
+        rfa.rollover().unwrap();
+        for hour in 1..4 {
+            let now = Local.with_ymd_and_hms(2099, 3, 1, hour, 0, 0).unwrap();
+            rfa.write_with_datetime(format!("error {}\n", hour).as_bytes(), &now)
+                .unwrap();
+        }
+        rfa.flush().unwrap();
+
+        // Search and consolidation recognize the files by the configured suffix format
+        let range =
+            Local.with_ymd_and_hms(2099, 3, 1, 2, 0, 0).unwrap()..Local.with_ymd_and_hms(2099, 3, 1, 4, 0, 0).unwrap();
+        let lines = rfa
+            .search(range, |line| line.starts_with("error"))
+            .unwrap()
+            .map(|m| m.unwrap().line)
+            .collect::<Vec<_>>();
+        assert_eq!(lines, ["error 2", "error 3"]);
+        assert_eq!(rfa.consolidate_small_files(1000).unwrap(), 1);
+        let read = |name: &str| std::fs::read_to_string(dir.path().join(name)).unwrap();
+        assert_eq!(read("log.log.2099-03-01_0100"), "error 1\nerror 2\n");
+        assert_eq!(read("log.log.2099-03-01_0300"), "error 3\n");
+    }
+
+    #[test]
+    fn test_sequential_names_without_staging() {
+        let dir = tempfile::tempdir().unwrap();
+        let staging = tempfile::tempdir().unwrap();
+        let rfa = BasicRollingFileAppender::builder()
+            .folder(dir.path().to_str().unwrap())
+            .prefix("log.log")
+            .condition(RollingConditionBasic::new().daily())
+            .naming_scheme(SequentialNaming::new("log.log"))
+            .build()
+            .unwrap();
+        // Each rollover would move a new `log.log.1` over the previous one
+        let e = rfa.with_staging_folder(staging.path().to_str().unwrap()).unwrap_err();
+        assert!(matches!(RollingFileError::from(e), RollingFileError::InvalidConfig(_)));
+    }
+
+    #[test]
+    fn test_retention_ignores_other_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let others = [
+            "log.log-important-backup",
+            "log.log.audit.20990101.000000",
+            "log.log.txt",
+        ];
+        for name in others {
+            std::fs::write(dir.path().join(name), "keep\n").unwrap();
+        }
+        let mut rfa =
+            BasicRollingFileAppender::new(folder, "log.log", RollingConditionBasic::new().daily(), 1).unwrap();
+        rfa.rollover().unwrap();
+        for day in 1..=3 {
+            let now = Local.with_ymd_and_hms(2099, 3, day, 1, 0, 0).unwrap();
+            rfa.write_with_datetime(b"line\n", &now).unwrap();
+        }
+        rfa.flush().unwrap();
+
+        let mut names = std::fs::read_dir(folder)
+            .unwrap()
+            .map(|f| f.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(
+            names,
+            [
+                "log.log",
+                "log.log-important-backup",
+                "log.log.20990303.010000",
+                "log.log.audit.20990101.000000",
+                "log.log.txt"
+            ]
+        );
+        assert!(rfa.list_rotated_files().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_daily_folders() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let mut rfa = BasicRollingFileAppender::builder()
+            .folder(folder)
+            .prefix("log.log")
+            .condition(RollingConditionBasic::new().daily())
+            .max_files(2)
+            .naming_scheme(DailyFolderNaming::new("log.log"))
+            .build()
+            .unwrap();
+        rfa.rollover().unwrap();
+        for day in 1..=3 {
+            let now = Local.with_ymd_and_hms(2099, 3, day, 1, 2, 3).unwrap();
+            rfa.write_with_datetime(format!("day {}\n", day).as_bytes(), &now)
+                .unwrap();
+        }
+        rfa.flush().unwrap();
+
+        // The folder of the first day was removed with its file
+        let mut names = std::fs::read_dir(folder)
+            .unwrap()
+            .map(|f| f.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names, ["2099-03-02", "2099-03-03", "log.log"]);
+        let latest = std::fs::read_to_string(dir.path().join("log.log")).unwrap();
+        assert_eq!(latest, "day 3\n");
+        assert_eq!(
+            rfa.list_rotated_files().unwrap(),
+            [dir.path().join("2099-03-02/log.log.010203")]
+        );
+    }
+
+    #[test]
+    fn test_daily_folders_in_utc() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let mut rfa = BasicRollingFileAppender::builder()
+            .folder(folder)
+            .prefix("log.log")
+            .condition(RollingConditionBasic::new().daily().timezone(Timezone::Utc))
+            .max_files(2)
+            .naming_scheme(DailyFolderNaming::new("log.log").timezone(Timezone::Utc))
+            .build()
+            .unwrap();
+        // Either side of midnight UTC, whatever the local time zone
+        for (day, hour) in [(1, 23), (2, 0)] {
+            let now = Utc.with_ymd_and_hms(2099, 3, day, hour, 30, 0).unwrap().with_timezone(&Local);
+            rfa.write_with_datetime(format!("{} {}\n", day, hour).as_bytes(), &now)
+                .unwrap();
+        }
+        rfa.flush().unwrap();
+
+        let rolled = dir.path().join("2099-03-01/log.log.233000");
+        assert_eq!(std::fs::read_to_string(&rolled).unwrap(), "1 23\n");
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("2099-03-02/log.log.003000")).unwrap(),
+            "2 0\n"
+        );
+        assert_eq!(rfa.list_rotated_files().unwrap(), [rolled]);
+        let started = Utc.with_ymd_and_hms(2099, 3, 1, 23, 30, 0).unwrap();
+        assert_eq!(
+            DailyFolderNaming::new("log.log")
+                .timezone(Timezone::Utc)
+                .started("2099-03-01/log.log.233000"),
+            Some(started.with_timezone(&Local))
+        );
+    }
+}
@@ -0,0 +1,484 @@
+//! Writing from the application threads without waiting for the disk.
+//!
+//! A [`NonBlockingRollingFileAppender`] hands each write to a worker thread through a bounded
+//! channel; the worker owns the appender and does all file I/O, rollovers and retention. Handles
+//! are cheap to clone, so every thread can hold one. With the `tracing` feature a handle
+//! implements `MakeWriter`, so `tracing_subscriber::fmt().with_writer(handle)` sends each event to
+//! the worker.
+
+use crate::{MemoryLimit, RollingCondition, RollingFileAppender, RollingFileConfig, RollingFileError};
+use std::{
+    any::Any,
+    fmt,
+    io::{self, Write},
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        mpsc::{self, Receiver, RecvTimeoutError, SyncSender, TrySendError},
+        Arc,
+        Mutex,
+    },
+    thread::JoinHandle,
+    time::Duration,
+};
+
+/// How long a lossy handle's flush waits for the worker
+const LOSSY_FLUSH_TIMEOUT: Duration = Duration::from_secs(1);
+
+enum Message {
+    Write(Vec<u8>),
+    Flush(SyncSender<io::Result<()>>),
+    /// A boxed [`RollingFileConfig`] of the condition type of the appender
+    Reconfigure(Box<dyn Any + Send>, SyncSender<io::Result<()>>),
+    Shutdown,
+}
+
+/// A handle writing to an appender owned by a worker thread. Create it with
+/// [`NonBlockingRollingFileAppender::new`] and keep the returned [`WorkerGuard`] alive until the
+/// end of `main`.
+#[derive(Clone, Debug)]
+pub struct NonBlockingRollingFileAppender {
+    sender: SyncSender<Message>,
+    lossy: bool,
+    dropped: Arc<AtomicU64>,
+    /// Bytes sent to the worker and not written yet
+    queued: Arc<AtomicUsize>,
+    /// The memory limit of the appender and the memory held by its write buffers
+    budget_opt: Option<(MemoryLimit, usize)>,
+    worker: Arc<Mutex<Option<JoinHandle<io::Result<()>>>>>,
+}
+
+impl std::fmt::Debug for Message {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Message::Write(data) => write!(f, "Write({} bytes)", data.len()),
+            Message::Flush(_) => f.write_str("Flush"),
+            Message::Reconfigure(..) => f.write_str("Reconfigure"),
+            Message::Shutdown => f.write_str("Shutdown"),
+        }
+    }
+}
+
+impl NonBlockingRollingFileAppender {
+    /// Moves `appender` to a worker thread, which accepts up to `capacity` pending writes.
+    /// Writes block while the channel is full. If the appender has a [`MemoryLimit`], the bytes
+    /// of the pending writes count against it, and a write which doesn't fit is rejected or
+    /// dropped according to its policy.
+    pub fn new<RC>(appender: RollingFileAppender<RC>, capacity: usize) -> io::Result<(Self, WorkerGuard)>
+    where
+        RC: RollingCondition + Send + 'static,
+    {
+        let (sender, receiver) = mpsc::sync_channel(capacity.max(1));
+        let options = appender.thread_options().clone();
+        let buffers = appender.effective_buffer_capacity() * appender.buffer_count();
+        let budget_opt = appender.memory_limit_opt.map(|limit| (limit, buffers));
+        let queued = Arc::new(AtomicUsize::new(0));
+        let worker_queued = queued.clone();
+        let thread = options.spawn("rolling-file-writer", move || {
+            run_worker(appender, receiver, &worker_queued)
+        })?;
+        let handle = NonBlockingRollingFileAppender {
+            sender,
+            lossy: false,
+            dropped: Arc::new(AtomicU64::new(0)),
+            queued,
+            budget_opt,
+            worker: Arc::new(Mutex::new(Some(thread))),
+        };
+        let guard = WorkerGuard { handle: handle.clone() };
+        Ok((handle, guard))
+    }
+
+    /// Drops writes while the channel is full instead of blocking, counting them in
+    /// [`NonBlockingRollingFileAppender::dropped_writes`]. Applies to this handle and its clones.
+    pub fn lossy(mut self, lossy: bool) -> Self {
+        self.lossy = lossy;
+        self
+    }
+
+    /// Returns how many writes were dropped by lossy handles or, with a memory limit discarding
+    /// writes, because they didn't fit
+    pub fn dropped_writes(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Has the worker apply `config` after the writes sent before, see
+    /// [`RollingFileAppender::reconfigure`], and waits for it. `RC` must be the condition type of
+    /// the appender.
+    pub fn reconfigure<RC>(&self, config: RollingFileConfig<RC>) -> io::Result<()>
+    where
+        RC: RollingCondition + Send + 'static,
+    {
+        let (reply, done) = mpsc::sync_channel(1);
+        self.sender
+            .send(Message::Reconfigure(Box::new(config), reply))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "the log writer thread has stopped"))?;
+        done.recv().unwrap_or_else(|_| {
+            Err(io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                "the log writer thread has stopped",
+            ))
+        })
+    }
+
+    /// Stops the worker after it wrote and fsynced everything sent before and its queued
+    /// maintenance finished, and joins it. Later writes through any handle fail; stopping again
+    /// does nothing.
+    pub(crate) fn stop_worker(&self) -> io::Result<()> {
+        let thread_opt = self.worker.lock().unwrap_or_else(|e| e.into_inner()).take();
+        let Some(thread) = thread_opt else {
+            return Ok(());
+        };
+        let _ = self.sender.send(Message::Shutdown);
+        thread
+            .join()
+            .unwrap_or_else(|_| Err(io::Error::other("the log writer thread panicked")))
+    }
+
+    /// Queues `data` for the worker, counting it against the memory limit until it's written
+    fn send(&self, data: Vec<u8>) -> io::Result<()> {
+        let len = data.len();
+        let queued = self.queued.fetch_add(len, Ordering::Relaxed);
+        if let Some((limit, buffers)) = self.budget_opt {
+            match limit.admit(buffers.saturating_add(queued), len) {
+                Ok(true) => {},
+                admitted => {
+                    self.queued.fetch_sub(len, Ordering::Relaxed);
+                    admitted?;
+                    self.dropped.fetch_add(1, Ordering::Relaxed);
+                    return Ok(());
+                },
+            }
+        }
+        let disconnected = || io::Error::new(io::ErrorKind::BrokenPipe, "the log writer thread has stopped");
+        let result = if self.lossy {
+            match self.sender.try_send(Message::Write(data)) {
+                Ok(()) => return Ok(()),
+                Err(TrySendError::Full(_)) => {
+                    self.dropped.fetch_add(1, Ordering::Relaxed);
+                    Ok(())
+                },
+                Err(TrySendError::Disconnected(_)) => Err(disconnected()),
+            }
+        } else {
+            match self.sender.send(Message::Write(data)) {
+                Ok(()) => return Ok(()),
+                Err(_) => Err(disconnected()),
+            }
+        };
+        self.queued.fetch_sub(len, Ordering::Relaxed);
+        result
+    }
+}
+
+impl Write for NonBlockingRollingFileAppender {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.send(buf.to_vec())?;
+        Ok(buf.len())
+    }
+
+    /// Sends the formatted record in one message, as `write!` and `writeln!` on a handle do, so
+    /// a lossy handle drops whole records, never pieces of them
+    fn write_fmt(&mut self, args: fmt::Arguments<'_>) -> io::Result<()> {
+        let record = args.as_str().map_or_else(|| fmt::format(args), str::to_string);
+        self.send(record.into_bytes())
+    }
+
+    /// Waits until everything written before reached the file. A lossy handle doesn't wait for
+    /// room in the channel, and gives up after a while if the worker is busy.
+    fn flush(&mut self) -> io::Result<()> {
+        let (reply, done) = mpsc::sync_channel(1);
+        let not_flushed = || io::Error::new(io::ErrorKind::TimedOut, "the log writer didn't flush");
+        if self.lossy {
+            match self.sender.try_send(Message::Flush(reply)) {
+                Ok(()) => {},
+                Err(TrySendError::Full(_)) => return Err(not_flushed()),
+                Err(TrySendError::Disconnected(_)) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::BrokenPipe,
+                        "the log writer thread has stopped",
+                    ))
+                },
+            }
+        } else {
+            self.sender
+                .send(Message::Flush(reply))
+                .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "the log writer thread has stopped"))?;
+        }
+        let result = if self.lossy {
+            done.recv_timeout(LOSSY_FLUSH_TIMEOUT).map_err(|_| ())
+        } else {
+            done.recv().map_err(|_| ())
+        };
+        result.unwrap_or_else(|_| Err(not_flushed()))
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl<'a> tracing_subscriber::fmt::MakeWriter<'a> for NonBlockingRollingFileAppender {
+    type Writer = NonBlockingRollingFileAppender;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self.clone()
+    }
+}
+
+fn run_worker<RC>(
+    mut appender: RollingFileAppender<RC>,
+    receiver: Receiver<Message>,
+    queued: &AtomicUsize,
+) -> io::Result<()>
+where
+    RC: RollingCondition + 'static,
+{
+    let mut dirty = false;
+    loop {
+        // Flush whenever the channel runs dry, so data doesn't linger in the buffer
+        let message = match receiver.recv_timeout(Duration::ZERO) {
+            Ok(message) => message,
+            Err(RecvTimeoutError::Timeout) => {
+                if std::mem::take(&mut dirty) {
+                    if let Err(e) = appender.flush() {
+                        eprintln!("WARNING: Failed to flush logfile {}", e);
+                    }
+                }
+                match receiver.recv() {
+                    Ok(message) => message,
+                    Err(_) => break,
+                }
+            },
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+        match message {
+            Message::Write(data) => {
+                if let Err(e) = appender.write_all(&data) {
+                    eprintln!("WARNING: Failed to write logfile {}", e);
+                }
+                queued.fetch_sub(data.len(), Ordering::Relaxed);
+                dirty = true;
+            },
+            Message::Flush(reply) => {
+                dirty = false;
+                let _ = reply.send(appender.flush());
+            },
+            Message::Reconfigure(config, reply) => {
+                let result = match config.downcast::<RollingFileConfig<RC>>() {
+                    Ok(config) => appender.reconfigure(*config),
+                    Err(_) => {
+                        Err(RollingFileError::InvalidConfig("the condition type doesn't match".to_string()).into())
+                    },
+                };
+                let _ = reply.send(result);
+            },
+            Message::Shutdown => break,
+        }
+    }
+    let result = appender.sync();
+    if let Some(maintenance) = appender.maintenance_opt.as_ref() {
+        maintenance.drain();
+    }
+    result
+}
+
+/// Stops the worker thread of a [`NonBlockingRollingFileAppender`] when dropped, after it wrote
+/// and fsynced everything sent before and the maintenance it queued, e.g. compressing rolled
+/// files, finished. Mirrors `tracing_appender::non_blocking::WorkerGuard`.
+#[derive(Debug)]
+#[must_use = "dropping the guard immediately stops the writer thread, keep it alive instead"]
+pub struct WorkerGuard {
+    handle: NonBlockingRollingFileAppender,
+}
+
+impl Drop for WorkerGuard {
+    fn drop(&mut self) {
+        if let Err(e) = self.handle.stop_worker() {
+            eprintln!("WARNING: Failed to sync logfile {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod t {
+    use crate::*;
+    use std::io::Write;
+
+    #[test]
+    fn test_non_blocking_writes() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let rfa = BasicRollingFileAppender::new(folder, "log.log", RollingConditionBasic::new().daily(), 3).unwrap();
+        let (handle, guard) = NonBlockingRollingFileAppender::new(rfa, 16).unwrap();
+        let threads = (0..4)
+            .map(|i| {
+                let mut handle = handle.clone();
+                std::thread::spawn(move || {
+                    for j in 0..10 {
+                        writeln!(handle, "thread {} line {}", i, j).unwrap();
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        threads.into_iter().for_each(|t| t.join().unwrap());
+        handle.clone().flush().unwrap();
+        let contents = std::fs::read_to_string(dir.path().join("log.log")).unwrap();
+        assert_eq!(contents.lines().count(), 40);
+
+        writeln!(handle.clone(), "last").unwrap();
+        drop(guard);
+        let contents = std::fs::read_to_string(dir.path().join("log.log")).unwrap();
+        assert!(contents.ends_with("last\n"));
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn test_make_writer() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let rfa = BasicRollingFileAppender::new(folder, "log.log", RollingConditionBasic::new().daily(), 3).unwrap();
+        let (handle, guard) = NonBlockingRollingFileAppender::new(rfa, 16).unwrap();
+        let subscriber = tracing_subscriber::fmt()
+            .with_writer(handle)
+            .with_ansi(false)
+            .without_time()
+            .finish();
+        tracing::subscriber::with_default(subscriber, || {
+            for i in 0..10 {
+                tracing::info!(i, "event");
+            }
+        });
+        drop(guard);
+
+        let contents = std::fs::read_to_string(dir.path().join("log.log")).unwrap();
+        let lines = contents.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 10);
+        assert!(lines[9].contains("INFO") && lines[9].ends_with("event i=9"));
+    }
+
+    #[test]
+    fn test_guard_waits_for_maintenance() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let maintenance = Maintenance::new().unwrap();
+        let triggered = RollingConditionTriggered::new();
+        let trigger = triggered.handle();
+        let count = AtomicUsize::new(0);
+        let rfa = RollingFileAppender::builder()
+            .folder(folder)
+            .prefix("log.log")
+            .condition(triggered)
+            .max_files(1)
+            .file_name(move |_| format!("log.log.{}", count.fetch_add(1, Ordering::Relaxed)))
+            .symlink(false)
+            .build()
+            .unwrap()
+            .with_maintenance(maintenance.handle());
+        let (mut handle, guard) = NonBlockingRollingFileAppender::new(rfa, 16).unwrap();
+        for i in 0..3 {
+            writeln!(handle, "file {}", i).unwrap();
+            handle.flush().unwrap();
+            trigger.trigger();
+        }
+        drop(guard);
+
+        let mut names = std::fs::read_dir(folder)
+            .unwrap()
+            .map(|f| f.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names, ["log.log.2"]);
+        let contents = std::fs::read_to_string(dir.path().join("log.log.2")).unwrap();
+        assert_eq!(contents, "file 2\n");
+    }
+
+    #[test]
+    fn test_lossy_drops_whole_records() {
+        use std::sync::{mpsc, Mutex};
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let triggered = RollingConditionTriggered::new();
+        let trigger = triggered.handle();
+        let (entered, stuck) = mpsc::channel();
+        let (release, released) = mpsc::channel::<()>();
+        let (entered, released) = (Mutex::new(entered), Mutex::new(released));
+        let rfa = RollingFileAppender::builder()
+            .folder(folder)
+            .prefix("log.log")
+            .condition(triggered)
+            .max_files(9)
+            .symlink(false)
+            .on_rotate(move |_, _| {
+                let _ = entered.lock().unwrap().send(());
+                let _ = released.lock().unwrap().recv();
+            })
+            .build()
+            .unwrap();
+        let (handle, guard) = NonBlockingRollingFileAppender::new(rfa, 1).unwrap();
+        let mut handle = handle.lossy(true);
+        trigger.trigger();
+        writeln!(handle, "first").unwrap();
+        // The worker is busy rolling over, the channel has room for one record
+        stuck.recv().unwrap();
+        for i in 0..10 {
+            writeln!(handle, "record {} of {}", i, 10).unwrap();
+        }
+        let start = std::time::Instant::now();
+        assert_eq!(handle.flush().unwrap_err().kind(), io::ErrorKind::TimedOut);
+        assert!(start.elapsed() < super::LOSSY_FLUSH_TIMEOUT);
+        assert_eq!(handle.dropped_writes(), 9);
+        release.send(()).unwrap();
+        drop(guard);
+
+        let mut lines = vec![];
+        for f in std::fs::read_dir(folder).unwrap() {
+            let contents = std::fs::read_to_string(f.unwrap().path()).unwrap();
+            lines.extend(contents.lines().map(str::to_string));
+        }
+        lines.sort();
+        assert_eq!(lines, ["first", "record 0 of 10"]);
+    }
+
+    #[test]
+    fn test_memory_limit_counts_queue() {
+        use std::sync::{mpsc, Mutex};
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let triggered = RollingConditionTriggered::new();
+        let trigger = triggered.handle();
+        let (entered, stuck) = mpsc::channel();
+        let (release, released) = mpsc::channel::<()>();
+        let (entered, released) = (Mutex::new(entered), Mutex::new(released));
+        let rfa = RollingFileAppender::builder()
+            .folder(folder)
+            .prefix("log.log")
+            .condition(triggered)
+            .max_files(9)
+            .symlink(false)
+            .on_rotate(move |_, _| {
+                let _ = entered.lock().unwrap().send(());
+                let _ = released.lock().unwrap().recv();
+            })
+            .build()
+            .unwrap()
+            // Leaves 32 bytes for the queue next to a 32 byte write buffer
+            .with_memory_limit(MemoryLimit::new(64, MemoryPolicy::Reject));
+        let (mut handle, guard) = NonBlockingRollingFileAppender::new(rfa, 16).unwrap();
+        trigger.trigger();
+        handle.write_all(b"first\n").unwrap();
+        stuck.recv().unwrap();
+        // The worker holds the first write while rolling over
+        handle.write_all(&[b'x'; 20]).unwrap();
+        let err = handle.write_all(&[b'y'; 20]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::OutOfMemory);
+        release.send(()).unwrap();
+        handle.flush().unwrap();
+        handle.write_all(&[b'z'; 20]).unwrap();
+        drop(guard);
+
+        let mut contents = String::new();
+        for f in std::fs::read_dir(folder).unwrap() {
+            contents.push_str(&std::fs::read_to_string(f.unwrap().path()).unwrap());
+        }
+        assert!(!contents.contains('y'));
+        assert_eq!(contents.len(), 6 + 20 + 20);
+    }
+}
@@ -0,0 +1,102 @@
+//! Flushing appenders when the program panics.
+//!
+//! Buffered lines written just before a panic, often the ones explaining it, are lost if the
+//! process aborts or exits before the appender is dropped. [`install_panic_hook`] registers a
+//! panic hook flushing and fsyncing every appender registered with
+//! [`SharedRollingFileAppender::flush_on_panic`].
+
+use crate::{RollingCondition, RollingFileAppender, SharedRollingFileAppender};
+use std::{
+    panic,
+    sync::{Mutex, Once, TryLockError, Weak},
+    thread,
+    time::Duration,
+};
+
+trait PanicFlush: Send + Sync {
+    fn flush_on_panic(&self);
+}
+
+impl<RC> PanicFlush for Mutex<RollingFileAppender<RC>>
+where
+    RC: RollingCondition + Send,
+{
+    fn flush_on_panic(&self) {
+        // The panicking thread may hold the lock itself, so never block on it
+        for _ in 0..10 {
+            match self.try_lock() {
+                Ok(mut rfa) => {
+                    let _ = rfa.sync();
+                    return;
+                },
+                Err(TryLockError::Poisoned(e)) => {
+                    let _ = e.into_inner().sync();
+                    return;
+                },
+                Err(TryLockError::WouldBlock) => thread::sleep(Duration::from_millis(10)),
+            }
+        }
+    }
+}
+
+static REGISTRY: Mutex<Vec<Weak<dyn PanicFlush>>> = Mutex::new(Vec::new());
+static INSTALL: Once = Once::new();
+
+/// Installs a panic hook which flushes and fsyncs all registered appenders after running the
+/// previously installed hook. Calling it more than once has no further effect.
+pub fn install_panic_hook() {
+    INSTALL.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            previous(info);
+            flush_registered();
+        }));
+    });
+}
+
+/// Flushes and fsyncs all registered appenders which are still alive.
+pub fn flush_registered() {
+    let appenders = match REGISTRY.try_lock() {
+        Ok(registry) => registry.clone(),
+        Err(_) => return,
+    };
+    for appender in appenders.iter().filter_map(Weak::upgrade) {
+        appender.flush_on_panic();
+    }
+}
+
+impl<RC> SharedRollingFileAppender<RC>
+where
+    RC: RollingCondition + Send + 'static,
+{
+    /// Registers this appender to be flushed by the hook installed with [`install_panic_hook`].
+    /// The registration ends when the last handle to the appender is dropped.
+    pub fn flush_on_panic(&self) {
+        let mut registry = REGISTRY.lock().unwrap_or_else(|e| e.into_inner());
+        registry.retain(|a| a.strong_count() > 0);
+        let inner: std::sync::Arc<dyn PanicFlush> = self.inner.clone();
+        registry.push(std::sync::Arc::downgrade(&inner));
+    }
+}
+
+#[cfg(test)]
+mod t {
+    use super::*;
+    use crate::*;
+
+    #[test]
+    fn test_flush_registered() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let rfa = BasicRollingFileAppender::new(folder, "log.log", RollingConditionBasic::new().daily(), 3).unwrap();
+        let (shared, _guard) = SharedRollingFileAppender::new(rfa);
+        shared.flush_on_panic();
+        install_panic_hook();
+        shared.clone().write_all(b"last words\n").unwrap();
+
+        let result = thread::spawn(|| panic!("expected panic")).join();
+        assert!(result.is_err());
+        let contents = std::fs::read_to_string(dir.path().join("log.log")).unwrap();
+        assert_eq!(contents, "last words\n");
+    }
+}
@@ -0,0 +1,78 @@
+//! Bounded parallelism for maintenance work touching many files.
+
+use crate::ThreadOptions;
+use std::{
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+};
+
+/// Runs maintenance work on up to `threads` threads spawned with the appender's
+/// [`ThreadOptions`], see [`crate::RollingFileAppender::with_maintenance_threads`].
+#[derive(Clone, Debug)]
+pub(crate) struct Pool {
+    threads: usize,
+    options: ThreadOptions,
+}
+
+impl Pool {
+    pub(crate) fn new(threads: usize, options: ThreadOptions) -> Pool {
+        Pool { threads, options }
+    }
+
+    /// Returns the maximum number of threads used
+    pub(crate) fn threads(&self) -> usize {
+        self.threads
+    }
+
+    /// Calls `f` for every item, using at most `threads` threads. With a single thread, or a
+    /// single item, the work is done on the calling thread, as it is if no thread can be spawned.
+    /// Returns once every item has been processed.
+    pub(crate) fn for_each<T, F>(&self, items: &[T], f: F)
+    where
+        T: Sync,
+        F: Fn(&T) + Sync,
+    {
+        let threads = self.threads.clamp(1, items.len().max(1));
+        if threads == 1 {
+            items.iter().for_each(f);
+            return;
+        }
+        let next = AtomicUsize::new(0);
+        let work = || {
+            while let Some(item) = items.get(next.fetch_add(1, Ordering::Relaxed)) {
+                f(item);
+            }
+        };
+        thread::scope(|s| {
+            for _ in 1..threads {
+                if let Err(e) = self.options.spawn_scoped(s, "rolling-file-pool", work) {
+                    eprintln!("WARNING: Failed to spawn maintenance thread {}", e);
+                    break;
+                }
+            }
+            work();
+        });
+    }
+}
+
+#[cfg(test)]
+mod t {
+    use super::*;
+
+    #[test]
+    fn test_every_item_processed_once() {
+        let items = (0..100).collect::<Vec<usize>>();
+        let sum = AtomicUsize::new(0);
+        let names = std::sync::Mutex::new(std::collections::BTreeSet::new());
+        let pool = Pool::new(4, ThreadOptions::new().name("custom-pool"));
+        pool.for_each(&items, |i| {
+            sum.fetch_add(*i, Ordering::Relaxed);
+            let name = thread::current().name().map(str::to_string);
+            names.lock().unwrap().insert(name);
+            // Give the other threads a chance to pick up items
+            thread::sleep(std::time::Duration::from_millis(1));
+        });
+        assert_eq!(sum.into_inner(), 4950);
+        assert!(names.into_inner().unwrap().contains(&Some("custom-pool".to_string())));
+    }
+}
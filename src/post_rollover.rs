@@ -0,0 +1,391 @@
+//! The work following a rollover.
+//!
+//! Linking the new file, compressing, encrypting and recording the rolled one, and applying
+//! retention all touch the directory and may be slow. Each is its own [`MaintenanceJob`], run in
+//! order on the appender's maintenance scheduler if it has one, so a failing step is reported
+//! without keeping the later ones from running.
+
+#[cfg(feature = "bundle")]
+use crate::archive::{self, BundlePeriod};
+#[cfg(feature = "zstd")]
+use crate::compression::DictionaryCompression;
+#[cfg(feature = "manifest")]
+use crate::manifest;
+use crate::{
+    audit,
+    builder::Hooks,
+    compression,
+    consolidate,
+    encryption,
+    pool::Pool,
+    remove_expired_files,
+    tiering,
+    update_latest_link,
+    ArchivePolicy,
+    Clock,
+    Compression,
+    Encryptor,
+    Fs,
+    LatestLink,
+    MaintenanceJob,
+    NamingScheme,
+    RetentionManager,
+    RetentionPolicy,
+    TieringPolicy,
+};
+use chrono::{DateTime, Local};
+use std::{
+    io,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+        Mutex,
+    },
+};
+
+/// How the latest link to the active file is kept
+#[derive(Clone, Debug)]
+pub(crate) struct LinkSettings {
+    pub(crate) kind: LatestLink,
+    /// Defaults to the prefix
+    pub(crate) name_opt: Option<String>,
+}
+
+impl Default for LinkSettings {
+    fn default() -> LinkSettings {
+        LinkSettings {
+            kind: LatestLink::Symlink,
+            name_opt: None,
+        }
+    }
+}
+
+/// What happens to a file once it's rolled
+#[derive(Clone, Debug, Default)]
+pub(crate) struct RolledFileSettings {
+    /// Merges small rolled files of the same day up to this size
+    pub(crate) consolidation_opt: Option<u64>,
+    pub(crate) compression: Compression,
+    pub(crate) keep_uncompressed: usize,
+    #[cfg(feature = "zstd")]
+    pub(crate) dictionary_opt: Option<DictionaryCompression>,
+    pub(crate) encryptor_opt: Option<Arc<dyn Encryptor>>,
+    #[cfg(feature = "manifest")]
+    pub(crate) manifest: bool,
+    #[cfg(feature = "bundle")]
+    pub(crate) bundle_opt: Option<BundlePeriod>,
+}
+
+/// Which rolled files are kept, and what becomes of the others
+#[derive(Clone, Debug)]
+pub(crate) struct RetentionSettings {
+    pub(crate) policy: RetentionPolicy,
+    pub(crate) archive: ArchivePolicy,
+    pub(crate) archive_retention_opt: Option<RetentionPolicy>,
+    pub(crate) audit: bool,
+    pub(crate) tiering_opt: Option<TieringPolicy>,
+    pub(crate) manager_opt: Option<RetentionManager>,
+}
+
+impl RetentionSettings {
+    pub(crate) fn new(policy: RetentionPolicy) -> RetentionSettings {
+        RetentionSettings {
+            policy,
+            archive: ArchivePolicy::Delete,
+            archive_retention_opt: None,
+            audit: false,
+            tiering_opt: None,
+            manager_opt: None,
+        }
+    }
+}
+
+type Step = fn(&PostRollover) -> io::Result<()>;
+
+/// Everything the steps following one rollover need, shared by their jobs
+pub(crate) struct PostRollover {
+    pub(crate) fs: Arc<dyn Fs>,
+    pub(crate) folder: String,
+    pub(crate) prefix: String,
+    pub(crate) naming: Arc<dyn NamingScheme>,
+    pub(crate) now: DateTime<Local>,
+    pub(crate) new_file_path: PathBuf,
+    /// The file rolled, once moved out of the staging folder
+    pub(crate) rotated_opt: Option<PathBuf>,
+    pub(crate) link: LinkSettings,
+    pub(crate) rolled: RolledFileSettings,
+    pub(crate) retention: RetentionSettings,
+    pub(crate) pool: Arc<Pool>,
+    pub(crate) hooks: Hooks,
+    pub(crate) clock: Arc<dyn Clock>,
+    pub(crate) deleted_files: Arc<AtomicU64>,
+    /// Files deleted by the retention steps, for the audit
+    pub(crate) deleted: Mutex<Vec<PathBuf>>,
+}
+
+impl PostRollover {
+    /// Returns the jobs of the configured steps, to run in order
+    pub(crate) fn into_jobs(self) -> Vec<MaintenanceJob> {
+        let post = Arc::new(self);
+        post.steps()
+            .into_iter()
+            .map(|(name, step)| {
+                let post = post.clone();
+                MaintenanceJob::new(name, move || step(&post))
+            })
+            .collect()
+    }
+
+    fn steps(&self) -> Vec<(&'static str, Step)> {
+        let (rolled, retention) = (&self.rolled, &self.retention);
+        let mut steps: Vec<(&'static str, Step)> = vec![("link", Self::link)];
+        if rolled.consolidation_opt.is_some() {
+            steps.push(("consolidate", Self::consolidate));
+        }
+        if rolled.compression != Compression::None {
+            steps.push(("compress", Self::compress));
+        }
+        #[cfg(feature = "zstd")]
+        if rolled.dictionary_opt.is_some() {
+            steps.push(("dictionary compression", Self::compress_with_dictionary));
+        }
+        if rolled.encryptor_opt.is_some() && self.rotated_opt.is_some() {
+            steps.push(("encrypt", Self::encrypt));
+        }
+        #[cfg(feature = "manifest")]
+        if rolled.manifest && self.rotated_opt.is_some() {
+            steps.push(("manifest", Self::record_in_manifest));
+        }
+        #[cfg(feature = "upload")]
+        if self.hooks.uploads_opt.is_some() && self.rotated_opt.is_some() {
+            steps.push(("upload", Self::upload));
+        }
+        #[cfg(feature = "bundle")]
+        if rolled.bundle_opt.is_some() {
+            steps.push(("bundle", Self::bundle));
+        }
+        steps.push(("retention", Self::remove_expired_files));
+        #[cfg(feature = "bundle")]
+        if rolled.bundle_opt.is_some() {
+            steps.push(("archive retention", Self::remove_expired_archives));
+        }
+        if retention.audit {
+            steps.push(("audit", Self::audit));
+        }
+        if retention.tiering_opt.is_some() {
+            steps.push(("tiering", Self::apply_tiering));
+        }
+        if retention.manager_opt.is_some() {
+            steps.push(("retention manager", Self::enforce_budget));
+        }
+        steps
+    }
+
+    fn link(&self) -> io::Result<()> {
+        let link_name = self.link.name_opt.as_deref().unwrap_or(&self.prefix);
+        update_latest_link(&*self.fs, self.link.kind, &self.folder, link_name, &self.new_file_path);
+        if let (Some(on_rotate), Some(rotated)) = (self.hooks.on_rotate_opt.as_ref(), self.rotated_opt.as_ref()) {
+            on_rotate(rotated, &self.new_file_path);
+        }
+        Ok(())
+    }
+
+    fn consolidate(&self) -> io::Result<()> {
+        if let Some(max_size) = self.rolled.consolidation_opt {
+            // The file just rolled still has to be recorded and uploaded on its own
+            let skipped = [Some(self.new_file_path.as_path()), self.rotated_opt.as_deref()];
+            let skipped = skipped.into_iter().flatten().collect::<Vec<_>>();
+            consolidate::consolidate_except(&*self.fs, &self.folder, &*self.naming, max_size, &skipped)?;
+        }
+        Ok(())
+    }
+
+    fn compress(&self) -> io::Result<()> {
+        let rolled = self
+            .naming
+            .list(&*self.fs, Path::new(&self.folder))?
+            .into_iter()
+            .filter(|fname| compression::strip_rolled_extensions(fname) == fname)
+            .filter(|fname| self.naming.started(fname).is_some())
+            .collect::<Vec<_>>();
+        compression::compress_rolled_files(
+            &*self.fs,
+            &self.folder,
+            &rolled,
+            self.rolled.compression,
+            self.rolled.keep_uncompressed,
+            &self.new_file_path,
+            &self.pool,
+        )?;
+        Ok(())
+    }
+
+    #[cfg(feature = "zstd")]
+    fn compress_with_dictionary(&self) -> io::Result<()> {
+        if let Some(config) = self.rolled.dictionary_opt.as_ref() {
+            compression::compress_with_dictionary(
+                &*self.fs,
+                &self.folder,
+                &self.prefix,
+                &*self.naming,
+                config,
+                Some(&self.new_file_path),
+            )?;
+        }
+        Ok(())
+    }
+
+    fn encrypt(&self) -> io::Result<()> {
+        if let (Some(encryptor), Some(rotated)) = (self.rolled.encryptor_opt.as_ref(), self.rotated_opt.as_ref()) {
+            encryption::encrypt_rolled_file(&*self.fs, &**encryptor, rotated)?;
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "manifest")]
+    fn record_in_manifest(&self) -> io::Result<()> {
+        let Some(rotated) = self.rotated_opt.as_ref() else {
+            return Ok(());
+        };
+        let name = rotated.strip_prefix(&self.folder).unwrap_or(rotated).to_string_lossy();
+        let path = compression::rolled_version(&*self.fs, rotated).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("rolled logfile {} is gone", rotated.display()),
+            )
+        })?;
+        manifest::record_file(
+            &*self.fs,
+            &self.folder,
+            &path,
+            self.naming.started(&name),
+            Some(&self.now),
+        )
+    }
+
+    #[cfg(feature = "upload")]
+    fn upload(&self) -> io::Result<()> {
+        if let (Some(uploads), Some(rotated)) = (self.hooks.uploads_opt.as_ref(), self.rotated_opt.as_ref()) {
+            uploads.enqueue(&self.folder, rotated);
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "bundle")]
+    fn bundle(&self) -> io::Result<()> {
+        if let Some(period) = self.rolled.bundle_opt {
+            archive::bundle_rolled_files_in(
+                &*self.fs,
+                &self.folder,
+                &self.prefix,
+                &*self.naming,
+                period,
+                &self.now,
+                Some(&self.new_file_path),
+            )?;
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "bundle")]
+    fn remove_expired_archives(&self) -> io::Result<()> {
+        let retention = &self.retention;
+        let policy = retention.archive_retention_opt.as_ref().unwrap_or(&retention.policy);
+        let deleted = archive::remove_expired_archives(&*self.fs, &self.folder, &self.prefix, policy, &self.now)?;
+        self.record_deleted(deleted);
+        Ok(())
+    }
+
+    fn remove_expired_files(&self) -> io::Result<()> {
+        let retention = &self.retention;
+        let deleted = remove_expired_files(
+            &*self.fs,
+            &self.folder,
+            (&*self.naming, self.link.name_opt.as_deref()),
+            (&retention.policy, Some(&self.hooks)),
+            (&retention.archive, retention.archive_retention_opt.as_ref()),
+            &self.now,
+            &self.pool,
+        )?;
+        self.record_deleted(deleted);
+        Ok(())
+    }
+
+    /// Counts deleted files and reports them to the delete hook and the audit
+    fn record_deleted(&self, deleted: Vec<PathBuf>) {
+        self.deleted_files.fetch_add(deleted.len() as u64, Ordering::Relaxed);
+        if let Some(on_delete) = self.hooks.on_delete_opt.as_ref() {
+            deleted.iter().for_each(|path| on_delete(path));
+        }
+        self.deleted.lock().unwrap_or_else(|e| e.into_inner()).extend(deleted);
+    }
+
+    fn audit(&self) -> io::Result<()> {
+        let deleted = self.deleted.lock().unwrap_or_else(|e| e.into_inner());
+        audit::record_deletions(
+            &*self.fs,
+            &self.folder,
+            &self.prefix,
+            &deleted,
+            &self.retention.policy.describe(),
+            &self.clock.now(),
+        )
+    }
+
+    fn apply_tiering(&self) -> io::Result<()> {
+        if let Some(policy) = self.retention.tiering_opt.as_ref() {
+            tiering::apply_tiering(
+                &*self.fs,
+                &self.folder,
+                &*self.naming,
+                policy,
+                &self.now,
+                Some(&self.new_file_path),
+            )?;
+        }
+        Ok(())
+    }
+
+    fn enforce_budget(&self) -> io::Result<()> {
+        if let Some(manager) = self.retention.manager_opt.as_ref() {
+            manager.enforce()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod t {
+    use crate::*;
+    use std::io::Read;
+
+    #[derive(Debug)]
+    struct FailingEncryptor;
+
+    impl Encryptor for FailingEncryptor {
+        fn encrypt(&self, _plaintext: &mut dyn Read, _ciphertext: &mut dyn io::Write) -> io::Result<()> {
+            Err(io::Error::other("no key"))
+        }
+    }
+
+    #[test]
+    fn test_failing_step_keeps_later_steps_running() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let mut rfa = BasicRollingFileAppender::new(folder, "log.log", RollingConditionBasic::new().hourly(), 2)
+            .unwrap()
+            .with_encryption(FailingEncryptor)
+            .with_retention_audit(true);
+        for hour in 1..5 {
+            let now = Local.with_ymd_and_hms(2099, 3, 1, hour, 0, 0).unwrap();
+            rfa.write_with_datetime(b"line\n", &now).unwrap();
+        }
+        rfa.flush().unwrap();
+
+        // Retention and the audit still ran after each failed encryption
+        assert_eq!(rfa.list_rotated_files().unwrap().len(), 1);
+        assert!(rfa.stats().deleted_files >= 2);
+        assert!(!audit::read_audit_log(folder, "log.log").unwrap().is_empty());
+    }
+}
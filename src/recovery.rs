@@ -0,0 +1,122 @@
+//! Repairing a torn last line when resuming an existing log file.
+//!
+//! A crash in the middle of a write can leave a log file ending with a partial line. Appending to
+//! such a file glues the first new line onto the fragment, which confuses line oriented parsers.
+//! With [`crate::RollingFileAppender::with_torn_write_recovery`] the appender checks a resumed
+//! file before its first write and repairs a missing line end.
+
+use crate::{Encoding, Fs};
+use std::{io, path::Path};
+
+/// Size of the blocks read while searching backwards for the last complete line
+const SCAN_CHUNK: usize = 4096;
+
+/// How to repair a resumed file whose last line is incomplete
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum TornWriteRecovery {
+    /// Cuts the file after its last complete line
+    Truncate,
+    /// Terminates the partial line with the given marker followed by a line break
+    Marker(String),
+}
+
+/// The outcome of checking a resumed file
+#[derive(Debug, Eq, PartialEq)]
+pub(crate) enum Repair {
+    /// The file ends with a complete line
+    Intact,
+    /// The file was cut to the given length
+    Truncated(u64),
+    /// These bytes must be appended before any new data
+    Append(Vec<u8>),
+}
+
+/// Checks whether the file of `len` bytes at `path` ends with a line break in `encoding` and
+/// repairs it according to `recovery` if it doesn't.
+pub(crate) fn repair_torn_line(
+    fs: &dyn Fs,
+    path: &Path,
+    len: u64,
+    encoding: Encoding,
+    recovery: &TornWriteRecovery,
+) -> io::Result<Repair> {
+    let mut newline = vec![];
+    encoding.encode_into(&mut newline, "\n");
+    let line_end = last_line_end(fs, path, len, &newline)?;
+    if line_end == len {
+        return Ok(Repair::Intact);
+    }
+    internal_event!(path = %path.display(), len, line_end, "found torn last line");
+    match recovery {
+        TornWriteRecovery::Truncate => {
+            fs.set_len(path, line_end)?;
+            Ok(Repair::Truncated(line_end))
+        },
+        TornWriteRecovery::Marker(marker) => {
+            let mut out = vec![];
+            // An odd number of bytes means a torn UTF-16 code unit, pad it to keep the alignment
+            let torn_bytes = (len % newline.len() as u64) as usize;
+            if torn_bytes > 0 {
+                out.resize(newline.len() - torn_bytes, 0);
+            }
+            encoding.encode_into(&mut out, marker);
+            out.extend_from_slice(&newline);
+            Ok(Repair::Append(out))
+        },
+    }
+}
+
+/// Returns the offset just after the last line break, or 0 if there is none. Line breaks are only
+/// looked for at offsets aligned to their size, so UTF-16 files aren't misread.
+fn last_line_end(fs: &dyn Fs, path: &Path, len: u64, newline: &[u8]) -> io::Result<u64> {
+    let step = newline.len();
+    let mut end = len - len % step as u64;
+    let mut buf = vec![0; SCAN_CHUNK];
+    while end >= step as u64 {
+        let start = end.saturating_sub(SCAN_CHUNK as u64);
+        let chunk = &mut buf[..(end - start) as usize];
+        fs.read_exact_at(path, start, chunk)?;
+        let found = (0..=chunk.len() - step)
+            .rev()
+            .step_by(step)
+            .find(|i| &chunk[*i..*i + step] == newline);
+        if let Some(i) = found {
+            return Ok(start + (i + step) as u64);
+        }
+        end = start;
+    }
+    Ok(0)
+}
+
+#[cfg(test)]
+mod t {
+    use crate::*;
+
+    #[test]
+    fn test_torn_line_repairs() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let now = Local.with_ymd_and_hms(2099, 3, 30, 1, 2, 3).unwrap();
+        let path = dir.path().join("log.log.20990330.010203");
+        let condition = RollingConditionBasic::new().daily();
+
+        std::fs::write(&path, "complete\ntor").unwrap();
+        let mut rfa = BasicRollingFileAppender::new(folder, "log.log", condition, 9)
+            .unwrap()
+            .with_torn_write_recovery(TornWriteRecovery::Truncate);
+        // Resume the prepared file instead of the one opened at construction
+        rfa.rollover().unwrap();
+        rfa.write_with_datetime(b"next\n", &now).unwrap();
+        rfa.flush().unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "complete\nnext\n");
+
+        std::fs::write(&path, "complete\ntor").unwrap();
+        let mut rfa = BasicRollingFileAppender::new(folder, "log.log", condition, 9)
+            .unwrap()
+            .with_torn_write_recovery(TornWriteRecovery::Marker(" [torn]".to_string()));
+        rfa.rollover().unwrap();
+        rfa.write_with_datetime(b"next\n", &now).unwrap();
+        rfa.flush().unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "complete\ntor [torn]\nnext\n");
+    }
+}
@@ -0,0 +1,493 @@
+//! Which log files to keep.
+//!
+//! Each appender applies its [`RetentionPolicy`] after every rollover, bounding its files by
+//! count, total size, age or a combination of them.
+//!
+//! Per-appender limits can't express "keep these logs within 10 GB in total, sacrificing debug
+//! logs before error logs". A [`RetentionManager`] holds a combined disk budget; each appender
+//! registered with [`crate::RollingFileAppender::with_retention_manager`] enforces it after its
+//! rollovers by deleting the oldest rolled files of the lowest priority prefixes first.
+//!
+//! [`crate::RollingFileAppender::plan_retention`] tells what the next rollover would do to each
+//! file and why, without touching anything, to debug surprising deletions.
+
+use crate::{index, Fs, NamingScheme, RealFs, TimestampNaming};
+use chrono::{DateTime, Duration, Local};
+use std::{
+    fmt,
+    io,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+/// Which of an appender's files to keep. The newest file, which may be active, is always kept.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum RetentionPolicy {
+    /// Keeps the given number of newest files, the active one included
+    MaxFiles(usize),
+    /// Keeps the newest files as long as their total size stays within the given bytes
+    MaxTotalSize(u64),
+    /// Deletes files whose last write is older than the given duration. A file was last
+    /// written when the next one was started, as told by their names.
+    MaxAge(Duration),
+    /// Applies every policy: a file is deleted as soon as one of them doesn't keep it
+    All(Vec<RetentionPolicy>),
+}
+
+/// A log file considered for deletion, see [`RetentionPolicy::expired`]
+#[derive(Clone, Debug)]
+pub(crate) struct RetainedFile {
+    pub(crate) len: u64,
+    /// When the file was last written to, if known
+    pub(crate) ended_opt: Option<DateTime<Local>>,
+}
+
+impl RetentionPolicy {
+    /// Combines two policies, keeping only the files both keep. A limit of the same kind in
+    /// `self` is replaced by the one of `other`.
+    pub fn and(self, other: RetentionPolicy) -> RetentionPolicy {
+        let mut policies = match self {
+            RetentionPolicy::All(policies) => policies,
+            policy => vec![policy],
+        };
+        let others = match other {
+            RetentionPolicy::All(others) => others,
+            other => vec![other],
+        };
+        for other in others {
+            policies.retain(|p| std::mem::discriminant(p) != std::mem::discriminant(&other));
+            policies.push(other);
+        }
+        RetentionPolicy::All(policies)
+    }
+
+    /// Returns the maximum number of files kept, if the policy limits it
+    pub fn max_files(&self) -> Option<usize> {
+        match self {
+            RetentionPolicy::MaxFiles(n) => Some(*n),
+            RetentionPolicy::All(policies) => policies.iter().filter_map(RetentionPolicy::max_files).min(),
+            _ => None,
+        }
+    }
+
+    /// Returns true if the policy never deletes a file, so the folder needn't be listed
+    pub(crate) fn keeps_everything(&self) -> bool {
+        match self {
+            RetentionPolicy::MaxFiles(n) => *n == usize::MAX,
+            RetentionPolicy::All(policies) => policies.iter().all(RetentionPolicy::keeps_everything),
+            _ => false,
+        }
+    }
+
+    /// Returns true if the policy needs the sizes of the files
+    pub(crate) fn needs_sizes(&self) -> bool {
+        match self {
+            RetentionPolicy::MaxTotalSize(_) => true,
+            RetentionPolicy::All(policies) => policies.iter().any(RetentionPolicy::needs_sizes),
+            _ => false,
+        }
+    }
+
+    /// Describes the policy, e.g. `max_files=9` or `max_total_size=1048576 max_age=30d`.
+    pub fn describe(&self) -> String {
+        match self {
+            RetentionPolicy::MaxFiles(n) => format!("max_files={}", n),
+            RetentionPolicy::MaxTotalSize(bytes) => format!("max_total_size={}", bytes),
+            RetentionPolicy::MaxAge(age) if age.num_seconds() % 86400 == 0 => {
+                format!("max_age={}d", age.num_days())
+            },
+            RetentionPolicy::MaxAge(age) => format!("max_age={}s", age.num_seconds()),
+            RetentionPolicy::All(policies) => policies
+                .iter()
+                .map(RetentionPolicy::describe)
+                .collect::<Vec<_>>()
+                .join(" "),
+        }
+    }
+
+    /// Tells for each of `files`, sorted from the newest to the oldest, which rule deletes it,
+    /// e.g. `max_files=9`, or `None` if it is kept.
+    pub(crate) fn expiry_reasons(&self, files: &[RetainedFile], now: &DateTime<Local>) -> Vec<Option<String>> {
+        let mut reasons = match self {
+            RetentionPolicy::All(policies) => {
+                let mut reasons = vec![None; files.len()];
+                for policy in policies {
+                    for (r, x) in reasons.iter_mut().zip(policy.expiry_reasons(files, now)) {
+                        if r.is_none() {
+                            *r = x;
+                        }
+                    }
+                }
+                reasons
+            },
+            policy => {
+                let reason = policy.describe();
+                policy
+                    .expired(files, now)
+                    .into_iter()
+                    .map(|expired| expired.then(|| reason.clone()))
+                    .collect()
+            },
+        };
+        if let Some(newest) = reasons.first_mut() {
+            *newest = None;
+        }
+        reasons
+    }
+
+    /// Tells for each of `files`, sorted from the newest to the oldest, whether it must be deleted.
+    pub(crate) fn expired(&self, files: &[RetainedFile], now: &DateTime<Local>) -> Vec<bool> {
+        let mut expired = match self {
+            RetentionPolicy::MaxFiles(n) => (0..files.len()).map(|i| i >= *n).collect(),
+            RetentionPolicy::MaxTotalSize(max) => {
+                let mut total = 0u64;
+                files
+                    .iter()
+                    .map(|f| {
+                        total = total.saturating_add(f.len);
+                        total > *max
+                    })
+                    .collect::<Vec<_>>()
+            },
+            RetentionPolicy::MaxAge(age) => files
+                .iter()
+                .map(|f| f.ended_opt.is_some_and(|ended| *now - ended > *age))
+                .collect(),
+            RetentionPolicy::All(policies) => {
+                let mut expired = vec![false; files.len()];
+                for policy in policies {
+                    for (e, x) in expired.iter_mut().zip(policy.expired(files, now)) {
+                        *e |= x;
+                    }
+                }
+                expired
+            },
+        };
+        if let Some(newest) = expired.first_mut() {
+            *newest = false;
+        }
+        expired
+    }
+}
+
+/// What retention does to a log file
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum RetentionAction {
+    /// The file is left as it is
+    Keep,
+    /// The file is compressed
+    Compress,
+    /// The file is deleted
+    Delete,
+    /// The file is moved to the archive folder, see [`crate::ArchivePolicy::MoveTo`]
+    Archive,
+}
+
+/// A log file in a [`RetentionPlan`]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PlannedFile {
+    /// The log file
+    pub path: PathBuf,
+    /// What retention does to it
+    pub action: RetentionAction,
+    /// Why, e.g. `max_files=9` for a deletion
+    pub reason: String,
+}
+
+/// What retention would do to the files of an appender, see
+/// [`crate::RollingFileAppender::plan_retention`]. It lists one line per file when displayed.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct RetentionPlan {
+    /// The log files, newest first
+    pub files: Vec<PlannedFile>,
+}
+
+impl RetentionPlan {
+    /// Returns the paths of the files which would be deleted
+    pub fn deletions(&self) -> Vec<&Path> {
+        self.with_action(RetentionAction::Delete)
+    }
+
+    /// Returns the paths of the files which would be compressed
+    pub fn compressions(&self) -> Vec<&Path> {
+        self.with_action(RetentionAction::Compress)
+    }
+
+    fn with_action(&self, action: RetentionAction) -> Vec<&Path> {
+        self.files
+            .iter()
+            .filter(|f| f.action == action)
+            .map(|f| f.path.as_path())
+            .collect()
+    }
+}
+
+impl fmt::Display for RetentionPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for file in &self.files {
+            let action = match file.action {
+                RetentionAction::Keep => "keep",
+                RetentionAction::Compress => "compress",
+                RetentionAction::Delete => "delete",
+                RetentionAction::Archive => "archive",
+            };
+            writeln!(f, "{}\t{}\t{}", action, file.path.display(), file.reason)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Registration {
+    folder: String,
+    prefix: String,
+    naming: Arc<dyn NamingScheme>,
+    fs: Arc<dyn Fs>,
+    priority: u32,
+}
+
+/// A disk budget shared by the files of several prefixes. Cloning gives another handle to the
+/// same budget.
+#[derive(Clone, Debug)]
+pub struct RetentionManager {
+    budget: u64,
+    registrations: Arc<Mutex<Vec<Registration>>>,
+}
+
+impl RetentionManager {
+    /// Creates a manager keeping the registered files below `budget` bytes in total
+    pub fn new(budget: u64) -> RetentionManager {
+        RetentionManager {
+            budget,
+            registrations: Arc::new(Mutex::new(vec![])),
+        }
+    }
+
+    /// Adds the files of `prefix` in `folder` to the budget. When space must be freed, files of
+    /// a lower `priority` are deleted first; among equal priorities the oldest file goes first.
+    pub fn register(&self, folder: &str, prefix: &str, priority: u32) {
+        self.register_with_naming(folder, prefix, Arc::new(TimestampNaming::new(prefix)), priority);
+    }
+
+    /// Same as [`RetentionManager::register`], for files named by `naming`
+    pub fn register_with_naming(&self, folder: &str, prefix: &str, naming: Arc<dyn NamingScheme>, priority: u32) {
+        self.register_with_fs(folder, prefix, naming, Arc::new(RealFs), priority);
+    }
+
+    /// Same as [`RetentionManager::register_with_naming`], for files accessed through `fs`
+    pub(crate) fn register_with_fs(
+        &self,
+        folder: &str,
+        prefix: &str,
+        naming: Arc<dyn NamingScheme>,
+        fs: Arc<dyn Fs>,
+        priority: u32,
+    ) {
+        let mut registrations = self.registrations.lock().unwrap_or_else(|e| e.into_inner());
+        registrations.retain(|r| !(r.folder == folder && r.prefix == prefix));
+        registrations.push(Registration {
+            folder: folder.to_string(),
+            prefix: prefix.to_string(),
+            naming,
+            fs,
+            priority,
+        });
+    }
+
+    /// Deletes rolled files until all registered files fit in the budget. The newest file of
+    /// every prefix, which may be active, is never deleted. Returns the deleted files.
+    pub fn enforce(&self) -> io::Result<Vec<PathBuf>> {
+        let registrations = self.registrations.lock().unwrap_or_else(|e| e.into_inner()).clone();
+        internal_span!(
+            "retention_manager",
+            budget = self.budget,
+            prefixes = registrations.len()
+        );
+        let mut total = 0;
+        // (priority, file date, path, size, file system) of every file which may be deleted
+        let mut candidates = vec![];
+        for registration in &registrations {
+            let mut files = vec![];
+            let folder = Path::new(&registration.folder);
+            for name in registration.naming.list(&*registration.fs, folder)? {
+                if let Some(dt) = registration.naming.started(&name) {
+                    let path = folder.join(&name);
+                    // A file deleted since it was listed, e.g. by the appender's own retention
+                    let len = match registration.fs.file_len(&path) {
+                        Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                        len => len?,
+                    };
+                    total += len;
+                    files.push((registration.priority, dt, path, len, registration.fs.clone()));
+                }
+            }
+            files.sort_by_key(|a| a.1);
+            files.pop();
+            candidates.extend(files);
+        }
+        candidates.sort_by_key(|a| (a.0, a.1));
+
+        let mut deleted = vec![];
+        for (_, _, path, len, fs) in candidates {
+            if total <= self.budget {
+                break;
+            }
+            if let Err(e) = fs.remove_file(&path) {
+                tracing::error!(
+                    "WARNING: Failed to remove old logfile {}: {}",
+                    path.to_string_lossy(),
+                    e
+                );
+                continue;
+            }
+            internal_event!(path = %path.display(), "removed log file over the shared budget");
+            let _ = fs.remove_file(&index::index_path_for(&path));
+            total -= len;
+            deleted.push(path);
+        }
+        Ok(deleted)
+    }
+}
+
+#[cfg(test)]
+mod t {
+    use super::*;
+    use crate::*;
+    use std::fs;
+
+    #[test]
+    fn test_plan_retention() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let mut rfa = BasicRollingFileAppender::builder()
+            .folder(folder)
+            .prefix("log.log")
+            .condition(RollingConditionBasic::new().daily())
+            .max_files(2)
+            .veto_delete(|path| path.ends_with("log.log.20990301.010000"))
+            .build()
+            .unwrap();
+        rfa.rollover().unwrap();
+        for day in 1..=4 {
+            let now = Local.with_ymd_and_hms(2099, 3, day, 1, 0, 0).unwrap();
+            rfa.write_with_datetime(b"line\n", &now).unwrap();
+        }
+        let stray = dir.path().join("log.log.20990201.000000");
+        fs::write(&stray, "old\n").unwrap();
+
+        let plan = rfa.plan_retention().unwrap();
+        let actions = plan
+            .files
+            .iter()
+            .map(|f| {
+                (
+                    f.path.file_name().unwrap().to_str().unwrap(),
+                    f.action,
+                    f.reason.as_str(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            actions,
+            [
+                ("log.log.20990304.010000", RetentionAction::Keep, "newest file"),
+                ("log.log.20990303.010000", RetentionAction::Keep, "within max_files=2"),
+                (
+                    "log.log.20990301.010000",
+                    RetentionAction::Keep,
+                    "deletion by max_files=2 vetoed"
+                ),
+                ("log.log.20990201.000000", RetentionAction::Delete, "max_files=2"),
+            ]
+        );
+        assert_eq!(plan.deletions(), [stray.as_path()]);
+        assert!(plan
+            .to_string()
+            .ends_with(&format!("delete\t{}\tmax_files=2\n", stray.display())));
+        // Planning changes nothing
+        assert!(stray.exists());
+    }
+
+    #[test]
+    fn test_total_size_policy() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let retention = RetentionPolicy::All(vec![RetentionPolicy::MaxFiles(4), RetentionPolicy::MaxTotalSize(25)]);
+        let mut rfa = BasicRollingFileAppender::new(folder, "log.log", RollingConditionBasic::new().daily(), 99)
+            .unwrap()
+            .with_retention(retention);
+        rfa.rollover().unwrap();
+        for day in 1..=5 {
+            let now = Local.with_ymd_and_hms(2099, 3, day, 1, 0, 0).unwrap();
+            rfa.write_with_datetime(b"0123456789", &now).unwrap();
+        }
+        rfa.flush().unwrap();
+
+        let mut names = fs::read_dir(folder)
+            .unwrap()
+            .flatten()
+            .filter(|f| f.file_type().unwrap().is_file())
+            .map(|f| f.file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(
+            names,
+            [
+                "log.log.20990303.010000",
+                "log.log.20990304.010000",
+                "log.log.20990305.010000"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_low_priority_shrinks_first() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let manager = RetentionManager::new(40);
+        for (priority, prefix) in ["debug.log", "error.log"].into_iter().enumerate() {
+            let condition = RollingConditionBasic::new().daily();
+            let mut rfa = BasicRollingFileAppender::new(folder, prefix, condition, 99).unwrap();
+            rfa.rollover().unwrap();
+            for day in 1..=3 {
+                let now = Local.with_ymd_and_hms(2099, 3, day, 1, 0, 0).unwrap();
+                rfa.write_with_datetime(b"0123456789", &now).unwrap();
+            }
+            rfa.flush().unwrap();
+            manager.register(folder, prefix, priority as u32);
+        }
+
+        let deleted = manager.enforce().unwrap();
+        let names = deleted
+            .iter()
+            .map(|p| p.file_name().unwrap().to_str().unwrap())
+            .collect::<Vec<_>>();
+        // The empty file opened at construction is the oldest one
+        assert_eq!(names.len(), 3);
+        assert_eq!(names[1..], ["debug.log.20990301.010000", "debug.log.20990302.010000"]);
+        assert!(dir.path().join("debug.log.20990303.010000").exists());
+        assert!(dir.path().join("error.log.20990301.010000").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_enforce_skips_vanished_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let manager = RetentionManager::new(10);
+        let mut rfa =
+            BasicRollingFileAppender::new(folder, "log.log", RollingConditionBasic::new().daily(), 99).unwrap();
+        for day in 1..=3 {
+            let now = Local.with_ymd_and_hms(2099, 3, day, 1, 0, 0).unwrap();
+            rfa.write_with_datetime(b"0123456789", &now).unwrap();
+        }
+        rfa.flush().unwrap();
+        manager.register(folder, "log.log", 0);
+        // Listed but gone by the time its size is looked up
+        std::os::unix::fs::symlink(dir.path().join("gone"), dir.path().join("log.log.20990302.120000")).unwrap();
+
+        manager.enforce().unwrap();
+        assert!(!dir.path().join("log.log.20990301.010000").exists());
+        assert!(dir.path().join("log.log.20990303.010000").exists());
+    }
+}
@@ -0,0 +1,109 @@
+//! What to do when writing to the log files fails.
+//!
+//! By default a failed write returns its error and a failed rollover only prints a warning, the
+//! appender carrying on with the current file. An [`ErrorPolicy`] retries both with a growing
+//! delay first, e.g. to ride out a full disk being cleaned up, and can keep the data of writes
+//! that still fail on stderr, or drop it, instead of failing them.
+
+use std::time::Duration;
+
+/// What happens to a write which still fails after the retries
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum ErrorFallback {
+    /// Returns the error to the caller
+    #[default]
+    Propagate,
+    /// Writes the data to stderr instead, reporting success
+    Stderr,
+    /// Drops the data, reporting success, and counts the discarded bytes
+    Drop,
+}
+
+/// Handling of failed writes and rollovers. Retries sleep on the writing thread.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct ErrorPolicy {
+    retries: u32,
+    backoff: Duration,
+    fallback: ErrorFallback,
+}
+
+impl ErrorPolicy {
+    /// Creates a policy applying `fallback` to failed writes without retrying them
+    pub fn new(fallback: ErrorFallback) -> ErrorPolicy {
+        ErrorPolicy {
+            retries: 0,
+            backoff: Duration::ZERO,
+            fallback,
+        }
+    }
+
+    /// Retries failed writes and rollovers up to `retries` times, waiting `backoff` before the
+    /// first retry and twice as long before each next one
+    pub fn retry(mut self, retries: u32, backoff: Duration) -> ErrorPolicy {
+        self.retries = retries;
+        self.backoff = backoff;
+        self
+    }
+
+    /// Returns the number of retries
+    pub fn retries(&self) -> u32 {
+        self.retries
+    }
+
+    /// Returns the delay before the first retry
+    pub fn backoff(&self) -> Duration {
+        self.backoff
+    }
+
+    /// Returns what happens to writes which still fail after the retries
+    pub fn fallback(&self) -> ErrorFallback {
+        self.fallback
+    }
+
+    /// Returns the delays to wait before each retry
+    pub(crate) fn delays(&self) -> impl Iterator<Item = Duration> {
+        let backoff = self.backoff;
+        (0..self.retries).map(move |i| backoff.saturating_mul(2u32.saturating_pow(i)))
+    }
+}
+
+#[cfg(test)]
+mod t {
+    use crate::*;
+    use std::io::Write;
+
+    #[test]
+    fn test_error_policy() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().join("logs");
+        let mut rfa = BasicRollingFileAppender::new(
+            folder.to_str().unwrap(),
+            "log.log",
+            RollingConditionBasic::new().daily(),
+            3,
+        )
+        .unwrap();
+        rfa.rollover().unwrap();
+        let day1 = Local.with_ymd_and_hms(2099, 3, 1, 1, 0, 0).unwrap();
+        rfa.write_with_datetime(b"day 1\n", &day1).unwrap();
+
+        // The folder can't be recreated while a file stands in its place
+        std::fs::remove_dir_all(&folder).unwrap();
+        std::fs::write(&folder, b"").unwrap();
+        let day2 = Local.with_ymd_and_hms(2099, 3, 2, 1, 0, 0).unwrap();
+        assert!(rfa.write_with_datetime(b"lost\n", &day2).is_err());
+
+        let policy = ErrorPolicy::new(ErrorFallback::Drop).retry(2, std::time::Duration::from_millis(1));
+        let mut rfa = rfa.with_error_policy(policy);
+        assert_eq!(rfa.write_with_datetime(b"dropped\n", &day2).unwrap(), 8);
+        assert_eq!(rfa.discarded_bytes(), 8);
+
+        let mut rfa = rfa.with_error_policy(ErrorPolicy::new(ErrorFallback::Stderr));
+        rfa.write_with_datetime(b"on stderr\n", &day2).unwrap();
+
+        std::fs::remove_file(&folder).unwrap();
+        rfa.write_with_datetime(b"day 2\n", &day2).unwrap();
+        rfa.flush().unwrap();
+        assert_eq!(std::fs::read_to_string(folder.join("log.log")).unwrap(), "day 2\n");
+    }
+}
@@ -0,0 +1,295 @@
+//! Line-oriented search across all files of an appender.
+
+use crate::{compression, filesystem::FsReader, index, Fs, NamingScheme, RealFs};
+use chrono::prelude::*;
+use std::{
+    io::{self, BufRead},
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+/// A line that matched a search predicate.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SearchMatch {
+    /// File the line was found in
+    pub path: PathBuf,
+    /// Byte offset of the start of the line inside the file, inside the decompressed data for
+    /// compressed files
+    pub offset: u64,
+    /// The line itself, without its line terminator
+    pub line: String,
+}
+
+/// Tells whether this build can read the log file `name`: encrypted files and files compressed
+/// by a disabled feature can't be read
+fn is_readable(name: &str) -> bool {
+    if compression::strip_rolled_extensions(name) == name {
+        return true;
+    }
+    let extension = name.rsplit_once('.').map_or("", |(_, ext)| ext);
+    extension == compression::GZIP_EXTENSION && cfg!(feature = "gzip")
+        || extension == compression::ZSTD_EXTENSION && cfg!(feature = "zstd")
+}
+
+/// Returns the log files named by `naming` which may contain data written within `range` and can
+/// be read, oldest first.
+pub fn files_in_range(
+    folder: &str,
+    naming: &dyn NamingScheme,
+    range: &Range<DateTime<Local>>,
+) -> io::Result<Vec<PathBuf>> {
+    files_in_range_in(&RealFs, folder, naming, range)
+}
+
+/// Same as [`files_in_range`], through `fs`
+fn files_in_range_in(
+    fs: &dyn Fs,
+    folder: &str,
+    naming: &dyn NamingScheme,
+    range: &Range<DateTime<Local>>,
+) -> io::Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    for name in naming.list(fs, Path::new(folder))? {
+        if !is_readable(&name) {
+            continue;
+        }
+        if let Some(dt) = naming.started(&name) {
+            files.push((dt, Path::new(folder).join(name)));
+        }
+    }
+    files.sort();
+    // A file holds the data written from its own timestamp until the next file was opened.
+    let mut selected = vec![];
+    for (i, (start, path)) in files.iter().enumerate() {
+        let ends_after_range_start = files.get(i + 1).is_none_or(|(next, _)| *next > range.start);
+        if *start < range.end && ends_after_range_start {
+            selected.push(path.clone());
+        }
+    }
+    Ok(selected)
+}
+
+/// Streams lines matching a predicate across a list of files, in order.
+/// Created by [`search`] or [`crate::RollingFileAppender::search`].
+pub struct Search<P> {
+    fs: Arc<dyn Fs>,
+    files: std::vec::IntoIter<PathBuf>,
+    since: DateTime<Local>,
+    predicate: P,
+    #[cfg(feature = "zstd")]
+    dictionary: Vec<u8>,
+    current_opt: Option<(PathBuf, Box<dyn BufRead>, u64)>,
+}
+
+impl<P> Search<P>
+where
+    P: FnMut(&str) -> bool,
+{
+    /// Reads zstd files with the dictionary they were compressed with, see
+    /// [`crate::DictionaryCompression`]
+    #[cfg(feature = "zstd")]
+    pub fn dictionary(mut self, dictionary: Vec<u8>) -> Search<P> {
+        self.dictionary = dictionary;
+        self
+    }
+
+    fn open_next(&mut self) -> Option<io::Result<()>> {
+        let path = self.files.next()?;
+        Some(self.open(&path).map(|(reader, offset)| {
+            self.current_opt = Some((path, reader, offset));
+        }))
+    }
+
+    /// Opens a file positioned at the data written since the start of the range, decompressing
+    /// it on the fly. Compressed files have no time index, they are read from the start.
+    fn open(&self, path: &Path) -> io::Result<(Box<dyn BufRead>, u64)> {
+        let name = path.to_string_lossy();
+        let mut reader = FsReader::new(self.fs.clone(), path)?;
+        if compression::strip_rolled_extensions(&name) == name {
+            let since = index::TimeIndex::load_in(&*self.fs, path).map_or(0, |index| index.offset_for(&self.since));
+            let offset = reader.skip_to(since);
+            return Ok((Box::new(io::BufReader::new(reader)), offset));
+        }
+        #[cfg(any(feature = "gzip", feature = "zstd"))]
+        let file = io::BufReader::new(reader);
+        #[cfg(feature = "gzip")]
+        if name.ends_with(&format!(".{}", compression::GZIP_EXTENSION)) {
+            let decoder = flate2::bufread::MultiGzDecoder::new(file);
+            return Ok((Box::new(io::BufReader::new(decoder)), 0));
+        }
+        #[cfg(feature = "zstd")]
+        if name.ends_with(&format!(".{}", compression::ZSTD_EXTENSION)) {
+            let decoder = zstd::stream::Decoder::with_dictionary(file, &self.dictionary)?;
+            return Ok((Box::new(io::BufReader::new(decoder)), 0));
+        }
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("can't read {}", path.display()),
+        ))
+    }
+}
+
+impl<P> Iterator for Search<P>
+where
+    P: FnMut(&str) -> bool,
+{
+    type Item = io::Result<SearchMatch>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.current_opt.is_none() {
+                if let Err(e) = self.open_next()? {
+                    return Some(Err(e));
+                }
+            }
+            let (path, reader, offset) = self.current_opt.as_mut()?;
+            let mut buf = vec![];
+            match reader.read_until(b'\n', &mut buf) {
+                Ok(0) => {
+                    self.current_opt = None;
+                },
+                Ok(n) => {
+                    let line_offset = *offset;
+                    *offset += n as u64;
+                    let line = String::from_utf8_lossy(&buf);
+                    let line = line.trim_end_matches(['\n', '\r']);
+                    if (self.predicate)(line) {
+                        return Some(Ok(SearchMatch {
+                            path: path.clone(),
+                            offset: line_offset,
+                            line: line.to_string(),
+                        }));
+                    }
+                },
+                Err(e) => {
+                    self.current_opt = None;
+                    return Some(Err(e));
+                },
+            }
+        }
+    }
+}
+
+/// Searches the log files named by `naming` for lines matching `predicate`, oldest file first.
+/// Files are selected by the time range they cover, and the time index (if any) is used to skip
+/// data written before the range; lines themselves are not parsed for timestamps. Files
+/// compressed with gzip or zstd are decompressed while streaming if the matching feature is
+/// enabled, and skipped otherwise. Encrypted files are skipped.
+pub fn search<P>(
+    folder: &str,
+    naming: &dyn NamingScheme,
+    range: Range<DateTime<Local>>,
+    predicate: P,
+) -> io::Result<Search<P>>
+where
+    P: FnMut(&str) -> bool,
+{
+    search_in(Arc::new(RealFs), folder, naming, range, predicate)
+}
+
+/// Same as [`search`], through `fs`
+pub(crate) fn search_in<P>(
+    fs: Arc<dyn Fs>,
+    folder: &str,
+    naming: &dyn NamingScheme,
+    range: Range<DateTime<Local>>,
+    predicate: P,
+) -> io::Result<Search<P>>
+where
+    P: FnMut(&str) -> bool,
+{
+    let files = files_in_range_in(&*fs, folder, naming, &range)?;
+    Ok(Search {
+        fs,
+        files: files.into_iter(),
+        since: range.start,
+        predicate,
+        #[cfg(feature = "zstd")]
+        dictionary: vec![],
+        current_opt: None,
+    })
+}
+
+#[cfg(test)]
+mod t {
+    use super::*;
+    use crate::*;
+    use std::io::Write;
+
+    #[test]
+    fn test_search_across_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let mut rfa =
+            BasicRollingFileAppender::new(folder, "log.log", RollingConditionBasic::new().hourly(), 9).unwrap();
+        for hour in 1..4 {
+            let now = Local.with_ymd_and_hms(2021, 3, 30, hour, 0, 0).unwrap();
+            rfa.write_with_datetime(format!("error {}\nok {}\n", hour, hour).as_bytes(), &now)
+                .unwrap();
+        }
+        rfa.flush().unwrap();
+
+        let range = Local.with_ymd_and_hms(2021, 3, 30, 2, 0, 0).unwrap()
+            ..Local.with_ymd_and_hms(2021, 3, 30, 4, 0, 0).unwrap();
+        let matches = rfa
+            .search(range, |line| line.starts_with("error"))
+            .unwrap()
+            .collect::<io::Result<Vec<_>>>()
+            .unwrap();
+        let lines = matches.iter().map(|m| m.line.as_str()).collect::<Vec<_>>();
+        assert_eq!(lines, vec!["error 2", "error 3"]);
+        assert!(matches.iter().all(|m| m.offset == 0));
+    }
+
+    /// Writes one file per hour and returns the lines matching in the last three hours
+    #[cfg(any(feature = "gzip", feature = "zstd"))]
+    fn search_compressed(compression: Compression) -> Vec<String> {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let mut rfa = BasicRollingFileAppender::builder()
+            .folder(folder)
+            .prefix("log.log")
+            .condition(RollingConditionBasic::new().hourly())
+            .max_files(9)
+            .compression(compression)
+            .build()
+            .unwrap();
+        rfa.rollover().unwrap();
+        for hour in 1..5 {
+            let now = Local.with_ymd_and_hms(2099, 3, 30, hour, 0, 0).unwrap();
+            rfa.write_with_datetime(format!("error {}\nok {}\n", hour, hour).as_bytes(), &now)
+                .unwrap();
+        }
+        let extension = compression.extension().unwrap();
+        assert!(dir
+            .path()
+            .join(format!("log.log.20990330.020000.{}", extension))
+            .exists());
+
+        let range = Local.with_ymd_and_hms(2099, 3, 30, 2, 0, 0).unwrap()
+            ..Local.with_ymd_and_hms(2099, 3, 30, 5, 0, 0).unwrap();
+        rfa.search(range, |line| line.starts_with("error"))
+            .unwrap()
+            .map(|m| m.unwrap().line)
+            .collect()
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_search_gzip_files() {
+        assert_eq!(
+            search_compressed(Compression::Gzip(6)),
+            ["error 2", "error 3", "error 4"]
+        );
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_search_zstd_files() {
+        assert_eq!(
+            search_compressed(Compression::Zstd(3)),
+            ["error 2", "error 3", "error 4"]
+        );
+    }
+}
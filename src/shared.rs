@@ -0,0 +1,248 @@
+//! An appender shared between threads.
+
+use crate::{RollingCondition, RollingFileAppender};
+use std::{
+    fmt,
+    io::{self, Write},
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+/// A cheaply cloneable handle to a [`RollingFileAppender`] behind a mutex, so several threads
+/// can write to the same files. With the `tracing` feature it implements `MakeWriter`, so
+/// `tracing_subscriber::fmt().with_writer(shared)` writes each event under the lock.
+#[derive(Debug)]
+pub struct SharedRollingFileAppender<RC>
+where
+    RC: RollingCondition,
+{
+    pub(crate) inner: Arc<Mutex<RollingFileAppender<RC>>>,
+}
+
+impl<RC> Clone for SharedRollingFileAppender<RC>
+where
+    RC: RollingCondition,
+{
+    fn clone(&self) -> Self {
+        SharedRollingFileAppender {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<RC> SharedRollingFileAppender<RC>
+where
+    RC: RollingCondition,
+{
+    /// Wraps an appender, returning the shared handle and a guard which flushes all pending data
+    /// when dropped. Keep the guard alive until the end of `main`, like
+    /// `tracing_appender::non_blocking::WorkerGuard`.
+    pub fn new(appender: RollingFileAppender<RC>) -> (SharedRollingFileAppender<RC>, FlushGuard<RC>) {
+        let shared = SharedRollingFileAppender {
+            inner: Arc::new(Mutex::new(appender)),
+        };
+        let guard = FlushGuard { shared: shared.clone() };
+        (shared, guard)
+    }
+
+    /// Locks the appender for exclusive use. A poisoned lock is recovered, since a panic in
+    /// another writer doesn't invalidate the appender's state.
+    pub fn lock(&self) -> MutexGuard<'_, RollingFileAppender<RC>> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Formats a record and writes it under a single lock, as `write!` and `writeln!` on a handle
+    /// do, so records of several threads never interleave. Formatting straight into the
+    /// appender would take the lock for every piece of the record.
+    fn write_formatted(&self, args: fmt::Arguments<'_>) -> io::Result<()> {
+        let record = args.as_str().map_or_else(|| fmt::format(args), str::to_string);
+        self.lock().write_record(record.as_bytes())
+    }
+}
+
+impl<RC> Write for SharedRollingFileAppender<RC>
+where
+    RC: RollingCondition,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.lock().write(buf)
+    }
+
+    /// Formats the record first and writes it under a single lock, so records never interleave
+    fn write_fmt(&mut self, args: fmt::Arguments<'_>) -> io::Result<()> {
+        self.write_formatted(args)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.lock().flush()
+    }
+}
+
+impl<RC> Write for &SharedRollingFileAppender<RC>
+where
+    RC: RollingCondition,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.lock().write(buf)
+    }
+
+    /// Formats the record first and writes it under a single lock, so records never interleave
+    fn write_fmt(&mut self, args: fmt::Arguments<'_>) -> io::Result<()> {
+        self.write_formatted(args)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.lock().flush()
+    }
+}
+
+/// The writer handed out by [`SharedRollingFileAppender`] as a `MakeWriter`: the appender's lock
+/// guard, held while `tracing_subscriber` writes one event.
+#[cfg(feature = "tracing")]
+#[derive(Debug)]
+pub struct SharedWriter<'a, RC>
+where
+    RC: RollingCondition,
+{
+    guard: MutexGuard<'a, RollingFileAppender<RC>>,
+}
+
+#[cfg(feature = "tracing")]
+impl<RC> Write for SharedWriter<'_, RC>
+where
+    RC: RollingCondition,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.guard.write(buf)
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.guard.write_all(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.guard.flush()
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl<'a, RC> tracing_subscriber::fmt::MakeWriter<'a> for SharedRollingFileAppender<RC>
+where
+    RC: RollingCondition + 'a,
+{
+    type Writer = SharedWriter<'a, RC>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        SharedWriter { guard: self.lock() }
+    }
+}
+
+impl<RC> RollingFileAppender<RC>
+where
+    RC: RollingCondition,
+{
+    /// Moves the appender behind a [`SharedRollingFileAppender`]. Pending data is flushed once the
+    /// last handle is dropped; use [`SharedRollingFileAppender::new`] for a guard flushing earlier.
+    pub fn shared(self) -> SharedRollingFileAppender<RC> {
+        SharedRollingFileAppender {
+            inner: Arc::new(Mutex::new(self)),
+        }
+    }
+}
+
+/// Flushes and fsyncs a [`SharedRollingFileAppender`] and waits for its in-flight maintenance,
+/// e.g. compressing rolled files, when dropped, so short-lived programs don't lose their final
+/// lines.
+#[derive(Debug)]
+#[must_use = "dropping the guard immediately flushes the appender, keep it alive instead"]
+pub struct FlushGuard<RC>
+where
+    RC: RollingCondition,
+{
+    shared: SharedRollingFileAppender<RC>,
+}
+
+impl<RC> Drop for FlushGuard<RC>
+where
+    RC: RollingCondition,
+{
+    fn drop(&mut self) {
+        self.shared.lock().finish_pending();
+    }
+}
+
+#[cfg(test)]
+mod t {
+    use super::*;
+    use crate::*;
+
+    #[test]
+    fn test_guard_flushes_on_drop() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let rfa = BasicRollingFileAppender::new(folder, "log.log", RollingConditionBasic::new().daily(), 3).unwrap();
+        let (shared, guard) = SharedRollingFileAppender::new(rfa);
+        let threads = (0..4)
+            .map(|i| {
+                let mut shared = shared.clone();
+                std::thread::spawn(move || writeln!(shared, "thread {}", i).unwrap())
+            })
+            .collect::<Vec<_>>();
+        threads.into_iter().for_each(|t| t.join().unwrap());
+        drop(guard);
+
+        let contents = std::fs::read_to_string(dir.path().join("log.log")).unwrap();
+        assert_eq!(contents.lines().count(), 4);
+    }
+
+    #[test]
+    fn test_write_through_reference() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let rfa = BasicRollingFileAppender::new(folder, "log.log", RollingConditionBasic::new().daily(), 3).unwrap();
+        let shared = Arc::new(rfa.shared());
+        let threads = (0..4)
+            .map(|i| {
+                let shared = shared.clone();
+                std::thread::spawn(move || {
+                    for j in 0..100 {
+                        writeln!(&*shared, "thread {} line {}", i, j).unwrap();
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        threads.into_iter().for_each(|t| t.join().unwrap());
+        (&*shared).flush().unwrap();
+
+        // Each record was written whole
+        let contents = std::fs::read_to_string(dir.path().join("log.log")).unwrap();
+        assert_eq!(contents.lines().count(), 400);
+        assert!(contents
+            .lines()
+            .all(|line| matches!(line.split(' ').collect::<Vec<_>>()[..], ["thread", _, "line", _])));
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn test_make_writer() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let rfa = BasicRollingFileAppender::new(folder, "log.log", RollingConditionBasic::new().daily(), 3).unwrap();
+        let (shared, guard) = SharedRollingFileAppender::new(rfa);
+        let subscriber = tracing_subscriber::fmt()
+            .with_writer(shared)
+            .with_ansi(false)
+            .without_time()
+            .finish();
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!("first event");
+            tracing::warn!(answer = 42, "second event");
+        });
+        drop(guard);
+
+        let contents = std::fs::read_to_string(dir.path().join("log.log")).unwrap();
+        let lines = contents.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].contains("INFO") && lines[0].ends_with("first event"));
+        assert!(lines[1].contains("WARN") && lines[1].ends_with("second event answer=42"));
+    }
+}
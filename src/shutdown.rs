@@ -0,0 +1,159 @@
+//! Cooperative shutdown for applications running on tokio.
+
+use crate::{NonBlockingRollingFileAppender, RollingCondition, RollingFileAppender, SharedRollingFileAppender};
+use std::io;
+
+impl<RC> RollingFileAppender<RC>
+where
+    RC: RollingCondition + Send + 'static,
+{
+    /// Flushes and fsyncs the active file, waits until the maintenance scheduler (if any) has
+    /// finished every job queued so far, then closes the appender. The blocking work runs on
+    /// tokio's blocking thread pool, so this can be awaited in an async teardown path.
+    ///
+    /// Unlike the shutdown of [`SharedRollingFileAppender`] and [`AsyncRollingFileAppender`],
+    /// which keep the appender behind their handle, this takes the appender by value: the
+    /// blocking pool needs to own it, and there is nothing left to share it with. Wrap it in one
+    /// of those to shut it down by reference.
+    ///
+    /// [`AsyncRollingFileAppender`]: crate::AsyncRollingFileAppender
+    pub async fn shutdown(self) -> io::Result<()> {
+        let mut rfa = self;
+        tokio::task::spawn_blocking(move || rfa.try_finish_pending())
+            .await
+            .map_err(io::Error::other)?
+    }
+
+    /// Waits for Ctrl-C (SIGINT) and then shuts the appender down, see
+    /// [`RollingFileAppender::shutdown`].
+    pub async fn shutdown_on_ctrl_c(self) -> io::Result<()> {
+        tokio::signal::ctrl_c().await?;
+        self.shutdown().await
+    }
+}
+
+impl<RC> SharedRollingFileAppender<RC>
+where
+    RC: RollingCondition + Send + 'static,
+{
+    /// Flushes and fsyncs the shared appender and waits until the maintenance scheduler (if any)
+    /// has finished every job queued so far, like [`RollingFileAppender::shutdown`]. The appender
+    /// stays usable, so writes from other handles racing with the shutdown aren't lost.
+    pub async fn shutdown(&self) -> io::Result<()> {
+        let shared = self.clone();
+        tokio::task::spawn_blocking(move || shared.lock().try_finish_pending())
+            .await
+            .map_err(io::Error::other)?
+    }
+
+    /// Waits for Ctrl-C (SIGINT) and then shuts the appender down, see
+    /// [`SharedRollingFileAppender::shutdown`].
+    pub async fn shutdown_on_ctrl_c(&self) -> io::Result<()> {
+        tokio::signal::ctrl_c().await?;
+        self.shutdown().await
+    }
+}
+
+impl NonBlockingRollingFileAppender {
+    /// Stops the worker thread after it wrote and fsynced everything sent before and the
+    /// maintenance it queued finished, and waits for it. Writes through any handle fail afterwards.
+    pub async fn shutdown(&self) -> io::Result<()> {
+        let handle = self.clone();
+        tokio::task::spawn_blocking(move || handle.stop_worker())
+            .await
+            .map_err(io::Error::other)?
+    }
+
+    /// Waits for Ctrl-C (SIGINT) and then shuts the worker down, see
+    /// [`NonBlockingRollingFileAppender::shutdown`].
+    pub async fn shutdown_on_ctrl_c(&self) -> io::Result<()> {
+        tokio::signal::ctrl_c().await?;
+        self.shutdown().await
+    }
+}
+
+#[cfg(test)]
+mod t {
+    use crate::*;
+
+    #[tokio::test]
+    async fn test_shutdown_drains_maintenance() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let maintenance = Maintenance::new().unwrap();
+        let mut rfa = BasicRollingFileAppender::new(folder, "log.log", RollingConditionBasic::new().hourly(), 1)
+            .unwrap()
+            .with_maintenance(maintenance.handle());
+        for hour in 1..4 {
+            let now = Local.with_ymd_and_hms(2099, 3, 30, hour, 0, 0).unwrap();
+            rfa.write_with_datetime(b"Line\n", &now).unwrap();
+        }
+        rfa.shutdown().await.unwrap();
+
+        let files = std::fs::read_dir(folder)
+            .unwrap()
+            .flatten()
+            .filter(|f| f.file_name() != "log.log")
+            .map(|f| std::fs::read(f.path()).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(files, vec![b"Line\n".to_vec()]);
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_shared_and_non_blocking() {
+        use std::io::{self, Write};
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let rfa = BasicRollingFileAppender::new(folder, "shared.log", RollingConditionBasic::new().daily(), 1).unwrap();
+        let shared = rfa.shared();
+        writeln!(&shared, "shared").unwrap();
+        shared.shutdown().await.unwrap();
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("shared.log")).unwrap(),
+            "shared\n"
+        );
+
+        let rfa = BasicRollingFileAppender::new(folder, "worker.log", RollingConditionBasic::new().daily(), 1).unwrap();
+        let (mut handle, guard) = NonBlockingRollingFileAppender::new(rfa, 16).unwrap();
+        writeln!(handle, "worker").unwrap();
+        handle.shutdown().await.unwrap();
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("worker.log")).unwrap(),
+            "worker\n"
+        );
+        assert_eq!(writeln!(handle, "late").unwrap_err().kind(), io::ErrorKind::BrokenPipe);
+        // The worker is already gone
+        handle.shutdown().await.unwrap();
+        drop(guard);
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_async() {
+        use tokio::io::AsyncWriteExt;
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let maintenance = Maintenance::new().unwrap();
+        let rfa = BasicRollingFileAppender::new(folder, "log.log", RollingConditionBasic::new().max_size(5), 1)
+            .unwrap()
+            .with_maintenance(maintenance.handle());
+        let mut appender = AsyncRollingFileAppender::new(rfa);
+        for _ in 0..3 {
+            appender.write_all(b"Line\n").await.unwrap();
+            appender.flush().await.unwrap();
+        }
+        appender.write_all(b"Queued\n").await.unwrap();
+        // By reference, e.g. from a teardown path holding the appender in an Arc
+        let appender = std::sync::Arc::new(appender);
+        appender.shutdown().await.unwrap();
+
+        let files = std::fs::read_dir(folder)
+            .unwrap()
+            .flatten()
+            // Not the latest link
+            .filter(|f| f.file_type().unwrap().is_file())
+            .map(|f| std::fs::read_to_string(f.path()).unwrap())
+            .collect::<Vec<_>>();
+        // The queued line is written and the maintenance removed the rolled files
+        assert_eq!(files, vec!["Queued\n"]);
+    }
+}
@@ -0,0 +1,151 @@
+//! Reacting to SIGHUP the way logrotate expects.
+//!
+//! logrotate and similar tools move a log file away and then send SIGHUP so the daemon reopens
+//! it. With [`RollingFileAppender::with_sighup`] the appender handles the signal on its next
+//! write, either reopening its file at the original path or rolling over to a new one.
+
+use crate::{RollingCondition, RollingFileAppender};
+use signal_hook_registry::SigId;
+use std::{
+    io::{self, Write},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+/// What the appender does on its next write after receiving SIGHUP
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum SighupAction {
+    /// Close the current file and open the same path again, creating it if it was moved away
+    Reopen,
+    /// Roll over to a new file
+    Rotate,
+}
+
+/// A registered SIGHUP handler, unregistered when dropped
+#[derive(Debug)]
+pub(crate) struct SighupHook {
+    requested: Arc<AtomicBool>,
+    action: SighupAction,
+    id: SigId,
+}
+
+impl SighupHook {
+    fn install(action: SighupAction) -> io::Result<SighupHook> {
+        let requested = Arc::new(AtomicBool::new(false));
+        let flag = requested.clone();
+        // SAFETY: the handler only stores to an atomic, which is async-signal-safe
+        let id = unsafe { signal_hook_registry::register(libc::SIGHUP, move || flag.store(true, Ordering::Release)) }?;
+        Ok(SighupHook { requested, action, id })
+    }
+
+    fn take_request(&self) -> Option<SighupAction> {
+        self.requested.swap(false, Ordering::AcqRel).then_some(self.action)
+    }
+}
+
+impl Drop for SighupHook {
+    fn drop(&mut self) {
+        signal_hook_registry::unregister(self.id);
+    }
+}
+
+impl<RC> RollingFileAppender<RC>
+where
+    RC: RollingCondition,
+{
+    /// Installs a SIGHUP handler which makes the appender `action` its file on the next write.
+    /// The handler is removed when the appender is dropped.
+    pub fn with_sighup(mut self, action: SighupAction) -> io::Result<RollingFileAppender<RC>> {
+        self.sighup_opt = Some(SighupHook::install(action)?);
+        Ok(self)
+    }
+
+    /// Performs the action requested by a SIGHUP received since the last write, if any
+    pub(crate) fn handle_sighup(&mut self) {
+        let Some(action) = self.sighup_opt.as_ref().and_then(SighupHook::take_request) else {
+            return;
+        };
+        let result = match action {
+            SighupAction::Reopen => {
+                let flushed = self.flush();
+                self.writer_opt.take();
+                // Names may place files in subfolders, so the name is kept relative to the folder
+                self.resume_name_opt = self
+                    .current_path_opt
+                    .as_ref()
+                    .and_then(|path| path.strip_prefix(self.active_folder()).ok())
+                    .and_then(|name| name.to_str())
+                    .map(str::to_string);
+                flushed
+            },
+            SighupAction::Rotate => self.rollover(),
+        };
+        if let Err(e) = result {
+            eprintln!("WARNING: Failed to handle SIGHUP for logfile {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod t {
+    use crate::*;
+    use std::io::Write;
+
+    #[test]
+    fn test_reopen_on_sighup() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let mut rfa = BasicRollingFileAppender::new(folder, "log.log", RollingConditionBasic::new().daily(), 9)
+            .unwrap()
+            .with_sighup(SighupAction::Reopen)
+            .unwrap();
+        rfa.rollover().unwrap();
+        let now = Local.with_ymd_and_hms(2099, 3, 30, 1, 0, 0).unwrap();
+        rfa.write_with_datetime(b"before\n", &now).unwrap();
+        rfa.flush().unwrap();
+        // logrotate moves the file away and signals the process
+        let path = dir.path().join("log.log.20990330.010000");
+        std::fs::rename(&path, dir.path().join("rotated")).unwrap();
+        // SAFETY: raising a signal with a registered handler
+        assert_eq!(unsafe { libc::raise(libc::SIGHUP) }, 0);
+
+        let later = Local.with_ymd_and_hms(2099, 3, 30, 1, 0, 1).unwrap();
+        rfa.write_with_datetime(b"after\n", &later).unwrap();
+        rfa.flush().unwrap();
+        assert_eq!(std::fs::read_to_string(dir.path().join("rotated")).unwrap(), "before\n");
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "after\n");
+    }
+
+    #[test]
+    fn test_reopen_in_daily_folder() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let mut rfa = BasicRollingFileAppender::builder()
+            .folder(folder)
+            .prefix("log.log")
+            .condition(RollingConditionBasic::new().daily())
+            .max_files(9)
+            .naming_scheme(DailyFolderNaming::new("log.log"))
+            .build()
+            .unwrap()
+            .with_sighup(SighupAction::Reopen)
+            .unwrap();
+        rfa.rollover().unwrap();
+        let now = Local.with_ymd_and_hms(2099, 3, 30, 1, 0, 0).unwrap();
+        rfa.write_with_datetime(b"before\n", &now).unwrap();
+        rfa.flush().unwrap();
+        let path = dir.path().join("2099-03-30/log.log.010000");
+        std::fs::rename(&path, dir.path().join("rotated")).unwrap();
+        // SAFETY: raising a signal with a registered handler
+        assert_eq!(unsafe { libc::raise(libc::SIGHUP) }, 0);
+
+        let later = Local.with_ymd_and_hms(2099, 3, 30, 1, 0, 1).unwrap();
+        rfa.write_with_datetime(b"after\n", &later).unwrap();
+        rfa.flush().unwrap();
+        assert_eq!(std::fs::read_to_string(dir.path().join("rotated")).unwrap(), "before\n");
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "after\n");
+        assert!(!dir.path().join("log.log.010000").exists());
+    }
+}
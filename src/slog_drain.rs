@@ -0,0 +1,122 @@
+//! A [`slog`] drain writing into a rolling file.
+//!
+//! ```rust,no_run
+//! # use local_rolling_file::*;
+//! use slog::{o, Drain};
+//! let file_appender = BasicRollingFileAppender::new("./log", "log.log", RollingConditionBasic::new().daily(), 9).unwrap();
+//! let drain = slog_drain::RollingFileDrain::from(file_appender).fuse();
+//! let logger = slog::Logger::root(drain, o!("version" => "1.0"));
+//! slog::info!(logger, "written to ./log/log.log"; "user" => "alice");
+//! ```
+
+use crate::{RollingCondition, RollingFileAppender, SharedRollingFileAppender};
+use chrono::Local;
+use slog::{Drain, FlushError, Key, OwnedKVList, Record, Serializer, KV};
+use std::{
+    fmt::{self, Write as _},
+    io::{self, Write},
+};
+
+/// A [`Drain`] writing each record as one line, e.g.
+/// `2024-05-20 01:01:01.000001 INFO message, user: alice, version: 1.0`, through a
+/// [`SharedRollingFileAppender`]. Failed writes are returned as errors, so wrap it in
+/// `.fuse()` or `.ignore_res()` before handing it to `slog::Logger::root`.
+#[derive(Debug)]
+pub struct RollingFileDrain<RC>
+where
+    RC: RollingCondition,
+{
+    shared: SharedRollingFileAppender<RC>,
+}
+
+impl<RC> RollingFileDrain<RC>
+where
+    RC: RollingCondition,
+{
+    /// Creates a drain writing through `shared`, which other threads may keep writing to.
+    pub fn new(shared: SharedRollingFileAppender<RC>) -> RollingFileDrain<RC> {
+        RollingFileDrain { shared }
+    }
+}
+
+impl<RC> From<RollingFileAppender<RC>> for RollingFileDrain<RC>
+where
+    RC: RollingCondition,
+{
+    fn from(appender: RollingFileAppender<RC>) -> RollingFileDrain<RC> {
+        RollingFileDrain::new(appender.shared())
+    }
+}
+
+impl<RC> From<SharedRollingFileAppender<RC>> for RollingFileDrain<RC>
+where
+    RC: RollingCondition,
+{
+    fn from(shared: SharedRollingFileAppender<RC>) -> RollingFileDrain<RC> {
+        RollingFileDrain::new(shared)
+    }
+}
+
+impl<RC> Drain for RollingFileDrain<RC>
+where
+    RC: RollingCondition,
+{
+    type Ok = ();
+    type Err = io::Error;
+
+    fn log(&self, record: &Record, values: &OwnedKVList) -> io::Result<()> {
+        let mut line = KvLine(format!(
+            "{} {} {}",
+            Local::now().format("%Y-%m-%d %H:%M:%S%.6f"),
+            record.level().as_str(),
+            record.msg()
+        ));
+        record
+            .kv()
+            .serialize(record, &mut line)
+            .and_then(|_| values.serialize(record, &mut line))?;
+        line.0.push('\n');
+        // Formatted first, so the record is written under a single lock
+        (&self.shared).write_all(line.0.as_bytes())
+    }
+
+    fn flush(&self) -> Result<(), FlushError> {
+        Ok((&self.shared).flush()?)
+    }
+}
+
+/// Appends the key-value pairs of a record to its line
+struct KvLine(String);
+
+impl Serializer for KvLine {
+    fn emit_arguments(&mut self, key: Key, val: &fmt::Arguments) -> slog::Result {
+        write!(self.0, ", {}: {}", key, val)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod t {
+    use super::*;
+    use crate::*;
+    use slog::o;
+
+    #[test]
+    fn test_logger_writes_records() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let rfa = BasicRollingFileAppender::new(folder, "log.log", RollingConditionBasic::new().daily(), 3).unwrap();
+        let (shared, guard) = SharedRollingFileAppender::new(rfa);
+        let logger = slog::Logger::root(RollingFileDrain::new(shared).fuse(), o!("version" => "1.0"));
+        slog::info!(logger, "first record"; "user" => "alice");
+        slog::warn!(logger, "second record {}", 2);
+        drop(logger);
+        drop(guard);
+
+        let contents = std::fs::read_to_string(dir.path().join("log.log")).unwrap();
+        let lines = contents.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].ends_with(" INFO first record, user: alice, version: 1.0"));
+        assert!(lines[1].ends_with(" WARNING second record 2, version: 1.0"));
+    }
+}
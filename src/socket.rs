@@ -0,0 +1,234 @@
+//! Receiving log lines over a Unix domain socket.
+//!
+//! Short-lived helper processes often can't hold a rolling appender of their own. A
+//! [`SocketReceiver`] listens on a Unix socket and appends the newline delimited messages of every
+//! connection to the appender, each connection being copied like a captured pipe, see
+//! [`crate::capture`]. At most [`MAX_CONNECTIONS`] connections are copied at once, further clients
+//! wait in the socket's backlog until one of them closes.
+
+use crate::{RollingCondition, SharedRollingFileAppender};
+use std::{
+    io,
+    os::unix::{
+        fs::FileTypeExt,
+        net::{UnixListener, UnixStream},
+    },
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+        Condvar,
+        Mutex,
+    },
+    thread::JoinHandle,
+};
+
+/// Most connections copied at once by a [`SocketReceiver`]
+pub const MAX_CONNECTIONS: usize = 32;
+
+/// The number of connections being copied, and the condition the accepting thread waits on for
+/// one of them to close
+#[derive(Debug, Default)]
+struct Connections {
+    active: Mutex<usize>,
+    closed: Condvar,
+}
+
+impl Connections {
+    fn lock(&self) -> std::sync::MutexGuard<'_, usize> {
+        self.active.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Frees the slot of a connection when its thread ends, even if copying failed
+struct Slot(Arc<Connections>);
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        *self.0.lock() -= 1;
+        self.0.closed.notify_all();
+    }
+}
+
+/// Appends the lines received on a Unix socket to an appender. Start it with
+/// [`SharedRollingFileAppender::listen_unix`]; dropping it stops accepting connections and
+/// removes the socket file. Connections already accepted are copied until the client closes them.
+#[derive(Debug)]
+pub struct SocketReceiver {
+    path: PathBuf,
+    stop: Arc<AtomicBool>,
+    connections: Arc<Connections>,
+    thread_opt: Option<JoinHandle<()>>,
+}
+
+impl SocketReceiver {
+    /// Returns the path of the socket
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for SocketReceiver {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Release);
+        // Wake up the accepting thread, whether it waits for a slot or a connection
+        {
+            let _active = self.connections.lock();
+            self.connections.closed.notify_all();
+        }
+        let _ = UnixStream::connect(&self.path);
+        if let Some(thread) = self.thread_opt.take() {
+            let _ = thread.join();
+        }
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+impl<RC> SharedRollingFileAppender<RC>
+where
+    RC: RollingCondition + Send + 'static,
+{
+    /// Listens on a Unix socket at `path`, replacing a stale socket file left by an earlier run.
+    /// Fails if something else than a socket exists at `path`.
+    pub fn listen_unix(&self, path: &Path) -> io::Result<SocketReceiver> {
+        match std::fs::symlink_metadata(path) {
+            Ok(metadata) if metadata.file_type().is_socket() => std::fs::remove_file(path)?,
+            Ok(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("{} exists and isn't a socket", path.display()),
+                ))
+            },
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+            Err(_) => {},
+        }
+        let listener = UnixListener::bind(path)?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let connections = Arc::new(Connections::default());
+        let options = self.lock().thread_options().clone();
+        let thread = {
+            let shared = self.clone();
+            let (stop, connections) = (stop.clone(), connections.clone());
+            let connection_options = options.clone();
+            options.spawn("rolling-file-socket", move || loop {
+                // Leave further clients in the backlog while all slots are taken
+                let active = connections.lock();
+                let active = connections
+                    .closed
+                    .wait_while(active, |active| *active >= MAX_CONNECTIONS && !stop.load(Ordering::Acquire))
+                    .unwrap_or_else(|e| e.into_inner());
+                // Stopped while all slots were taken, the clients left in the backlog aren't served
+                if *active >= MAX_CONNECTIONS {
+                    break;
+                }
+                drop(active);
+                let stream = match listener.accept() {
+                    Ok((stream, _)) => stream,
+                    Err(e) => {
+                        eprintln!("WARNING: Failed to accept log connection {}", e);
+                        continue;
+                    },
+                };
+                // Connections accepted before stopping are still copied, the wake up one is empty
+                *connections.lock() += 1;
+                let slot = Slot(connections.clone());
+                let shared = shared.clone();
+                let result = connection_options.spawn("rolling-file-socket-conn", move || {
+                    let _slot = slot;
+                    if let Err(e) = shared.copy_lines(stream) {
+                        eprintln!("WARNING: Failed to copy log connection {}", e);
+                    }
+                });
+                if let Err(e) = result {
+                    eprintln!("WARNING: Failed to accept log connection {}", e);
+                }
+                if stop.load(Ordering::Acquire) {
+                    break;
+                }
+            })?
+        };
+        Ok(SocketReceiver {
+            path: path.to_path_buf(),
+            stop,
+            connections,
+            thread_opt: Some(thread),
+        })
+    }
+}
+
+#[cfg(test)]
+mod t {
+    use crate::*;
+    use std::{io::Write, os::unix::net::UnixStream};
+
+    #[test]
+    fn test_socket_receiver() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let rfa = BasicRollingFileAppender::new(folder, "log.log", RollingConditionBasic::new().daily(), 3).unwrap();
+        let (shared, _guard) = SharedRollingFileAppender::new(rfa);
+        let receiver = shared.listen_unix(&dir.path().join("log.sock")).unwrap();
+
+        let mut client = UnixStream::connect(receiver.path()).unwrap();
+        client.write_all(b"first\nsecond\n").unwrap();
+        drop(client);
+        drop(receiver);
+        // The connection is copied on its own thread, wait for it to reach the file
+        let path = dir.path().join("log.log");
+        for _ in 0..100 {
+            if std::fs::read_to_string(&path).unwrap() == "first\nsecond\n" {
+                return;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        panic!("lines weren't received");
+    }
+
+    #[test]
+    fn test_refuses_to_replace_other_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let rfa = BasicRollingFileAppender::new(folder, "log.log", RollingConditionBasic::new().daily(), 3).unwrap();
+        let (shared, _guard) = SharedRollingFileAppender::new(rfa);
+        let config = dir.path().join("app.toml");
+        std::fs::write(&config, "keep me").unwrap();
+        let e = shared.listen_unix(&config).unwrap_err();
+        assert_eq!(e.kind(), std::io::ErrorKind::AlreadyExists);
+        assert_eq!(std::fs::read_to_string(&config).unwrap(), "keep me");
+
+        // A stale socket is replaced
+        let path = dir.path().join("log.sock");
+        drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+        drop(shared.listen_unix(&path).unwrap());
+    }
+
+    #[test]
+    fn test_bounds_connections() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let rfa = BasicRollingFileAppender::new(folder, "log.log", RollingConditionBasic::new().daily(), 3).unwrap();
+        let (shared, _guard) = SharedRollingFileAppender::new(rfa);
+        let receiver = shared.listen_unix(&dir.path().join("log.sock")).unwrap();
+
+        // Idle clients take all the slots, the next one waits until one of them closes
+        let mut idle = (0..socket::MAX_CONNECTIONS)
+            .map(|_| UnixStream::connect(receiver.path()).unwrap())
+            .collect::<Vec<_>>();
+        let mut waiting = UnixStream::connect(receiver.path()).unwrap();
+        waiting.write_all(b"waited\n").unwrap();
+        drop(waiting);
+        let path = dir.path().join("log.log");
+        let received = || std::fs::read_to_string(&path).unwrap() == "waited\n";
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        assert!(!received());
+        idle.pop();
+        for _ in 0..100 {
+            if received() {
+                drop(idle);
+                return;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        panic!("the waiting connection wasn't served");
+    }
+}
@@ -0,0 +1,77 @@
+//! Counters describing what an appender has done, e.g. to export as metrics.
+
+use chrono::{DateTime, Local};
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+/// A snapshot of the counters of an appender, see [`crate::RollingFileAppender::stats`]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RollingFileStats {
+    /// Bytes written since the appender was created, including buffered ones
+    pub bytes_written: u64,
+    /// Bytes in the current file, see [`crate::RollingFileAppender::current_file_size`]
+    pub current_file_bytes: u64,
+    /// Number of times a new file was started after a previous one
+    pub rotations: u64,
+    /// Number of files deleted by retention
+    pub deleted_files: u64,
+    /// Time of the last rotation, by the clock of the writes
+    pub last_rotation: Option<DateTime<Local>>,
+    /// The last error returned by a write
+    pub last_write_error: Option<String>,
+}
+
+/// The counters kept by an appender
+#[derive(Debug, Default)]
+pub(crate) struct StatsCounters {
+    pub(crate) bytes_written: u64,
+    pub(crate) rotations: u64,
+    pub(crate) last_rotation_opt: Option<DateTime<Local>>,
+    pub(crate) last_write_error_opt: Option<String>,
+    // Updated by rollover jobs, which may run on the maintenance scheduler
+    pub(crate) deleted_files: Arc<AtomicU64>,
+}
+
+impl StatsCounters {
+    pub(crate) fn snapshot(&self, current_file_bytes: u64) -> RollingFileStats {
+        RollingFileStats {
+            bytes_written: self.bytes_written,
+            current_file_bytes,
+            rotations: self.rotations,
+            deleted_files: self.deleted_files.load(Ordering::Relaxed),
+            last_rotation: self.last_rotation_opt,
+            last_write_error: self.last_write_error_opt.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod t {
+    use crate::*;
+
+    #[test]
+    fn test_stats() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let mut rfa =
+            BasicRollingFileAppender::new(folder, "log.log", RollingConditionBasic::new().daily(), 2).unwrap();
+        rfa.rollover().unwrap();
+        for day in 1..=3 {
+            let now = Local.with_ymd_and_hms(2099, 3, day, 1, 0, 0).unwrap();
+            rfa.write_with_datetime(b"line\n", &now).unwrap();
+        }
+        let stats = rfa.stats();
+        assert_eq!(stats.bytes_written, 15);
+        assert_eq!(stats.current_file_bytes, 5);
+        assert_eq!(stats.rotations, 3);
+        // Including the file opened at construction
+        assert_eq!(stats.deleted_files, 2);
+        assert_eq!(
+            stats.last_rotation,
+            Some(Local.with_ymd_and_hms(2099, 3, 3, 1, 0, 0).unwrap())
+        );
+        assert_eq!(stats.last_write_error, None);
+    }
+}
@@ -0,0 +1,384 @@
+//! A small syslog sink writing to rolling files.
+//!
+//! A [`SyslogReceiver`] listens for RFC 3164 and RFC 5424 messages over UDP or TCP and appends
+//! them to an appender, so the appender's rotation and retention apply to them. Messages are
+//! written as received or normalized to a single readable format, see [`SyslogFormat`]. TCP
+//! streams may use newline delimited or octet counted framing (RFC 6587).
+
+use crate::{RollingCondition, SharedRollingFileAppender};
+use std::{
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, TcpStream, ToSocketAddrs, UdpSocket},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::JoinHandle,
+};
+
+/// Largest datagram accepted by the UDP receiver
+const MAX_DATAGRAM: usize = 64 * 1024;
+
+/// Largest frame accepted by the TCP receiver, a larger one closes the connection
+const MAX_FRAME: usize = 64 * 1024;
+
+const SEVERITIES: [&str; 8] = ["emerg", "alert", "crit", "err", "warning", "notice", "info", "debug"];
+
+/// How received messages are written
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum SyslogFormat {
+    /// Writes each message as received, followed by a line break
+    Raw,
+    /// Writes `<timestamp> <host> <app> <severity>: <message>`, with `-` for missing fields.
+    /// Messages which can't be parsed are written as received.
+    Normalized,
+}
+
+/// The fields of a syslog message
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SyslogMessage {
+    /// The facility code, 0 to 23
+    pub facility: u8,
+    /// The severity code, 0 (emergency) to 7 (debug)
+    pub severity: u8,
+    /// The timestamp as sent
+    pub timestamp_opt: Option<String>,
+    /// The name of the sending host
+    pub hostname_opt: Option<String>,
+    /// The name of the sending application
+    pub app_name_opt: Option<String>,
+    /// The free form message
+    pub message: String,
+}
+
+impl SyslogMessage {
+    /// Parses an RFC 5424 or RFC 3164 message
+    pub fn parse(data: &[u8]) -> Option<SyslogMessage> {
+        let text = String::from_utf8_lossy(data);
+        let text = text.trim_end_matches(['\r', '\n', '\0']);
+        let rest = text.strip_prefix('<')?;
+        let (pri, rest) = rest.split_once('>')?;
+        let pri = pri.parse::<u8>().ok().filter(|pri| *pri < 192)?;
+        let mut message = match rest.strip_prefix("1 ") {
+            Some(rest) => parse_rfc5424(rest),
+            None => parse_rfc3164(rest),
+        };
+        message.facility = pri / 8;
+        message.severity = pri % 8;
+        Some(message)
+    }
+
+    /// Returns the single line written in [`SyslogFormat::Normalized`]
+    pub fn normalized(&self) -> String {
+        let field = |f: &Option<String>| f.clone().unwrap_or_else(|| "-".to_string());
+        format!(
+            "{} {} {} {}: {}",
+            field(&self.timestamp_opt),
+            field(&self.hostname_opt),
+            field(&self.app_name_opt),
+            SEVERITIES[self.severity as usize],
+            self.message.replace('\n', " ")
+        )
+    }
+}
+
+fn nil_to_none(field: &str) -> Option<String> {
+    (field != "-").then(|| field.to_string())
+}
+
+/// Parses `TIMESTAMP HOSTNAME APP-NAME PROCID MSGID STRUCTURED-DATA MSG`
+fn parse_rfc5424(rest: &str) -> SyslogMessage {
+    let mut fields = rest.splitn(6, ' ');
+    let timestamp_opt = fields.next().and_then(nil_to_none);
+    let hostname_opt = fields.next().and_then(nil_to_none);
+    let app_name_opt = fields.next().and_then(nil_to_none);
+    let _procid = fields.next();
+    let _msgid = fields.next();
+    let message = fields.next().map(skip_structured_data).unwrap_or_default();
+    SyslogMessage {
+        facility: 0,
+        severity: 0,
+        timestamp_opt,
+        hostname_opt,
+        app_name_opt,
+        message: message.trim_start_matches('\u{feff}').to_string(),
+    }
+}
+
+/// Skips the structured data at the start of `rest`, which is either `-` or `[...]` elements.
+fn skip_structured_data(rest: &str) -> &str {
+    if let Some(message) = rest.strip_prefix('-') {
+        return message.strip_prefix(' ').unwrap_or(message);
+    }
+    let mut in_element = false;
+    let mut escaped = false;
+    for (i, c) in rest.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_element => escaped = true,
+            '[' if !in_element => in_element = true,
+            ']' if in_element => in_element = false,
+            ' ' if !in_element => return &rest[i + 1..],
+            _ => {},
+        }
+    }
+    ""
+}
+
+/// Parses `Mmm dd hh:mm:ss HOSTNAME TAG: MSG`, where the header may be missing
+fn parse_rfc3164(rest: &str) -> SyslogMessage {
+    let mut message = SyslogMessage {
+        facility: 0,
+        severity: 0,
+        timestamp_opt: None,
+        hostname_opt: None,
+        app_name_opt: None,
+        message: rest.to_string(),
+    };
+    // The timestamp has a fixed width, with the day padded by a space
+    let Some(timestamp) = rest.get(..15).filter(|t| t.as_bytes().get(9) == Some(&b':')) else {
+        return message;
+    };
+    let mut fields = rest[15..].trim_start().splitn(2, ' ');
+    let hostname = fields.next().unwrap_or_default();
+    let rest = fields.next().unwrap_or_default();
+    message.timestamp_opt = Some(timestamp.to_string());
+    message.hostname_opt = Some(hostname.to_string());
+    message.message = rest.to_string();
+    if let Some((tag, msg)) = rest.split_once(": ") {
+        if !tag.contains(' ') {
+            let app = tag.split_once('[').map_or(tag, |(app, _)| app);
+            message.app_name_opt = Some(app.to_string());
+            message.message = msg.to_string();
+        }
+    }
+    message
+}
+
+fn format_message(data: &[u8], format: SyslogFormat) -> Vec<u8> {
+    let mut line = match format {
+        SyslogFormat::Normalized => match SyslogMessage::parse(data) {
+            Some(message) => message.normalized().into_bytes(),
+            None => data.to_vec(),
+        },
+        SyslogFormat::Raw => data.to_vec(),
+    };
+    while line.last().is_some_and(|b| matches!(b, b'\n' | b'\r' | b'\0')) {
+        line.pop();
+    }
+    line.push(b'\n');
+    line
+}
+
+/// Reads the frames of a TCP stream, each either prefixed by its length or ended by a line break.
+fn read_frame(reader: &mut impl BufRead, frame: &mut Vec<u8>) -> io::Result<bool> {
+    frame.clear();
+    let too_large = || io::Error::new(io::ErrorKind::InvalidData, "syslog frame exceeds 64 KiB");
+    let octet_counted = reader.fill_buf()?.first().is_some_and(u8::is_ascii_digit);
+    if !octet_counted {
+        let len = reader.by_ref().take(MAX_FRAME as u64).read_until(b'\n', frame)?;
+        if len == MAX_FRAME && !frame.ends_with(b"\n") {
+            return Err(too_large());
+        }
+        return Ok(len > 0);
+    }
+    let mut len = vec![];
+    // The digits of MAX_FRAME and the space
+    reader.by_ref().take(8).read_until(b' ', &mut len)?;
+    let len = std::str::from_utf8(&len)
+        .ok()
+        .and_then(|len| len.trim_end().parse::<usize>().ok())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "invalid syslog frame length"))?;
+    if len > MAX_FRAME {
+        return Err(too_large());
+    }
+    frame.resize(len, 0);
+    reader.read_exact(frame)?;
+    Ok(true)
+}
+
+/// Appends syslog messages to an appender. Start it with
+/// [`SharedRollingFileAppender::listen_syslog_udp`] or
+/// [`SharedRollingFileAppender::listen_syslog_tcp`]; dropping it stops listening. TCP connections
+/// already accepted are read until the client closes them.
+#[derive(Debug)]
+pub struct SyslogReceiver {
+    local_addr: SocketAddr,
+    tcp: bool,
+    stop: Arc<AtomicBool>,
+    thread_opt: Option<JoinHandle<()>>,
+}
+
+impl SyslogReceiver {
+    /// Returns the address the receiver listens on
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+}
+
+impl Drop for SyslogReceiver {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Release);
+        // Wake up the listening thread
+        let mut addr = self.local_addr;
+        if addr.ip().is_unspecified() {
+            addr.set_ip(match addr {
+                SocketAddr::V4(_) => IpAddr::V4(Ipv4Addr::LOCALHOST),
+                SocketAddr::V6(_) => IpAddr::V6(Ipv6Addr::LOCALHOST),
+            });
+        }
+        if self.tcp {
+            let _ = TcpStream::connect(addr);
+        } else {
+            let unspecified: SocketAddr = match addr {
+                SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+                SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+            };
+            let _ = UdpSocket::bind(unspecified).and_then(|s| s.send_to(&[], addr));
+        }
+        if let Some(thread) = self.thread_opt.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl<RC> SharedRollingFileAppender<RC>
+where
+    RC: RollingCondition + Send + 'static,
+{
+    /// Receives syslog datagrams on `addr`, e.g. `"127.0.0.1:514"`.
+    pub fn listen_syslog_udp(&self, addr: impl ToSocketAddrs, format: SyslogFormat) -> io::Result<SyslogReceiver> {
+        let socket = UdpSocket::bind(addr)?;
+        let local_addr = socket.local_addr()?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let options = self.lock().thread_options().clone();
+        let thread = {
+            let shared = self.clone();
+            let stop = stop.clone();
+            options.spawn("rolling-file-syslog", move || {
+                let mut buf = vec![0; MAX_DATAGRAM];
+                while !stop.load(Ordering::Acquire) {
+                    match socket.recv(&mut buf) {
+                        Ok(0) => {},
+                        Ok(n) => {
+                            let mut rfa = shared.lock();
+                            if let Err(e) = rfa.write_all(&format_message(&buf[..n], format)) {
+                                eprintln!("WARNING: Failed to write syslog message {}", e);
+                            }
+                        },
+                        Err(e) => eprintln!("WARNING: Failed to receive syslog message {}", e),
+                    }
+                }
+            })?
+        };
+        Ok(SyslogReceiver {
+            local_addr,
+            tcp: false,
+            stop,
+            thread_opt: Some(thread),
+        })
+    }
+
+    /// Accepts syslog connections on `addr`, reading each one on its own thread.
+    pub fn listen_syslog_tcp(&self, addr: impl ToSocketAddrs, format: SyslogFormat) -> io::Result<SyslogReceiver> {
+        let listener = TcpListener::bind(addr)?;
+        let local_addr = listener.local_addr()?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let options = self.lock().thread_options().clone();
+        let thread = {
+            let shared = self.clone();
+            let stop = stop.clone();
+            let conn_options = options.clone();
+            options.spawn("rolling-file-syslog", move || {
+                for stream in listener.incoming() {
+                    let shared = shared.clone();
+                    // Connections accepted before stopping are still read, the wake up one is empty
+                    let result = stream.and_then(|stream| {
+                        conn_options.spawn("rolling-file-syslog-conn", move || {
+                            let mut reader = BufReader::new(stream);
+                            let mut frame = vec![];
+                            loop {
+                                match read_frame(&mut reader, &mut frame) {
+                                    Ok(true) => {
+                                        let mut rfa = shared.lock();
+                                        if let Err(e) = rfa.write_all(&format_message(&frame, format)) {
+                                            eprintln!("WARNING: Failed to write syslog message {}", e);
+                                        }
+                                    },
+                                    Ok(false) => break,
+                                    Err(e) => {
+                                        eprintln!("WARNING: Failed to receive syslog message {}", e);
+                                        break;
+                                    },
+                                }
+                            }
+                        })
+                    });
+                    if let Err(e) = result {
+                        eprintln!("WARNING: Failed to accept syslog connection {}", e);
+                    }
+                    if stop.load(Ordering::Acquire) {
+                        break;
+                    }
+                }
+            })?
+        };
+        Ok(SyslogReceiver {
+            local_addr,
+            tcp: true,
+            stop,
+            thread_opt: Some(thread),
+        })
+    }
+}
+
+#[cfg(test)]
+mod t {
+    use super::*;
+    use crate::*;
+
+    #[test]
+    fn test_frame_limit() {
+        let mut frame = vec![];
+        let mut reader = &b"5 hello6 world!"[..];
+        assert!(read_frame(&mut reader, &mut frame).unwrap());
+        assert_eq!(frame, b"hello");
+        let e = read_frame(&mut &b"4000000000 x"[..], &mut frame).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        let e = read_frame(&mut &[b'x'; MAX_FRAME + 1][..], &mut frame).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_parse_and_receive() {
+        let m = SyslogMessage::parse(b"<34>1 2099-03-30T01:02:03Z host app 123 ID47 [a b=\"]\"] hello").unwrap();
+        assert_eq!(m.normalized(), "2099-03-30T01:02:03Z host app crit: hello");
+        let m = SyslogMessage::parse(b"<13>Mar  3 01:02:03 host sshd[42]: hello\n").unwrap();
+        assert_eq!((m.facility, m.severity), (1, 5));
+        assert_eq!(m.normalized(), "Mar  3 01:02:03 host sshd notice: hello");
+
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let rfa = BasicRollingFileAppender::new(folder, "log.log", RollingConditionBasic::new().daily(), 3).unwrap();
+        let (shared, _guard) = SharedRollingFileAppender::new(rfa);
+        let receiver = shared
+            .listen_syslog_tcp("127.0.0.1:0", SyslogFormat::Normalized)
+            .unwrap();
+        let mut client = TcpStream::connect(receiver.local_addr()).unwrap();
+        client
+            .write_all(b"<14>Mar 30 01:02:03 host app: one\n23 <14>1 - - app - - - two")
+            .unwrap();
+        drop(client);
+
+        let path = dir.path().join("log.log");
+        let expected = "Mar 30 01:02:03 host app info: one\n- - app info: two\n";
+        for _ in 0..100 {
+            shared.lock().flush().unwrap();
+            if std::fs::read_to_string(&path).unwrap() == expected {
+                return;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        panic!("messages weren't received");
+    }
+}
@@ -0,0 +1,118 @@
+//! Naming and prioritizing the background threads spawned by the crate.
+
+use std::{
+    io,
+    thread::{self, JoinHandle, Scope, ScopedJoinHandle},
+};
+
+/// Name and scheduling priority of a background thread, so it is identifiable in profilers and
+/// can yield to application threads. Threads keep their default name, such as
+/// `rolling-file-maintenance`, and priority unless set here.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ThreadOptions {
+    name_opt: Option<String>,
+    nice_opt: Option<i32>,
+}
+
+impl ThreadOptions {
+    /// Keeps the default name and priority
+    pub fn new() -> ThreadOptions {
+        ThreadOptions::default()
+    }
+
+    /// Sets the thread name
+    pub fn name(mut self, name: &str) -> ThreadOptions {
+        self.name_opt = Some(name.to_string());
+        self
+    }
+
+    /// Sets the nice value of the thread, from -20 (highest priority) to 19 (lowest). Only
+    /// supported on Linux, ignored elsewhere.
+    pub fn nice(mut self, nice: i32) -> ThreadOptions {
+        self.nice_opt = Some(nice);
+        self
+    }
+
+    /// Spawns a thread with these options, named `default_name` unless a name was set.
+    pub(crate) fn spawn<F, T>(&self, default_name: &str, f: F) -> io::Result<JoinHandle<T>>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let name = self.name_opt.as_deref().unwrap_or(default_name).to_string();
+        let nice_opt = self.nice_opt;
+        thread::Builder::new().name(name).spawn(move || {
+            apply_nice(nice_opt);
+            f()
+        })
+    }
+
+    /// Same as [`ThreadOptions::spawn`], for a thread of `scope` which may borrow from the caller
+    pub(crate) fn spawn_scoped<'scope, 'env, F, T>(
+        &self,
+        scope: &'scope Scope<'scope, 'env>,
+        default_name: &str,
+        f: F,
+    ) -> io::Result<ScopedJoinHandle<'scope, T>>
+    where
+        F: FnOnce() -> T + Send + 'scope,
+        T: Send + 'scope,
+    {
+        let name = self.name_opt.as_deref().unwrap_or(default_name).to_string();
+        let nice_opt = self.nice_opt;
+        thread::Builder::new().name(name).spawn_scoped(scope, move || {
+            apply_nice(nice_opt);
+            f()
+        })
+    }
+}
+
+fn apply_nice(nice_opt: Option<i32>) {
+    if let Some(nice) = nice_opt {
+        if let Err(e) = set_current_thread_nice(nice) {
+            eprintln!("WARNING: Failed to set thread priority {}", e);
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn set_current_thread_nice(nice: i32) -> io::Result<()> {
+    // On Linux the nice value is a per-thread attribute addressed by the thread id
+    // SAFETY: gettid and setpriority have no memory safety requirements
+    let result = unsafe {
+        let tid = libc::gettid();
+        libc::setpriority(libc::PRIO_PROCESS, tid as libc::id_t, nice)
+    };
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_current_thread_nice(_nice: i32) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod t {
+    use super::*;
+
+    #[test]
+    fn test_thread_name() {
+        let options = ThreadOptions::new().name("custom-worker").nice(19);
+        let name = options
+            .spawn("default", || thread::current().name().map(str::to_string))
+            .unwrap()
+            .join()
+            .unwrap();
+        assert_eq!(name.as_deref(), Some("custom-worker"));
+        let name = ThreadOptions::new()
+            .spawn("default", || thread::current().name().map(str::to_string))
+            .unwrap()
+            .join()
+            .unwrap();
+        assert_eq!(name.as_deref(), Some("default"));
+    }
+}
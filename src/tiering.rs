@@ -0,0 +1,171 @@
+//! A declarative lifecycle for rolled files.
+//!
+//! Rolled files usually go through stages: the newest stay plain for quick grepping, older ones
+//! are compressed, old ones move to cheaper storage and the oldest are deleted. A
+//! [`TieringPolicy`] describes all stages by file age in one place; attach it with
+//! [`crate::RollingFileAppender::with_tiering`] and it is applied after every rollover.
+
+use crate::{filesystem, index, Fs, NamingScheme};
+use chrono::{DateTime, Duration, Local};
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+/// Ages at which rolled files change tier. The age of a file is measured from the time in its
+/// name. Stages which aren't set are skipped.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct TieringPolicy {
+    #[cfg(feature = "zstd")]
+    compress_opt: Option<(Duration, i32)>,
+    move_opt: Option<(Duration, String)>,
+    delete_after_opt: Option<Duration>,
+}
+
+impl TieringPolicy {
+    /// Creates a policy keeping every file plain and in place
+    pub fn new() -> TieringPolicy {
+        TieringPolicy::default()
+    }
+
+    /// Compresses files older than `age` with zstd at the given level
+    #[cfg(feature = "zstd")]
+    pub fn compress_after(mut self, age: Duration, level: i32) -> TieringPolicy {
+        self.compress_opt = Some((age, level));
+        self
+    }
+
+    /// Moves files older than `age` to `cold_folder`, e.g. on slower, cheaper storage
+    pub fn move_after(mut self, age: Duration, cold_folder: &str) -> TieringPolicy {
+        self.move_opt = Some((age, cold_folder.to_string()));
+        self
+    }
+
+    /// Deletes files older than `age`, wherever they are
+    pub fn delete_after(mut self, age: Duration) -> TieringPolicy {
+        self.delete_after_opt = Some(age);
+        self
+    }
+}
+
+/// Returns the start time and path of the rolled files named by `naming` in `folder`, compressed
+/// or not, oldest first
+fn rolled_files(
+    fs: &dyn Fs,
+    folder: &str,
+    naming: &dyn NamingScheme,
+    active: Option<&Path>,
+) -> io::Result<Vec<(DateTime<Local>, PathBuf)>> {
+    let mut files = vec![];
+    let names = match naming.list(fs, Path::new(folder)) {
+        Ok(names) => names,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(files),
+        Err(e) => return Err(e),
+    };
+    for name in names {
+        let path = Path::new(folder).join(&name);
+        if Some(path.as_path()) == active {
+            continue;
+        }
+        if let Some(dt) = naming.started(&name) {
+            files.push((dt, path));
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+fn remove_with_sidecar(fs: &dyn Fs, path: &Path) -> io::Result<()> {
+    fs.remove_file(path)?;
+    let _ = fs.remove_file(&index::index_path_for(path));
+    internal_event!(path = %path.display(), "deleted file past its tiering age");
+    Ok(())
+}
+
+/// Moves every rolled file named by `naming` except `active` to the tier matching its age at
+/// `now`.
+/// Returns the number of files changed.
+pub(crate) fn apply_tiering(
+    fs: &dyn Fs,
+    folder: &str,
+    naming: &dyn NamingScheme,
+    policy: &TieringPolicy,
+    now: &DateTime<Local>,
+    active: Option<&Path>,
+) -> io::Result<usize> {
+    internal_span!("tiering", folder);
+    let is_older = |dt: &DateTime<Local>, age: Duration| *now - *dt >= age;
+    let mut changed = 0;
+    for (dt, path) in rolled_files(fs, folder, naming, active)? {
+        if policy.delete_after_opt.is_some_and(|age| is_older(&dt, age)) {
+            remove_with_sidecar(fs, &path)?;
+            changed += 1;
+            continue;
+        }
+        if let Some((_, cold_folder)) = policy.move_opt.as_ref().filter(|(age, _)| is_older(&dt, *age)) {
+            // Files in subfolders of the log folder keep their relative path
+            let target = Path::new(cold_folder).join(path.strip_prefix(folder).unwrap_or(&path));
+            if let Some(parent) = target.parent() {
+                fs.create_dir_all(parent)?;
+            }
+            let index_path = index::index_path_for(&path);
+            if filesystem::exists(fs, &index_path) {
+                filesystem::move_file(fs, &index_path, &index::index_path_for(&target))?;
+            }
+            filesystem::move_file(fs, &path, &target)?;
+            internal_event!(source = %path.display(), target = %target.display(), "moved file to cold tier");
+            changed += 1;
+            continue;
+        }
+        #[cfg(feature = "zstd")]
+        if let Some((_, level)) = policy.compress_opt.filter(|(age, _)| is_older(&dt, *age)) {
+            let fname = path.file_name().unwrap_or_default().to_string_lossy();
+            if crate::compression::strip_rolled_extensions(&fname) == fname {
+                crate::Compression::Zstd(level).compress_file_in(fs, &path)?;
+                changed += 1;
+            }
+        }
+    }
+    if let (Some(age), Some((_, cold_folder))) = (policy.delete_after_opt, policy.move_opt.as_ref()) {
+        for (dt, path) in rolled_files(fs, cold_folder, naming, None)? {
+            if is_older(&dt, age) {
+                remove_with_sidecar(fs, &path)?;
+                changed += 1;
+            }
+        }
+    }
+    Ok(changed)
+}
+
+#[cfg(test)]
+mod t {
+    use super::*;
+    use crate::*;
+
+    #[test]
+    fn test_tiers_by_age() {
+        let dir = tempfile::tempdir().unwrap();
+        let cold = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let policy = TieringPolicy::new()
+            .move_after(Duration::days(7), cold.path().to_str().unwrap())
+            .delete_after(Duration::days(30));
+        let mut rfa = BasicRollingFileAppender::new(folder, "log.log", RollingConditionBasic::new().daily(), 99)
+            .unwrap()
+            .with_tiering(policy);
+        rfa.rollover().unwrap();
+        for day in [1, 20, 27, 28] {
+            let now = Local.with_ymd_and_hms(2099, 3, day, 1, 0, 0).unwrap();
+            rfa.write_with_datetime(b"line\n", &now).unwrap();
+        }
+        rfa.flush().unwrap();
+
+        let now = Local.with_ymd_and_hms(2099, 4, 1, 0, 0, 0).unwrap();
+        rfa.apply_tiering(&now).unwrap();
+        assert!(!dir.path().join("log.log.20990301.010000").exists());
+        assert!(!cold.path().join("log.log.20990301.010000").exists());
+        assert!(cold.path().join("log.log.20990320.010000").exists());
+        assert!(dir.path().join("log.log.20990327.010000").exists());
+        assert!(dir.path().join("log.log.20990328.010000").exists());
+    }
+}
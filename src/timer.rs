@@ -0,0 +1,250 @@
+//! Rolling over at the time boundary even when nothing is written.
+//!
+//! An appender normally switches files on the first write after a boundary, so a quiet appender
+//! keeps yesterday's file as the latest one until something is logged. A [`RolloverTimer`] runs a
+//! thread that sleeps until the next boundary of the rolling condition and rolls over on time,
+//! closing the old file and pointing the symlink at the new one. A [`FlushTimer`] likewise
+//! flushes the write buffer periodically, so lines of a quiet service don't sit in memory.
+
+use crate::{RollingCondition, SharedRollingFileAppender};
+use std::{
+    io::{self, Write},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+/// How often the timer checks again when the rolling condition has no time based boundary
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How long the timer waits before trying again after a failed rollover, doubled on each
+/// further failure up to [`IDLE_CHECK_INTERVAL`]
+const RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Rolls a [`SharedRollingFileAppender`] over at each time boundary. Start it with
+/// [`SharedRollingFileAppender::rollover_on_time`]; dropping it stops the timer thread.
+#[derive(Debug)]
+pub struct RolloverTimer {
+    stop: Arc<AtomicBool>,
+    thread_opt: Option<JoinHandle<()>>,
+}
+
+impl<RC> SharedRollingFileAppender<RC>
+where
+    RC: RollingCondition + Send + 'static,
+{
+    /// Starts a thread rolling the appender over as soon as a time boundary of its rolling
+    /// condition passes, instead of on the next write. The thread only holds a weak reference,
+    /// so it doesn't keep the appender alive.
+    pub fn rollover_on_time(&self) -> io::Result<RolloverTimer> {
+        let appender = Arc::downgrade(&self.inner);
+        let (options, clock) = {
+            let rfa = self.lock();
+            (rfa.thread_options().clone(), rfa.clock.clone())
+        };
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let stop = stop.clone();
+            options.spawn("rolling-file-timer", move || {
+                // The boundary stays in the past while the rollover fails
+                let mut backoff = Duration::ZERO;
+                while !stop.load(Ordering::Acquire) {
+                    let shared = match appender.upgrade() {
+                        Some(inner) => SharedRollingFileAppender { inner },
+                        None => break,
+                    };
+                    let now = clock.now();
+                    let mut rfa = shared.lock();
+                    match rfa.rollover_if_due(&now) {
+                        Ok(_) => backoff = Duration::ZERO,
+                        Err(e) => {
+                            eprintln!("WARNING: Failed to rotate logfile  {}", e);
+                            backoff = (backoff * 2).clamp(RETRY_INTERVAL, IDLE_CHECK_INTERVAL);
+                        },
+                    }
+                    let wait = rfa
+                        .condition_ref()
+                        .next_rollover(&now)
+                        .map_or(IDLE_CHECK_INTERVAL, |next| {
+                            (next - clock.now()).to_std().unwrap_or_default()
+                        });
+                    drop(rfa);
+                    drop(shared);
+                    // Wake up just after the boundary, not just before it
+                    thread::park_timeout(wait.max(backoff) + Duration::from_millis(1));
+                }
+            })?
+        };
+        Ok(RolloverTimer {
+            stop,
+            thread_opt: Some(thread),
+        })
+    }
+}
+
+impl Drop for RolloverTimer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Release);
+        if let Some(thread) = self.thread_opt.take() {
+            thread.thread().unpark();
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Flushes a [`SharedRollingFileAppender`] periodically. Start it with
+/// [`SharedRollingFileAppender::flush_interval`]; dropping it stops the timer thread.
+#[derive(Debug)]
+pub struct FlushTimer {
+    stop: Arc<AtomicBool>,
+    thread_opt: Option<JoinHandle<()>>,
+}
+
+impl<RC> SharedRollingFileAppender<RC>
+where
+    RC: RollingCondition + Send + 'static,
+{
+    /// Starts a thread flushing the appender every `interval`. The thread only holds a weak
+    /// reference, so it doesn't keep the appender alive.
+    pub fn flush_interval(&self, interval: Duration) -> io::Result<FlushTimer> {
+        let appender = Arc::downgrade(&self.inner);
+        let options = self.lock().thread_options().clone();
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let stop = stop.clone();
+            options.spawn("rolling-file-flush-timer", move || loop {
+                thread::park_timeout(interval);
+                if stop.load(Ordering::Acquire) {
+                    break;
+                }
+                let Some(inner) = appender.upgrade() else {
+                    break;
+                };
+                let shared = SharedRollingFileAppender { inner };
+                let flushed = shared.lock().flush();
+                if let Err(e) = flushed {
+                    eprintln!("WARNING: Failed to flush logfile {}", e);
+                }
+            })?
+        };
+        Ok(FlushTimer {
+            stop,
+            thread_opt: Some(thread),
+        })
+    }
+}
+
+impl Drop for FlushTimer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Release);
+        if let Some(thread) = self.thread_opt.take() {
+            thread.thread().unpark();
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod t {
+    use crate::*;
+
+    #[test]
+    fn test_rollover_without_writes() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let mut rfa =
+            BasicRollingFileAppender::new(folder, "log.log", RollingConditionBasic::new().daily(), 9).unwrap();
+        let day1 = Local.with_ymd_and_hms(2099, 3, 30, 23, 59, 0).unwrap();
+        let day2 = Local.with_ymd_and_hms(2099, 3, 31, 0, 0, 0).unwrap();
+        rfa.write_with_datetime(b"yesterday\n", &day1).unwrap();
+        assert!(!rfa.rollover_if_due(&day1).unwrap());
+        assert!(rfa.rollover_if_due(&day2).unwrap());
+
+        let new_file = dir.path().join("log.log.20990331.000000");
+        assert!(new_file.exists());
+        assert_eq!(
+            std::fs::canonicalize(dir.path().join("log.log")).unwrap(),
+            std::fs::canonicalize(new_file).unwrap()
+        );
+        let old = std::fs::read_to_string(dir.path().join("log.log.20990330.235900")).unwrap();
+        assert_eq!(old, "yesterday\n");
+
+        let (shared, _guard) = SharedRollingFileAppender::new(rfa);
+        drop(shared.rollover_on_time().unwrap());
+    }
+
+    #[test]
+    fn test_rollover_on_time_follows_clock() {
+        use std::{io::Write, time::Duration};
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let clock = MockClock::new(Local.with_ymd_and_hms(2099, 3, 30, 23, 59, 0).unwrap());
+        let mut rfa = BasicRollingFileAppender::builder()
+            .folder(folder)
+            .prefix("log.log")
+            .condition(RollingConditionBasic::new().daily())
+            .max_files(9)
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+        rfa.write_all(b"yesterday\n").unwrap();
+        clock.set(Local.with_ymd_and_hms(2099, 3, 31, 0, 0, 0).unwrap());
+        let (shared, _guard) = SharedRollingFileAppender::new(rfa);
+        let timer = shared.rollover_on_time().unwrap();
+        let new_file = dir.path().join("log.log.20990331.000000");
+        let rolled = (0..200).any(|_| {
+            std::thread::sleep(Duration::from_millis(10));
+            new_file.exists()
+        });
+        drop(timer);
+        assert!(rolled);
+    }
+
+    #[test]
+    fn test_idle_rollover_on_time() {
+        use std::{io::Write, time::Duration};
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let condition = RollingConditionBasic::new().idle_rollover(chrono::Duration::milliseconds(200));
+        let mut rfa = BasicRollingFileAppender::new(folder, "log.log", condition, 9).unwrap();
+        rfa.write_all(b"burst\n").unwrap();
+        let (shared, _guard) = SharedRollingFileAppender::new(rfa);
+        let _timer = shared.rollover_on_time().unwrap();
+        let rolled = (0..200).any(|_| {
+            std::thread::sleep(Duration::from_millis(10));
+            !shared.lock().list_rotated_files().unwrap().is_empty()
+        });
+        assert!(rolled);
+        // Only once until the next write, the timer checks don't count as writes
+        std::thread::sleep(Duration::from_millis(500));
+        let rotated = shared.lock().list_rotated_files().unwrap();
+        assert_eq!(rotated.len(), 1);
+        assert_eq!(std::fs::read_to_string(&rotated[0]).unwrap(), "burst\n");
+    }
+
+    #[test]
+    fn test_flush_interval() {
+        use std::{io::Write, time::Duration};
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let mut rfa =
+            BasicRollingFileAppender::new(folder, "log.log", RollingConditionBasic::new().daily(), 9).unwrap();
+        rfa.rollover().unwrap();
+        let now = Local.with_ymd_and_hms(2099, 3, 30, 1, 0, 0).unwrap();
+        rfa.write_with_datetime(b"line\n", &now).unwrap();
+        let path = dir.path().join("log.log.20990330.010000");
+        assert_eq!(std::fs::read(&path).unwrap(), b"");
+
+        let (shared, _guard) = SharedRollingFileAppender::new(rfa);
+        let _timer = shared.flush_interval(Duration::from_millis(10)).unwrap();
+        let flushed = (0..200).any(|_| {
+            std::thread::sleep(Duration::from_millis(10));
+            std::fs::read(&path).unwrap() == b"line\n"
+        });
+        assert!(flushed);
+        shared.lock().flush().unwrap();
+    }
+}
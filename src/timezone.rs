@@ -0,0 +1,83 @@
+//! The time zone used for file names and rollover boundaries.
+//!
+//! Times are passed around as `DateTime<Local>`, which is only an instant; a [`Timezone`] decides
+//! on which wall clock it is read. Servers in different time zones can then roll and name their
+//! files in UTC, so the files line up.
+
+use chrono::{DateTime, FixedOffset, Local, NaiveDateTime, TimeZone, Utc};
+
+/// The wall clock file names and rollover boundaries follow
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum Timezone {
+    /// The local time zone of the system
+    #[default]
+    Local,
+    /// Coordinated Universal Time
+    Utc,
+    /// A fixed offset from UTC
+    Fixed(FixedOffset),
+}
+
+impl Timezone {
+    /// Returns the wall clock time of `dt` in this zone
+    pub fn to_naive(&self, dt: &DateTime<Local>) -> NaiveDateTime {
+        match self {
+            Timezone::Local => dt.naive_local(),
+            Timezone::Utc => dt.naive_utc(),
+            Timezone::Fixed(offset) => dt.with_timezone(offset).naive_local(),
+        }
+    }
+
+    /// Returns the instant of a wall clock time in this zone, the earliest one if it is ambiguous,
+    /// or `None` if it doesn't exist, e.g. in a DST gap.
+    pub fn from_naive(&self, naive: &NaiveDateTime) -> Option<DateTime<Local>> {
+        match self {
+            Timezone::Local => Local.from_local_datetime(naive).earliest(),
+            Timezone::Utc => Some(Utc.from_utc_datetime(naive).with_timezone(&Local)),
+            Timezone::Fixed(offset) => offset
+                .from_local_datetime(naive)
+                .earliest()
+                .map(|dt| dt.with_timezone(&Local)),
+        }
+    }
+
+    /// Formats `dt` in this zone
+    pub(crate) fn format(&self, dt: &DateTime<Local>, format: &str) -> String {
+        match self {
+            Timezone::Local => dt.format(format).to_string(),
+            Timezone::Utc => dt.with_timezone(&Utc).format(format).to_string(),
+            Timezone::Fixed(offset) => dt.with_timezone(offset).format(format).to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod t {
+    use crate::*;
+
+    #[test]
+    fn test_fixed_offset_naming() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let timezone = Timezone::Fixed(FixedOffset::east_opt(8 * 3600).unwrap());
+        let mut rfa = BasicRollingFileAppender::builder()
+            .folder(folder)
+            .prefix("log.log")
+            .condition(RollingConditionBasic::new().daily().timezone(timezone))
+            .timezone(timezone)
+            .build()
+            .unwrap();
+        rfa.rollover().unwrap();
+        // 23:30 and 00:30 of the next day at UTC+8
+        for hour in [15, 16] {
+            let now = Utc
+                .with_ymd_and_hms(2099, 3, 30, hour, 30, 0)
+                .unwrap()
+                .with_timezone(&Local);
+            rfa.write_with_datetime(b"line\n", &now).unwrap();
+        }
+        rfa.flush().unwrap();
+        assert!(dir.path().join("log.log.20990330.233000").exists());
+        assert!(dir.path().join("log.log.20990331.003000").exists());
+    }
+}
@@ -0,0 +1,221 @@
+//! Atomic creation of log files with `O_TMPFILE` on Linux.
+//!
+//! A new log file normally appears in the folder as soon as it is opened, empty until the first
+//! buffer is written. A process crashing in between leaves an empty stray file behind, and
+//! directory scanners may pick up the file before it has any content. [`TmpfileFs`] creates new
+//! files as anonymous `O_TMPFILE` inodes instead and links them into place once their first data
+//! is written, so a log file is either absent or non-empty. If linking fails, the data written is
+//! still accepted, and the link is tried again on the next write and on flush, which reports the
+//! failure.
+
+use crate::{Fs, LogFile, RealFs};
+use std::{
+    ffi::CString,
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    os::unix::{ffi::OsStrExt, fs::OpenOptionsExt, io::AsRawFd},
+    path::{Path, PathBuf},
+};
+
+/// The operating system's file system, creating new log files with `O_TMPFILE`. Files which
+/// already exist are opened as usual, and so are new files on file systems without `O_TMPFILE`
+/// support. Pass it to [`crate::RollingFileAppender::new_with_fs`].
+#[derive(Copy, Clone, Debug, Default)]
+pub struct TmpfileFs;
+
+/// A log file which is linked into place on its first write
+#[derive(Debug)]
+struct TmpFile {
+    file: File,
+    link_opt: Option<PathBuf>,
+    // Data was written, but the file couldn't be linked yet
+    unlinked_data: bool,
+}
+
+impl TmpFile {
+    fn link_if_needed(&mut self) -> io::Result<()> {
+        let Some(path) = self.link_opt.as_ref() else {
+            return Ok(());
+        };
+        let proc_path = CString::new(format!("/proc/self/fd/{}", self.file.as_raw_fd()))?;
+        let target = CString::new(path.as_os_str().as_bytes())?;
+        // SAFETY: both paths are valid NUL terminated strings
+        let result = unsafe {
+            libc::linkat(
+                libc::AT_FDCWD,
+                proc_path.as_ptr(),
+                libc::AT_FDCWD,
+                target.as_ptr(),
+                libc::AT_SYMLINK_FOLLOW,
+            )
+        };
+        if result != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        internal_event!(path = %path.display(), "linked temporary file");
+        self.link_opt = None;
+        Ok(())
+    }
+}
+
+impl Write for TmpFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.file.write(buf)?;
+        // The data is in the file already, a failure to link is reported on flush
+        if n > 0 && self.link_if_needed().is_err() {
+            self.unlinked_data = true;
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        if self.unlinked_data {
+            self.link_if_needed()?;
+            self.unlinked_data = false;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for TmpFile {
+    fn drop(&mut self) {
+        if self.unlinked_data {
+            if let Err(e) = self.link_if_needed() {
+                let path = self.link_opt.as_deref().unwrap_or(Path::new(""));
+                eprintln!(
+                    "WARNING: Failed to link logfile {}, its data is lost {}",
+                    path.display(),
+                    e
+                );
+            }
+        }
+    }
+}
+
+impl LogFile for TmpFile {
+    fn sync_all(&self) -> io::Result<()> {
+        self.file.sync_all()
+    }
+}
+
+impl Fs for TmpfileFs {
+    fn open_append(&self, path: &Path) -> io::Result<Box<dyn LogFile>> {
+        if path.exists() {
+            return RealFs.open_append(path);
+        }
+        let folder = path
+            .parent()
+            .filter(|p| !p.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+        let opened = OpenOptions::new()
+            .append(true)
+            .custom_flags(libc::O_TMPFILE)
+            .mode(0o644)
+            .open(folder);
+        match opened {
+            Ok(file) => Ok(Box::new(TmpFile {
+                file,
+                link_opt: Some(path.to_path_buf()),
+                unlinked_data: false,
+            })),
+            // Old kernels and some file systems don't support O_TMPFILE
+            Err(e) if matches!(e.raw_os_error(), Some(libc::EOPNOTSUPP | libc::EISDIR)) => RealFs.open_append(path),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn file_len(&self, path: &Path) -> io::Result<u64> {
+        RealFs.file_len(path)
+    }
+
+    fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+        RealFs.create_dir_all(path)
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<String>> {
+        RealFs.read_dir(path)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        RealFs.rename(from, to)
+    }
+
+    fn rename_no_replace(&self, from: &Path, to: &Path) -> io::Result<()> {
+        RealFs.rename_no_replace(from, to)
+    }
+
+    fn copy(&self, from: &Path, to: &Path) -> io::Result<u64> {
+        RealFs.copy(from, to)
+    }
+
+    fn read_exact_at(&self, path: &Path, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+        RealFs.read_exact_at(path, offset, buf)
+    }
+
+    fn set_len(&self, path: &Path, len: u64) -> io::Result<()> {
+        RealFs.set_len(path, len)
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        RealFs.remove_file(path)
+    }
+
+    fn remove_dir(&self, path: &Path) -> io::Result<()> {
+        RealFs.remove_dir(path)
+    }
+
+    fn symlink(&self, target: &Path, link: &Path) -> io::Result<()> {
+        RealFs.symlink(target, link)
+    }
+
+    fn hard_link(&self, target: &Path, link: &Path) -> io::Result<()> {
+        RealFs.hard_link(target, link)
+    }
+}
+
+#[cfg(test)]
+mod t {
+    use crate::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_linked_on_first_write() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let condition = RollingConditionBasic::new().daily();
+        let mut rfa =
+            BasicRollingFileAppender::new_with_fs(folder, "log.log", condition, 9, Arc::new(TmpfileFs)).unwrap();
+        rfa.rollover().unwrap();
+        // Opened at construction but never written to, so only the dangling symlink is left
+        let files = std::fs::read_dir(folder)
+            .unwrap()
+            .flatten()
+            .filter(|f| f.path().is_file());
+        assert_eq!(files.count(), 0);
+
+        let now = Local.with_ymd_and_hms(2099, 3, 1, 1, 0, 0).unwrap();
+        rfa.write_with_datetime(b"line\n", &now).unwrap();
+        let path = dir.path().join("log.log.20990301.010000");
+        assert!(!path.exists());
+        rfa.flush().unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "line\n");
+        assert_eq!(std::fs::read_to_string(dir.path().join("log.log")).unwrap(), "line\n");
+    }
+
+    #[test]
+    fn test_link_failure_reported_on_flush() {
+        use std::io::Write;
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("log.log.20990301.010000");
+        let mut file = TmpfileFs.open_append(&path).unwrap();
+        // Another process took the name in the meantime
+        std::fs::write(&path, b"other\n").unwrap();
+        assert_eq!(file.write(b"line\n").unwrap(), 5);
+        assert_eq!(file.flush().unwrap_err().kind(), std::io::ErrorKind::AlreadyExists);
+
+        std::fs::remove_file(&path).unwrap();
+        file.flush().unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "line\n");
+    }
+}
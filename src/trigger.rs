@@ -0,0 +1,96 @@
+//! Requesting a rollover from anywhere.
+//!
+//! [`RollingFileAppender::rollover`](crate::RollingFileAppender::rollover) needs the appender
+//! itself, which is out of reach once it is moved to a worker thread. A [`TriggerHandle`] can be
+//! cloned into other threads, signal handlers or admin endpoints instead; the rollover it
+//! requests takes effect on the next write.
+
+use crate::RollingCondition;
+use chrono::{DateTime, Local};
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+/// Requests a rollover from a [`RollingConditionTriggered`]
+#[derive(Clone, Debug, Default)]
+pub struct TriggerHandle {
+    requested: Arc<AtomicBool>,
+}
+
+impl TriggerHandle {
+    /// Makes the condition roll over on the next write. Only async-signal-safe operations are
+    /// used, so this may be called from a signal handler.
+    pub fn trigger(&self) {
+        self.requested.store(true, Ordering::Release);
+    }
+
+    /// Returns true if a requested rollover hasn't happened yet
+    pub fn is_pending(&self) -> bool {
+        self.requested.load(Ordering::Acquire)
+    }
+}
+
+/// A condition met once after each [`TriggerHandle::trigger`]. Combine it with other conditions
+/// with [`crate::RollingConditionExt::or`].
+///
+/// # Examples
+///
+/// ```rust
+/// use local_rolling_file::*;
+/// let triggered = RollingConditionTriggered::new();
+/// let handle = triggered.handle();
+/// let c = RollingConditionBasic::new().daily().or(triggered);
+/// handle.trigger();
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct RollingConditionTriggered {
+    handle: TriggerHandle,
+}
+
+impl RollingConditionTriggered {
+    /// Creates a condition which isn't triggered yet
+    pub fn new() -> RollingConditionTriggered {
+        RollingConditionTriggered::default()
+    }
+
+    /// Returns a handle triggering this condition
+    pub fn handle(&self) -> TriggerHandle {
+        self.handle.clone()
+    }
+}
+
+impl RollingCondition for RollingConditionTriggered {
+    fn should_rollover(&mut self, _now: &DateTime<Local>, _current_filesize: u64) -> bool {
+        self.handle.requested.swap(false, Ordering::AcqRel)
+    }
+
+    fn describe(&self) -> String {
+        "rollover=on-demand".to_string()
+    }
+}
+
+#[cfg(test)]
+mod t {
+    use crate::*;
+    use std::io::Write;
+
+    #[test]
+    fn test_triggered_rollover() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let triggered = RollingConditionTriggered::new();
+        let handle = triggered.handle();
+        let mut rfa = RollingFileAppender::new(folder, "log.log", triggered, 9).unwrap();
+        rfa.rollover().unwrap();
+        let at = |second| Local.with_ymd_and_hms(2099, 3, 30, 1, 0, second).unwrap();
+        rfa.write_with_datetime(b"first\n", &at(0)).unwrap();
+        rfa.write_with_datetime(b"first\n", &at(1)).unwrap();
+        std::thread::spawn(move || handle.trigger()).join().unwrap();
+        rfa.write_with_datetime(b"second\n", &at(2)).unwrap();
+        rfa.flush().unwrap();
+        let read = |name: &str| std::fs::read_to_string(dir.path().join(name)).unwrap();
+        assert_eq!(read("log.log.20990330.010000"), "first\nfirst\n");
+        assert_eq!(read("log.log.20990330.010002"), "second\n");
+    }
+}
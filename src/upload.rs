@@ -0,0 +1,462 @@
+//! Uploading rolled files to remote storage.
+//!
+//! Logs often have to end up in an object store such as S3 or GCS. An [`UploadQueue`] owns a
+//! worker thread pushing each rolled file through an [`Uploader`], retrying failures with a
+//! growing delay, so the writing thread never waits for the network. Attach it with
+//! [`crate::RollingFileAppender::with_uploads`]; retention doesn't delete a file until it was
+//! uploaded, even across restarts: a marker file `<file>.upload` stays next to each file until it
+//! is uploaded. A failed upload is tried again later, with a growing delay. The crate doesn't
+//! depend on any storage client, implement [`Uploader`] with the one of your choice, or pass a
+//! closure:
+//!
+//! ```rust
+//! use local_rolling_file::*;
+//!
+//! # fn docs() {
+//! let uploads = UploadQueue::new(
+//!     |path: &std::path::Path, name: &str| {
+//!         // e.g. put the contents of `path` to `s3://bucket/logs/{name}`
+//!         Ok(())
+//!     },
+//!     5,
+//! )
+//! .unwrap();
+//! let file_appender = BasicRollingFileAppender::builder()
+//!     .folder("./log")
+//!     .prefix("log.log")
+//!     .condition(RollingConditionBasic::new().daily())
+//!     .max_files(9)
+//!     .uploads(uploads.handle())
+//!     .build()
+//!     .unwrap();
+//! # }
+//! ```
+
+use crate::{compression, RealFs, ThreadOptions};
+use std::{
+    collections::HashSet,
+    fmt,
+    fs,
+    io,
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{self, Receiver, RecvTimeoutError, Sender},
+        Arc,
+        Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+/// Extension of the marker files of the rolled files waiting for their upload
+pub const PENDING_EXTENSION: &str = "upload";
+
+/// Delay before the first retry of a failed upload
+const FIRST_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// Longest delay between two attempts to upload a file
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(3600);
+
+/// Pushes a rolled file to remote storage
+pub trait Uploader: Send + Sync {
+    /// Uploads the file at `path`. `name` is its path relative to the log folder, e.g.
+    /// `log.log.20240520.010101.gz`, usable as the object key.
+    fn upload(&self, path: &Path, name: &str) -> io::Result<()>;
+}
+
+impl<F> Uploader for F
+where
+    F: Fn(&Path, &str) -> io::Result<()> + Send + Sync,
+{
+    fn upload(&self, path: &Path, name: &str) -> io::Result<()> {
+        self(path, name)
+    }
+}
+
+/// The rolled file `key` of a folder, named without the extensions compression and encryption
+/// add, since it may be compressed while it waits
+struct Upload {
+    folder: String,
+    key: PathBuf,
+}
+
+enum Message {
+    Upload(Upload),
+    Drain(Sender<()>),
+    Shutdown,
+}
+
+/// Queues files on an [`UploadQueue`]. Cheap to clone.
+#[derive(Clone, Debug)]
+pub struct UploadHandle {
+    sender: Sender<Message>,
+    /// The files queued and not uploaded yet, named like [`Upload::key`]
+    pending: Arc<Mutex<HashSet<PathBuf>>>,
+}
+
+impl UploadHandle {
+    /// Queues the rolled file `rotated` of `folder`. Whichever version of it exists when its turn
+    /// comes is uploaded, e.g. the compressed one. A file the queue can't take any more, after a
+    /// shutdown, stays pending, so it is never deleted.
+    pub(crate) fn enqueue(&self, folder: &str, rotated: &Path) {
+        let key = upload_key(rotated);
+        if let Err(e) = fs::write(marker_path(&key), b"") {
+            eprintln!("WARNING: Failed to mark {} for upload {}", key.display(), e);
+        }
+        self.queue(folder, key);
+    }
+
+    /// Queues the files of `folder` and its subfolders marked for upload by an earlier run
+    pub(crate) fn resume(&self, folder: &str) {
+        let mut dirs = vec![PathBuf::from(folder)];
+        while let Some(dir) = dirs.pop() {
+            for entry in fs::read_dir(&dir).into_iter().flatten().flatten() {
+                let path = entry.path();
+                if entry.file_type().is_ok_and(|t| t.is_dir()) {
+                    dirs.push(path);
+                } else if path.extension().is_some_and(|ext| ext == PENDING_EXTENSION) {
+                    let key = path.with_extension("");
+                    if !self.lock().contains(&key) {
+                        self.queue(folder, key);
+                    }
+                }
+            }
+        }
+    }
+
+    fn queue(&self, folder: &str, key: PathBuf) {
+        self.lock().insert(key.clone());
+        let name = key.display().to_string();
+        let upload = Upload {
+            folder: folder.to_string(),
+            key,
+        };
+        if self.sender.send(Message::Upload(upload)).is_err() {
+            eprintln!("WARNING: Upload queue is shut down, {} is kept locally", name);
+        }
+    }
+
+    /// Returns true if `path`, or the file it was compressed or encrypted from, is queued or
+    /// marked for upload and not uploaded yet
+    pub fn is_pending(&self, path: &Path) -> bool {
+        let key = upload_key(path);
+        self.lock().contains(&key) || is_marked(path)
+    }
+
+    /// Returns the number of files queued and not uploaded yet, including those whose upload
+    /// failed
+    pub fn pending_count(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// Blocks until every file queued so far was uploaded or failed once. Returns immediately if
+    /// the queue has shut down.
+    pub fn drain(&self) {
+        let (done, drained) = mpsc::channel();
+        if self.sender.send(Message::Drain(done)).is_ok() {
+            let _ = drained.recv();
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashSet<PathBuf>> {
+        self.pending.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Tells whether `path`, or the file it was compressed or encrypted from, is marked for upload.
+/// Maintenance leaves such files alone until they are uploaded.
+pub(crate) fn is_marked(path: &Path) -> bool {
+    marker_path(&upload_key(path)).exists()
+}
+
+/// Returns the path of a rolled file without the extensions compression and encryption add
+fn upload_key(path: &Path) -> PathBuf {
+    match path.file_name() {
+        Some(fname) => path.with_file_name(compression::strip_rolled_extensions(&fname.to_string_lossy())),
+        None => path.to_path_buf(),
+    }
+}
+
+/// Returns the path of the marker of the file `key`
+fn marker_path(key: &Path) -> PathBuf {
+    let mut path = key.as_os_str().to_owned();
+    path.push(format!(".{}", PENDING_EXTENSION));
+    PathBuf::from(path)
+}
+
+/// Owns the upload worker thread. Dropping it finishes the queued uploads and joins the thread;
+/// files whose upload failed stay marked and are queued again by the next appender given a
+/// handle.
+pub struct UploadQueue {
+    handle: UploadHandle,
+    thread_opt: Option<JoinHandle<()>>,
+}
+
+impl UploadQueue {
+    /// Starts a worker thread uploading with `uploader`, retrying a failed upload up to `retries`
+    /// times in a row. A file whose upload still failed is kept locally and tried again later.
+    pub fn new<U>(uploader: U, retries: u32) -> io::Result<UploadQueue>
+    where
+        U: Uploader + 'static,
+    {
+        Self::with_thread_options(uploader, retries, &ThreadOptions::new())
+    }
+
+    /// Like [`UploadQueue::new`], with the given name and priority for the worker thread
+    pub fn with_thread_options<U>(uploader: U, retries: u32, options: &ThreadOptions) -> io::Result<UploadQueue>
+    where
+        U: Uploader + 'static,
+    {
+        let (sender, receiver) = mpsc::channel();
+        let pending = Arc::new(Mutex::new(HashSet::new()));
+        let thread = {
+            let pending = pending.clone();
+            options.spawn("rolling-file-upload", move || {
+                Self::work(&uploader, retries, receiver, &pending)
+            })?
+        };
+        Ok(UploadQueue {
+            handle: UploadHandle { sender, pending },
+            thread_opt: Some(thread),
+        })
+    }
+
+    /// Returns a handle to pass to [`crate::RollingFileAppender::with_uploads`]
+    pub fn handle(&self) -> UploadHandle {
+        self.handle.clone()
+    }
+
+    /// Uploads every file queued so far, then stops the worker thread
+    pub fn shutdown(mut self) {
+        self.stop();
+    }
+
+    fn stop(&mut self) {
+        if let Some(thread) = self.thread_opt.take() {
+            let _ = self.handle.sender.send(Message::Shutdown);
+            let _ = thread.join();
+        }
+    }
+
+    fn work(uploader: &dyn Uploader, retries: u32, receiver: Receiver<Message>, pending: &Mutex<HashSet<PathBuf>>) {
+        // The failed uploads, with the time of their next attempt and the delay before it
+        let mut failed: Vec<(Instant, Duration, Upload)> = vec![];
+        loop {
+            let message = match failed.iter().map(|(at, _, _)| *at).min() {
+                Some(at) => receiver.recv_timeout(at.saturating_duration_since(Instant::now())),
+                None => receiver.recv().map_err(|_| RecvTimeoutError::Disconnected),
+            };
+            let due = match message {
+                Ok(Message::Upload(upload)) => vec![(FIRST_RETRY_DELAY, upload)],
+                Ok(Message::Drain(done)) => {
+                    let _ = done.send(());
+                    continue;
+                },
+                Ok(Message::Shutdown) | Err(RecvTimeoutError::Disconnected) => break,
+                Err(RecvTimeoutError::Timeout) => {
+                    let now = Instant::now();
+                    let (due, later) = failed.into_iter().partition::<Vec<_>, _>(|(at, _, _)| *at <= now);
+                    failed = later;
+                    due.into_iter().map(|(_, delay, upload)| (delay, upload)).collect()
+                },
+            };
+            for (mut delay, upload) in due {
+                if !upload_file(uploader, retries, &upload, &mut delay, pending) {
+                    failed.push((Instant::now() + delay, (delay * 2).min(MAX_RETRY_DELAY), upload));
+                }
+            }
+        }
+    }
+}
+
+/// Uploads the current version of a rolled file and removes its marker. Returns false if the
+/// upload has to be tried again after `delay`.
+fn upload_file(
+    uploader: &dyn Uploader,
+    retries: u32,
+    upload: &Upload,
+    delay: &mut Duration,
+    pending: &Mutex<HashSet<PathBuf>>,
+) -> bool {
+    match compression::rolled_version(&RealFs, &upload.key) {
+        Some(path) => {
+            let name = path.strip_prefix(&upload.folder).unwrap_or(&path).to_string_lossy();
+            if !upload_with_retries(uploader, retries, &path, &name, delay) {
+                return false;
+            }
+        },
+        None => eprintln!("WARNING: Rolled logfile {} to upload is gone", upload.key.display()),
+    }
+    pending.lock().unwrap_or_else(|e| e.into_inner()).remove(&upload.key);
+    let _ = fs::remove_file(marker_path(&upload.key));
+    true
+}
+
+/// Uploads a file, retrying with a growing delay starting at `delay`. Returns true on success.
+fn upload_with_retries(uploader: &dyn Uploader, retries: u32, path: &Path, name: &str, delay: &mut Duration) -> bool {
+    internal_span!("upload", path = %path.display());
+    for attempt in 0..=retries {
+        match uploader.upload(path, name) {
+            Ok(()) => {
+                internal_event!(path = %path.display(), "uploaded rolled file");
+                return true;
+            },
+            Err(e) if attempt == retries => {
+                eprintln!(
+                    "WARNING: Failed to upload logfile {}, it is kept locally and retried in {:?}: {}",
+                    name, delay, e
+                );
+            },
+            Err(_e) => {
+                internal_event!(error = %_e, attempt, "upload failed, retrying");
+                thread::sleep(*delay);
+                *delay = (*delay * 2).min(MAX_RETRY_DELAY);
+            },
+        }
+    }
+    false
+}
+
+impl fmt::Debug for UploadQueue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UploadQueue")
+            .field("pending", &self.handle.pending_count())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for UploadQueue {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+#[cfg(test)]
+mod t {
+    use super::*;
+    use crate::*;
+
+    #[test]
+    fn test_upload_before_deletion() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let uploaded = Arc::new(Mutex::new(vec![]));
+        let uploads = {
+            let uploaded = uploaded.clone();
+            UploadQueue::new(
+                move |path: &Path, name: &str| {
+                    if name.ends_with("20990302.010000") {
+                        return Err(io::Error::other("unreachable"));
+                    }
+                    let contents = std::fs::read_to_string(path)?;
+                    uploaded.lock().unwrap().push((name.to_string(), contents));
+                    Ok(())
+                },
+                0,
+            )
+            .unwrap()
+        };
+        let mut rfa = BasicRollingFileAppender::builder()
+            .folder(folder)
+            .prefix("log.log")
+            .condition(RollingConditionBasic::new().daily())
+            .max_files(1)
+            .uploads(uploads.handle())
+            .build()
+            .unwrap();
+        rfa.rollover().unwrap();
+        for day in 1..=3 {
+            let now = Local.with_ymd_and_hms(2099, 3, day, 1, 0, 0).unwrap();
+            uploads.handle().drain();
+            rfa.write_with_datetime(format!("day {}\n", day).as_bytes(), &now)
+                .unwrap();
+        }
+        uploads.handle().drain();
+
+        // The first upload is the empty file opened by the builder
+        let uploaded = uploaded.lock().unwrap();
+        assert_eq!(uploaded.len(), 2);
+        assert_eq!(
+            uploaded[1],
+            ("log.log.20990301.010000".to_string(), "day 1\n".to_string())
+        );
+        // The file which failed to upload is kept, and stays marked
+        assert!(uploads.handle().is_pending(&dir.path().join("log.log.20990302.010000")));
+        assert!(uploads
+            .handle()
+            .is_pending(&dir.path().join("log.log.20990302.010000.gz")));
+        assert!(dir.path().join("log.log.20990302.010000.upload").exists());
+        assert!(!dir.path().join("log.log.20990301.010000.upload").exists());
+        let rotated = rfa.list_rotated_files().unwrap();
+        assert_eq!(rotated, [dir.path().join("log.log.20990302.010000")]);
+    }
+
+    #[test]
+    fn test_retry_failed_uploads() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let attempts = Arc::new(Mutex::new(0));
+        let uploader = {
+            let attempts = attempts.clone();
+            move |_: &Path, _: &str| {
+                let mut attempts = attempts.lock().unwrap();
+                *attempts += 1;
+                match *attempts {
+                    1 | 2 => Err(io::Error::other("unreachable")),
+                    _ => Ok(()),
+                }
+            }
+        };
+        let uploads = UploadQueue::new(uploader, 0).unwrap();
+        let rotated = dir.path().join("log.log.20990301.010000");
+        std::fs::write(&rotated, b"day 1\n").unwrap();
+        uploads.handle().enqueue(folder, &rotated);
+        uploads.handle().drain();
+        assert!(uploads.handle().is_pending(&rotated));
+
+        // The failed upload is retried later, until it succeeds
+        let start = Instant::now();
+        while uploads.handle().pending_count() > 0 {
+            assert!(start.elapsed() < Duration::from_secs(10));
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(*attempts.lock().unwrap(), 3);
+        assert!(!uploads.handle().is_pending(&rotated));
+        assert!(!dir.path().join("log.log.20990301.010000.upload").exists());
+    }
+
+    #[test]
+    fn test_resume_pending_uploads() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let rotated = dir.path().join("log.log.20990301.010000");
+        std::fs::write(rotated.with_extension("010000.gz"), b"").unwrap();
+        std::fs::write(dir.path().join("log.log.20990301.010000.upload"), b"").unwrap();
+
+        // A marker left by an earlier run is queued, with the compressed version uploaded
+        let uploaded = Arc::new(Mutex::new(vec![]));
+        let uploads = {
+            let uploaded = uploaded.clone();
+            UploadQueue::new(
+                move |_: &Path, name: &str| {
+                    uploaded.lock().unwrap().push(name.to_string());
+                    Ok(())
+                },
+                0,
+            )
+            .unwrap()
+        };
+        assert!(uploads.handle().is_pending(&rotated));
+        let _rfa = BasicRollingFileAppender::builder()
+            .folder(folder)
+            .prefix("log.log")
+            .condition(RollingConditionBasic::new().daily())
+            .max_files(1)
+            .uploads(uploads.handle())
+            .build()
+            .unwrap();
+        uploads.handle().drain();
+        assert_eq!(*uploaded.lock().unwrap(), ["log.log.20990301.010000.gz"]);
+        assert!(!uploads.handle().is_pending(&rotated));
+    }
+}
@@ -0,0 +1,177 @@
+//! Detection of writes and flushes that block for an abnormally long time.
+//!
+//! A hung network filesystem or a dying disk can make a single write block for minutes. A
+//! [`Watchdog`] runs a thread that notices an appender operation exceeding a threshold and calls a
+//! user supplied handler, which may ask the appender to reopen its file once the operation
+//! returns.
+
+use crate::ThreadOptions;
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+/// What the appender should do after a stalled operation finally returns
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum StallAction {
+    /// Keep using the current file
+    Ignore,
+    /// Drop the current file handle and open a new file on the next write
+    Reopen,
+}
+
+/// Tracks the operation an appender is currently performing.
+#[derive(Debug)]
+pub(crate) struct IoProbe {
+    base: Instant,
+    // Milliseconds since `base` plus one at which the current operation started, 0 when idle
+    started: AtomicU64,
+    reopen: AtomicBool,
+}
+
+impl IoProbe {
+    fn new() -> IoProbe {
+        IoProbe {
+            base: Instant::now(),
+            started: AtomicU64::new(0),
+            reopen: AtomicBool::new(false),
+        }
+    }
+
+    /// Marks the start of an operation, which ends when the returned guard is dropped.
+    pub(crate) fn begin(&self) -> IoProbeGuard<'_> {
+        let now = self.base.elapsed().as_millis() as u64 + 1;
+        self.started.store(now, Ordering::Release);
+        IoProbeGuard { probe: self }
+    }
+
+    /// Returns true once if the watchdog asked for the file to be reopened.
+    pub(crate) fn take_reopen_request(&self) -> bool {
+        self.reopen.swap(false, Ordering::AcqRel)
+    }
+
+    fn running_for(&self) -> Option<Duration> {
+        match self.started.load(Ordering::Acquire) {
+            0 => None,
+            started => {
+                let now = self.base.elapsed().as_millis() as u64 + 1;
+                Some(Duration::from_millis(now.saturating_sub(started)))
+            },
+        }
+    }
+}
+
+pub(crate) struct IoProbeGuard<'a> {
+    probe: &'a IoProbe,
+}
+
+impl Drop for IoProbeGuard<'_> {
+    fn drop(&mut self) {
+        self.probe.started.store(0, Ordering::Release);
+    }
+}
+
+/// Watches one appender for stalled writes and flushes. Attach it with
+/// [`crate::RollingFileAppender::with_watchdog`]; dropping it stops the watchdog thread.
+#[derive(Debug)]
+pub struct Watchdog {
+    probe: Arc<IoProbe>,
+    stop: Arc<AtomicBool>,
+    thread_opt: Option<JoinHandle<()>>,
+}
+
+impl Watchdog {
+    /// Starts a watchdog calling `on_stall` with the elapsed time whenever an operation has been
+    /// running for longer than `threshold`. The handler is called once per stalled operation.
+    pub fn new<F>(threshold: Duration, on_stall: F) -> std::io::Result<Watchdog>
+    where
+        F: FnMut(Duration) -> StallAction + Send + 'static,
+    {
+        Self::with_thread_options(threshold, &ThreadOptions::new(), on_stall)
+    }
+
+    /// Like [`Watchdog::new`], with the given name and priority for the watchdog thread
+    pub fn with_thread_options<F>(
+        threshold: Duration,
+        options: &ThreadOptions,
+        mut on_stall: F,
+    ) -> std::io::Result<Watchdog>
+    where
+        F: FnMut(Duration) -> StallAction + Send + 'static,
+    {
+        let probe = Arc::new(IoProbe::new());
+        let stop = Arc::new(AtomicBool::new(false));
+        let interval = (threshold / 4).max(Duration::from_millis(1));
+        let thread = {
+            let probe = probe.clone();
+            let stop = stop.clone();
+            options.spawn("rolling-file-watchdog", move || {
+                let mut reported_opt = None;
+                while !stop.load(Ordering::Acquire) {
+                    thread::park_timeout(interval);
+                    let started = probe.started.load(Ordering::Acquire);
+                    match probe.running_for() {
+                        Some(elapsed) if elapsed > threshold && reported_opt != Some(started) => {
+                            reported_opt = Some(started);
+                            if on_stall(elapsed) == StallAction::Reopen {
+                                probe.reopen.store(true, Ordering::Release);
+                            }
+                        },
+                        _ => {},
+                    }
+                }
+            })?
+        };
+        Ok(Watchdog {
+            probe,
+            stop,
+            thread_opt: Some(thread),
+        })
+    }
+
+    pub(crate) fn probe(&self) -> Arc<IoProbe> {
+        self.probe.clone()
+    }
+}
+
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Release);
+        if let Some(thread) = self.thread_opt.take() {
+            thread.thread().unpark();
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod t {
+    use super::*;
+    use std::sync::mpsc;
+
+    #[test]
+    fn test_stall_reported_once() {
+        let (sender, receiver) = mpsc::channel();
+        let watchdog = Watchdog::new(Duration::from_millis(20), move |elapsed| {
+            sender.send(elapsed).unwrap();
+            StallAction::Reopen
+        })
+        .unwrap();
+        let probe = watchdog.probe();
+        {
+            let _guard = probe.begin();
+            thread::sleep(Duration::from_millis(150));
+        }
+        thread::sleep(Duration::from_millis(50));
+        drop(watchdog);
+        let reports = receiver.iter().collect::<Vec<_>>();
+        assert_eq!(reports.len(), 1);
+        assert!(reports[0] > Duration::from_millis(20));
+        assert!(probe.take_reopen_request());
+        assert!(!probe.take_reopen_request());
+    }
+}
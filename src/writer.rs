@@ -0,0 +1,407 @@
+//! Writers used for the active log file.
+
+use crate::{LogFile, ThreadOptions};
+use std::{
+    io::{self, BufWriter, IoSlice, Write},
+    sync::{
+        mpsc::{self, Receiver, SyncSender},
+        Arc,
+        Mutex,
+        MutexGuard,
+    },
+    thread::JoinHandle,
+};
+
+/// The writer of the active file, either a plain [`BufWriter`], a [`DoubleBufferWriter`] or the
+/// file itself when the buffer capacity is 0.
+#[derive(Debug)]
+pub(crate) enum FileWriter {
+    Direct(Box<dyn LogFile>),
+    Buffered(BufWriter<Box<dyn LogFile>>),
+    DoubleBuffered(DoubleBufferWriter),
+}
+
+impl FileWriter {
+    pub(crate) fn new(
+        f: Box<dyn LogFile>,
+        capacity: usize,
+        double_buffered: bool,
+        options: &ThreadOptions,
+    ) -> io::Result<FileWriter> {
+        Ok(if double_buffered {
+            FileWriter::DoubleBuffered(DoubleBufferWriter::new(f, capacity, options)?)
+        } else if capacity == 0 {
+            FileWriter::Direct(f)
+        } else {
+            FileWriter::Buffered(BufWriter::with_capacity(capacity, f))
+        })
+    }
+
+    /// Returns the memory held for buffering
+    pub(crate) fn capacity(&self) -> usize {
+        match self {
+            FileWriter::Direct(_) => 0,
+            FileWriter::Buffered(w) => w.capacity(),
+            FileWriter::DoubleBuffered(w) => 2 * w.capacity,
+        }
+    }
+
+    /// Flushes all data and asks the OS to persist it to disk.
+    pub(crate) fn sync_all(&mut self) -> io::Result<()> {
+        self.flush()?;
+        match self {
+            FileWriter::Direct(f) => f.sync_all(),
+            FileWriter::Buffered(w) => w.get_ref().sync_all(),
+            FileWriter::DoubleBuffered(w) => w.lock_file().sync_all(),
+        }
+    }
+
+    /// Writes all of `bufs` after their first `skip` bytes, without copying them into the buffer
+    /// when they don't fit. Adds the number of bytes accepted to `written`, also when it fails
+    /// midway. The slices are walked in place, a partly written one is finished with a plain
+    /// write before the rest go out vectored again.
+    pub(crate) fn write_all_vectored(
+        &mut self,
+        mut bufs: &[IoSlice<'_>],
+        skip: usize,
+        written: &mut usize,
+    ) -> io::Result<()> {
+        // How much of the first slice is written
+        let mut offset = skip;
+        loop {
+            while let Some(first) = bufs.first().filter(|first| offset >= first.len()) {
+                offset -= first.len();
+                bufs = &bufs[1..];
+            }
+            let Some(first) = bufs.first() else {
+                return Ok(());
+            };
+            let result = if offset == 0 {
+                self.write_vectored(bufs)
+            } else {
+                self.write(&first[offset..])
+            };
+            match result {
+                Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+                Ok(n) => {
+                    *written += n;
+                    offset += n;
+                },
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {},
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Closes the file without writing the data still buffered, e.g. because its disk is full,
+    /// and returns that data.
+    pub(crate) fn into_unwritten(self) -> Vec<u8> {
+        match self {
+            FileWriter::Direct(_) => vec![],
+            FileWriter::Buffered(w) => w.into_parts().1.unwrap_or_else(|e| e.into_inner()),
+            FileWriter::DoubleBuffered(w) => w.into_unwritten(),
+        }
+    }
+
+    /// Flushes all data and returns the file. Flush first to keep the data on failure.
+    pub(crate) fn into_file(self) -> io::Result<Box<dyn LogFile>> {
+        match self {
+            FileWriter::Direct(f) => Ok(f),
+            FileWriter::Buffered(w) => w.into_inner().map_err(|e| e.into_error()),
+            FileWriter::DoubleBuffered(w) => w.into_file(),
+        }
+    }
+}
+
+impl Write for FileWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            FileWriter::Direct(f) => f.write(buf),
+            FileWriter::Buffered(w) => w.write(buf),
+            FileWriter::DoubleBuffered(w) => w.write(buf),
+        }
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        match self {
+            FileWriter::Direct(f) => f.write_vectored(bufs),
+            FileWriter::Buffered(w) => w.write_vectored(bufs),
+            FileWriter::DoubleBuffered(w) => w.write_vectored(bufs),
+        }
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        match self {
+            FileWriter::Direct(f) => f.write_all(buf),
+            FileWriter::Buffered(w) => w.write_all(buf),
+            FileWriter::DoubleBuffered(w) => w.write_all(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            FileWriter::Direct(f) => f.flush(),
+            FileWriter::Buffered(w) => w.flush(),
+            FileWriter::DoubleBuffered(w) => w.flush(),
+        }
+    }
+}
+
+/// What the background thread hands back: the emptied buffer, or the error and the data it
+/// didn't get to the file
+type FlushResult = Result<Vec<u8>, (io::Error, Vec<u8>)>;
+
+/// Fills one buffer while a background thread writes the previous one to the file, so
+/// formatting and disk I/O overlap. Only two buffers exist: if the background thread hasn't
+/// finished with the previous buffer when the current one is full, the writer waits for it.
+///
+/// A write error of the background thread is returned by the next write or flush, and the data
+/// not written is kept in front of the current buffer to be tried again.
+#[derive(Debug)]
+pub(crate) struct DoubleBufferWriter {
+    capacity: usize,
+    current: Vec<u8>,
+    spare_opt: Option<Vec<u8>>,
+    error_opt: Option<io::Error>,
+    to_flusher_opt: Option<SyncSender<Vec<u8>>>,
+    from_flusher: Receiver<FlushResult>,
+    file: Arc<Mutex<Box<dyn LogFile>>>,
+    thread_opt: Option<JoinHandle<()>>,
+}
+
+impl DoubleBufferWriter {
+    pub(crate) fn new(f: Box<dyn LogFile>, capacity: usize, options: &ThreadOptions) -> io::Result<DoubleBufferWriter> {
+        let capacity = capacity.max(1);
+        let file = Arc::new(Mutex::new(f));
+        let (to_flusher, buffers) = mpsc::sync_channel::<Vec<u8>>(1);
+        let (results, from_flusher) = mpsc::channel();
+        let thread = {
+            let file = file.clone();
+            options.spawn("rolling-file-flusher", move || {
+                for mut buf in buffers {
+                    let mut f = file.lock().unwrap_or_else(|e| e.into_inner());
+                    let mut written = 0;
+                    let result = loop {
+                        if written == buf.len() {
+                            break Ok(());
+                        }
+                        match f.write(&buf[written..]) {
+                            Ok(0) => break Err(io::ErrorKind::WriteZero.into()),
+                            Ok(n) => written += n,
+                            Err(e) if e.kind() == io::ErrorKind::Interrupted => {},
+                            Err(e) => break Err(e),
+                        }
+                    };
+                    drop(f);
+                    buf.drain(..written);
+                    let result = match result {
+                        Ok(()) => Ok(buf),
+                        Err(e) => Err((e, buf)),
+                    };
+                    if results.send(result).is_err() {
+                        break;
+                    }
+                }
+            })?
+        };
+        Ok(DoubleBufferWriter {
+            capacity,
+            current: Vec::with_capacity(capacity),
+            spare_opt: Some(Vec::with_capacity(capacity)),
+            error_opt: None,
+            to_flusher_opt: Some(to_flusher),
+            from_flusher,
+            file,
+            thread_opt: Some(thread),
+        })
+    }
+
+    /// Waits until the background thread is done with the previous buffer. The data it failed
+    /// to write goes back in front of the current buffer.
+    fn wait_for_spare(&mut self) -> io::Result<()> {
+        if self.spare_opt.is_none() {
+            match self.from_flusher.recv() {
+                Ok(Ok(buf)) => self.spare_opt = Some(buf),
+                Ok(Err((e, mut unwritten))) => {
+                    unwritten.extend_from_slice(&self.current);
+                    self.current = unwritten;
+                    self.spare_opt = Some(Vec::with_capacity(self.capacity));
+                    return Err(e);
+                },
+                Err(_) => return Err(io::Error::other("flusher thread is gone")),
+            }
+        }
+        Ok(())
+    }
+
+    /// Hands the current buffer to the background thread.
+    fn swap(&mut self) -> io::Result<()> {
+        self.wait_for_spare()?;
+        let spare = self.spare_opt.take().unwrap_or_default();
+        let full = std::mem::replace(&mut self.current, spare);
+        match self.to_flusher_opt.as_ref().map(|s| s.send(full)) {
+            Some(Ok(())) => Ok(()),
+            _ => Err(io::Error::other("flusher thread is gone")),
+        }
+    }
+
+    fn lock_file(&self) -> MutexGuard<'_, Box<dyn LogFile>> {
+        self.file.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn into_file(mut self) -> io::Result<Box<dyn LogFile>> {
+        self.flush()?;
+        self.stop();
+        // The flusher thread has exited, so this is the last reference
+        let file = self.file.clone();
+        drop(self);
+        Arc::try_unwrap(file)
+            .map(|f| f.into_inner().unwrap_or_else(|e| e.into_inner()))
+            .map_err(|_| io::Error::other("log file is still in use"))
+    }
+
+    /// Stops the background thread and returns the data not written
+    fn into_unwritten(mut self) -> Vec<u8> {
+        self.stop();
+        let _ = self.wait_for_spare();
+        std::mem::take(&mut self.current)
+    }
+
+    fn stop(&mut self) {
+        self.to_flusher_opt.take();
+        if let Some(thread) = self.thread_opt.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Write for DoubleBufferWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let Some(e) = self.error_opt.take() {
+            return Err(e);
+        }
+        // Still full after a failed write of the background thread
+        if self.current.len() >= self.capacity {
+            self.swap()?;
+        }
+        let n = buf.len().min(self.capacity - self.current.len());
+        self.current.extend_from_slice(&buf[..n]);
+        if self.current.len() >= self.capacity {
+            // The data is buffered already, so the error is reported by the next call
+            if let Err(e) = self.swap() {
+                self.error_opt = Some(e);
+            }
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if let Some(e) = self.error_opt.take() {
+            return Err(e);
+        }
+        if !self.current.is_empty() {
+            self.swap()?;
+        }
+        self.wait_for_spare()
+    }
+}
+
+impl Drop for DoubleBufferWriter {
+    fn drop(&mut self) {
+        if self.thread_opt.is_some() {
+            if let Err(e) = self.flush() {
+                eprintln!("WARNING: Failed to flush logfile {}", e);
+            }
+            self.stop();
+        }
+    }
+}
+
+#[cfg(test)]
+mod t {
+    use super::*;
+    use std::fs::File;
+
+    #[test]
+    fn test_double_buffer_writes_everything_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("f");
+        let mut w = DoubleBufferWriter::new(Box::new(File::create(&path).unwrap()), 16, &ThreadOptions::new()).unwrap();
+        let mut expected = vec![];
+        for i in 0..100 {
+            let line = format!("line {}\n", i);
+            w.write_all(line.as_bytes()).unwrap();
+            expected.extend_from_slice(line.as_bytes());
+        }
+        w.flush().unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), expected);
+        w.write_all(b"tail").unwrap();
+        let f = w.into_file().unwrap();
+        drop(f);
+        expected.extend_from_slice(b"tail");
+        assert_eq!(std::fs::read(&path).unwrap(), expected);
+    }
+
+    /// Accepts `room` bytes, then fails until given more room
+    #[derive(Debug, Clone, Default)]
+    struct FailingFile {
+        data: Arc<Mutex<Vec<u8>>>,
+        room: Arc<Mutex<usize>>,
+    }
+
+    impl Write for FailingFile {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let mut room = self.room.lock().unwrap();
+            if *room == 0 {
+                return Err(io::Error::other("disk full"));
+            }
+            let n = buf.len().min(*room);
+            *room -= n;
+            self.data.lock().unwrap().extend_from_slice(&buf[..n]);
+            Ok(n)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl LogFile for FailingFile {
+        fn sync_all(&self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_double_buffer_keeps_data_on_error() {
+        let file = FailingFile::default();
+        *file.room.lock().unwrap() = 4;
+        let mut w = DoubleBufferWriter::new(Box::new(file.clone()), 8, &ThreadOptions::new()).unwrap();
+        // A large write is taken up to the capacity only
+        assert_eq!(w.write(b"0123456789").unwrap(), 8);
+        assert_eq!(w.flush().unwrap_err().to_string(), "disk full");
+        // Nothing accepted twice and nothing lost
+        assert_eq!(w.write(b"89").unwrap(), 2);
+        assert!(w.current.len() <= 2 * w.capacity);
+        *file.room.lock().unwrap() = 100;
+        w.flush().unwrap();
+        assert_eq!(*file.data.lock().unwrap(), b"0123456789");
+    }
+
+    #[test]
+    fn test_vectored_write_resumes_midway() {
+        let file = FailingFile::default();
+        *file.room.lock().unwrap() = 5;
+        let mut w = FileWriter::Direct(Box::new(file.clone()));
+        let bufs = [IoSlice::new(b"abc"), IoSlice::new(b""), IoSlice::new(b"defgh")];
+        let mut written = 0;
+        assert!(w.write_all_vectored(&bufs, 0, &mut written).is_err());
+        assert_eq!(written, 5);
+        // Picks up in the middle of the last slice
+        *file.room.lock().unwrap() = 100;
+        let mut rest = 0;
+        w.write_all_vectored(&bufs, written, &mut rest).unwrap();
+        assert_eq!(rest, 3);
+        assert_eq!(*file.data.lock().unwrap(), b"abcdefgh");
+    }
+}
@@ -0,0 +1,66 @@
+//! Writes into the current file must not allocate. The counting allocator replaces the allocator of
+//! the whole binary, so this runs as its own test binary with a single test.
+
+use chrono::prelude::*;
+use local_rolling_file::*;
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+};
+
+/// Counts the allocations of the current thread
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<u64> = const { Cell::new(0) };
+}
+
+// SAFETY: forwards to the system allocator
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|n| n.set(n.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+#[test]
+fn test_writes_without_allocations() {
+    let dir = tempfile::tempdir().unwrap();
+    let folder = dir.path().to_str().unwrap();
+    let mut rfa = BasicRollingFileAppender::builder()
+        .folder(folder)
+        .prefix("log.log")
+        .condition(RollingConditionBasic::new().daily())
+        .max_files(2)
+        .background_maintenance(true)
+        .build()
+        .unwrap();
+    rfa.rollover().unwrap();
+    let now = Local.with_ymd_and_hms(2099, 3, 1, 1, 0, 0).unwrap();
+    rfa.write_with_datetime(b"first\n", &now).unwrap();
+    let before = ALLOCATIONS.with(Cell::get);
+    for _ in 0..1000 {
+        rfa.write_with_datetime(b"line\n", &now).unwrap();
+    }
+    let record = [std::io::IoSlice::new(b"vectored "), std::io::IoSlice::new(b"line\n")];
+    for _ in 0..1000 {
+        rfa.write_vectored_with_datetime(&record, &now).unwrap();
+    }
+    assert_eq!(ALLOCATIONS.with(Cell::get), before);
+
+    for day in 2..=4 {
+        let now = Local.with_ymd_and_hms(2099, 3, day, 1, 0, 0).unwrap();
+        rfa.write_with_datetime(b"line\n", &now).unwrap();
+    }
+    // Retention runs in the background, and is done once the appender is dropped
+    drop(rfa);
+    assert!(!dir.path().join("log.log.20990302.010000").exists());
+    assert!(dir.path().join("log.log.20990303.010000").exists());
+}